@@ -1,3 +1,28 @@
 fn main() {
+  sidecar_versions();
   tauri_build::build()
 }
+
+/// Expose the versions of the bundled WPPConnect sidecar, so WhatsApp session
+/// snapshots can record which build produced them
+fn sidecar_versions() {
+  let lock_path = "../wppconnect-sidecar/package-lock.json";
+  println!("cargo:rerun-if-changed={}", lock_path);
+
+  let lock: serde_json::Value = std::fs::read_to_string(lock_path)
+    .ok()
+    .and_then(|contents| serde_json::from_str(&contents).ok())
+    .unwrap_or_default();
+  let version = |package: &str| {
+    lock["packages"][package]["version"]
+      .as_str()
+      .unwrap_or("unknown")
+      .to_string()
+  };
+
+  println!("cargo:rustc-env=WPPCONNECT_SIDECAR_VERSION={}", version(""));
+  println!(
+    "cargo:rustc-env=WPPCONNECT_LIB_VERSION={}",
+    version("node_modules/@wppconnect-team/wppconnect")
+  );
+}
@@ -0,0 +1,422 @@
+//! Writing a consistent snapshot of `pb_data` to an encrypted archive
+//!
+//! The snapshot is zipped, then sealed with [`crypto::seal`] under the
+//! backup passphrase. The cleartext header carries a [`BackupInfo`], so an
+//! archive can be identified and checked for compatibility before the
+//! passphrase is asked for.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::backup::Backup;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::crypto;
+use crate::db;
+
+const MAGIC: &[u8; 8] = b"LMPBBKUP";
+/// Bump when the archive contents change shape
+pub const FORMAT: u32 = 2;
+/// Extension of backup archives
+pub const EXTENSION: &str = "lmbk";
+/// PocketBase's SQLite databases, in the order they are archived
+pub const DATABASES: &[&str] = &["data.db", "auxiliary.db"];
+/// Uploaded files, stored by PocketBase outside the databases
+pub const STORAGE_DIR: &str = "storage";
+pub const MANIFEST: &str = "manifest.json";
+
+/// File in a backup, with its checksum before compression
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+    /// Path inside `pb_data`, with `/` separators
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Description of a backup, stored as the archive's last entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub format: u32,
+    /// Unix timestamp (ms)
+    pub created_at: i64,
+    pub app_version: String,
+    /// PocketBase migrations applied to the backed-up database
+    pub migrations: Vec<String>,
+    pub files: Vec<ManifestFile>,
+    /// Uploads PocketBase deleted while they were being archived
+    pub missing_files: Vec<String>,
+}
+
+/// Cleartext description of a backup, stored in the archive header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub format: u32,
+    /// Unix timestamp (ms)
+    pub created_at: i64,
+    pub app_version: String,
+    /// PocketBase migrations applied to the backed-up database
+    pub migrations: Vec<String>,
+    pub files: u32,
+    pub missing_files: u32,
+    /// Size of the unencrypted payload in bytes
+    pub size: u64,
+    /// SHA-256 of the unencrypted payload
+    pub sha256: String,
+}
+
+/// What was written, for the backup index
+#[derive(Debug, Clone)]
+pub struct ArchiveSummary {
+    pub size: u64,
+    /// SHA-256 of the archive file itself
+    pub sha256: String,
+    pub files: u32,
+    pub missing_files: u32,
+}
+
+/// Reader that hashes what passes through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+/// Writer that hashes what passes through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Copy a live SQLite database to `dest` in one backup step. A single step
+/// reads from one snapshot, and as PocketBase runs in WAL mode its writers
+/// are not blocked meanwhile.
+pub(super) fn copy_database(src: &Path, dest: &Path) -> Result<(), String> {
+    let source =
+        Connection::open(src).map_err(|e| format!("Failed to open {}: {}", src.display(), e))?;
+    source
+        .busy_timeout(Duration::from_secs(10))
+        .map_err(|e| format!("Failed to open {}: {}", src.display(), e))?;
+    let mut copy = Connection::open(dest)
+        .map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
+
+    Backup::new(&source, &mut copy)
+        .and_then(|backup| backup.run_to_completion(i32::MAX, Duration::ZERO, None))
+        .map_err(|e| format!("Failed to copy {}: {}", src.display(), e))?;
+
+    // Leave a self-contained file, without a -wal next to it
+    copy.pragma_update(None, "journal_mode", "DELETE")
+        .map_err(|e| format!("Failed to finish copy of {}: {}", src.display(), e))?;
+    let check: String = copy
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to check copy of {}: {}", src.display(), e))?;
+    if check != "ok" {
+        return Err(format!("Copy of {} is corrupt: {}", src.display(), check));
+    }
+    Ok(())
+}
+
+/// Names of the PocketBase migrations applied to a database
+pub fn applied_migrations(database: &Path) -> Result<Vec<String>, String> {
+    let conn = Connection::open_with_flags(database, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", database.display(), e))?;
+    let mut stmt = conn
+        .prepare("SELECT file FROM _migrations ORDER BY file")
+        .map_err(|e| format!("Failed to read migrations: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| row.get(0))
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Failed to read migrations: {}", e));
+    rows
+}
+
+/// Files under `dir`, as paths relative to `base` with `/` separators
+fn walk(base: &Path, dir: &Path, files: &mut Vec<String>) -> Result<(), String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if file_type.is_dir() {
+            walk(base, &path, files)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(base).unwrap_or(&path);
+            let parts: Vec<_> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            files.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Add `src` to the archive as `name`. Returns `None` if the file vanished.
+fn add_file(
+    zip: &mut zip::ZipWriter<File>,
+    src: &Path,
+    name: &str,
+) -> Result<Option<ManifestFile>, String> {
+    let file = match File::open(src) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", src.display(), e)),
+    };
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(size >= u32::MAX as u64);
+    zip.start_file(name, options)
+        .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+
+    let mut reader = HashingReader {
+        inner: BufReader::new(file),
+        hasher: Sha256::new(),
+        len: 0,
+    };
+    std::io::copy(&mut reader, zip)
+        .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+    Ok(Some(ManifestFile {
+        path: name.to_string(),
+        size: reader.len,
+        sha256: hex::encode(reader.hasher.finalize()),
+    }))
+}
+
+fn write_zip(pb_data: &Path, staging: &Path, dest: &Path) -> Result<Manifest, String> {
+    let file =
+        File::create(dest).map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let mut files = Vec::new();
+
+    let mut migrations = Vec::new();
+    for name in DATABASES {
+        let src = pb_data.join(name);
+        if !src.exists() {
+            continue;
+        }
+        let copy = staging.join(name);
+        copy_database(&src, &copy)?;
+        if *name == "data.db" {
+            migrations = applied_migrations(&copy)?;
+        }
+        files.extend(add_file(&mut zip, &copy, name)?);
+    }
+    if files.is_empty() {
+        return Err(format!(
+            "No PocketBase database found in {}",
+            pb_data.display()
+        ));
+    }
+
+    // Uploads are copied after the databases, so a file the copies
+    // reference is only missing if PocketBase deleted it in the meantime
+    let mut uploads = Vec::new();
+    walk(pb_data, &pb_data.join(STORAGE_DIR), &mut uploads)?;
+    uploads.sort();
+    let mut missing_files = Vec::new();
+    for name in uploads {
+        match add_file(&mut zip, &pb_data.join(&name), &name)? {
+            Some(file) => files.push(file),
+            None => missing_files.push(name),
+        }
+    }
+
+    let manifest = Manifest {
+        format: FORMAT,
+        created_at: db::now_millis(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        migrations,
+        files,
+        missing_files,
+    };
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file(MANIFEST, options)
+        .and_then(|_| {
+            let json = serde_json::to_vec_pretty(&manifest).expect("manifest serializes");
+            zip.write_all(&json).map_err(Into::into)
+        })
+        .map_err(|e| format!("Failed to write backup manifest: {}", e))?;
+
+    let file = zip
+        .finish()
+        .map_err(|e| format!("Failed to finish backup: {}", e))?;
+    file.sync_all()
+        .map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+    Ok(manifest)
+}
+
+/// Checksum file written next to an archive, in `sha256sum` format
+pub fn checksum_path(archive: &Path) -> PathBuf {
+    let mut name = archive.file_name().unwrap_or_default().to_os_string();
+    name.push(".sha256");
+    archive.with_file_name(name)
+}
+
+/// Snapshot `pb_data` into the archive `dest`, encrypted with
+/// `passphrase`. The archive appears under its final name only once
+/// complete, with a checksum file next to it.
+pub fn write(pb_data: &Path, dest: &Path, passphrase: &str) -> Result<ArchiveSummary, String> {
+    let dir = dest
+        .parent()
+        .ok_or_else(|| format!("Invalid backup path: {}", dest.display()))?;
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let staging = dest.with_extension("staging");
+    let partial = dest.with_extension("partial");
+    std::fs::create_dir_all(&staging)
+        .map_err(|e| format!("Failed to create {}: {}", staging.display(), e))?;
+
+    let result = (|| {
+        let payload = staging.join("payload.zip");
+        let manifest = write_zip(pb_data, &staging, &payload)?;
+        let open_payload = || {
+            File::open(&payload)
+                .map(BufReader::new)
+                .map_err(|e| format!("Failed to read {}: {}", payload.display(), e))
+        };
+        let info = BackupInfo {
+            format: FORMAT,
+            created_at: manifest.created_at,
+            app_version: manifest.app_version,
+            migrations: manifest.migrations,
+            files: manifest.files.len() as u32,
+            missing_files: manifest.missing_files.len() as u32,
+            size: std::fs::metadata(&payload).map(|m| m.len()).unwrap_or(0),
+            sha256: crypto::sha256_hex(open_payload()?)?,
+        };
+
+        let out = File::create(&partial)
+            .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+        let mut writer = BufWriter::new(out);
+        crypto::seal(
+            MAGIC,
+            info.clone(),
+            passphrase,
+            open_payload()?,
+            &mut writer,
+        )
+        .and_then(|_| {
+            writer
+                .into_inner()
+                .map_err(|e| e.to_string())?
+                .sync_all()
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|e| format!("Failed to write backup: {}", e))?;
+
+        let sha256 = crypto::sha256_hex(BufReader::new(
+            File::open(&partial)
+                .map_err(|e| format!("Failed to read {}: {}", partial.display(), e))?,
+        ))?;
+        let size = std::fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+
+        std::fs::rename(&partial, dest)
+            .map_err(|e| format!("Failed to move backup to {}: {}", dest.display(), e))?;
+        let file_name = dest.file_name().unwrap_or_default().to_string_lossy();
+        std::fs::write(checksum_path(dest), format!("{}  {}\n", sha256, file_name))
+            .map_err(|e| format!("Failed to write checksum for {}: {}", dest.display(), e))?;
+
+        Ok(ArchiveSummary {
+            size,
+            sha256,
+            files: info.files,
+            missing_files: info.missing_files,
+        })
+    })();
+
+    let _ = std::fs::remove_dir_all(&staging);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+fn open_archive(src: &Path) -> Result<BufReader<File>, String> {
+    File::open(src)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to open {}: {}", src.display(), e))
+}
+
+/// Read a backup's header without decrypting it
+pub fn inspect(src: &Path) -> Result<BackupInfo, String> {
+    let (header, _) =
+        crypto::read_header::<BackupInfo>(MAGIC, &mut open_archive(src)?).map_err(not_a_backup)?;
+    Ok(header.metadata)
+}
+
+/// Decrypt a backup into `writer`, checking it against its header
+pub fn decrypt(src: &Path, passphrase: &str, writer: impl Write) -> Result<BackupInfo, String> {
+    let mut reader = open_archive(src)?;
+    let (header, header_bytes) =
+        crypto::read_header::<BackupInfo>(MAGIC, &mut reader).map_err(not_a_backup)?;
+    let info = &header.metadata;
+    if info.format > FORMAT {
+        return Err(format!(
+            "Backup was made by a newer version of the app ({})",
+            info.app_version
+        ));
+    }
+
+    let mut writer = HashingWriter {
+        inner: writer,
+        hasher: Sha256::new(),
+        len: 0,
+    };
+    crypto::open(&header, &header_bytes, passphrase, reader, &mut writer)?;
+    if writer.len != info.size || hex::encode(writer.hasher.finalize()) != info.sha256 {
+        return Err("Backup checksum does not match, the archive is corrupted".to_string());
+    }
+    Ok(header.metadata)
+}
+
+/// Check `passphrase` opens a backup and that its contents are intact,
+/// without writing them anywhere
+pub fn verify(src: &Path, passphrase: &str) -> Result<BackupInfo, String> {
+    decrypt(src, passphrase, std::io::sink())
+}
+
+fn not_a_backup(error: String) -> String {
+    if error == "Not a recognized archive" {
+        "Not a Luminila backup".to_string()
+    } else {
+        error
+    }
+}
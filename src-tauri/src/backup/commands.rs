@@ -0,0 +1,124 @@
+use tauri::{AppHandle, State};
+
+use super::{
+    archive, emit, BackupInfo, BackupOverview, BackupRecord, BackupSettings, Backups, CopyRecord,
+    Destination, PointInTimePlan, RestorePlan, Trigger,
+};
+
+/// Tauri command to back up PocketBase now. Resolves once the archive is
+/// written; a failed backup is returned with its error.
+#[tauri::command]
+pub async fn backup_now(
+    app: AppHandle,
+    backups: State<'_, Backups>,
+) -> Result<BackupRecord, String> {
+    backups
+        .run(Trigger::Manual, |record| emit(&app, record))
+        .await
+}
+
+/// Tauri command to list backups, newest first
+#[tauri::command]
+pub fn backup_list(
+    backups: State<'_, Backups>,
+    limit: Option<u32>,
+) -> Result<Vec<BackupRecord>, String> {
+    backups.list(limit.unwrap_or(50))
+}
+
+/// Tauri command to get the backup settings and latest results
+#[tauri::command]
+pub fn backup_status(backups: State<'_, Backups>) -> Result<BackupOverview, String> {
+    backups.overview()
+}
+
+/// Tauri command to list where a backup has been copied to
+#[tauri::command]
+pub fn backup_copies(
+    backups: State<'_, Backups>,
+    backup_id: String,
+) -> Result<Vec<CopyRecord>, String> {
+    backups.copies(&backup_id)
+}
+
+/// Tauri command to change the backup schedule, folder, retention or
+/// destinations
+#[tauri::command]
+pub fn backup_save_settings(
+    app: AppHandle,
+    backups: State<'_, Backups>,
+    settings: BackupSettings,
+) -> Result<BackupOverview, String> {
+    backups.save_settings(&app, settings)?;
+    backups.overview()
+}
+
+/// Tauri command to check a destination is reachable and writable by
+/// writing and deleting a small test file, before it is saved
+#[tauri::command]
+pub async fn backup_test_destination(
+    backups: State<'_, Backups>,
+    destination: Destination,
+) -> Result<(), String> {
+    backups.test_destination(&destination).await
+}
+
+/// Tauri command to set the passphrase backups are encrypted with, or clear
+/// it (`None`) to stop backing up
+#[tauri::command]
+pub fn backup_set_passphrase(
+    backups: State<'_, Backups>,
+    passphrase: Option<String>,
+) -> Result<BackupOverview, String> {
+    backups.set_passphrase(passphrase)?;
+    backups.overview()
+}
+
+/// Tauri command to read what a backup contains without decrypting it
+#[tauri::command]
+pub fn backup_inspect(path: String) -> Result<BackupInfo, String> {
+    archive::inspect(path.as_ref())
+}
+
+/// Tauri command to check a passphrase opens a backup and that the backup is
+/// intact, without restoring it
+#[tauri::command]
+pub async fn backup_verify(path: String, passphrase: String) -> Result<BackupInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || archive::verify(path.as_ref(), &passphrase))
+        .await
+        .map_err(|e| format!("Verify task failed: {}", e))?
+}
+
+/// Tauri command to restore PocketBase's data from a backup. With `dryRun`
+/// nothing changes and the result lists what a restore would add, change and
+/// remove. Otherwise PocketBase is restarted on the restored data, and the
+/// previous data is put back if it fails to come up.
+#[tauri::command]
+pub async fn backup_restore(
+    app: AppHandle,
+    backups: State<'_, Backups>,
+    path: String,
+    passphrase: String,
+    dry_run: Option<bool>,
+) -> Result<RestorePlan, String> {
+    backups
+        .restore(&app, path.into(), passphrase, dry_run.unwrap_or(true))
+        .await
+}
+
+/// Tauri command to restore PocketBase's database to a point in time
+/// (`at`, Unix ms) from the shipped write-ahead log. With `dryRun` nothing
+/// changes and the result tells the time the data would be from and what
+/// would be added, changed and removed. Uploads are left as they are.
+#[tauri::command]
+pub async fn backup_restore_point_in_time(
+    app: AppHandle,
+    backups: State<'_, Backups>,
+    at: i64,
+    passphrase: String,
+    dry_run: Option<bool>,
+) -> Result<PointInTimePlan, String> {
+    backups
+        .restore_to(&app, at, passphrase, dry_run.unwrap_or(true))
+        .await
+}
@@ -0,0 +1,504 @@
+//! Places backups are copied to after they are written
+//!
+//! A destination is a folder (a network share or a synced folder, say), a
+//! removable drive found by its name whenever it is plugged in, or an
+//! S3-compatible bucket. Every copy is checked against the archive's
+//! SHA-256 before it counts, and an interrupted copy picks up where it
+//! stopped on the next attempt.
+
+pub mod s3;
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::archive;
+use crate::crypto;
+pub use s3::{S3Client, S3Target, UploadState};
+
+/// File written and removed again to test a destination
+const PROBE_FILE: &str = ".luminila-backup-probe";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DestinationKind {
+    Folder {
+        /// Absolute path of the folder
+        path: String,
+    },
+    /// Removable drive, used whenever one with this name is mounted
+    Drive {
+        /// Volume label, e.g. `BACKUP`
+        label: String,
+        /// Folder on the drive, relative to its root
+        #[serde(default)]
+        folder: String,
+    },
+    S3(S3Target),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Destination {
+    /// Stable id copies are recorded under; assigned when first saved
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub kind: DestinationKind,
+}
+
+/// Why a copy did not happen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CopyError {
+    /// The destination isn't reachable right now, e.g. the drive is not
+    /// plugged in. Not counted as a failed attempt.
+    Unavailable(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for CopyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CopyError::Unavailable(e) | CopyError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<CopyError> for String {
+    fn from(e: CopyError) -> Self {
+        e.to_string()
+    }
+}
+
+impl From<s3::S3Error> for CopyError {
+    fn from(e: s3::S3Error) -> Self {
+        match e {
+            s3::S3Error::Request(_) => CopyError::Unavailable(e.to_string()),
+            s3::S3Error::Api { .. } | s3::S3Error::Io(_) => CopyError::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Archive to copy, as recorded when it was written
+pub struct Archive<'a> {
+    pub path: &'a Path,
+    pub file_name: &'a str,
+    pub sha256: &'a str,
+}
+
+impl Destination {
+    pub fn s3(&self) -> Option<&S3Target> {
+        match &self.kind {
+            DestinationKind::S3(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn s3_mut(&mut self) -> Option<&mut S3Target> {
+        match &mut self.kind {
+            DestinationKind::S3(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Check the settings make sense, without contacting the destination
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Backup destinations need a name".to_string());
+        }
+        match &self.kind {
+            DestinationKind::Folder { path } => {
+                if !Path::new(path).is_absolute() {
+                    return Err(format!(
+                        "Destination folder must be an absolute path: {}",
+                        path
+                    ));
+                }
+            }
+            DestinationKind::Drive { label, folder } => {
+                if label.trim().is_empty() {
+                    return Err(format!("Enter the drive name for {}", self.name));
+                }
+                let relative = Path::new(folder)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)));
+                if !relative {
+                    return Err(format!(
+                        "Folder on the drive must be relative to its root: {}",
+                        folder
+                    ));
+                }
+            }
+            DestinationKind::S3(target) => {
+                S3Client::new(reqwest::Client::new(), target)?;
+                if target.access_key_id.is_empty() || target.secret_access_key.is_empty() {
+                    return Err(format!("Enter the access keys for {}", self.name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The same destination, narrowed to `folder` inside it (`/`-separated)
+    pub fn within(&self, folder: &str) -> Destination {
+        let mut inner = self.clone();
+        match &mut inner.kind {
+            DestinationKind::Folder { path } => {
+                *path = Path::new(path.as_str()).join(folder).display().to_string();
+            }
+            DestinationKind::Drive { folder: base, .. } => {
+                *base = Path::new(base.as_str()).join(folder).display().to_string();
+            }
+            DestinationKind::S3(target) => {
+                let base = target.prefix.trim_matches('/');
+                target.prefix = if base.is_empty() {
+                    folder.to_string()
+                } else {
+                    format!("{}/{}", base, folder)
+                };
+            }
+        }
+        inner
+    }
+
+    /// Folder archives go to, for folders and drives. A drive that is not
+    /// plugged in is [`CopyError::Unavailable`].
+    fn folder(&self) -> Result<PathBuf, CopyError> {
+        match &self.kind {
+            DestinationKind::Folder { path } => Ok(PathBuf::from(path)),
+            DestinationKind::Drive { label, folder } => find_drive(label)
+                .map(|mount| mount.join(folder))
+                .ok_or_else(|| CopyError::Unavailable(format!("Drive {} is not connected", label))),
+            DestinationKind::S3(_) => unreachable!("S3 destinations have no folder"),
+        }
+    }
+
+    /// Copy an archive and its checksum file here. S3 uploads resume from
+    /// `resume`, and report their progress to `progress` to be persisted.
+    /// Returns where the copy ended up.
+    pub async fn copy(
+        &self,
+        http: &reqwest::Client,
+        archive: &Archive<'_>,
+        resume: Option<UploadState>,
+        progress: impl Fn(&UploadState),
+    ) -> Result<String, CopyError> {
+        if let DestinationKind::S3(target) = &self.kind {
+            let client = S3Client::new(http.clone(), target).map_err(CopyError::Failed)?;
+            let key = client.key(archive.file_name);
+            client
+                .upload(
+                    archive.path,
+                    &key,
+                    archive.sha256,
+                    s3::PART_SIZE,
+                    resume,
+                    progress,
+                )
+                .await?;
+            client
+                .put(
+                    &client.key(&checksum_name(archive.file_name)),
+                    checksum_line(archive).into_bytes(),
+                )
+                .await?;
+            return Ok(format!("s3://{}/{}", target.bucket, key));
+        }
+
+        let dir = self.folder()?;
+        let src = archive.path.to_path_buf();
+        let file_name = archive.file_name.to_string();
+        let sha256 = archive.sha256.to_string();
+        tauri::async_runtime::spawn_blocking(move || {
+            copy_to_folder(&src, &dir, &file_name, &sha256)
+        })
+        .await
+        .map_err(|e| CopyError::Failed(format!("Copy task failed: {}", e)))?
+        .map(|dest| dest.display().to_string())
+        .map_err(CopyError::Failed)
+    }
+
+    /// Delete a copy, e.g. once the retention policy drops its backup
+    pub async fn remove(&self, http: &reqwest::Client, file_name: &str) -> Result<(), CopyError> {
+        if let DestinationKind::S3(target) = &self.kind {
+            let client = S3Client::new(http.clone(), target).map_err(CopyError::Failed)?;
+            client.delete(&client.key(file_name)).await?;
+            client
+                .delete(&client.key(&checksum_name(file_name)))
+                .await?;
+            return Ok(());
+        }
+
+        let path = self.folder()?.join(file_name);
+        for path in [archive::checksum_path(&path), path] {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(CopyError::Failed(format!(
+                        "Failed to delete {}: {}",
+                        path.display(),
+                        e
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Every file here, as paths relative to the destination with `/`
+    /// separators. A folder that does not exist yet has none.
+    pub async fn list(&self, http: &reqwest::Client) -> Result<Vec<String>, CopyError> {
+        if let DestinationKind::S3(target) = &self.kind {
+            let client = S3Client::new(http.clone(), target).map_err(CopyError::Failed)?;
+            let prefix = client.key("");
+            let keys = client.list(&prefix).await?;
+            return Ok(keys
+                .into_iter()
+                .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+                .collect());
+        }
+
+        let dir = self.folder()?;
+        tauri::async_runtime::spawn_blocking(move || {
+            let mut files = Vec::new();
+            list_folder(&dir, "", &mut files)?;
+            files.sort();
+            Ok(files)
+        })
+        .await
+        .map_err(|e| CopyError::Failed(format!("List task failed: {}", e)))?
+        .map_err(CopyError::Failed)
+    }
+
+    /// Download a file from here into `dest`
+    pub async fn fetch(
+        &self,
+        http: &reqwest::Client,
+        file_name: &str,
+        dest: &Path,
+    ) -> Result<(), CopyError> {
+        if let DestinationKind::S3(target) = &self.kind {
+            let client = S3Client::new(http.clone(), target).map_err(CopyError::Failed)?;
+            client.download(&client.key(file_name), dest).await?;
+            return Ok(());
+        }
+
+        let src = self.folder()?.join(file_name);
+        let dest = dest.to_path_buf();
+        tauri::async_runtime::spawn_blocking(move || {
+            std::fs::copy(&src, &dest)
+                .map(|_| ())
+                .map_err(|e| format!("Failed to read {}: {}", src.display(), e))
+        })
+        .await
+        .map_err(|e| CopyError::Failed(format!("Copy task failed: {}", e)))?
+        .map_err(CopyError::Failed)
+    }
+
+    /// Abandon an interrupted S3 upload
+    pub async fn abort(&self, http: &reqwest::Client, file_name: &str, upload: &UploadState) {
+        if let DestinationKind::S3(target) = &self.kind {
+            if let Ok(client) = S3Client::new(http.clone(), target) {
+                if let Err(e) = client
+                    .abort_upload(&client.key(file_name), &upload.upload_id)
+                    .await
+                {
+                    log::warn!("Failed to abort upload of {}: {}", file_name, e);
+                }
+            }
+        }
+    }
+
+    /// Write, read back and delete a small file, to check the destination
+    /// is reachable and writable with these settings
+    pub async fn test(&self, http: &reqwest::Client) -> Result<(), String> {
+        self.validate()?;
+        let probe = format!(
+            "Luminila backup destination test {}",
+            crate::db::now_millis()
+        );
+
+        if let DestinationKind::S3(target) = &self.kind {
+            let client = S3Client::new(http.clone(), target)?;
+            let key = client.key(PROBE_FILE);
+            client.put(&key, probe.clone().into_bytes()).await?;
+            match client.head(&key).await? {
+                Some((size, _)) if size == probe.len() as u64 => {}
+                _ => return Err("Test file did not arrive in the bucket intact".to_string()),
+            }
+            client.delete(&key).await?;
+            return Ok(());
+        }
+
+        let dir = self.folder()?;
+        tauri::async_runtime::spawn_blocking(move || {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            let path = dir.join(PROBE_FILE);
+            std::fs::write(&path, &probe)
+                .map_err(|e| format!("Failed to write to {}: {}", dir.display(), e))?;
+            let read = std::fs::read_to_string(&path);
+            let _ = std::fs::remove_file(&path);
+            match read {
+                Ok(read) if read == probe => Ok(()),
+                Ok(_) => Err(format!("Test file in {} came back changed", dir.display())),
+                Err(e) => Err(format!("Failed to read back from {}: {}", dir.display(), e)),
+            }
+        })
+        .await
+        .map_err(|e| format!("Test task failed: {}", e))?
+    }
+}
+
+/// Add the files under `dir` to `files`, prefixed with `relative`
+fn list_folder(dir: &Path, relative: &str, files: &mut Vec<String>) -> Result<(), String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let name = format!("{}{}", relative, entry.file_name().to_string_lossy());
+        let is_dir = entry
+            .file_type()
+            .map_err(|e| format!("Failed to read {}: {}", entry.path().display(), e))?
+            .is_dir();
+        if is_dir {
+            list_folder(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+fn checksum_name(file_name: &str) -> String {
+    format!("{}.sha256", file_name)
+}
+
+/// Checksum file contents, in `sha256sum` format
+fn checksum_line(archive: &Archive) -> String {
+    format!("{}  {}\n", archive.sha256, archive.file_name)
+}
+
+fn file_sha256(path: &Path) -> Result<String, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        .and_then(crypto::sha256_hex)
+}
+
+/// Copy `src` into `dir` through a `.partial` file, appending to one left
+/// by an interrupted copy. The copy only takes its final name once its
+/// SHA-256 matches.
+pub fn copy_to_folder(
+    src: &Path,
+    dir: &Path,
+    file_name: &str,
+    sha256: &str,
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let dest = dir.join(file_name);
+    let partial = dir.join(format!("{}.partial", file_name));
+
+    let copied = dest.exists() && file_sha256(&dest)? == sha256;
+    if !copied {
+        let mut source =
+            File::open(src).map_err(|e| format!("Failed to read {}: {}", src.display(), e))?;
+        let size = source
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", src.display(), e))?
+            .len();
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial)
+            .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+        let mut done = out
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", partial.display(), e))?
+            .len();
+        if done > size {
+            out.set_len(0)
+                .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+            done = 0;
+        }
+        source
+            .seek(SeekFrom::Start(done))
+            .and_then(|_| std::io::copy(&mut source, &mut out))
+            .and_then(|_| out.sync_all())
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        drop(out);
+
+        if file_sha256(&partial)? != sha256 {
+            let _ = std::fs::remove_file(&partial);
+            return Err(format!(
+                "Copy of {} in {} does not match the backup's checksum",
+                file_name,
+                dir.display()
+            ));
+        }
+        std::fs::rename(&partial, &dest)
+            .map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+    }
+
+    let line = checksum_line(&Archive {
+        path: src,
+        file_name,
+        sha256,
+    });
+    File::create(archive::checksum_path(&dest))
+        .and_then(|mut file| {
+            file.write_all(line.as_bytes())?;
+            file.sync_all()
+        })
+        .map_err(|e| format!("Failed to write checksum for {}: {}", dest.display(), e))?;
+    Ok(dest)
+}
+
+/// Mounted disk, as reported by the OS
+pub struct Disk {
+    /// Volume label on Windows and macOS, device name on Linux
+    pub name: String,
+    pub mount_point: PathBuf,
+    pub removable: bool,
+}
+
+/// Mount point of the drive called `label`. Linux reports device names
+/// rather than labels, but mounts removable drives under their label, so
+/// the mount point's last component is matched too. Removable disks win
+/// over fixed ones with the same name.
+pub fn pick_drive(disks: &[Disk], label: &str) -> Option<PathBuf> {
+    let label = label.trim();
+    let matches = |disk: &&Disk| {
+        disk.name.eq_ignore_ascii_case(label)
+            || disk
+                .mount_point
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(label))
+    };
+    disks
+        .iter()
+        .filter(matches)
+        .max_by_key(|disk| disk.removable)
+        .map(|disk| disk.mount_point.clone())
+}
+
+fn find_drive(label: &str) -> Option<PathBuf> {
+    let disks: Vec<Disk> = sysinfo::Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|disk| Disk {
+            name: disk.name().to_string_lossy().to_string(),
+            mount_point: disk.mount_point().to_path_buf(),
+            removable: disk.is_removable(),
+        })
+        .collect();
+    pick_drive(&disks, label)
+}
@@ -0,0 +1,806 @@
+//! Minimal S3 client for backup uploads
+//!
+//! Talks to AWS S3 or any compatible service (MinIO, Backblaze B2, Wasabi,
+//! Cloudflare R2) with Signature Version 4, and lists and downloads what
+//! was uploaded for point-in-time restores. Archives go up as multipart
+//! uploads whose progress is handed back to the caller after every part, so
+//! an interrupted upload resumes where it stopped instead of starting over.
+//! Every part is sent with its SHA-256 for S3 to check, and the parts S3
+//! holds are compared with the ones sent before the upload is completed.
+//! Services that don't report those checksums back have the completed
+//! object downloaded again and hashed instead.
+
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::Engine;
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Part size for multipart uploads. S3 requires at least 5 MiB for every
+/// part but the last.
+pub const PART_SIZE: u64 = 8 * 1024 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+/// Object metadata holding the archive's SHA-256
+const SHA256_META: &str = "x-amz-meta-sha256";
+/// Base64 SHA-256 of a part, which S3 checks the part against
+const CHECKSUM_HEADER: &str = "x-amz-checksum-sha256";
+
+/// Bucket and credentials of an S3-compatible destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct S3Target {
+    /// e.g. `https://s3.eu-central-1.amazonaws.com` or `http://127.0.0.1:9000`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Key prefix archives are stored under, e.g. `shop-1/`
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Address the bucket as `endpoint/bucket` rather than
+    /// `bucket.endpoint`. MinIO and most self-hosted services need this.
+    #[serde(default)]
+    pub path_style: bool,
+}
+
+#[derive(Debug)]
+pub enum S3Error {
+    /// The service could not be reached or the connection broke
+    Request(String),
+    /// The archive could not be read
+    Io(String),
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for S3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            S3Error::Request(e) => write!(f, "S3 request failed: {}", e),
+            S3Error::Io(e) => f.write_str(e),
+            S3Error::Api {
+                status,
+                code,
+                message,
+            } if message.is_empty() => write!(f, "S3 error {} ({})", code, status),
+            S3Error::Api {
+                status,
+                code,
+                message,
+            } => write!(f, "S3 error {} ({}): {}", code, status, message),
+        }
+    }
+}
+
+impl std::error::Error for S3Error {}
+
+impl From<reqwest::Error> for S3Error {
+    fn from(e: reqwest::Error) -> Self {
+        S3Error::Request(e.to_string())
+    }
+}
+
+impl From<S3Error> for String {
+    fn from(e: S3Error) -> Self {
+        e.to_string()
+    }
+}
+
+/// Uploaded part of a multipart upload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedPart {
+    pub number: u32,
+    pub etag: String,
+    pub size: u64,
+    /// Base64 SHA-256 of the part; empty if S3 does not report it
+    #[serde(default)]
+    pub checksum: String,
+}
+
+/// Progress of a multipart upload, persisted between attempts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadState {
+    pub upload_id: String,
+    pub part_size: u64,
+    pub parts: Vec<UploadedPart>,
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters (and `/`
+/// when encoding a path), as SigV4 canonicalization requires
+pub fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Encoded `key=value` pairs sorted and joined as SigV4 expects
+pub fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .iter()
+        .map(|(key, value)| (uri_encode(key, false), uri_encode(value, false)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Request as covered by the signature
+pub struct Canonical<'a> {
+    pub method: &'a str,
+    /// Already URI-encoded path
+    pub path: &'a str,
+    /// Output of [`canonical_query`]
+    pub query: &'a str,
+    /// Lowercase header names with trimmed values, `host` included
+    pub headers: &'a [(String, String)],
+    /// Hex SHA-256 of the body
+    pub payload_hash: &'a str,
+}
+
+/// `Authorization` header value for a request made at `amz_date`
+/// (`YYYYMMDDTHHMMSSZ`)
+pub fn authorization(
+    access_key_id: &str,
+    secret_access_key: &str,
+    region: &str,
+    amz_date: &str,
+    request: &Canonical,
+) -> String {
+    let mut headers: Vec<&(String, String)> = request.headers.iter().collect();
+    headers.sort();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method,
+        request.path,
+        request.query,
+        canonical_headers,
+        signed_headers,
+        request.payload_hash
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [date, region, "s3", "aws4_request"].iter().fold(
+        format!("AWS4{}", secret_access_key).into_bytes(),
+        |key, part| hmac_sha256(&key, part.as_bytes()).to_vec(),
+    );
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Text of every `<name>` element in `xml`. S3 responses are flat enough
+/// that this is all the parsing they need.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else {
+            break;
+        };
+        found.push(&after[..end]);
+        rest = &after[end + close.len()..];
+    }
+    found
+}
+
+fn element(xml: &str, name: &str) -> Option<String> {
+    elements(xml, name).first().map(|text| unescape_xml(text))
+}
+
+fn checksum(body: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(Sha256::digest(body))
+}
+
+/// Checksum S3 reports for a completed multipart upload: the SHA-256 of
+/// the parts' SHA-256s, with the number of parts
+fn composite_checksum(parts: &[UploadedPart]) -> Option<String> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(
+            base64::engine::general_purpose::STANDARD
+                .decode(&part.checksum)
+                .ok()?,
+        );
+    }
+    Some(format!(
+        "{}-{}",
+        base64::engine::general_purpose::STANDARD.encode(hasher.finalize()),
+        parts.len()
+    ))
+}
+
+fn verification_failed(message: String) -> S3Error {
+    S3Error::Api {
+        status: 200,
+        code: "VerificationFailed".to_string(),
+        message,
+    }
+}
+
+pub struct S3Client {
+    http: reqwest::Client,
+    target: S3Target,
+    endpoint: Url,
+}
+
+impl S3Client {
+    pub fn new(http: reqwest::Client, target: &S3Target) -> Result<Self, String> {
+        let endpoint = Url::parse(target.endpoint.trim_end_matches('/'))
+            .map_err(|e| format!("Invalid S3 endpoint {}: {}", target.endpoint, e))?;
+        if !matches!(endpoint.scheme(), "http" | "https") || endpoint.host_str().is_none() {
+            return Err(format!("Invalid S3 endpoint: {}", target.endpoint));
+        }
+        if target.bucket.is_empty() || target.region.is_empty() {
+            return Err("S3 bucket and region are required".to_string());
+        }
+        Ok(Self {
+            http,
+            target: target.clone(),
+            endpoint,
+        })
+    }
+
+    /// Object key for a file name, under the configured prefix
+    pub fn key(&self, file_name: &str) -> String {
+        let prefix = self.target.prefix.trim_matches('/');
+        if prefix.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}/{}", prefix, file_name)
+        }
+    }
+
+    /// URL and canonical path of an object
+    fn object_url(&self, key: &str) -> (Url, String) {
+        let mut url = self.endpoint.clone();
+        let base = url.path().trim_end_matches('/').to_string();
+        let path = if self.target.path_style {
+            format!(
+                "{}/{}/{}",
+                base,
+                uri_encode(&self.target.bucket, false),
+                uri_encode(key, true)
+            )
+        } else {
+            let host = format!(
+                "{}.{}",
+                self.target.bucket,
+                url.host_str().unwrap_or_default()
+            );
+            let _ = url.set_host(Some(&host));
+            format!("{}/{}", base, uri_encode(key, true))
+        };
+        url.set_path(&path);
+        (url, path)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        extra_headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, S3Error> {
+        let (mut url, path) = self.object_url(key);
+        let query = canonical_query(query);
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        headers.extend(
+            extra_headers
+                .iter()
+                .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string())),
+        );
+        let authorization = authorization(
+            &self.target.access_key_id,
+            &self.target.secret_access_key,
+            &self.target.region,
+            &amz_date,
+            &Canonical {
+                method: method.as_str(),
+                path: &path,
+                query: &query,
+                headers: &headers,
+                payload_hash: &payload_hash,
+            },
+        );
+
+        let mut request = self
+            .http
+            .request(method, url)
+            .timeout(REQUEST_TIMEOUT)
+            .header(reqwest::header::AUTHORIZATION, authorization);
+        // reqwest sets Host itself, from the same URL
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let response = request.body(body).send().await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let text = response.text().await.unwrap_or_default();
+        Err(S3Error::Api {
+            status: status.as_u16(),
+            code: element(&text, "Code").unwrap_or_else(|| {
+                status
+                    .canonical_reason()
+                    .unwrap_or("Unknown")
+                    .replace(' ', "")
+            }),
+            message: element(&text, "Message").unwrap_or_default(),
+        })
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), S3Error> {
+        self.send(Method::PUT, key, &[], &[], body).await?;
+        Ok(())
+    }
+
+    /// Size and recorded SHA-256 of an object, or `None` if it doesn't exist
+    pub async fn head(&self, key: &str) -> Result<Option<(u64, Option<String>)>, S3Error> {
+        match self.send(Method::HEAD, key, &[], &[], Vec::new()).await {
+            Ok(response) => {
+                let header = |name: &str| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                let size = header("content-length")
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or(0);
+                Ok(Some((size, header(SHA256_META))))
+            }
+            Err(S3Error::Api { status, .. }) if status == StatusCode::NOT_FOUND.as_u16() => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), S3Error> {
+        match self.send(Method::DELETE, key, &[], &[], Vec::new()).await {
+            Err(S3Error::Api { status, .. }) if status == StatusCode::NOT_FOUND.as_u16() => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Download an object into `dest`
+    pub async fn download(&self, key: &str, dest: &Path) -> Result<(), S3Error> {
+        let mut response = self.send(Method::GET, key, &[], &[], Vec::new()).await?;
+        let write_err =
+            |e: std::io::Error| S3Error::Io(format!("Failed to write {}: {}", dest.display(), e));
+        let mut file = std::fs::File::create(dest).map_err(write_err)?;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).map_err(write_err)?;
+        }
+        file.sync_all().map_err(write_err)
+    }
+
+    /// Keys of every object whose key starts with `prefix`
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>, S3Error> {
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send(Method::GET, "", &query, &[], Vec::new()).await?;
+            let text = response.text().await?;
+            keys.extend(
+                elements(&text, "Contents")
+                    .into_iter()
+                    .filter_map(|object| element(object, "Key")),
+            );
+            token = element(&text, "NextContinuationToken")
+                .filter(|_| element(&text, "IsTruncated").as_deref() == Some("true"));
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    async fn create_upload(&self, key: &str, sha256: &str) -> Result<String, S3Error> {
+        let response = self
+            .send(
+                Method::POST,
+                key,
+                &[("uploads", "")],
+                &[
+                    (SHA256_META, sha256),
+                    ("x-amz-checksum-algorithm", "SHA256"),
+                ],
+                Vec::new(),
+            )
+            .await?;
+        let text = response.text().await?;
+        element(&text, "UploadId").ok_or_else(|| S3Error::Api {
+            status: 200,
+            code: "InvalidResponse".to_string(),
+            message: "No UploadId in response".to_string(),
+        })
+    }
+
+    /// Parts S3 has received for an upload
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>, S3Error> {
+        let response = self
+            .send(
+                Method::GET,
+                key,
+                &[("uploadId", upload_id)],
+                &[],
+                Vec::new(),
+            )
+            .await?;
+        let text = response.text().await?;
+        Ok(elements(&text, "Part")
+            .into_iter()
+            .filter_map(|part| {
+                Some(UploadedPart {
+                    number: element(part, "PartNumber")?.parse().ok()?,
+                    etag: element(part, "ETag")?,
+                    size: element(part, "Size")?.parse().ok()?,
+                    checksum: element(part, "ChecksumSHA256").unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    /// Upload one part. S3 refuses it if the bytes it got don't match the
+    /// checksum sent along.
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        number: u32,
+        body: Vec<u8>,
+    ) -> Result<UploadedPart, S3Error> {
+        let size = body.len() as u64;
+        let checksum = checksum(&body);
+        let response = self
+            .send(
+                Method::PUT,
+                key,
+                &[("partNumber", &number.to_string()), ("uploadId", upload_id)],
+                &[(CHECKSUM_HEADER, &checksum)],
+                body,
+            )
+            .await?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(reqwest::header::ETAG.as_str()).ok_or_else(|| S3Error::Api {
+            status: 200,
+            code: "InvalidResponse".to_string(),
+            message: "No ETag for uploaded part".to_string(),
+        })?;
+        if let Some(received) = header(CHECKSUM_HEADER).filter(|received| *received != checksum) {
+            return Err(verification_failed(format!(
+                "Part {} arrived with checksum {}, expected {}",
+                number, received, checksum
+            )));
+        }
+        Ok(UploadedPart {
+            number,
+            etag,
+            size,
+            checksum,
+        })
+    }
+
+    /// Check S3 holds exactly the parts `state` says were uploaded. Returns
+    /// whether S3 confirmed every part's checksum, which services without
+    /// checksum support can't.
+    async fn verify_parts(&self, key: &str, state: &UploadState) -> Result<bool, S3Error> {
+        let listed = self.list_parts(key, &state.upload_id).await?;
+        let mut confirmed = true;
+        for part in &state.parts {
+            let Some(listed) = listed.iter().find(|listed| {
+                listed.number == part.number && listed.etag == part.etag && listed.size == part.size
+            }) else {
+                return Err(verification_failed(format!(
+                    "Part {} in the bucket does not match the part uploaded",
+                    part.number
+                )));
+            };
+            if listed.checksum.is_empty() || part.checksum.is_empty() {
+                confirmed = false;
+            } else if listed.checksum != part.checksum {
+                return Err(verification_failed(format!(
+                    "Part {} in the bucket has checksum {}, expected {}",
+                    part.number, listed.checksum, part.checksum
+                )));
+            }
+        }
+        Ok(confirmed)
+    }
+
+    /// Complete the upload. Returns whether S3 reported a checksum for the
+    /// whole object, which is checked against the parts sent.
+    async fn complete_upload(&self, key: &str, state: &UploadState) -> Result<bool, S3Error> {
+        let mut parts = state.parts.clone();
+        parts.sort_by_key(|part| part.number);
+        let body: String = parts
+            .iter()
+            .map(|part| {
+                let checksum = if part.checksum.is_empty() {
+                    String::new()
+                } else {
+                    format!("<ChecksumSHA256>{}</ChecksumSHA256>", part.checksum)
+                };
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag>{}</Part>",
+                    part.number,
+                    part.etag.replace('&', "&amp;").replace('"', "&quot;"),
+                    checksum
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            body
+        );
+        let response = self
+            .send(
+                Method::POST,
+                key,
+                &[("uploadId", &state.upload_id)],
+                &[],
+                body.into_bytes(),
+            )
+            .await?;
+        // Completion can fail after a 200, with the error in the body
+        let text = response.text().await?;
+        if let Some(code) = element(&text, "Code") {
+            return Err(S3Error::Api {
+                status: 200,
+                code,
+                message: element(&text, "Message").unwrap_or_default(),
+            });
+        }
+        // Parts from before checksums were sent have none to combine
+        let expected = parts
+            .iter()
+            .all(|part| !part.checksum.is_empty())
+            .then(|| composite_checksum(&parts))
+            .flatten();
+        match (element(&text, "ChecksumSHA256"), expected) {
+            (Some(reported), Some(expected)) if reported != expected => {
+                Err(verification_failed(format!(
+                    "Uploaded object has checksum {}, expected {}",
+                    reported, expected
+                )))
+            }
+            (Some(_), Some(_)) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Size and hex SHA-256 of an object, read back from S3
+    async fn hash_object(&self, key: &str) -> Result<(u64, String), S3Error> {
+        let mut response = self.send(Method::GET, key, &[], &[], Vec::new()).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await? {
+            hasher.update(&chunk);
+            size += chunk.len() as u64;
+        }
+        Ok((size, hex::encode(hasher.finalize())))
+    }
+
+    /// Discard the parts of an upload that will not be completed
+    pub async fn abort_upload(&self, key: &str, upload_id: &str) -> Result<(), S3Error> {
+        match self
+            .send(
+                Method::DELETE,
+                key,
+                &[("uploadId", upload_id)],
+                &[],
+                Vec::new(),
+            )
+            .await
+        {
+            Err(S3Error::Api { status, .. }) if status == StatusCode::NOT_FOUND.as_u16() => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Upload `src` to `key` in parts of `part_size` bytes, or resume
+    /// `resume` if given. `progress` is called with the upload state
+    /// whenever it changes, so it can be persisted. `sha256` is stored with
+    /// the object for restores to check.
+    pub async fn upload(
+        &self,
+        src: &Path,
+        key: &str,
+        sha256: &str,
+        part_size: u64,
+        resume: Option<UploadState>,
+        progress: impl Fn(&UploadState),
+    ) -> Result<(), S3Error> {
+        let size = std::fs::metadata(src)
+            .map_err(|e| S3Error::Io(format!("Failed to read {}: {}", src.display(), e)))?
+            .len();
+
+        let resumed = match resume {
+            Some(state) => match self.list_parts(key, &state.upload_id).await {
+                Ok(listed) => {
+                    // Keep only complete parts S3 holds as they were sent
+                    let parts = state
+                        .parts
+                        .iter()
+                        .filter(|part| {
+                            part.size == expected_part_size(size, state.part_size, part.number)
+                                && listed.iter().any(|listed| {
+                                    listed.number == part.number
+                                        && listed.etag == part.etag
+                                        && listed.size == part.size
+                                })
+                        })
+                        .cloned()
+                        .collect();
+                    Some(UploadState { parts, ..state })
+                }
+                Err(S3Error::Api { code, .. }) if code == "NoSuchUpload" => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+        let mut state = match resumed {
+            Some(state) => state,
+            None => UploadState {
+                upload_id: self.create_upload(key, sha256).await?,
+                part_size,
+                parts: Vec::new(),
+            },
+        };
+        progress(&state);
+
+        let count = size.div_ceil(state.part_size).max(1) as u32;
+        for number in 1..=count {
+            if state.parts.iter().any(|part| part.number == number) {
+                continue;
+            }
+            let offset = (number as u64 - 1) * state.part_size;
+            let len = expected_part_size(size, state.part_size, number);
+            let body = read_part(src.to_path_buf(), offset, len).await?;
+            let part = self
+                .upload_part(key, &state.upload_id, number, body)
+                .await?;
+            state.parts.push(part);
+            progress(&state);
+        }
+        let parts_confirmed = self.verify_parts(key, &state).await?;
+        let object_confirmed = self.complete_upload(key, &state).await?;
+
+        if !(parts_confirmed && object_confirmed) {
+            // S3 couldn't vouch for the bytes, so read them back
+            let (uploaded, hash) = self.hash_object(key).await?;
+            if uploaded != size || hash != sha256 {
+                self.delete(key).await?;
+                return Err(verification_failed(format!(
+                    "Uploaded object has {} bytes with SHA-256 {}, expected {} bytes with {}",
+                    uploaded, hash, size, sha256
+                )));
+            }
+            return Ok(());
+        }
+        match self.head(key).await? {
+            Some((uploaded, _)) if uploaded == size => Ok(()),
+            Some((uploaded, _)) => Err(verification_failed(format!(
+                "Uploaded object has {} bytes, expected {}",
+                uploaded, size
+            ))),
+            None => Err(S3Error::Api {
+                status: 404,
+                code: "VerificationFailed".to_string(),
+                message: "Uploaded object is missing".to_string(),
+            }),
+        }
+    }
+}
+
+fn expected_part_size(size: u64, part_size: u64, number: u32) -> u64 {
+    let offset = (number as u64 - 1) * part_size;
+    size.saturating_sub(offset).min(part_size)
+}
+
+async fn read_part(src: PathBuf, offset: u64, len: u64) -> Result<Vec<u8>, S3Error> {
+    let path = src.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut body = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut body)?;
+        Ok::<_, std::io::Error>(body)
+    })
+    .await
+    .map_err(|e| S3Error::Io(format!("Read task failed: {}", e)))?
+    .map_err(|e| S3Error::Io(format!("Failed to read {}: {}", src.display(), e)))
+}
@@ -0,0 +1,1267 @@
+//! Backups of PocketBase's data directory
+//!
+//! A backup is a zip archive of consistent copies of PocketBase's SQLite
+//! databases, taken with SQLite's online backup API while PocketBase keeps
+//! serving, and of the uploads under `storage/`. A manifest inside lists
+//! every file with its SHA-256 and the migrations the database had applied.
+//! The archive is encrypted with the backup passphrase, and a `.sha256` file
+//! next to it covers the encrypted file.
+//!
+//! Restoring swaps a backup in for `pb_data` while PocketBase is paused; see
+//! [`restore`].
+//!
+//! Scheduled backups run unattended, so the passphrase is kept in a key file
+//! in the app config directory. Nothing is backed up until one is set. S3
+//! secret keys are kept in a key file next to it rather than in the
+//! settings, and are never handed back to the webview.
+//!
+//! Backups run hourly or daily, and after each one older backups are pruned
+//! to the retention policy. Every backup is recorded in the local database
+//! and announced with a `backup` event as it starts, finishes or is pruned.
+//!
+//! Finished backups are then copied to each enabled [`destination`] in the
+//! background, retrying with backoff until the copy is verified, and pruned
+//! there along with the local archive. Each copy is announced with a
+//! `backup-copy` event.
+//!
+//! Between backups, PocketBase's write-ahead log can be shipped to one of
+//! the destinations every few seconds, so its database can be restored to
+//! any point in time since; see [`wal`]. Shipping progress is announced
+//! with a `backup-wal` event.
+
+mod archive;
+pub mod commands;
+pub mod destination;
+mod restore;
+mod schedule;
+#[cfg(test)]
+mod tests;
+mod wal;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::db::{self, LocalDb};
+use crate::settings::SettingsStore;
+pub use archive::BackupInfo;
+use destination::{Archive, CopyError, Destination, UploadState};
+pub use restore::RestorePlan;
+pub use schedule::{Frequency, Retention};
+pub use wal::{PointInTimePlan, WalSettings, WalShipper, WalStatus};
+
+/// How often the worker checks whether a backup is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Wait before retrying a failed scheduled backup
+const RETRY_DELAY_MS: i64 = 15 * 60 * 1000;
+const MIN_PASSPHRASE_LEN: usize = 8;
+/// Stands in for saved S3 secret keys in settings shown to the webview.
+/// Saving it back keeps the saved key.
+const SAVED_SECRET: &str = "********";
+/// Wait before looking for a destination that was unreachable again
+const UNAVAILABLE_RETRY_MS: i64 = 60 * 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupSettings {
+    pub enabled: bool,
+    pub frequency: Frequency,
+    /// Local hour (0-23) daily backups run at
+    pub hour: u32,
+    /// Where archives are written; the app data directory when unset
+    pub directory: Option<String>,
+    pub retention: Retention,
+    /// Where finished backups are copied to
+    pub destinations: Vec<Destination>,
+    /// Continuous shipping of PocketBase's write-ahead log
+    pub wal: WalSettings,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frequency: Frequency::Daily,
+            // After the shop closes
+            hour: 22,
+            directory: None,
+            retention: Retention::default(),
+            destinations: Vec::new(),
+            wal: WalSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Schedule,
+    Manual,
+}
+
+impl Trigger {
+    fn as_str(self) -> &'static str {
+        match self {
+            Trigger::Schedule => "schedule",
+            Trigger::Manual => "manual",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "manual" => Trigger::Manual,
+            _ => Trigger::Schedule,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupState {
+    Running,
+    Success,
+    Failed,
+    /// Deleted by the retention policy
+    Pruned,
+}
+
+impl BackupState {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackupState::Running => "running",
+            BackupState::Success => "success",
+            BackupState::Failed => "failed",
+            BackupState::Pruned => "pruned",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "running" => BackupState::Running,
+            "success" => BackupState::Success,
+            "pruned" => BackupState::Pruned,
+            _ => BackupState::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRecord {
+    pub id: String,
+    pub file_name: String,
+    pub directory: String,
+    pub trigger: Trigger,
+    pub state: BackupState,
+    /// Archive size in bytes
+    pub size: Option<u64>,
+    /// SHA-256 of the archive
+    pub sha256: Option<String>,
+    /// Files in the archive, databases included
+    pub files: Option<u32>,
+    /// Uploads deleted while the backup ran
+    pub missing_files: Option<u32>,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+const RECORD_COLUMNS: &str = "id, file_name, directory, trigger, status, size, sha256, files, missing_files, error, started_at, finished_at";
+
+impl BackupRecord {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            file_name: row.get(1)?,
+            directory: row.get(2)?,
+            trigger: Trigger::parse(&row.get::<_, String>(3)?),
+            state: BackupState::parse(&row.get::<_, String>(4)?),
+            size: row.get(5)?,
+            sha256: row.get(6)?,
+            files: row.get(7)?,
+            missing_files: row.get(8)?,
+            error: row.get(9)?,
+            started_at: row.get(10)?,
+            finished_at: row.get(11)?,
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        Path::new(&self.directory).join(&self.file_name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyState {
+    /// Not copied yet, or waiting to retry
+    Pending,
+    /// Copied and verified
+    Done,
+    /// Removed from the destination after the backup was pruned
+    Deleted,
+}
+
+impl CopyState {
+    fn as_str(self) -> &'static str {
+        match self {
+            CopyState::Pending => "pending",
+            CopyState::Done => "done",
+            CopyState::Deleted => "deleted",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "done" => CopyState::Done,
+            "deleted" => CopyState::Deleted,
+            _ => CopyState::Pending,
+        }
+    }
+}
+
+/// Copy of a backup at one destination
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyRecord {
+    pub backup_id: String,
+    pub destination_id: String,
+    pub state: CopyState,
+    /// Path or URL of the copy once made
+    pub location: Option<String>,
+    /// Failed attempts so far
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub next_attempt_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+const COPY_COLUMNS: &str = "c.backup_id, c.destination_id, c.status, c.location, c.attempts, c.error, c.next_attempt_at, c.finished_at";
+
+impl CopyRecord {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            backup_id: row.get(0)?,
+            destination_id: row.get(1)?,
+            state: CopyState::parse(&row.get::<_, String>(2)?),
+            location: row.get(3)?,
+            attempts: row.get(4)?,
+            error: row.get(5)?,
+            next_attempt_at: row.get(6)?,
+            finished_at: row.get(7)?,
+        })
+    }
+}
+
+/// How copies to one destination are going
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationStatus {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    /// Unix timestamp (ms) of the last verified copy
+    pub last_success_at: Option<i64>,
+    /// Why the oldest waiting copy has not been made yet
+    pub last_error: Option<String>,
+    /// Backups waiting to be copied
+    pub pending: u32,
+}
+
+/// Backup configuration and recent results, for the settings page
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupOverview {
+    pub settings: BackupSettings,
+    /// Directory new archives are written to
+    pub directory: String,
+    pub running: bool,
+    /// Whether a passphrase is set. Nothing is backed up without one.
+    pub passphrase_set: bool,
+    pub last_success: Option<BackupRecord>,
+    pub last_failure: Option<BackupRecord>,
+    /// Unix timestamp (ms) of the next scheduled backup, if enabled
+    pub next_due_at: Option<i64>,
+    pub destinations: Vec<DestinationStatus>,
+    pub wal: WalStatus,
+}
+
+/// Wait before retrying a failed copy: 1 minute, doubling up to an hour
+fn retry_delay_ms(attempts: u32) -> i64 {
+    let delay_mins = 2i64.pow(attempts.saturating_sub(1).min(6)).min(60);
+    delay_mins * 60 * 1000
+}
+
+fn local_time(millis: i64) -> Option<NaiveDateTime> {
+    chrono::DateTime::from_timestamp_millis(millis).map(|at| at.with_timezone(&Local).naive_local())
+}
+
+fn local_millis(at: NaiveDateTime) -> Option<i64> {
+    Local
+        .from_local_datetime(&at)
+        .earliest()
+        .map(|at| at.timestamp_millis())
+}
+
+pub struct Backups {
+    db: Arc<LocalDb>,
+    settings: Mutex<BackupSettings>,
+    /// PocketBase's data directory
+    pb_data: PathBuf,
+    /// Used when no directory is configured
+    default_dir: PathBuf,
+    /// Where the passphrase is kept
+    key_file: PathBuf,
+    passphrase: Mutex<Option<String>>,
+    /// Where S3 secret keys are kept, by destination id
+    secrets_file: PathBuf,
+    http: reqwest::Client,
+    /// Held while a backup runs
+    running: tokio::sync::Mutex<()>,
+    /// Held while backups are copied to their destinations
+    copying: tokio::sync::Mutex<()>,
+    /// Wakes the worker when there are copies to make
+    wake: tokio::sync::Notify,
+    wal: Arc<WalShipper>,
+}
+
+impl Backups {
+    pub fn new(
+        db: Arc<LocalDb>,
+        mut settings: BackupSettings,
+        pb_data: PathBuf,
+        default_dir: PathBuf,
+        key_file: PathBuf,
+        http: reqwest::Client,
+    ) -> Self {
+        let passphrase = std::fs::read_to_string(&key_file)
+            .ok()
+            .filter(|passphrase| !passphrase.is_empty());
+        let secrets_file = key_file.with_file_name("backup-secrets.json");
+        let secrets: std::collections::HashMap<String, String> =
+            std::fs::read_to_string(&secrets_file)
+                .ok()
+                .and_then(|secrets| serde_json::from_str(&secrets).ok())
+                .unwrap_or_default();
+        for destination in &mut settings.destinations {
+            let secret = secrets.get(&destination.id);
+            if let (Some(target), Some(secret)) = (destination.s3_mut(), secret) {
+                if target.secret_access_key.is_empty() {
+                    target.secret_access_key = secret.clone();
+                }
+            }
+        }
+        let wal = Arc::new(WalShipper::new(
+            db.clone(),
+            pb_data.clone(),
+            default_dir.join(wal::FOLDER),
+            wal::CHECKPOINT_BYTES,
+        ));
+        Self {
+            db,
+            settings: Mutex::new(settings),
+            pb_data,
+            default_dir,
+            key_file,
+            passphrase: Mutex::new(passphrase),
+            secrets_file,
+            http,
+            running: tokio::sync::Mutex::new(()),
+            copying: tokio::sync::Mutex::new(()),
+            wake: tokio::sync::Notify::new(),
+            wal,
+        }
+    }
+
+    /// Shipper of PocketBase's write-ahead log, to be told when PocketBase
+    /// starts and exits
+    pub fn wal(&self) -> Arc<WalShipper> {
+        self.wal.clone()
+    }
+
+    pub fn settings(&self) -> BackupSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    fn directory(&self, settings: &BackupSettings) -> PathBuf {
+        settings
+            .directory
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.default_dir.clone())
+    }
+
+    /// Validate, apply and persist new settings
+    pub fn save_settings(
+        &self,
+        app: &tauri::AppHandle,
+        settings: BackupSettings,
+    ) -> Result<(), String> {
+        let store = app.state::<SettingsStore>();
+        self.apply_settings(settings, |settings| {
+            store.update(|stored| stored.backup = redact_secrets(settings.clone(), ""))
+        })
+    }
+
+    /// Move S3 secret keys that earlier versions kept in settings.json into
+    /// the secrets file
+    pub fn migrate_secrets(&self, store: &SettingsStore) -> Result<(), String> {
+        let plaintext = store
+            .get()
+            .backup
+            .destinations
+            .iter()
+            .filter_map(Destination::s3)
+            .any(|target| !target.secret_access_key.is_empty());
+        if !plaintext {
+            return Ok(());
+        }
+        self.write_secrets(&self.settings())?;
+        store.update(|stored| stored.backup = redact_secrets(stored.backup.clone(), ""))
+    }
+
+    /// Put the saved secret key back into a destination sent by the
+    /// webview with [`SAVED_SECRET`] in its place
+    fn restore_secret(&self, destination: &mut Destination) {
+        let settings = self.settings.lock().unwrap();
+        let saved = settings
+            .destinations
+            .iter()
+            .find(|saved| saved.id == destination.id)
+            .and_then(Destination::s3)
+            .map(|saved| saved.secret_access_key.clone());
+        if let Some(target) = destination.s3_mut() {
+            if target.secret_access_key == SAVED_SECRET {
+                target.secret_access_key = saved.unwrap_or_default();
+            }
+        }
+    }
+
+    /// Validate and apply new settings once `persist` has saved them. The
+    /// secrets file is put back if saving fails. Destinations that are new
+    /// get the latest backup queued, so they are tried out straight away.
+    fn apply_settings(
+        &self,
+        settings: BackupSettings,
+        persist: impl FnOnce(&BackupSettings) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let settings = self.check_settings(settings)?;
+        self.write_secrets(&settings)?;
+        if let Err(e) = persist(&settings) {
+            return match self.write_secrets(&self.settings()) {
+                Ok(()) => Err(e),
+                Err(restore) => Err(format!(
+                    "{}; putting back the saved secret keys also failed: {}",
+                    e, restore
+                )),
+            };
+        }
+
+        let previous = std::mem::replace(&mut *self.settings.lock().unwrap(), settings.clone());
+        let added: Vec<&Destination> = settings
+            .destinations
+            .iter()
+            .filter(|destination| {
+                destination.enabled
+                    && !previous
+                        .destinations
+                        .iter()
+                        .any(|old| old.id == destination.id && old.enabled)
+            })
+            .collect();
+        if let Some(latest) = self.latest(BackupState::Success)? {
+            if !added.is_empty() {
+                self.queue_copies(&latest.id, &added)?;
+                self.wake.notify_one();
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings as they will be saved: validated, with saved secret keys put
+    /// back and an id for every destination
+    fn check_settings(&self, mut settings: BackupSettings) -> Result<BackupSettings, String> {
+        if settings.hour > 23 {
+            return Err(format!("Invalid backup hour: {}", settings.hour));
+        }
+        let retention = settings.retention;
+        if retention.daily + retention.weekly + retention.monthly == 0 {
+            return Err("Retention must keep at least one backup".to_string());
+        }
+        if let Some(directory) = &settings.directory {
+            let path = Path::new(directory);
+            if !path.is_absolute() {
+                return Err(format!(
+                    "Backup folder must be an absolute path: {}",
+                    directory
+                ));
+            }
+            std::fs::create_dir_all(path)
+                .map_err(|e| format!("Failed to create {}: {}", directory, e))?;
+        }
+        let mut ids = std::collections::HashSet::new();
+        for destination in &mut settings.destinations {
+            self.restore_secret(destination);
+            destination.validate()?;
+            if destination.id.is_empty() {
+                destination.id = db::new_id();
+            }
+            if !ids.insert(destination.id.clone()) {
+                return Err(format!("Duplicate backup destination: {}", destination.id));
+            }
+        }
+        if settings.wal.enabled && wal_destination(&settings).is_none() {
+            return Err("Choose a destination to ship the write-ahead log to".to_string());
+        }
+        if settings.wal.retention_days == 0 {
+            return Err("Point-in-time recovery must reach back at least a day".to_string());
+        }
+        Ok(settings)
+    }
+
+    /// Write the S3 secret keys of `settings` to the secrets file
+    fn write_secrets(&self, settings: &BackupSettings) -> Result<(), String> {
+        let secrets: std::collections::HashMap<&str, &str> = settings
+            .destinations
+            .iter()
+            .filter_map(|destination| {
+                let target = destination.s3()?;
+                Some((destination.id.as_str(), target.secret_access_key.as_str()))
+            })
+            .collect();
+        let secrets = serde_json::to_string(&secrets)
+            .map_err(|e| format!("Failed to save S3 secret keys: {}", e))?;
+        write_key_file(&self.secrets_file, &secrets)
+    }
+
+    fn passphrase(&self) -> Option<String> {
+        self.passphrase.lock().unwrap().clone()
+    }
+
+    /// Set the passphrase new backups are encrypted with, or clear it to
+    /// stop backing up. Existing backups keep the passphrase they were
+    /// made with.
+    pub fn set_passphrase(&self, passphrase: Option<String>) -> Result<(), String> {
+        match &passphrase {
+            Some(passphrase) => {
+                if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+                    return Err(format!(
+                        "Backup passphrase must be at least {} characters",
+                        MIN_PASSPHRASE_LEN
+                    ));
+                }
+                write_key_file(&self.key_file, passphrase)?;
+            }
+            None => match std::fs::remove_file(&self.key_file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(format!(
+                        "Failed to remove {}: {}",
+                        self.key_file.display(),
+                        e
+                    ));
+                }
+                _ => {}
+            },
+        }
+        *self.passphrase.lock().unwrap() = passphrase;
+        Ok(())
+    }
+
+    /// Backups, newest first
+    pub fn list(&self, limit: u32) -> Result<Vec<BackupRecord>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM backups ORDER BY started_at DESC LIMIT ?1",
+                RECORD_COLUMNS
+            ))?;
+            let rows = stmt.query_map([limit], BackupRecord::from_row)?;
+            rows.collect()
+        })
+    }
+
+    fn latest(&self, state: BackupState) -> Result<Option<BackupRecord>, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                &format!(
+                    "SELECT {} FROM backups WHERE status = ?1 ORDER BY started_at DESC LIMIT 1",
+                    RECORD_COLUMNS
+                ),
+                [state.as_str()],
+                BackupRecord::from_row,
+            )
+            .optional()
+        })
+    }
+
+    pub fn overview(&self) -> Result<BackupOverview, String> {
+        let settings = self.settings();
+        let last_success = self.latest(BackupState::Success)?;
+        let next_due_at = if settings.enabled && self.passphrase().is_some() {
+            let now = Local::now().naive_local();
+            let last = last_success
+                .as_ref()
+                .and_then(|record| local_time(record.started_at));
+            if schedule::is_due(settings.frequency, settings.hour, last, now) {
+                Some(db::now_millis())
+            } else {
+                local_millis(schedule::next_slot(settings.frequency, settings.hour, now))
+            }
+        } else {
+            None
+        };
+
+        Ok(BackupOverview {
+            destinations: self.destination_status(&settings)?,
+            directory: self.directory(&settings).display().to_string(),
+            running: self.running.try_lock().is_err(),
+            passphrase_set: self.passphrase().is_some(),
+            last_success,
+            last_failure: self.latest(BackupState::Failed)?,
+            next_due_at,
+            wal: self.wal.status(&settings.wal)?,
+            settings: redact_secrets(settings, SAVED_SECRET),
+        })
+    }
+
+    /// Whether the schedule calls for a backup now. A failed backup is
+    /// retried after a pause rather than on every check.
+    fn is_due(&self, settings: &BackupSettings) -> Result<bool, String> {
+        let now = db::now_millis();
+        if let Some(failure) = self.latest(BackupState::Failed)? {
+            if now - failure.started_at < RETRY_DELAY_MS {
+                return Ok(false);
+            }
+        }
+        let last_success = self
+            .latest(BackupState::Success)?
+            .and_then(|record| local_time(record.started_at));
+        Ok(schedule::is_due(
+            settings.frequency,
+            settings.hour,
+            last_success,
+            Local::now().naive_local(),
+        ))
+    }
+
+    fn finish(&self, record: &BackupRecord) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE backups
+                 SET status = ?2, size = ?3, sha256 = ?4, files = ?5, missing_files = ?6, error = ?7, finished_at = ?8
+                 WHERE id = ?1",
+                params![
+                    record.id,
+                    record.state.as_str(),
+                    record.size,
+                    record.sha256,
+                    record.files,
+                    record.missing_files,
+                    record.error,
+                    record.finished_at
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Take a backup now, then prune to the retention policy. `notify` is
+    /// called with every record that changes. A backup that fails is
+    /// returned with its error, not as `Err`.
+    pub async fn run(
+        &self,
+        trigger: Trigger,
+        notify: impl Fn(&BackupRecord),
+    ) -> Result<BackupRecord, String> {
+        let _running = self
+            .running
+            .try_lock()
+            .map_err(|_| "A backup is already running".to_string())?;
+        let passphrase = self
+            .passphrase()
+            .ok_or_else(|| "Set a backup passphrase before backing up".to_string())?;
+        let settings = self.settings();
+        let directory = self.directory(&settings);
+
+        let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut file_name = format!("luminila-pb-{}.{}", stamp, archive::EXTENSION);
+        let mut n = 2;
+        while directory.join(&file_name).exists() {
+            file_name = format!("luminila-pb-{}-{}.{}", stamp, n, archive::EXTENSION);
+            n += 1;
+        }
+
+        let mut record = BackupRecord {
+            id: db::new_id(),
+            file_name,
+            directory: directory.display().to_string(),
+            trigger,
+            state: BackupState::Running,
+            size: None,
+            sha256: None,
+            files: None,
+            missing_files: None,
+            error: None,
+            started_at: db::now_millis(),
+            finished_at: None,
+        };
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO backups (id, file_name, directory, trigger, status, started_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    record.id,
+                    record.file_name,
+                    record.directory,
+                    trigger.as_str(),
+                    record.state.as_str(),
+                    record.started_at
+                ],
+            )
+        })?;
+        notify(&record);
+
+        let pb_data = self.pb_data.clone();
+        let dest = record.path();
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            archive::write(&pb_data, &dest, &passphrase)
+        })
+        .await
+        .map_err(|e| format!("Backup task failed: {}", e))
+        .and_then(|result| result);
+        record.finished_at = Some(db::now_millis());
+        match result {
+            Ok(summary) => {
+                record.state = BackupState::Success;
+                record.size = Some(summary.size);
+                record.sha256 = Some(summary.sha256);
+                record.files = Some(summary.files);
+                record.missing_files = Some(summary.missing_files);
+            }
+            Err(e) => {
+                log::error!("Backup failed: {}", e);
+                record.state = BackupState::Failed;
+                record.error = Some(e);
+            }
+        }
+        self.finish(&record)?;
+        notify(&record);
+
+        if record.state == BackupState::Success {
+            let destinations: Vec<&Destination> = settings
+                .destinations
+                .iter()
+                .filter(|destination| destination.enabled)
+                .collect();
+            self.queue_copies(&record.id, &destinations)?;
+            for pruned in self.prune(&settings.retention)? {
+                notify(&pruned);
+            }
+            self.wake.notify_one();
+        }
+        Ok(record)
+    }
+
+    /// Restore PocketBase's data from the backup `src`, or with `dry_run`
+    /// only report what restoring it would change. No backup runs meanwhile.
+    pub async fn restore(
+        &self,
+        app: &tauri::AppHandle,
+        src: PathBuf,
+        passphrase: String,
+        dry_run: bool,
+    ) -> Result<RestorePlan, String> {
+        let _running = self
+            .running
+            .try_lock()
+            .map_err(|_| "A backup is running, restore once it has finished".to_string())?;
+        restore::restore(app, self.pb_data.clone(), src, passphrase, dry_run).await
+    }
+
+    /// Restore PocketBase's database as it was at `target` (Unix ms) from
+    /// the write-ahead log shipped to its destination, or with `dry_run`
+    /// only report what that would change. No backup runs meanwhile.
+    pub async fn restore_to(
+        &self,
+        app: &tauri::AppHandle,
+        target: i64,
+        passphrase: String,
+        dry_run: bool,
+    ) -> Result<PointInTimePlan, String> {
+        let _running = self
+            .running
+            .try_lock()
+            .map_err(|_| "A backup is running, restore once it has finished".to_string())?;
+        let settings = self.settings();
+        let destination = wal_destination(&settings)
+            .ok_or_else(|| "Choose where the write-ahead log is shipped to first".to_string())?;
+        wal::recover::restore(
+            app,
+            &self.http,
+            destination,
+            self.pb_data.clone(),
+            target,
+            passphrase,
+            dry_run,
+        )
+        .await
+    }
+
+    /// Ship PocketBase's write-ahead log if shipping is on, attaching to its
+    /// database first if need be, then copy new segments to the destination
+    /// and prune old generations. Shipping stops, ending its generation,
+    /// once it is turned off or the passphrase is cleared. `notify` is
+    /// called when segments were copied.
+    pub async fn ship_wal(&self, notify: impl Fn(&WalStatus)) -> Result<(), String> {
+        let settings = self.settings();
+        let target = wal_destination(&settings).filter(|_| settings.wal.enabled);
+        let wal = self.wal.clone();
+        let shipped = match (target, self.passphrase()) {
+            (Some(target), Some(passphrase)) => {
+                let destination_id = target.id.clone();
+                tauri::async_runtime::spawn_blocking(move || {
+                    wal.attach(&destination_id, &passphrase)?;
+                    wal.ship()
+                })
+                .await
+            }
+            _ => tauri::async_runtime::spawn_blocking(move || wal.stop()).await,
+        }
+        .map_err(|e| format!("Log shipping task failed: {}", e))
+        .and_then(|result| result);
+
+        let copied = match target.filter(|target| target.enabled) {
+            Some(target) => self.wal.upload(&self.http, target).await,
+            None => Ok(0),
+        };
+        if matches!(copied, Ok(n) if n > 0) {
+            notify(&self.wal.status(&settings.wal)?);
+        }
+        let pruned = self
+            .wal
+            .prune(
+                &self.http,
+                &settings.destinations,
+                settings.wal.retention_days,
+            )
+            .await;
+        shipped.and(copied).and(pruned)
+    }
+
+    fn queue_copies(&self, backup_id: &str, destinations: &[&Destination]) -> Result<(), String> {
+        let now = db::now_millis();
+        self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            for destination in destinations {
+                tx.execute(
+                    "INSERT OR IGNORE INTO backup_copies (backup_id, destination_id, status, next_attempt_at)
+                     VALUES (?1, ?2, 'pending', ?3)",
+                    params![backup_id, destination.id, now],
+                )?;
+            }
+            tx.commit()
+        })
+    }
+
+    /// Copies of a backup, at every destination it was queued for
+    pub fn copies(&self, backup_id: &str) -> Result<Vec<CopyRecord>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM backup_copies c WHERE c.backup_id = ?1 ORDER BY c.destination_id",
+                COPY_COLUMNS
+            ))?;
+            let rows = stmt.query_map([backup_id], CopyRecord::from_row)?;
+            rows.collect()
+        })
+    }
+
+    fn destination_status(
+        &self,
+        settings: &BackupSettings,
+    ) -> Result<Vec<DestinationStatus>, String> {
+        settings
+            .destinations
+            .iter()
+            .map(|destination| {
+                let (last_success_at, pending, last_error) = self.db.with(|conn| {
+                    conn.query_row(
+                        "SELECT
+                            (SELECT MAX(finished_at) FROM backup_copies
+                             WHERE destination_id = ?1 AND status != 'pending'),
+                            (SELECT COUNT(*) FROM backup_copies
+                             WHERE destination_id = ?1 AND status = 'pending'),
+                            (SELECT c.error FROM backup_copies c JOIN backups b ON b.id = c.backup_id
+                             WHERE c.destination_id = ?1 AND c.status = 'pending' AND c.error IS NOT NULL
+                             ORDER BY b.started_at LIMIT 1)",
+                        [&destination.id],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    )
+                })?;
+                Ok(DestinationStatus {
+                    id: destination.id.clone(),
+                    name: destination.name.clone(),
+                    enabled: destination.enabled,
+                    last_success_at,
+                    last_error,
+                    pending,
+                })
+            })
+            .collect()
+    }
+
+    /// Check a destination is reachable and writable, before saving it
+    pub async fn test_destination(&self, destination: &Destination) -> Result<(), String> {
+        let mut destination = destination.clone();
+        self.restore_secret(&mut destination);
+        destination.test(&self.http).await
+    }
+
+    fn update_copy(&self, copy: &CopyRecord, upload: Option<&UploadState>) -> Result<(), String> {
+        let upload = upload.and_then(|upload| serde_json::to_string(upload).ok());
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE backup_copies
+                 SET status = ?3, location = ?4, upload = ?5, attempts = ?6, error = ?7, next_attempt_at = ?8, finished_at = ?9
+                 WHERE backup_id = ?1 AND destination_id = ?2",
+                params![
+                    copy.backup_id,
+                    copy.destination_id,
+                    copy.state.as_str(),
+                    copy.location,
+                    upload,
+                    copy.attempts,
+                    copy.error,
+                    copy.next_attempt_at,
+                    copy.finished_at
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn forget_copy(&self, copy: &CopyRecord) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "DELETE FROM backup_copies WHERE backup_id = ?1 AND destination_id = ?2",
+                params![copy.backup_id, copy.destination_id],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Copies matching `condition`, oldest backup first, with any
+    /// interrupted upload
+    fn copies_where(
+        &self,
+        condition: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<(CopyRecord, Option<UploadState>)>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {}, c.upload FROM backup_copies c JOIN backups b ON b.id = c.backup_id
+                 WHERE {} ORDER BY b.started_at",
+                COPY_COLUMNS, condition
+            ))?;
+            let rows = stmt.query_map(params, |row| {
+                let upload: Option<String> = row.get(8)?;
+                Ok((
+                    CopyRecord::from_row(row)?,
+                    upload.and_then(|upload| serde_json::from_str(&upload).ok()),
+                ))
+            })?;
+            rows.collect()
+        })
+    }
+
+    fn get(&self, id: &str) -> Result<BackupRecord, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                &format!("SELECT {} FROM backups WHERE id = ?1", RECORD_COLUMNS),
+                [id],
+                BackupRecord::from_row,
+            )
+        })
+    }
+
+    /// Make the copies that are due, then remove copies of pruned backups.
+    /// `notify` is called with every copy that changes. Returns straight
+    /// away if another pass is still running.
+    pub async fn sync_destinations(&self, notify: impl Fn(&CopyRecord)) -> Result<(), String> {
+        let Ok(_copying) = self.copying.try_lock() else {
+            return Ok(());
+        };
+        let settings = self.settings();
+        let destination = |id: &str| {
+            settings
+                .destinations
+                .iter()
+                .find(|destination| destination.id == id)
+        };
+
+        let due = self.copies_where(
+            "c.status = 'pending' AND c.next_attempt_at <= ?1 AND b.status = 'success'",
+            [db::now_millis()],
+        )?;
+        for (mut copy, upload) in due {
+            let Some(target) = destination(&copy.destination_id) else {
+                // The destination was removed from the settings
+                self.forget_copy(&copy)?;
+                continue;
+            };
+            if !target.enabled {
+                continue;
+            }
+            let backup = self.get(&copy.backup_id)?;
+            let Some(sha256) = backup.sha256.as_deref() else {
+                continue;
+            };
+
+            let path = backup.path();
+            let archive = Archive {
+                path: &path,
+                file_name: &backup.file_name,
+                sha256,
+            };
+            let progress = |state: &UploadState| {
+                if let Err(e) = self.update_copy(&copy, Some(state)) {
+                    log::warn!("Failed to record upload progress: {}", e);
+                }
+            };
+            let result = target.copy(&self.http, &archive, upload, progress).await;
+
+            let now = db::now_millis();
+            let mut upload = None;
+            match result {
+                Ok(location) => {
+                    log::info!("Copied backup {} to {}", backup.file_name, location);
+                    copy.state = CopyState::Done;
+                    copy.location = Some(location);
+                    copy.error = None;
+                    copy.finished_at = Some(now);
+                }
+                Err(CopyError::Unavailable(e)) => {
+                    copy.error = Some(e);
+                    copy.next_attempt_at = now + UNAVAILABLE_RETRY_MS;
+                    upload = self.saved_upload(&copy)?;
+                }
+                Err(CopyError::Failed(e)) => {
+                    log::warn!(
+                        "Failed to copy backup {} to {}: {}",
+                        backup.file_name,
+                        target.name,
+                        e
+                    );
+                    copy.attempts += 1;
+                    copy.error = Some(e);
+                    copy.next_attempt_at = now + retry_delay_ms(copy.attempts);
+                    upload = self.saved_upload(&copy)?;
+                }
+            }
+            self.update_copy(&copy, upload.as_ref())?;
+            notify(&copy);
+        }
+
+        let pruned =
+            self.copies_where("c.status != 'deleted' AND b.status = 'pruned'", params![])?;
+        for (mut copy, upload) in pruned {
+            let Some(target) = destination(&copy.destination_id) else {
+                self.forget_copy(&copy)?;
+                continue;
+            };
+            let backup = self.get(&copy.backup_id)?;
+            if copy.state == CopyState::Pending {
+                if let Some(upload) = &upload {
+                    target.abort(&self.http, &backup.file_name, upload).await;
+                }
+                self.forget_copy(&copy)?;
+                continue;
+            }
+            match target.remove(&self.http, &backup.file_name).await {
+                Ok(()) => {
+                    copy.state = CopyState::Deleted;
+                    self.update_copy(&copy, None)?;
+                    notify(&copy);
+                }
+                Err(CopyError::Unavailable(_)) => {}
+                Err(CopyError::Failed(e)) => log::warn!(
+                    "Failed to prune backup {} from {}: {}",
+                    backup.file_name,
+                    target.name,
+                    e
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Upload progress recorded while a copy was being made
+    fn saved_upload(&self, copy: &CopyRecord) -> Result<Option<UploadState>, String> {
+        let upload: Option<String> = self.db.with(|conn| {
+            conn.query_row(
+                "SELECT upload FROM backup_copies WHERE backup_id = ?1 AND destination_id = ?2",
+                params![copy.backup_id, copy.destination_id],
+                |row| row.get(0),
+            )
+        })?;
+        Ok(upload.and_then(|upload| serde_json::from_str(&upload).ok()))
+    }
+
+    /// Delete successful backups the retention policy no longer keeps
+    fn prune(&self, retention: &Retention) -> Result<Vec<BackupRecord>, String> {
+        let backups = self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM backups WHERE status = 'success'",
+                RECORD_COLUMNS
+            ))?;
+            let rows = stmt.query_map([], BackupRecord::from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+        let stamped: Vec<(String, NaiveDateTime)> = backups
+            .iter()
+            .filter_map(|record| Some((record.id.clone(), local_time(record.started_at)?)))
+            .collect();
+        let kept = schedule::keep(&stamped, retention);
+
+        let mut pruned = Vec::new();
+        for mut record in backups {
+            if kept.contains(&record.id) {
+                continue;
+            }
+            let path = record.path();
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("Failed to prune backup {}: {}", path.display(), e);
+                    continue;
+                }
+                _ => {}
+            }
+            let _ = std::fs::remove_file(archive::checksum_path(&path));
+            self.db.with(|conn| {
+                conn.execute(
+                    "UPDATE backups SET status = 'pruned' WHERE id = ?1",
+                    [&record.id],
+                )
+            })?;
+            record.state = BackupState::Pruned;
+            pruned.push(record);
+        }
+        Ok(pruned)
+    }
+}
+
+/// Destination the write-ahead log is shipped to, if it is still configured
+fn wal_destination(settings: &BackupSettings) -> Option<&Destination> {
+    let id = settings.wal.destination_id.as_deref()?;
+    settings
+        .destinations
+        .iter()
+        .find(|destination| destination.id == id)
+}
+
+/// Settings with S3 secret keys that are set replaced by `placeholder`
+fn redact_secrets(mut settings: BackupSettings, placeholder: &str) -> BackupSettings {
+    for destination in &mut settings.destinations {
+        if let Some(target) = destination.s3_mut() {
+            if !target.secret_access_key.is_empty() {
+                target.secret_access_key = placeholder.to_string();
+            }
+        }
+    }
+    settings
+}
+
+/// Keep a passphrase or secret keys readable by the current user only
+fn write_key_file(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| {
+            use std::io::Write;
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+fn emit(app: &tauri::AppHandle, record: &BackupRecord) {
+    let _ = app.emit("backup", record);
+}
+
+fn emit_copy(app: &tauri::AppHandle, copy: &CopyRecord) {
+    let _ = app.emit("backup-copy", copy);
+}
+
+fn emit_wal(app: &tauri::AppHandle, status: &WalStatus) {
+    let _ = app.emit("backup-wal", status);
+}
+
+async fn run_scheduled(app: &tauri::AppHandle, backups: &Backups) {
+    let settings = backups.settings();
+    if !settings.enabled || backups.passphrase().is_none() {
+        return;
+    }
+    match backups.is_due(&settings) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            log::error!("Failed to read backup history: {}", e);
+            return;
+        }
+    }
+
+    if let Err(e) = backups
+        .run(Trigger::Schedule, |record| emit(app, record))
+        .await
+    {
+        log::warn!("Scheduled backup did not run: {}", e);
+    }
+}
+
+/// Background worker taking scheduled backups and copying them to their
+/// destinations
+pub async fn run_worker(app: tauri::AppHandle) {
+    let backups = app.state::<Backups>();
+    loop {
+        // Woken early when a backup finishes or a destination is added
+        let _ = tokio::time::timeout(CHECK_INTERVAL, backups.wake.notified()).await;
+
+        run_scheduled(&app, &backups).await;
+        if let Err(e) = backups
+            .sync_destinations(|copy| emit_copy(&app, copy))
+            .await
+        {
+            log::error!("Failed to copy backups to their destinations: {}", e);
+        }
+    }
+}
+
+/// Background worker shipping PocketBase's write-ahead log
+pub async fn run_wal_worker(app: tauri::AppHandle) {
+    let backups = app.state::<Backups>();
+    loop {
+        // Woken early when PocketBase becomes ready
+        let _ = tokio::time::timeout(wal::SHIP_INTERVAL, backups.wal.wake.notified()).await;
+
+        let result = backups.ship_wal(|status| emit_wal(&app, status)).await;
+        if backups.wal.set_error(result.err()) {
+            match backups.wal.status(&backups.settings().wal) {
+                Ok(status) => emit_wal(&app, &status),
+                Err(e) => log::error!("Failed to read log shipping status: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! Restoring `pb_data` from a backup
+//!
+//! A backup is decrypted, checked and unpacked next to the live `pb_data`
+//! before PocketBase is touched, so a wrong passphrase, a corrupted archive
+//! or an incompatible schema never stops the server. Files in `pb_data` a
+//! backup doesn't hold are copied into the staged data, and the swap itself
+//! is two directory renames while PocketBase is paused. The replaced data is
+//! kept in `pb_data.previous` until the next restore, and put back
+//! automatically if PocketBase does not come up healthy on the restored
+//! data.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::Manager;
+
+use super::archive::{self, BackupInfo, Manifest, MANIFEST};
+use crate::crypto;
+use crate::pocketbase::{self, SIDECAR_NAME};
+use crate::sidecar::SidecarSupervisor;
+
+/// Records a restore would add, change and remove in one collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionDiff {
+    pub name: String,
+    /// In the backup but not in the live data
+    pub added: u64,
+    /// In both, with different values
+    pub changed: u64,
+    /// In the live data but not in the backup
+    pub removed: u64,
+}
+
+/// What restoring a backup changes
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestorePlan {
+    pub info: BackupInfo,
+    /// Bundled migrations the backup predates, which PocketBase applies when
+    /// it starts on the restored data
+    pub pending_migrations: Vec<String>,
+    /// Collections with differences, by name
+    pub collections: Vec<CollectionDiff>,
+    /// Uploads that were deleted while the backup was taken
+    pub missing_files: Vec<String>,
+    /// False for a dry run
+    pub applied: bool,
+}
+
+/// Scratch locations next to the live `pb_data`, on the same filesystem so
+/// directories can be renamed into place
+pub(super) struct Paths {
+    live: PathBuf,
+    staging: PathBuf,
+    payload: PathBuf,
+    previous: PathBuf,
+    failed: PathBuf,
+}
+
+/// `pb_data` with `suffix` appended to its name
+pub(super) fn sibling(pb_data: &Path, suffix: &str) -> PathBuf {
+    let mut name = pb_data.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    pb_data.with_file_name(name)
+}
+
+impl Paths {
+    pub(super) fn new(pb_data: &Path) -> Self {
+        Self {
+            live: pb_data.to_path_buf(),
+            staging: sibling(pb_data, ".restore"),
+            payload: sibling(pb_data, ".restore.zip"),
+            previous: sibling(pb_data, ".previous"),
+            failed: sibling(pb_data, ".failed"),
+        }
+    }
+}
+
+pub(super) fn remove_dir(dir: &Path) -> Result<(), String> {
+    match std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to clear {}: {}", dir.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Names of the migration files in the bundled migrations directory
+pub fn bundled_migrations(dir: &Path) -> Result<Vec<String>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".js"))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// A backup is restorable if every app migration it had applied is one we
+/// ship. Migrations we ship that it lacks are applied by PocketBase on
+/// start, and are returned. PocketBase's own system migrations are not
+/// compared, as the bundled PocketBase upgrades those itself.
+pub fn check_schema(backup: &[String], bundled: &[String]) -> Result<Vec<String>, String> {
+    let bundled_set: BTreeSet<&str> = bundled.iter().map(String::as_str).collect();
+    let backup_set: BTreeSet<&str> = backup
+        .iter()
+        .map(String::as_str)
+        .filter(|name| name.ends_with(".js"))
+        .collect();
+
+    let unknown: Vec<&str> = backup_set.difference(&bundled_set).copied().collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "Backup has a database schema this version of the app does not know ({} unknown migration{}, e.g. {}). Update the app before restoring it.",
+            unknown.len(),
+            if unknown.len() == 1 { "" } else { "s" },
+            unknown[0]
+        ));
+    }
+    Ok(bundled_set
+        .difference(&backup_set)
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Decrypt `src` and unpack it into `paths.staging`, checking every file
+/// against the manifest
+fn stage(src: &Path, passphrase: &str, paths: &Paths) -> Result<(BackupInfo, Manifest), String> {
+    remove_dir(&paths.staging)?;
+    let result = (|| {
+        let out = File::create(&paths.payload)
+            .map_err(|e| format!("Failed to create {}: {}", paths.payload.display(), e))?;
+        let mut writer = BufWriter::new(out);
+        let info = archive::decrypt(src, passphrase, &mut writer)?;
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write {}: {}", paths.payload.display(), e))?;
+        drop(writer);
+
+        let payload = File::open(&paths.payload)
+            .map_err(|e| format!("Failed to read {}: {}", paths.payload.display(), e))?;
+        zip::ZipArchive::new(payload)
+            .and_then(|mut zip| zip.extract(&paths.staging))
+            .map_err(|e| format!("Failed to unpack backup: {}", e))?;
+
+        let manifest_path = paths.staging.join(MANIFEST);
+        let manifest: Manifest = std::fs::read(&manifest_path)
+            .map_err(|e| format!("Backup has no manifest: {}", e))
+            .and_then(|bytes| {
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| format!("Backup manifest is corrupt: {}", e))
+            })?;
+        std::fs::remove_file(&manifest_path)
+            .map_err(|e| format!("Failed to remove {}: {}", manifest_path.display(), e))?;
+
+        if !manifest.files.iter().any(|file| file.path == "data.db") {
+            return Err("Backup does not contain data.db".to_string());
+        }
+        for file in &manifest.files {
+            let path = paths.staging.join(&file.path);
+            let sha256 = File::open(&path)
+                .map(BufReader::new)
+                .map_err(|e| format!("Backup is missing {}: {}", file.path, e))
+                .and_then(crypto::sha256_hex)?;
+            if sha256 != file.sha256 {
+                return Err(format!(
+                    "Checksum of {} does not match, the backup is corrupted",
+                    file.path
+                ));
+            }
+        }
+        Ok((info, manifest))
+    })();
+
+    let _ = std::fs::remove_file(&paths.payload);
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&paths.staging);
+    }
+    result
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Record collections (views have no data of their own) in a schema
+fn collections(conn: &Connection, schema: &str) -> rusqlite::Result<BTreeSet<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name FROM {}._collections WHERE type != 'view'",
+        schema
+    ))?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+fn table_exists(conn: &Connection, schema: &str, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        &format!(
+            "SELECT 1 FROM {}.sqlite_master WHERE type = 'table' AND name = ?1",
+            schema
+        ),
+        [table],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+}
+
+fn columns(conn: &Connection, schema: &str, table: &str) -> rusqlite::Result<BTreeSet<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "PRAGMA {}.table_info({})",
+        schema,
+        quote_ident(table)
+    ))?;
+    let rows = stmt.query_map([], |row| row.get(1))?;
+    rows.collect()
+}
+
+fn count(conn: &Connection, sql: &str) -> rusqlite::Result<u64> {
+    conn.query_row(sql, [], |row| row.get::<_, i64>(0))
+        .map(|n| n as u64)
+}
+
+/// The live database is the connection's main one, so that it is opened
+/// read-only and PocketBase's writers are not held up
+const LIVE: &str = "main";
+const BACKUP: &str = "backup";
+
+fn diff_collection(conn: &Connection, name: &str) -> rusqlite::Result<CollectionDiff> {
+    let table = quote_ident(name);
+    let in_backup = table_exists(conn, BACKUP, name)?;
+    let in_live = table_exists(conn, LIVE, name)?;
+    let mut diff = CollectionDiff {
+        name: name.to_string(),
+        added: 0,
+        changed: 0,
+        removed: 0,
+    };
+    match (in_backup, in_live) {
+        (true, true) => {
+            diff.added = count(
+                conn,
+                &format!(
+                    "SELECT COUNT(*) FROM {b}.{t} WHERE id NOT IN (SELECT id FROM {l}.{t})",
+                    t = table,
+                    b = BACKUP,
+                    l = LIVE
+                ),
+            )?;
+            diff.removed = count(
+                conn,
+                &format!(
+                    "SELECT COUNT(*) FROM {l}.{t} WHERE id NOT IN (SELECT id FROM {b}.{t})",
+                    t = table,
+                    b = BACKUP,
+                    l = LIVE
+                ),
+            )?;
+            // Fields added or removed since the backup don't count as a change
+            let shared: Vec<String> = columns(conn, BACKUP, name)?
+                .intersection(&columns(conn, LIVE, name)?)
+                .filter(|column| column.as_str() != "id")
+                .map(|column| {
+                    let column = quote_ident(column);
+                    format!("b.{c} IS l.{c}", c = column)
+                })
+                .collect();
+            if !shared.is_empty() {
+                diff.changed = count(
+                    conn,
+                    &format!(
+                        "SELECT COUNT(*) FROM {b}.{t} b JOIN {l}.{t} l ON l.id = b.id WHERE NOT ({})",
+                        shared.join(" AND "),
+                        t = table,
+                        b = BACKUP,
+                        l = LIVE
+                    ),
+                )?;
+            }
+        }
+        (true, false) => {
+            diff.added = count(conn, &format!("SELECT COUNT(*) FROM {}.{}", BACKUP, table))?;
+        }
+        (false, true) => {
+            diff.removed = count(conn, &format!("SELECT COUNT(*) FROM {}.{}", LIVE, table))?;
+        }
+        (false, false) => {}
+    }
+    Ok(diff)
+}
+
+/// Per-collection differences between the live database and a restored
+/// copy, for collections with any
+pub fn diff(live: &Path, restored: &Path) -> Result<Vec<CollectionDiff>, String> {
+    let compare = || -> rusqlite::Result<Vec<CollectionDiff>> {
+        let conn = if live.exists() {
+            Connection::open_with_flags(live, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        } else {
+            Connection::open_in_memory()?
+        };
+        conn.execute(
+            &format!("ATTACH DATABASE ?1 AS {}", BACKUP),
+            [restored.display().to_string()],
+        )?;
+
+        let mut names = collections(&conn, BACKUP)?;
+        if live.exists() {
+            names.extend(collections(&conn, LIVE)?);
+        }
+        let mut diffs = Vec::new();
+        for name in names {
+            let diff = diff_collection(&conn, &name)?;
+            if diff.added + diff.changed + diff.removed > 0 {
+                diffs.push(diff);
+            }
+        }
+        Ok(diffs)
+    };
+    compare().map_err(|e| format!("Failed to compare backup with current data: {}", e))
+}
+
+/// Entries of `pb_data` a backup replaces: the databases with their `-wal`
+/// and `-shm` files, and the uploads
+fn restored_entries() -> Vec<String> {
+    let mut entries: Vec<String> = archive::DATABASES
+        .iter()
+        .flat_map(|database| ["", "-wal", "-shm"].map(|suffix| format!("{}{}", database, suffix)))
+        .collect();
+    entries.push(archive::STORAGE_DIR.to_string());
+    entries
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Copy what a backup doesn't hold from the live data into `staging`, so
+/// the staged data is complete and can be swapped in with one rename
+fn complete_staging(paths: &Paths) -> Result<(), String> {
+    let entries = match std::fs::read_dir(&paths.live) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Failed to read {}: {}", paths.live.display(), e)),
+    };
+    let restored = restored_entries();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", paths.live.display(), e))?;
+        let name = entry.file_name();
+        if restored.iter().any(|restored| name == restored.as_str()) {
+            continue;
+        }
+        copy_recursive(&entry.path(), &paths.staging.join(&name))
+            .map_err(|e| format!("Failed to copy {}: {}", entry.path().display(), e))?;
+    }
+    Ok(())
+}
+
+/// Move the live data aside to `previous` and the staged data in its place.
+/// Files in `pb_data` that are not part of a backup are carried over.
+pub(super) fn swap_in(paths: &Paths) -> Result<(), String> {
+    remove_dir(&paths.previous)?;
+    complete_staging(paths)?;
+    let had_live = paths.live.exists();
+    if had_live {
+        std::fs::rename(&paths.live, &paths.previous)
+            .map_err(|e| format!("Failed to move current data aside: {}", e))?;
+    }
+    if let Err(e) = std::fs::rename(&paths.staging, &paths.live) {
+        if !had_live {
+            return Err(format!("Failed to move restored data in place: {}", e));
+        }
+        return match std::fs::rename(&paths.previous, &paths.live) {
+            Ok(()) => Err(format!("Failed to move restored data in place: {}", e)),
+            Err(back) => Err(format!(
+                "Failed to move restored data in place: {}, and putting the current data back failed: {}. It is in {}.",
+                e,
+                back,
+                paths.previous.display()
+            )),
+        };
+    }
+    Ok(())
+}
+
+/// Put the data moved aside by [`swap_in`] back. The rejected data is kept
+/// in `failed` for inspection.
+pub(super) fn roll_back(paths: &Paths) -> Result<(), String> {
+    remove_dir(&paths.failed)?;
+    std::fs::rename(&paths.live, &paths.failed)
+        .map_err(|e| format!("Failed to move restored data aside: {}", e))?;
+    std::fs::rename(&paths.previous, &paths.live)
+        .map_err(|e| format!("Failed to put previous data back: {}", e))
+}
+
+/// Decrypt, check and unpack a backup, and work out what restoring it
+/// would change
+pub(super) fn prepare(
+    src: &Path,
+    passphrase: &str,
+    bundled: &[String],
+    paths: &Paths,
+) -> Result<RestorePlan, String> {
+    let (info, manifest) = stage(src, passphrase, paths)?;
+    let result = check_schema(&info.migrations, bundled).and_then(|pending_migrations| {
+        Ok(RestorePlan {
+            collections: diff(&paths.live.join("data.db"), &paths.staging.join("data.db"))?,
+            info,
+            pending_migrations,
+            missing_files: manifest.missing_files,
+            applied: false,
+        })
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&paths.staging);
+    }
+    result
+}
+
+/// Restore `pb_data` from a backup. With `dry_run` nothing is changed and
+/// the returned plan only describes the restore.
+pub async fn restore(
+    app: &tauri::AppHandle,
+    pb_data: PathBuf,
+    src: PathBuf,
+    passphrase: String,
+    dry_run: bool,
+) -> Result<RestorePlan, String> {
+    let migrations_dir = pocketbase::migrations_dir(app)
+        .ok_or_else(|| "Bundled PocketBase migrations not found".to_string())?;
+    let bundled = bundled_migrations(&migrations_dir)?;
+
+    // Everything that can fail on a bad archive happens before PocketBase
+    // is touched
+    let mut plan = tauri::async_runtime::spawn_blocking({
+        let pb_data = pb_data.clone();
+        let src = src.clone();
+        move || {
+            let paths = Paths::new(&pb_data);
+            let plan = prepare(&src, &passphrase, &bundled, &paths)?;
+            if dry_run {
+                remove_dir(&paths.staging)?;
+            }
+            Ok::<_, String>(plan)
+        }
+    })
+    .await
+    .map_err(|e| format!("Restore task failed: {}", e))??;
+    if dry_run {
+        return Ok(plan);
+    }
+
+    let paths = Paths::new(&pb_data);
+    let swapped = swap_paused(
+        app,
+        || swap_in(&paths),
+        || roll_back(&paths),
+        &paths.previous,
+    )
+    .await;
+    if swapped.is_err() {
+        let _ = std::fs::remove_dir_all(&paths.staging);
+    }
+    swapped?;
+    plan.applied = true;
+    log::info!("Restored PocketBase data from {}", src.display());
+    Ok(plan)
+}
+
+/// Run `swap` while PocketBase is paused, and if PocketBase then does not
+/// come up healthy, `roll_back` to the data `swap` kept in `previous`
+pub(super) async fn swap_paused(
+    app: &tauri::AppHandle,
+    swap: impl FnOnce() -> Result<(), String>,
+    roll_back: impl FnOnce() -> Result<(), String>,
+    previous: &Path,
+) -> Result<(), String> {
+    let supervisor = app.state::<SidecarSupervisor>();
+    supervisor.pause(app, SIDECAR_NAME).await?;
+    if let Err(e) = swap() {
+        if let Err(e) = supervisor.resume(app, SIDECAR_NAME).await {
+            log::error!("Failed to restart PocketBase after a failed restore: {}", e);
+        }
+        return Err(e);
+    }
+
+    // Starting waits for PocketBase to pass its health check
+    let started = match supervisor.resume(app, SIDECAR_NAME).await {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+
+    log::error!(
+        "PocketBase failed to start on restored data, rolling back: {}",
+        started
+    );
+    if let Err(e) = supervisor.pause(app, SIDECAR_NAME).await {
+        log::error!("Failed to stop PocketBase for rollback: {}", e);
+    }
+    let rolled_back = roll_back();
+    if let Err(e) = supervisor.resume(app, SIDECAR_NAME).await {
+        log::error!("Failed to restart PocketBase after rollback: {}", e);
+    }
+    match rolled_back {
+        Ok(()) => Err(format!(
+            "PocketBase did not start with the restored data ({}). The previous data was put back.",
+            started
+        )),
+        Err(e) => Err(format!(
+            "PocketBase did not start with the restored data ({}), and rolling back failed: {}. The previous data is in {}.",
+            started,
+            e,
+            previous.display()
+        )),
+    }
+}
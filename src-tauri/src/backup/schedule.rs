@@ -0,0 +1,115 @@
+//! When backups are due and which ones to keep
+//!
+//! Times here are local wall-clock times, as staff think of "the backup
+//! from Tuesday" in local days, weeks and months.
+
+use std::collections::HashSet;
+
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Hourly,
+    /// Once a day, at the configured hour
+    Daily,
+}
+
+/// How many backups to keep, grandfather-father-son style: the newest
+/// backup of each of the last `daily` days that have one, of the last
+/// `weekly` weeks and of the last `monthly` months
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Retention {
+    pub daily: u32,
+    pub weekly: u32,
+    pub monthly: u32,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            daily: 7,
+            weekly: 4,
+            monthly: 12,
+        }
+    }
+}
+
+/// Most recent time a backup was scheduled for, at or before `now`
+pub fn last_slot(frequency: Frequency, hour: u32, now: NaiveDateTime) -> NaiveDateTime {
+    let start_of_hour = now
+        .date()
+        .and_hms_opt(now.hour(), 0, 0)
+        .expect("start of the hour is valid");
+    match frequency {
+        Frequency::Hourly => start_of_hour,
+        Frequency::Daily => {
+            let today = now
+                .date()
+                .and_hms_opt(hour.min(23), 0, 0)
+                .expect("hour is valid");
+            if today <= now {
+                today
+            } else {
+                today - Duration::days(1)
+            }
+        }
+    }
+}
+
+/// First scheduled time after `now`
+pub fn next_slot(frequency: Frequency, hour: u32, now: NaiveDateTime) -> NaiveDateTime {
+    let last = last_slot(frequency, hour, now);
+    match frequency {
+        Frequency::Hourly => last + Duration::hours(1),
+        Frequency::Daily => last + Duration::days(1),
+    }
+}
+
+/// Whether a backup is due, given when the last successful one was taken
+pub fn is_due(
+    frequency: Frequency,
+    hour: u32,
+    last_success: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> bool {
+    last_success.map_or(true, |last| last < last_slot(frequency, hour, now))
+}
+
+/// Key of the day, week or month a backup falls in
+type Bucket = fn(&NaiveDateTime) -> (i32, u32);
+
+/// Ids of the backups `retention` keeps. The newest backup is always kept.
+pub fn keep(backups: &[(String, NaiveDateTime)], retention: &Retention) -> HashSet<String> {
+    let mut newest_first: Vec<&(String, NaiveDateTime)> = backups.iter().collect();
+    newest_first.sort_by_key(|(_, at)| std::cmp::Reverse(*at));
+
+    let mut kept = HashSet::new();
+    if let Some((id, _)) = newest_first.first() {
+        kept.insert(id.clone());
+    }
+
+    let buckets: [(u32, Bucket); 3] = [
+        (retention.daily, |at| (at.year(), at.ordinal())),
+        (retention.weekly, |at| {
+            let week = at.iso_week();
+            (week.year(), week.week())
+        }),
+        (retention.monthly, |at| (at.year(), at.month())),
+    ];
+    for (count, bucket) in buckets {
+        let mut seen = HashSet::new();
+        for (id, at) in &newest_first {
+            if seen.len() >= count as usize {
+                break;
+            }
+            // The first backup seen in a bucket is its newest
+            if seen.insert(bucket(at)) {
+                kept.insert(id.clone());
+            }
+        }
+    }
+    kept
+}
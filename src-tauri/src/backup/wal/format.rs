@@ -0,0 +1,189 @@
+//! SQLite's write-ahead log format
+//!
+//! A log is a 32-byte header followed by frames, each a 24-byte frame
+//! header and one database page. A frame belongs to the log only if it
+//! carries the header's salts and its checksum, chained from the frame
+//! before it, is right; a frame whose database size field is set commits a
+//! transaction. Frames are read here exactly as SQLite recovers a log, see
+//! <https://www.sqlite.org/fileformat2.html#the_write_ahead_log>.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+pub const HEADER_SIZE: u64 = 32;
+pub const FRAME_HEADER_SIZE: usize = 24;
+/// Magic number with the low bit clear; a set bit means big-endian checksums
+const MAGIC: u32 = 0x377f0682;
+const VERSION: u32 = 3007000;
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// SQLite's WAL checksum of `data`, continuing from `seed`
+fn checksum(big_endian: bool, data: &[u8], seed: [u32; 2]) -> [u32; 2] {
+    let [mut s1, mut s2] = seed;
+    for words in data.chunks_exact(8) {
+        let word = |bytes: &[u8]| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        s1 = s1.wrapping_add(word(&words[..4])).wrapping_add(s2);
+        s2 = s2.wrapping_add(word(&words[4..])).wrapping_add(s1);
+    }
+    [s1, s2]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalHeader {
+    big_endian: bool,
+    pub page_size: u32,
+    /// Times the log has been started over, counted by SQLite
+    pub checkpoints: u32,
+    pub salt: [u32; 2],
+    checksum: [u32; 2],
+}
+
+impl WalHeader {
+    /// Parse a log's first bytes. `None` unless they are a complete, valid
+    /// header, e.g. while the log is empty.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE as usize {
+            return None;
+        }
+        let magic = be32(bytes);
+        if magic & !1 != MAGIC || be32(&bytes[4..]) != VERSION {
+            return None;
+        }
+        let page_size = match be32(&bytes[8..]) {
+            1 => 65536,
+            size => size,
+        };
+        if !(512..=65536).contains(&page_size) || !page_size.is_power_of_two() {
+            return None;
+        }
+        let header = Self {
+            big_endian: magic & 1 == 1,
+            page_size,
+            checkpoints: be32(&bytes[12..]),
+            salt: [be32(&bytes[16..]), be32(&bytes[20..])],
+            checksum: [be32(&bytes[24..]), be32(&bytes[28..])],
+        };
+        (checksum(header.big_endian, &bytes[..24], [0, 0]) == header.checksum).then_some(header)
+    }
+
+    /// Read the header of the log at `file`, if it has one
+    pub fn read(file: &mut File) -> std::io::Result<Option<Self>> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE as usize);
+        file.seek(SeekFrom::Start(0))?;
+        file.take(HEADER_SIZE).read_to_end(&mut bytes)?;
+        Ok(Self::parse(&bytes))
+    }
+
+    /// Whether `next` is this log started over once, which SQLite does when
+    /// a writer finds every frame checkpointed
+    pub fn restarted_as(&self, next: &WalHeader) -> bool {
+        next.checkpoints == self.checkpoints.wrapping_add(1)
+            && next.salt[0] == self.salt[0].wrapping_add(1)
+    }
+}
+
+/// How far into a log frames have been read: just past a commit frame, or
+/// at the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub header: WalHeader,
+    pub offset: u64,
+    /// Checksum of the frame before `offset`, which the next one chains from
+    checksum: [u32; 2],
+}
+
+impl Position {
+    pub fn start(header: WalHeader) -> Self {
+        Self {
+            header,
+            offset: HEADER_SIZE,
+            checksum: header.checksum,
+        }
+    }
+}
+
+/// Committed frames read from a log
+pub struct Frames {
+    /// The frames as they are in the log, frame headers included
+    pub bytes: Vec<u8>,
+    pub count: u32,
+    /// Position after the last commit frame
+    pub end: Position,
+}
+
+/// Read the committed frames after `from`. Frames of a transaction still
+/// being written, and frames left over from before the log started over,
+/// are left out.
+pub fn read_frames(file: &mut File, from: Position) -> std::io::Result<Frames> {
+    let header = from.header;
+    let frame_size = FRAME_HEADER_SIZE + header.page_size as usize;
+    file.seek(SeekFrom::Start(from.offset))?;
+    let mut reader = BufReader::new(file);
+
+    let mut frame = vec![0u8; frame_size];
+    let mut bytes = Vec::new();
+    let mut chained = from.checksum;
+    let mut end = from;
+    let mut count = 0;
+    loop {
+        match reader.read_exact(&mut frame) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if [be32(&frame[8..]), be32(&frame[12..])] != header.salt {
+            break;
+        }
+        let sum = checksum(header.big_endian, &frame[..8], chained);
+        let sum = checksum(header.big_endian, &frame[FRAME_HEADER_SIZE..], sum);
+        if sum != [be32(&frame[16..]), be32(&frame[20..])] {
+            break;
+        }
+        chained = sum;
+        bytes.extend_from_slice(&frame);
+
+        if be32(&frame[4..]) != 0 {
+            count = (bytes.len() / frame_size) as u32;
+            end = Position {
+                header,
+                offset: from.offset + bytes.len() as u64,
+                checksum: sum,
+            };
+        }
+    }
+    bytes.truncate(count as usize * frame_size);
+    Ok(Frames { bytes, count, end })
+}
+
+/// Write committed frames into a database file as a checkpoint would: each
+/// page at its place, and the file cut to the size every commit records
+pub fn apply(db: &mut File, page_size: u32, frames: &[u8]) -> std::io::Result<()> {
+    let frame_size = FRAME_HEADER_SIZE + page_size as usize;
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    if frames.len() % frame_size != 0 {
+        return Err(invalid("segment does not hold whole frames"));
+    }
+    for frame in frames.chunks_exact(frame_size) {
+        let page = be32(frame) as u64;
+        if page == 0 {
+            return Err(invalid("frame has no page number"));
+        }
+        db.seek(SeekFrom::Start((page - 1) * page_size as u64))?;
+        db.write_all(&frame[FRAME_HEADER_SIZE..])?;
+        let pages = be32(&frame[4..]) as u64;
+        if pages != 0 {
+            db.set_len(pages * page_size as u64)?;
+        }
+    }
+    db.sync_all()
+}
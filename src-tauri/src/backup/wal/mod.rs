@@ -0,0 +1,885 @@
+//! Continuous shipping of PocketBase's write-ahead log, for point-in-time
+//! recovery
+//!
+//! PocketBase's database runs in WAL mode: every transaction is appended to
+//! `data.db-wal` as page frames, which SQLite later copies into the database
+//! before starting the log over. Every few seconds the shipper reads the
+//! frames committed since its last pass and seals them into the next
+//! numbered segment, encrypted with the backup passphrase. A *generation*
+//! is a snapshot of the database followed by the segments that continue
+//! it. Segments are staged locally and copied in order to the configured
+//! [`Destination`], under `wal/<generation>/`. Restoring to a time replays
+//! the covering generation's segments up to that time onto its snapshot,
+//! see [`recover`].
+//!
+//! Frames must be read before SQLite starts the log over. While attached,
+//! the shipper holds a read transaction on the database, which keeps SQLite
+//! from starting the log over past it. Once the log has grown large, the
+//! shipper takes the write lock for a moment, reads what is left,
+//! checkpoints and renews its read transaction, so the log only ever starts
+//! over with nothing in it unread. The log's header tells whether it
+//! started over, and if it did with frames never read, e.g. because
+//! something else wrote to the database while the shipper was away, a new
+//! generation starts rather than one with a gap.
+//!
+//! Shipping follows PocketBase through the supervisor's [`Lifecycle`]
+//! hooks. When PocketBase exits, the end of the log is shipped and the
+//! database let go of before a restore may touch it, and the generation is
+//! left open. When PocketBase is ready again, the generation continues if
+//! the database is the one that was left, and a new one starts otherwise.
+//! Generations are also started afresh daily, and pruned once they ended
+//! more than `retention_days` ago.
+
+pub mod format;
+pub(super) mod recover;
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::destination::{Archive, CopyError, Destination};
+use crate::crypto;
+use crate::db::{self, LocalDb};
+use crate::sidecar::Lifecycle;
+use format::{Frames, Position, WalHeader};
+pub use recover::PointInTimePlan;
+
+/// How often committed frames are shipped
+pub const SHIP_INTERVAL: Duration = Duration::from_secs(5);
+/// Log size past which the shipper lets SQLite start the log over
+pub const CHECKPOINT_BYTES: u64 = 4 * 1024 * 1024;
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+/// Age at which a generation ends and a new one starts with a fresh
+/// snapshot, so old segments can be pruned
+const GENERATION_MS: i64 = DAY_MS;
+pub const EXTENSION: &str = "lmwal";
+const MAGIC: &[u8; 8] = b"LMPBWALS";
+/// Folder generations are kept in, locally and at the destination
+pub const FOLDER: &str = "wal";
+const DATABASE: &str = "data.db";
+/// Wait for PocketBase's writer before giving up on the write lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WalSettings {
+    pub enabled: bool,
+    /// Backup destination segments are copied to
+    pub destination_id: Option<String>,
+    /// Days back a restore can reach
+    pub retention_days: u32,
+}
+
+impl Default for WalSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            destination_id: None,
+            retention_days: 7,
+        }
+    }
+}
+
+/// Cleartext header of a segment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentInfo {
+    pub generation: String,
+    /// 0 for the generation's snapshot, then 1, 2, ...
+    pub seq: u64,
+    /// Unix timestamp (ms) the segment was cut. It holds every transaction
+    /// committed since the previous segment.
+    pub created_at: i64,
+    pub page_size: u32,
+    /// Log frames in the segment; none in a snapshot
+    pub frames: u32,
+    pub app_version: String,
+}
+
+/// How log shipping is going, for the settings page
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalStatus {
+    pub enabled: bool,
+    /// Whether PocketBase's log is being shipped right now
+    pub shipping: bool,
+    pub generation: Option<String>,
+    /// Unix timestamp (ms) of the earliest time that can be restored to
+    pub restorable_from: Option<i64>,
+    /// Unix timestamp (ms) of the latest shipped segment. Anything committed
+    /// after it is only on this computer.
+    pub shipped_through: Option<i64>,
+    /// Segments waiting to be copied
+    pub pending: u32,
+    pub last_error: Option<String>,
+}
+
+/// `<seq>-<created_at>.lmwal`, so a listing sorts in replay order and
+/// tells what each segment covers without opening it
+fn segment_name(seq: u64, created_at: i64) -> String {
+    format!("{:08}-{}.{}", seq, created_at, EXTENSION)
+}
+
+fn parse_segment_name(name: &str) -> Option<(u64, i64)> {
+    let stem = name.strip_suffix(EXTENSION)?.strip_suffix('.')?;
+    let (seq, created_at) = stem.split_once('-')?;
+    Some((seq.parse().ok()?, created_at.parse().ok()?))
+}
+
+fn generation_id() -> String {
+    format!(
+        "{}-{:08x}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+        rand::random::<u32>()
+    )
+}
+
+/// What the log holds past the shipped position
+enum Unshipped {
+    /// No log, or an empty one
+    Nothing,
+    Frames(Frames),
+    /// The log started over with frames in it that were never read
+    Gap,
+}
+
+fn open_log(log: &Path) -> Result<Option<(File, WalHeader)>, String> {
+    let read_err = |e: std::io::Error| format!("Failed to read {}: {}", log.display(), e);
+    let mut file = match File::open(log) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(read_err(e)),
+    };
+    Ok(WalHeader::read(&mut file)
+        .map_err(read_err)?
+        .map(|header| (file, header)))
+}
+
+fn read_frames(log: &Path, file: &mut File, from: Position) -> Result<Frames, String> {
+    format::read_frames(file, from).map_err(|e| format!("Failed to read {}: {}", log.display(), e))
+}
+
+/// Read the frames committed after `shipped`. With nothing shipped yet,
+/// the log is read from its start, as long as it never started over.
+fn read_unshipped(log: &Path, shipped: Option<Position>) -> Result<Unshipped, String> {
+    let Some((mut file, header)) = open_log(log)? else {
+        return Ok(Unshipped::Nothing);
+    };
+    let from = match shipped {
+        Some(position) if position.header.salt == header.salt => position,
+        Some(position) if position.header.restarted_as(&header) => Position::start(header),
+        None if header.checkpoints == 0 => Position::start(header),
+        _ => return Ok(Unshipped::Gap),
+    };
+    read_frames(log, &mut file, from).map(Unshipped::Frames)
+}
+
+/// Where the log's committed frames end, whatever was shipped
+fn log_end(log: &Path) -> Result<Option<Position>, String> {
+    let Some((mut file, header)) = open_log(log)? else {
+        return Ok(None);
+    };
+    read_frames(log, &mut file, Position::start(header)).map(|frames| Some(frames.end))
+}
+
+/// Connections to PocketBase's database and shipping progress, while
+/// attached
+struct Attached {
+    /// Holds the read transaction that keeps the log from starting over
+    reader: Connection,
+    /// Takes the write lock while the log is read to its end
+    writer: Connection,
+    generation: String,
+    started_at: i64,
+    destination_id: String,
+    passphrase: String,
+    key: crypto::Key,
+    page_size: u32,
+    /// End of the last shipped frame; `None` while the generation holds
+    /// the whole log
+    position: Option<Position>,
+    next_seq: u64,
+}
+
+impl Attached {
+    /// Take the write lock, waiting for PocketBase's writer to commit
+    fn lock_writes(&self) -> Result<(), String> {
+        self.writer
+            .execute_batch("BEGIN IMMEDIATE")
+            .map_err(|e| format!("Failed to lock PocketBase's database: {}", e))
+    }
+
+    fn unlock_writes(&self) {
+        if let Err(e) = self.writer.execute_batch("ROLLBACK") {
+            log::warn!("Failed to unlock PocketBase's database: {}", e);
+        }
+    }
+
+    /// End the read transaction held so far, if any, and start a new one
+    fn renew_read(&self) -> Result<(), String> {
+        let renew = || -> rusqlite::Result<()> {
+            if !self.reader.is_autocommit() {
+                self.reader.execute_batch("COMMIT")?;
+            }
+            self.reader.execute_batch("BEGIN")?;
+            self.reader
+                .query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))
+        };
+        renew().map_err(|e| format!("Failed to hold PocketBase's log: {}", e))
+    }
+
+    /// Let SQLite copy the log into the database, which our read
+    /// transaction otherwise prevents, so it can start the log over. Only
+    /// called with the write lock held and the log shipped to its end.
+    fn checkpoint(&self) -> Result<(), String> {
+        let checkpoint = || -> rusqlite::Result<()> {
+            if !self.reader.is_autocommit() {
+                self.reader.execute_batch("COMMIT")?;
+            }
+            self.reader
+                .query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(()))
+        };
+        checkpoint().map_err(|e| format!("Failed to checkpoint PocketBase's log: {}", e))?;
+        self.renew_read()
+    }
+}
+
+/// The open generation, as recorded
+struct OpenGeneration {
+    id: String,
+    destination_id: String,
+    started_at: i64,
+    /// Set when it was left with the database unchanged
+    detached_fingerprint: Option<String>,
+    next_seq: u64,
+}
+
+/// Segment waiting to be copied
+struct PendingSegment {
+    generation_id: String,
+    seq: u64,
+    file_name: String,
+    sha256: String,
+    attempts: u32,
+    next_attempt_at: i64,
+}
+
+pub struct WalShipper {
+    db: Arc<LocalDb>,
+    /// PocketBase's data directory
+    pb_data: PathBuf,
+    /// Where segments wait to be copied
+    staging: PathBuf,
+    checkpoint_bytes: u64,
+    attached: Mutex<Option<Attached>>,
+    /// Whether PocketBase is up, so there is a database to attach to
+    ready: AtomicBool,
+    /// Wakes the worker when PocketBase becomes ready
+    pub(super) wake: tokio::sync::Notify,
+    last_error: Mutex<Option<String>>,
+}
+
+impl WalShipper {
+    pub fn new(
+        db: Arc<LocalDb>,
+        pb_data: PathBuf,
+        staging: PathBuf,
+        checkpoint_bytes: u64,
+    ) -> Self {
+        Self {
+            db,
+            pb_data,
+            staging,
+            checkpoint_bytes,
+            attached: Mutex::new(None),
+            ready: AtomicBool::new(false),
+            wake: tokio::sync::Notify::new(),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    fn database(&self) -> PathBuf {
+        self.pb_data.join(DATABASE)
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.pb_data.join(format!("{}-wal", DATABASE))
+    }
+
+    fn generation_dir(&self, generation: &str) -> PathBuf {
+        self.staging.join(generation)
+    }
+
+    /// Follow PocketBase: attach once it is ready, and ship the end of the
+    /// log and let go of the database as soon as it exits
+    pub fn on_lifecycle(&self, event: Lifecycle) {
+        match event {
+            Lifecycle::Ready => {
+                self.ready.store(true, Ordering::SeqCst);
+                self.wake.notify_one();
+            }
+            Lifecycle::Exited => {
+                self.ready.store(false, Ordering::SeqCst);
+                let mut attached = self.attached.lock().unwrap();
+                if let Some(current) = attached.take() {
+                    if let Err(e) = self.release(current, false) {
+                        self.set_error(Some(format!(
+                            "Failed to ship the end of PocketBase's log: {}",
+                            e
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Record the latest error, logging it when it is new. Returns whether
+    /// it changed.
+    pub fn set_error(&self, error: Option<String>) -> bool {
+        let mut last = self.last_error.lock().unwrap();
+        if *last == error {
+            return false;
+        }
+        if let Some(e) = &error {
+            log::warn!("Write-ahead log shipping: {}", e);
+        }
+        *last = error;
+        true
+    }
+
+    /// Attach to PocketBase's database if it is ready. The open generation
+    /// continues if it is for the same destination and the database is as
+    /// it was left; otherwise a new one starts. A change of destination or
+    /// passphrase ends the current generation.
+    pub fn attach(&self, destination_id: &str, passphrase: &str) -> Result<(), String> {
+        let mut attached = self.attached.lock().unwrap();
+        if let Some(current) = attached.as_ref() {
+            if current.destination_id == destination_id && current.passphrase == passphrase {
+                return Ok(());
+            }
+        }
+        if let Some(current) = attached.take() {
+            self.release(current, true)?;
+        }
+        if !self.ready.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let mut current = self.open(destination_id, passphrase)?;
+        let continued = match self.open_generation()? {
+            Some(open)
+                if open.destination_id == destination_id
+                    && open.detached_fingerprint.is_some()
+                    && open.detached_fingerprint == Some(self.fingerprint()?) =>
+            {
+                current.generation = open.id;
+                current.started_at = open.started_at;
+                current.next_seq = open.next_seq;
+                self.resume(&mut current)?
+            }
+            _ => false,
+        };
+        if !continued {
+            self.begin_generation(&mut current)?;
+        }
+        *attached = Some(current);
+        Ok(())
+    }
+
+    fn open(&self, destination_id: &str, passphrase: &str) -> Result<Attached, String> {
+        let path = self.database();
+        let connect = || -> rusqlite::Result<Connection> {
+            // Never create the database, that is PocketBase's job
+            let conn = Connection::open_with_flags(
+                &path,
+                OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?;
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            Ok(conn)
+        };
+        let open = || -> rusqlite::Result<(Connection, Connection, String, u32)> {
+            let reader = connect()?;
+            let writer = connect()?;
+            let mode = reader.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+            let page_size = reader.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+            Ok((reader, writer, mode, page_size))
+        };
+        let (reader, writer, mode, page_size) =
+            open().map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(format!("{} is not in WAL mode", path.display()));
+        }
+        Ok(Attached {
+            reader,
+            writer,
+            generation: String::new(),
+            started_at: 0,
+            destination_id: destination_id.to_string(),
+            passphrase: passphrase.to_string(),
+            key: crypto::Key::new(passphrase)?,
+            page_size,
+            position: None,
+            next_seq: 0,
+        })
+    }
+
+    fn open_generation(&self) -> Result<Option<OpenGeneration>, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                "SELECT g.id, g.destination_id, g.started_at, g.detached_fingerprint,
+                    (SELECT COALESCE(MAX(s.seq) + 1, 0) FROM wal_segments s WHERE s.generation_id = g.id)
+                 FROM wal_generations g WHERE g.ended_at IS NULL
+                 ORDER BY g.started_at DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok(OpenGeneration {
+                        id: row.get(0)?,
+                        destination_id: row.get(1)?,
+                        started_at: row.get(2)?,
+                        detached_fingerprint: row.get(3)?,
+                        next_seq: row.get(4)?,
+                    })
+                },
+            )
+            .optional()
+        })
+    }
+
+    /// SHA-256 of the database file, to tell whether it changed while
+    /// detached
+    fn fingerprint(&self) -> Result<String, String> {
+        let path = self.database();
+        File::open(&path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+            .and_then(crypto::sha256_hex)
+    }
+
+    /// Continue a generation left when PocketBase exited, shipping what it
+    /// has written since it started again. `false` if the log started over
+    /// meanwhile.
+    fn resume(&self, attached: &mut Attached) -> Result<bool, String> {
+        attached.lock_writes()?;
+        let result = self.ship_unshipped(attached).and_then(|continued| {
+            if continued {
+                attached.renew_read()?;
+            }
+            Ok(continued)
+        });
+        attached.unlock_writes();
+        if result == Ok(true) {
+            self.db.with(|conn| {
+                conn.execute(
+                    "UPDATE wal_generations SET detached_fingerprint = NULL WHERE id = ?1",
+                    [&attached.generation],
+                )
+            })?;
+        }
+        result
+    }
+
+    /// End any open generation and start a new one with a snapshot of the
+    /// database as it is now
+    fn begin_generation(&self, attached: &mut Attached) -> Result<(), String> {
+        let now = db::now_millis();
+        let id = generation_id();
+        self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "UPDATE wal_generations SET ended_at = ?1 WHERE ended_at IS NULL",
+                [now],
+            )?;
+            tx.execute(
+                "INSERT INTO wal_generations (id, destination_id, started_at) VALUES (?1, ?2, ?3)",
+                params![id, attached.destination_id, now],
+            )?;
+            tx.commit()
+        })?;
+        log::info!("Starting write-ahead log generation {}", id);
+        attached.generation = id;
+        attached.started_at = now;
+        attached.next_seq = 0;
+
+        // The snapshot is taken from a read transaction begun at the end of
+        // the log, which segments continue from
+        attached.lock_writes()?;
+        let held = log_end(&self.log_path()).and_then(|end| {
+            attached.position = end;
+            attached.renew_read()
+        });
+        attached.unlock_writes();
+        held?;
+        self.write_snapshot(attached)
+    }
+
+    /// Seal a copy of the database, as the read transaction sees it, as
+    /// segment 0
+    fn write_snapshot(&self, attached: &mut Attached) -> Result<(), String> {
+        let dir = self.generation_dir(&attached.generation);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let copy_path = dir.join("snapshot.db");
+        let result = (|| {
+            let copy = || -> rusqlite::Result<()> {
+                let mut copy = Connection::open(&copy_path)?;
+                Backup::new(&attached.reader, &mut copy)?.run_to_completion(
+                    i32::MAX,
+                    Duration::ZERO,
+                    None,
+                )?;
+                copy.pragma_update(None, "journal_mode", "DELETE")
+            };
+            copy().map_err(|e| format!("Failed to snapshot PocketBase's database: {}", e))?;
+            let file = File::open(&copy_path)
+                .map_err(|e| format!("Failed to read {}: {}", copy_path.display(), e))?;
+            self.write_segment(attached, 0, BufReader::new(file))
+        })();
+        let _ = std::fs::remove_file(&copy_path);
+        result
+    }
+
+    /// Seal `payload` as the generation's next segment, to be copied
+    fn write_segment(
+        &self,
+        attached: &mut Attached,
+        frames: u32,
+        payload: impl Read,
+    ) -> Result<(), String> {
+        let seq = attached.next_seq;
+        let created_at = db::now_millis();
+        let file_name = segment_name(seq, created_at);
+        let path = self.generation_dir(&attached.generation).join(&file_name);
+        let info = SegmentInfo {
+            generation: attached.generation.clone(),
+            seq,
+            created_at,
+            page_size: attached.page_size,
+            frames,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+
+        let written = (|| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            }
+            let write_err =
+                |e: std::io::Error| format!("Failed to write {}: {}", path.display(), e);
+            let mut writer = BufWriter::new(File::create(&path).map_err(write_err)?);
+            crypto::seal_with(MAGIC, info, &attached.key, payload, &mut writer)?;
+            writer
+                .into_inner()
+                .map_err(|e| write_err(e.into_error()))?
+                .sync_all()
+                .map_err(write_err)?;
+            let size = std::fs::metadata(&path).map_err(write_err)?.len();
+            let sha256 = File::open(&path)
+                .map(BufReader::new)
+                .map_err(write_err)
+                .and_then(crypto::sha256_hex)?;
+            Ok((size, sha256))
+        })();
+        let (size, sha256) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO wal_segments (generation_id, seq, file_name, frames, size, sha256, status, created_at, next_attempt_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'pending', ?7, ?7)",
+                params![attached.generation, seq, file_name, frames, size, sha256, created_at],
+            )
+        })?;
+        attached.next_seq += 1;
+        Ok(())
+    }
+
+    /// Seal the frames committed since the shipped position into the next
+    /// segment. `false` if the log started over with frames never read,
+    /// leaving a gap only a new generation can close.
+    fn ship_unshipped(&self, attached: &mut Attached) -> Result<bool, String> {
+        match read_unshipped(&self.log_path(), attached.position)? {
+            Unshipped::Nothing => Ok(true),
+            Unshipped::Gap => Ok(false),
+            Unshipped::Frames(frames) => {
+                if frames.count > 0 {
+                    self.write_segment(attached, frames.count, &frames.bytes[..])?;
+                }
+                attached.position = Some(frames.end);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Ship what was committed since the last pass. Once the log has grown
+    /// past `checkpoint_bytes` it is shipped to its end under the write lock
+    /// and checkpointed, so SQLite can start it over.
+    pub fn ship(&self) -> Result<(), String> {
+        let mut attached = self.attached.lock().unwrap();
+        let Some(attached) = attached.as_mut() else {
+            return Ok(());
+        };
+        if db::now_millis() - attached.started_at >= GENERATION_MS {
+            self.ship_unshipped(attached)?;
+            return self.begin_generation(attached);
+        }
+
+        let log_size = std::fs::metadata(self.log_path())
+            .map(|meta| meta.len())
+            .unwrap_or(0);
+        let checkpoint = log_size >= self.checkpoint_bytes;
+        if checkpoint {
+            attached.lock_writes()?;
+        }
+        let result = self.ship_unshipped(attached).and_then(|continued| {
+            if continued && checkpoint {
+                attached.checkpoint()?;
+            }
+            Ok(continued)
+        });
+        if checkpoint {
+            attached.unlock_writes();
+        }
+        if !result? {
+            log::warn!("PocketBase's log started over before it was shipped");
+            self.begin_generation(attached)?;
+        }
+        Ok(())
+    }
+
+    /// Ship what is left of the log and let go of the database. The
+    /// generation stays open to be continued if the tail shipped and `end`
+    /// is false, and ends otherwise.
+    fn release(&self, mut attached: Attached, end: bool) -> Result<(), String> {
+        let shipped = attached.lock_writes().and_then(|()| {
+            let shipped = self.ship_unshipped(&mut attached);
+            attached.unlock_writes();
+            shipped
+        });
+        let generation = std::mem::take(&mut attached.generation);
+        // Closing the last connection checkpoints the log into the
+        // database, which is then what the fingerprint covers
+        drop(attached);
+
+        let fingerprint = match (&shipped, end) {
+            (Ok(true), false) => self.fingerprint().ok(),
+            _ => None,
+        };
+        self.db.with(|conn| match &fingerprint {
+            Some(fingerprint) => conn.execute(
+                "UPDATE wal_generations SET detached_fingerprint = ?2 WHERE id = ?1",
+                params![generation, fingerprint],
+            ),
+            None => conn.execute(
+                "UPDATE wal_generations SET ended_at = ?2 WHERE id = ?1",
+                params![generation, db::now_millis()],
+            ),
+        })?;
+        shipped.map(|_| ())
+    }
+
+    /// Stop shipping and end the open generation, e.g. once shipping is
+    /// turned off
+    pub fn stop(&self) -> Result<(), String> {
+        let mut attached = self.attached.lock().unwrap();
+        if let Some(current) = attached.take() {
+            self.release(current, true)?;
+        }
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE wal_generations SET ended_at = ?1 WHERE ended_at IS NULL",
+                [db::now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Copy the segments that are due to `destination`, oldest first,
+    /// deleting each local file once its copy is verified. Stops at the
+    /// first segment that can't be copied, so the destination never has a
+    /// gap. Returns how many were copied.
+    pub async fn upload(
+        &self,
+        http: &reqwest::Client,
+        destination: &Destination,
+    ) -> Result<u32, String> {
+        let due = self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT s.generation_id, s.seq, s.file_name, s.sha256, s.attempts, s.next_attempt_at
+                 FROM wal_segments s JOIN wal_generations g ON g.id = s.generation_id
+                 WHERE s.status = 'pending' AND g.destination_id = ?1
+                 ORDER BY g.started_at, s.seq",
+            )?;
+            let rows = stmt.query_map([&destination.id], |row| {
+                Ok(PendingSegment {
+                    generation_id: row.get(0)?,
+                    seq: row.get(1)?,
+                    file_name: row.get(2)?,
+                    sha256: row.get(3)?,
+                    attempts: row.get(4)?,
+                    next_attempt_at: row.get(5)?,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+
+        let mut copied = 0;
+        for segment in due {
+            let now = db::now_millis();
+            if segment.next_attempt_at > now {
+                break;
+            }
+            let path = self
+                .generation_dir(&segment.generation_id)
+                .join(&segment.file_name);
+            let archive = Archive {
+                path: &path,
+                file_name: &segment.file_name,
+                sha256: &segment.sha256,
+            };
+            let target = destination.within(&format!("{}/{}", FOLDER, segment.generation_id));
+            let (attempts, delay_ms, error) = match target.copy(http, &archive, None, |_| {}).await
+            {
+                Ok(_) => {
+                    self.db.with(|conn| {
+                        conn.execute(
+                            "UPDATE wal_segments SET status = 'done', error = NULL, shipped_at = ?3
+                             WHERE generation_id = ?1 AND seq = ?2",
+                            params![segment.generation_id, segment.seq, now],
+                        )
+                    })?;
+                    let _ = std::fs::remove_file(&path);
+                    copied += 1;
+                    continue;
+                }
+                Err(CopyError::Unavailable(e)) => {
+                    (segment.attempts, super::UNAVAILABLE_RETRY_MS, e)
+                }
+                Err(CopyError::Failed(e)) => {
+                    let attempts = segment.attempts + 1;
+                    (attempts, super::retry_delay_ms(attempts), e)
+                }
+            };
+            self.db.with(|conn| {
+                conn.execute(
+                    "UPDATE wal_segments SET attempts = ?3, error = ?4, next_attempt_at = ?5
+                     WHERE generation_id = ?1 AND seq = ?2",
+                    params![
+                        segment.generation_id,
+                        segment.seq,
+                        attempts,
+                        error,
+                        now + delay_ms
+                    ],
+                )
+            })?;
+            return Err(format!(
+                "Failed to copy {} to {}: {}",
+                segment.file_name, destination.name, error
+            ));
+        }
+        Ok(copied)
+    }
+
+    /// Forget generations that ended more than `retention_days` ago,
+    /// removing their segments at their destination and here
+    pub async fn prune(
+        &self,
+        http: &reqwest::Client,
+        destinations: &[Destination],
+        retention_days: u32,
+    ) -> Result<(), String> {
+        let cutoff = db::now_millis() - retention_days as i64 * DAY_MS;
+        let expired: Vec<(String, String)> = self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, destination_id FROM wal_generations
+                 WHERE ended_at IS NOT NULL AND ended_at < ?1",
+            )?;
+            let rows = stmt.query_map([cutoff], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })?;
+
+        'generations: for (generation, destination_id) in expired {
+            if let Some(destination) = destinations.iter().find(|d| d.id == destination_id) {
+                let target = destination.within(&format!("{}/{}", FOLDER, generation));
+                let shipped: Vec<String> = self.db.with(|conn| {
+                    let mut stmt = conn.prepare(
+                        "SELECT file_name FROM wal_segments WHERE generation_id = ?1 AND status = 'done'",
+                    )?;
+                    let rows = stmt.query_map([&generation], |row| row.get(0))?;
+                    rows.collect()
+                })?;
+                for file_name in shipped {
+                    match target.remove(http, &file_name).await {
+                        Ok(()) => {}
+                        Err(CopyError::Unavailable(_)) => continue 'generations,
+                        Err(CopyError::Failed(e)) => {
+                            log::warn!(
+                                "Failed to prune {} from {}: {}",
+                                file_name,
+                                destination.name,
+                                e
+                            );
+                            continue 'generations;
+                        }
+                    }
+                }
+            }
+            let dir = self.generation_dir(&generation);
+            match std::fs::remove_dir_all(&dir) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("Failed to prune {}: {}", dir.display(), e);
+                    continue;
+                }
+                _ => {}
+            }
+            self.db.with(|conn| {
+                conn.execute("DELETE FROM wal_generations WHERE id = ?1", [&generation])
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, settings: &WalSettings) -> Result<WalStatus, String> {
+        let (generation, restorable_from, shipped_through, pending) = self.db.with(|conn| {
+            conn.query_row(
+                "SELECT
+                    (SELECT id FROM wal_generations
+                     WHERE ended_at IS NULL AND detached_fingerprint IS NULL
+                     ORDER BY started_at DESC LIMIT 1),
+                    (SELECT MIN(created_at) FROM wal_segments WHERE seq = 0 AND status = 'done'),
+                    (SELECT MAX(s.created_at) FROM wal_segments s
+                     WHERE s.status = 'done' AND EXISTS (
+                        SELECT 1 FROM wal_segments z
+                        WHERE z.generation_id = s.generation_id AND z.seq = 0 AND z.status = 'done')),
+                    (SELECT COUNT(*) FROM wal_segments WHERE status = 'pending')",
+                [],
+                |row| {
+                    let generation: Option<String> = row.get(0)?;
+                    Ok((generation, row.get(1)?, row.get(2)?, row.get(3)?))
+                },
+            )
+        })?;
+        let shipping = settings.enabled && self.ready.load(Ordering::SeqCst);
+        Ok(WalStatus {
+            enabled: settings.enabled,
+            shipping: shipping && generation.is_some(),
+            generation: generation.filter(|_| shipping),
+            restorable_from,
+            shipped_through,
+            pending,
+            last_error: self.last_error.lock().unwrap().clone(),
+        })
+    }
+}
@@ -0,0 +1,320 @@
+//! Restoring PocketBase's database to a point in time
+//!
+//! The generation covering the time is found by listing the destination.
+//! Its snapshot and the segments cut by then are downloaded, decrypted and
+//! replayed into a copy of `data.db` next to `pb_data`, which is checked and
+//! compared with the live database before PocketBase is touched. Only
+//! `data.db` is replaced; uploads and `auxiliary.db` stay as they are. The
+//! replaced database is kept in `pb_data.pitr-previous` until the next
+//! restore to a point in time, and put back automatically if PocketBase
+//! does not come up healthy on the restored one.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use super::{format, parse_segment_name, SegmentInfo, DATABASE, EXTENSION, FOLDER, MAGIC};
+use crate::backup::archive;
+use crate::backup::destination::Destination;
+use crate::backup::restore::{self, CollectionDiff};
+use crate::crypto;
+use crate::pocketbase;
+
+/// PocketBase's main database files, which are all a restore to a point in
+/// time replaces
+const DATABASE_FILES: [&str; 3] = ["data.db", "data.db-wal", "data.db-shm"];
+
+/// What restoring to a point in time changes
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointInTimePlan {
+    /// Unix timestamp (ms) asked for
+    pub target: i64,
+    /// Unix timestamp (ms) the restored data is from: when the last
+    /// replayed segment was cut
+    pub restored_to: i64,
+    pub generation: String,
+    /// Segments replayed onto the generation's snapshot
+    pub segments: u32,
+    /// Bundled migrations the restored database predates, which PocketBase
+    /// applies when it starts on it
+    pub pending_migrations: Vec<String>,
+    /// Collections with differences, by name
+    pub collections: Vec<CollectionDiff>,
+    /// False for a dry run
+    pub applied: bool,
+}
+
+/// Scratch locations next to the live `pb_data`
+pub(in crate::backup) struct Paths {
+    live: PathBuf,
+    staging: PathBuf,
+    previous: PathBuf,
+    failed: PathBuf,
+}
+
+impl Paths {
+    pub(in crate::backup) fn new(pb_data: &Path) -> Self {
+        Self {
+            live: pb_data.to_path_buf(),
+            staging: restore::sibling(pb_data, ".pitr"),
+            previous: restore::sibling(pb_data, ".pitr-previous"),
+            failed: restore::sibling(pb_data, ".pitr-failed"),
+        }
+    }
+}
+
+/// A segment found at the destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(in crate::backup) struct Found {
+    pub generation: String,
+    pub seq: u64,
+    pub created_at: i64,
+    /// Path below the destination's `wal` folder
+    pub path: String,
+}
+
+fn parse(path: &str) -> Option<Found> {
+    let (generation, name) = path.split_once('/')?;
+    let (seq, created_at) = parse_segment_name(name)?;
+    Some(Found {
+        generation: generation.to_string(),
+        seq,
+        created_at,
+        path: path.to_string(),
+    })
+}
+
+/// Segments to replay to restore `target`: the snapshot of the generation
+/// started last by then, and its segments cut by then, up to any that is
+/// missing
+pub(in crate::backup) fn pick(files: &[String], target: i64) -> Option<Vec<Found>> {
+    let found: Vec<Found> = files.iter().filter_map(|path| parse(path)).collect();
+    let snapshot = found
+        .iter()
+        .filter(|segment| segment.seq == 0 && segment.created_at <= target)
+        .max_by_key(|segment| segment.created_at)?;
+    let mut segments: Vec<&Found> = found
+        .iter()
+        .filter(|segment| {
+            segment.generation == snapshot.generation
+                && segment.seq > 0
+                && segment.created_at <= target
+        })
+        .collect();
+    segments.sort_by_key(|segment| segment.seq);
+
+    let mut picked = vec![snapshot.clone()];
+    for segment in segments {
+        if segment.seq != picked.len() as u64 {
+            break;
+        }
+        picked.push(segment.clone());
+    }
+    Some(picked)
+}
+
+/// Decrypt the snapshot into `dest` and replay the segments after it
+fn replay(segments: &[(Found, PathBuf)], passphrase: &str, dest: &Path) -> Result<(), String> {
+    // A generation's segments usually share a key, derive it once
+    let mut keys: Vec<crypto::Key> = Vec::new();
+    let mut page_size = 0;
+    for (segment, path) in segments {
+        let mut reader = File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let (header, header_bytes) = crypto::read_header::<SegmentInfo>(MAGIC, &mut reader)?;
+        let info = &header.metadata;
+        if info.generation != segment.generation || info.seq != segment.seq {
+            return Err(format!(
+                "{} is not the segment it is named as",
+                segment.path
+            ));
+        }
+        let key = match keys.iter().position(|key| key.fits(&header)) {
+            Some(index) => &keys[index],
+            None => {
+                keys.push(crypto::Key::for_header(passphrase, &header)?);
+                &keys[keys.len() - 1]
+            }
+        };
+
+        if segment.seq == 0 {
+            let write_err =
+                |e: std::io::Error| format!("Failed to write {}: {}", dest.display(), e);
+            let mut writer = BufWriter::new(File::create(dest).map_err(write_err)?);
+            crypto::open_with(&header, &header_bytes, key, reader, &mut writer)?;
+            writer.flush().map_err(write_err)?;
+            page_size = info.page_size;
+            continue;
+        }
+        if info.page_size != page_size {
+            return Err(format!(
+                "{} has a different page size than its snapshot",
+                segment.path
+            ));
+        }
+        let mut frames = Vec::new();
+        crypto::open_with(&header, &header_bytes, key, reader, &mut frames)?;
+        OpenOptions::new()
+            .write(true)
+            .open(dest)
+            .and_then(|mut db| format::apply(&mut db, page_size, &frames))
+            .map_err(|e| format!("Failed to replay {}: {}", segment.path, e))?;
+    }
+
+    let check: String = Connection::open(dest)
+        .and_then(|conn| conn.query_row("PRAGMA quick_check", [], |row| row.get(0)))
+        .map_err(|e| format!("Failed to check the restored database: {}", e))?;
+    if check != "ok" {
+        return Err(format!("Restored database is corrupt: {}", check));
+    }
+    Ok(())
+}
+
+/// Download and replay the segments covering `target` into the staging
+/// folder, and work out what restoring them would change
+pub(in crate::backup) async fn prepare(
+    http: &reqwest::Client,
+    destination: &Destination,
+    target: i64,
+    passphrase: &str,
+    bundled: &[String],
+    paths: &Paths,
+) -> Result<PointInTimePlan, String> {
+    restore::remove_dir(&paths.staging)?;
+    let result = async {
+        let root = destination.within(FOLDER);
+        let files = root.list(http).await?;
+        let picked = pick(&files, target).ok_or_else(|| {
+            format!(
+                "No point-in-time backup at {} reaches back that far",
+                destination.name
+            )
+        })?;
+
+        let downloads = paths.staging.join("segments");
+        std::fs::create_dir_all(&downloads)
+            .map_err(|e| format!("Failed to create {}: {}", downloads.display(), e))?;
+        let mut fetched = Vec::new();
+        for segment in picked {
+            let path = downloads.join(format!("{:08}.{}", segment.seq, EXTENSION));
+            root.fetch(http, &segment.path, &path).await?;
+            fetched.push((segment, path));
+        }
+
+        let passphrase = passphrase.to_string();
+        let bundled = bundled.to_vec();
+        let live = paths.live.join(DATABASE);
+        let restored = paths.staging.join(DATABASE);
+        tauri::async_runtime::spawn_blocking(move || {
+            replay(&fetched, &passphrase, &restored)?;
+            restore::remove_dir(&downloads)?;
+            let pending_migrations =
+                restore::check_schema(&archive::applied_migrations(&restored)?, &bundled)?;
+            let (last, _) = &fetched[fetched.len() - 1];
+            Ok(PointInTimePlan {
+                target,
+                restored_to: last.created_at,
+                generation: last.generation.clone(),
+                segments: fetched.len() as u32 - 1,
+                pending_migrations,
+                collections: restore::diff(&live, &restored)?,
+                applied: false,
+            })
+        })
+        .await
+        .map_err(|e| format!("Restore task failed: {}", e))?
+    }
+    .await;
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&paths.staging);
+    }
+    result
+}
+
+/// Move whichever database files exist from one folder to another
+fn move_database(from: &Path, to: &Path) -> std::io::Result<()> {
+    for name in DATABASE_FILES {
+        match std::fs::rename(from.join(name), to.join(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Move the live database aside to `previous` and the restored one in its
+/// place
+pub(in crate::backup) fn swap_in(paths: &Paths) -> Result<(), String> {
+    restore::remove_dir(&paths.previous)?;
+    std::fs::create_dir_all(&paths.previous)
+        .map_err(|e| format!("Failed to create {}: {}", paths.previous.display(), e))?;
+    if let Err(e) = move_database(&paths.live, &paths.previous) {
+        let _ = move_database(&paths.previous, &paths.live);
+        return Err(format!("Failed to move current data aside: {}", e));
+    }
+    if let Err(e) = std::fs::rename(paths.staging.join(DATABASE), paths.live.join(DATABASE)) {
+        let _ = move_database(&paths.previous, &paths.live);
+        return Err(format!("Failed to move restored data in place: {}", e));
+    }
+    restore::remove_dir(&paths.staging)
+}
+
+/// Put the database moved aside by [`swap_in`] back. The rejected one is
+/// kept in `failed` for inspection.
+pub(in crate::backup) fn roll_back(paths: &Paths) -> Result<(), String> {
+    restore::remove_dir(&paths.failed)?;
+    std::fs::create_dir_all(&paths.failed)
+        .map_err(|e| format!("Failed to create {}: {}", paths.failed.display(), e))?;
+    move_database(&paths.live, &paths.failed)
+        .map_err(|e| format!("Failed to move restored data aside: {}", e))?;
+    move_database(&paths.previous, &paths.live)
+        .map_err(|e| format!("Failed to put previous data back: {}", e))
+}
+
+/// Restore PocketBase's database as it was at `target` from the segments
+/// shipped to `destination`. With `dry_run` nothing is changed and the
+/// returned plan only describes the restore.
+pub(in crate::backup) async fn restore(
+    app: &tauri::AppHandle,
+    http: &reqwest::Client,
+    destination: &Destination,
+    pb_data: PathBuf,
+    target: i64,
+    passphrase: String,
+    dry_run: bool,
+) -> Result<PointInTimePlan, String> {
+    let migrations_dir = pocketbase::migrations_dir(app)
+        .ok_or_else(|| "Bundled PocketBase migrations not found".to_string())?;
+    let bundled = restore::bundled_migrations(&migrations_dir)?;
+
+    let paths = Paths::new(&pb_data);
+    let mut plan = prepare(http, destination, target, &passphrase, &bundled, &paths).await?;
+    if dry_run {
+        restore::remove_dir(&paths.staging)?;
+        return Ok(plan);
+    }
+
+    let swapped = restore::swap_paused(
+        app,
+        || swap_in(&paths),
+        || roll_back(&paths),
+        &paths.previous,
+    )
+    .await;
+    if swapped.is_err() {
+        let _ = std::fs::remove_dir_all(&paths.staging);
+    }
+    swapped?;
+    plan.applied = true;
+    log::info!(
+        "Restored PocketBase's database to {} from {}",
+        plan.restored_to,
+        destination.name
+    );
+    Ok(plan)
+}
@@ -0,0 +1,342 @@
+//! Passphrase-encrypted archive container
+//!
+//! Layout: an 8-byte magic identifying the archive kind, a little-endian
+//! `u32` header length, the JSON [`Header`], then the payload encrypted with
+//! AES-256-GCM in fixed-size chunks. The key is derived from the passphrase
+//! with Argon2id. Every chunk authenticates the header bytes and its own
+//! position, and the last chunk is flagged, so a tampered header, reordered
+//! chunks or a truncated file all fail to decrypt.
+//!
+//! The header is readable without the passphrase, which lets the app show
+//! what an archive contains and check compatibility before asking for it.
+
+use std::io::{Read, Write};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Plaintext bytes per encrypted chunk
+const CHUNK_SIZE: usize = 1024 * 1024;
+const TAG_SIZE: usize = 16;
+/// Refuse headers larger than this, they can only come from a corrupt file
+const MAX_HEADER_SIZE: u32 = 64 * 1024;
+/// Argon2id costs new archives are sealed with
+const MEMORY_KIB: u32 = 64 * 1024;
+const ITERATIONS: u32 = 3;
+const PARALLELISM: u32 = 1;
+/// Archives asking for more than this many times the costs above are
+/// refused before deriving, as their header would make the app exhaust
+/// memory or hang
+const MAX_COST_FACTOR: u32 = 4;
+
+/// Argon2id cost parameters, stored so they can be raised later without
+/// breaking old archives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Hex-encoded random salt
+    pub salt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Encryption {
+    pub cipher: String,
+    pub kdf: KdfParams,
+    /// Hex-encoded 7-byte prefix of every chunk nonce
+    pub nonce_prefix: String,
+    pub chunk_size: u32,
+}
+
+/// Cleartext archive header. `metadata` is defined by the archive kind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Header<M> {
+    pub metadata: M,
+    pub encryption: Encryption,
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Key derived from a passphrase, for sealing or opening many archives
+/// while paying for Argon2 once. Archives sealed with the same key share
+/// its salt, and each still gets its own random nonce prefix.
+pub struct Key {
+    kdf: KdfParams,
+    cipher: Aes256Gcm,
+}
+
+impl Key {
+    /// Derive a key with a fresh salt, for sealing
+    pub fn new(passphrase: &str) -> Result<Self, String> {
+        let kdf = KdfParams {
+            memory_kib: MEMORY_KIB,
+            iterations: ITERATIONS,
+            parallelism: PARALLELISM,
+            salt: hex::encode(random_bytes::<16>()),
+        };
+        Ok(Self {
+            cipher: derive_key(passphrase, &kdf)?,
+            kdf,
+        })
+    }
+
+    /// Derive the key an archive was sealed with
+    pub fn for_header<M>(passphrase: &str, header: &Header<M>) -> Result<Self, String> {
+        let kdf = header.encryption.kdf.clone();
+        Ok(Self {
+            cipher: derive_key(passphrase, &kdf)?,
+            kdf,
+        })
+    }
+
+    /// Whether this key opens archives with `header`, given the same passphrase
+    pub fn fits<M>(&self, header: &Header<M>) -> bool {
+        let kdf = &header.encryption.kdf;
+        kdf.salt == self.kdf.salt
+            && kdf.memory_kib == self.kdf.memory_kib
+            && kdf.iterations == self.kdf.iterations
+            && kdf.parallelism == self.kdf.parallelism
+    }
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<Aes256Gcm, String> {
+    if passphrase.is_empty() {
+        return Err("A passphrase is required".to_string());
+    }
+    if kdf.memory_kib > MEMORY_KIB * MAX_COST_FACTOR
+        || kdf.iterations > ITERATIONS * MAX_COST_FACTOR
+        || kdf.parallelism > PARALLELISM * MAX_COST_FACTOR
+    {
+        return Err(format!(
+            "Archive asks for more key derivation work than allowed ({} KiB, {} iterations, {} lanes)",
+            kdf.memory_kib, kdf.iterations, kdf.parallelism
+        ));
+    }
+    let salt = hex::decode(&kdf.salt).map_err(|_| "Invalid archive salt".to_string())?;
+    let params = argon2::Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+    let argon = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+    let mut key = [0u8; 32];
+    argon
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {}", e))?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+fn chunk_nonce(prefix: &[u8; 7], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..7].copy_from_slice(prefix);
+    nonce[7..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Read until `buf` is full or the reader is exhausted
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Encrypt `reader` into `writer` as an archive of kind `magic`
+pub fn seal<M: Serialize>(
+    magic: &[u8; 8],
+    metadata: M,
+    passphrase: &str,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<(), String> {
+    seal_with(magic, metadata, &Key::new(passphrase)?, reader, writer)
+}
+
+/// [`seal`] with a key derived beforehand
+pub fn seal_with<M: Serialize>(
+    magic: &[u8; 8],
+    metadata: M,
+    key: &Key,
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<(), String> {
+    let nonce_prefix: [u8; 7] = random_bytes();
+    let header = Header {
+        metadata,
+        encryption: Encryption {
+            cipher: "aes-256-gcm".to_string(),
+            kdf: key.kdf.clone(),
+            nonce_prefix: hex::encode(nonce_prefix),
+            chunk_size: CHUNK_SIZE as u32,
+        },
+    };
+    let header_bytes = serde_json::to_vec(&header)
+        .map_err(|e| format!("Failed to encode archive header: {}", e))?;
+    let cipher = &key.cipher;
+
+    let write_err = |e: std::io::Error| format!("Failed to write archive: {}", e);
+    writer.write_all(magic).map_err(write_err)?;
+    writer
+        .write_all(&(header_bytes.len() as u32).to_le_bytes())
+        .map_err(write_err)?;
+    writer.write_all(&header_bytes).map_err(write_err)?;
+
+    // A short (possibly empty) chunk always ends the stream
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut index: u32 = 0;
+    loop {
+        let len = read_full(&mut reader, &mut buf)
+            .map_err(|e| format!("Failed to read archive contents: {}", e))?;
+        let last = len < CHUNK_SIZE;
+        let nonce = chunk_nonce(&nonce_prefix, index, last);
+        let sealed = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &buf[..len],
+                    aad: &header_bytes,
+                },
+            )
+            .map_err(|_| "Encryption failed".to_string())?;
+        writer.write_all(&sealed).map_err(write_err)?;
+
+        if last {
+            break;
+        }
+        index = index
+            .checked_add(1)
+            .ok_or_else(|| "Archive is too large".to_string())?;
+    }
+    writer.flush().map_err(write_err)
+}
+
+/// Read an archive's cleartext header, leaving `reader` at the payload.
+/// Returns the parsed header and its raw bytes, needed to decrypt.
+pub fn read_header<M: DeserializeOwned>(
+    magic: &[u8; 8],
+    reader: &mut impl Read,
+) -> Result<(Header<M>, Vec<u8>), String> {
+    let read_err = |e: std::io::Error| format!("Failed to read archive: {}", e);
+
+    let mut found = [0u8; 8];
+    reader.read_exact(&mut found).map_err(read_err)?;
+    if &found != magic {
+        return Err("Not a recognized archive".to_string());
+    }
+
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).map_err(read_err)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_HEADER_SIZE {
+        return Err("Archive header is corrupt".to_string());
+    }
+
+    let mut header_bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut header_bytes).map_err(read_err)?;
+    let header = serde_json::from_slice(&header_bytes)
+        .map_err(|e| format!("Archive header is corrupt: {}", e))?;
+    Ok((header, header_bytes))
+}
+
+/// Decrypt the payload following a header read with [`read_header`]
+pub fn open<M>(
+    header: &Header<M>,
+    header_bytes: &[u8],
+    passphrase: &str,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<(), String> {
+    let key = Key::for_header(passphrase, header)?;
+    open_with(header, header_bytes, &key, reader, writer)
+}
+
+/// [`open`] with a key derived beforehand, see [`Key::fits`]
+pub fn open_with<M>(
+    header: &Header<M>,
+    header_bytes: &[u8],
+    key: &Key,
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<(), String> {
+    let encryption = &header.encryption;
+    if encryption.cipher != "aes-256-gcm" {
+        return Err(format!("Unsupported cipher: {}", encryption.cipher));
+    }
+    let nonce_prefix: [u8; 7] = hex::decode(&encryption.nonce_prefix)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "Archive header is corrupt".to_string())?;
+    let chunk_size = encryption.chunk_size as usize;
+    if chunk_size == 0 || chunk_size > 16 * CHUNK_SIZE {
+        return Err("Archive header is corrupt".to_string());
+    }
+    let cipher = &key.cipher;
+
+    let mut buf = vec![0u8; chunk_size + TAG_SIZE];
+    let mut index: u32 = 0;
+    loop {
+        let len = read_full(&mut reader, &mut buf)
+            .map_err(|e| format!("Failed to read archive: {}", e))?;
+        let last = len < buf.len();
+        let nonce = chunk_nonce(&nonce_prefix, index, last);
+        let plain = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &buf[..len],
+                    aad: header_bytes,
+                },
+            )
+            .map_err(|_| {
+                if index == 0 {
+                    "Wrong passphrase or corrupted archive".to_string()
+                } else {
+                    "Archive is corrupted or truncated".to_string()
+                }
+            })?;
+        writer
+            .write_all(&plain)
+            .map_err(|e| format!("Failed to write decrypted data: {}", e))?;
+
+        if last {
+            break;
+        }
+        index = index
+            .checked_add(1)
+            .ok_or_else(|| "Archive is too large".to_string())?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write decrypted data: {}", e))
+}
+
+/// Hex SHA-256 of everything `reader` yields
+pub fn sha256_hex(mut reader: impl Read) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to read data for checksum: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
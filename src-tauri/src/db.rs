@@ -0,0 +1,280 @@
+//! Embedded SQLite store for state the Rust side must keep across restarts
+//!
+//! This is separate from PocketBase: it holds queues and journals that have
+//! to work while PocketBase or the WhatsApp sidecar is down.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::Connection;
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how many
+/// have run, so only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: outbound WhatsApp queue
+    "CREATE TABLE whatsapp_outbox (
+        id TEXT PRIMARY KEY,
+        session TEXT NOT NULL,
+        recipient TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        message_id TEXT,
+        created_at INTEGER NOT NULL,
+        next_attempt_at INTEGER NOT NULL,
+        sent_at INTEGER
+    );
+    CREATE INDEX idx_whatsapp_outbox_due ON whatsapp_outbox (status, next_attempt_at);",
+    // 2: inbound WhatsApp events
+    "CREATE TABLE whatsapp_messages (
+        session TEXT NOT NULL,
+        id TEXT NOT NULL,
+        chat_id TEXT NOT NULL,
+        from_jid TEXT NOT NULL,
+        to_jid TEXT NOT NULL,
+        sender_name TEXT,
+        body TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        from_me INTEGER NOT NULL,
+        is_group INTEGER NOT NULL,
+        timestamp INTEGER,
+        received_at INTEGER NOT NULL,
+        PRIMARY KEY (session, id)
+    );
+    CREATE INDEX idx_whatsapp_messages_chat ON whatsapp_messages (session, chat_id, received_at);
+    CREATE TABLE whatsapp_acks (
+        message_id TEXT PRIMARY KEY,
+        session TEXT NOT NULL,
+        ack INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE whatsapp_session_state (
+        session TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );",
+    // 3: admin commands received over WhatsApp
+    "CREATE TABLE whatsapp_command_audit (
+        id TEXT PRIMARY KEY,
+        session TEXT NOT NULL,
+        message_id TEXT NOT NULL,
+        sender TEXT NOT NULL,
+        user_id TEXT,
+        user_name TEXT,
+        command TEXT NOT NULL,
+        body TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        detail TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX idx_whatsapp_command_audit_created ON whatsapp_command_audit (created_at);",
+    // 4: broadcast campaigns and marketing opt-outs
+    "CREATE TABLE whatsapp_campaigns (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        session TEXT NOT NULL,
+        template TEXT NOT NULL,
+        audience TEXT NOT NULL,
+        interval_secs INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        scheduled_at INTEGER NOT NULL,
+        started_at INTEGER,
+        finished_at INTEGER,
+        last_error TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX idx_whatsapp_campaigns_due ON whatsapp_campaigns (status, scheduled_at);
+    CREATE TABLE whatsapp_campaign_recipients (
+        campaign_id TEXT NOT NULL REFERENCES whatsapp_campaigns (id) ON DELETE CASCADE,
+        phone TEXT NOT NULL,
+        customer_id TEXT NOT NULL,
+        customer_name TEXT NOT NULL,
+        message TEXT NOT NULL,
+        status TEXT NOT NULL,
+        outbox_id TEXT,
+        error TEXT,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, phone)
+    );
+    CREATE INDEX idx_whatsapp_campaign_recipients_status ON whatsapp_campaign_recipients (status);
+    CREATE TABLE whatsapp_opt_outs (
+        phone TEXT PRIMARY KEY,
+        session TEXT NOT NULL,
+        keyword TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );",
+    // 5: WhatsApp Business catalog sync state
+    "CREATE TABLE whatsapp_catalog_items (
+        session TEXT NOT NULL,
+        product_id TEXT NOT NULL,
+        sku TEXT NOT NULL,
+        catalog_id TEXT,
+        content_hash TEXT,
+        image_url TEXT,
+        image_hash TEXT,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        next_attempt_at INTEGER NOT NULL DEFAULT 0,
+        synced_at INTEGER,
+        PRIMARY KEY (session, product_id)
+    );
+    CREATE TABLE whatsapp_catalog_runs (
+        id TEXT PRIMARY KEY,
+        session TEXT NOT NULL,
+        trigger TEXT NOT NULL,
+        status TEXT NOT NULL,
+        added INTEGER NOT NULL DEFAULT 0,
+        updated INTEGER NOT NULL DEFAULT 0,
+        deleted INTEGER NOT NULL DEFAULT 0,
+        unchanged INTEGER NOT NULL DEFAULT 0,
+        skipped INTEGER NOT NULL DEFAULT 0,
+        failed INTEGER NOT NULL DEFAULT 0,
+        deferred INTEGER NOT NULL DEFAULT 0,
+        error TEXT,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER
+    );
+    CREATE INDEX idx_whatsapp_catalog_runs_started ON whatsapp_catalog_runs (started_at);",
+    // 6: POS sales journalled for replay to PocketBase
+    "CREATE TABLE pos_journal (
+        id TEXT PRIMARY KEY,
+        transaction_id TEXT NOT NULL,
+        shift_id TEXT NOT NULL,
+        payment_method TEXT NOT NULL,
+        total REAL NOT NULL,
+        item_count INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        conflict TEXT,
+        warnings TEXT NOT NULL DEFAULT '[]',
+        invoice_id TEXT,
+        invoice_number TEXT,
+        created_at INTEGER NOT NULL,
+        next_attempt_at INTEGER NOT NULL,
+        synced_at INTEGER
+    );
+    CREATE INDEX idx_pos_journal_due ON pos_journal (status, next_attempt_at);
+    CREATE TABLE pos_journal_ops (
+        sale_id TEXT NOT NULL REFERENCES pos_journal (id) ON DELETE CASCADE,
+        seq INTEGER NOT NULL,
+        op TEXT NOT NULL,
+        done_at INTEGER,
+        PRIMARY KEY (sale_id, seq)
+    );",
+    // 7: backups of PocketBase's data directory
+    "CREATE TABLE backups (
+        id TEXT PRIMARY KEY,
+        file_name TEXT NOT NULL,
+        directory TEXT NOT NULL,
+        trigger TEXT NOT NULL,
+        status TEXT NOT NULL,
+        size INTEGER,
+        sha256 TEXT,
+        files INTEGER,
+        missing_files INTEGER,
+        error TEXT,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER
+    );
+    CREATE INDEX idx_backups_started ON backups (started_at);",
+    // 8: copies of backups at their destinations
+    "CREATE TABLE backup_copies (
+        backup_id TEXT NOT NULL REFERENCES backups (id) ON DELETE CASCADE,
+        destination_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        location TEXT,
+        upload TEXT,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        error TEXT,
+        next_attempt_at INTEGER NOT NULL,
+        finished_at INTEGER,
+        PRIMARY KEY (backup_id, destination_id)
+    );
+    CREATE INDEX idx_backup_copies_due ON backup_copies (status, next_attempt_at);",
+    // 9: PocketBase write-ahead log shipped for point-in-time recovery
+    "CREATE TABLE wal_generations (
+        id TEXT PRIMARY KEY,
+        destination_id TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER,
+        detached_fingerprint TEXT
+    );
+    CREATE TABLE wal_segments (
+        generation_id TEXT NOT NULL REFERENCES wal_generations (id) ON DELETE CASCADE,
+        seq INTEGER NOT NULL,
+        file_name TEXT NOT NULL,
+        frames INTEGER NOT NULL,
+        size INTEGER NOT NULL,
+        sha256 TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        error TEXT,
+        created_at INTEGER NOT NULL,
+        next_attempt_at INTEGER NOT NULL,
+        shipped_at INTEGER,
+        PRIMARY KEY (generation_id, seq)
+    );
+    CREATE INDEX idx_wal_segments_due ON wal_segments (status, next_attempt_at);",
+    // 10: values POS journal increments write, planned before writing them
+    "ALTER TABLE pos_journal_ops ADD COLUMN planned TEXT;",
+];
+
+pub struct LocalDb {
+    conn: Mutex<Connection>,
+}
+
+impl LocalDb {
+    /// Open (or create) the database and bring its schema up to date
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let mut conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")
+            .map_err(|e| format!("Failed to configure local database: {}", e))?;
+        migrate(&mut conn).map_err(|e| format!("Failed to migrate local database: {}", e))?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Run `f` with exclusive access to the connection
+    pub fn with<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, String> {
+        let mut conn = self.conn.lock().unwrap();
+        f(&mut conn).map_err(|e| format!("Local database error: {}", e))
+    }
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+        log::info!("Applied local database migration {}", index + 1);
+    }
+    Ok(())
+}
+
+/// Current time as Unix milliseconds, the timestamp format used in this store
+pub fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Random 128-bit id rendered as hex
+pub fn new_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
@@ -1,126 +1,29 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
-use tauri::Emitter;
-use tauri_plugin_shell::process::CommandChild;
+use std::sync::Arc;
 
-static SIDECAR_RUNNING: AtomicBool = AtomicBool::new(false);
+use tauri::{Manager, RunEvent};
 
-/// Check if WPPConnect sidecar is healthy
-async fn check_sidecar_health() -> bool {
-    match reqwest::get("http://127.0.0.1:21465/health").await {
-        Ok(response) => response.status().is_success(),
-        Err(_) => false,
-    }
-}
+mod backup;
+mod crypto;
+mod db;
+mod order_parser;
+mod pocketbase;
+mod pos;
+mod settings;
+mod sidecar;
+#[cfg(test)]
+mod testing;
+mod whatsapp;
 
-/// Start the WPPConnect sidecar process
-fn start_sidecar(app: &tauri::AppHandle) -> Result<CommandChild, String> {
-    use tauri_plugin_shell::ShellExt;
-    
-    let sidecar = app
-        .shell()
-        .sidecar("wppconnect-server")
-        .map_err(|e| format!("Failed to create sidecar command: {}", e))?;
-    
-    let (mut rx, child) = sidecar
-        .spawn()
-        .map_err(|e| format!("Failed to spawn sidecar: {}", e))?;
-    
-    // Log sidecar output in background
-    tauri::async_runtime::spawn(async move {
-        use tauri_plugin_shell::process::CommandEvent;
-        while let Some(event) = rx.recv().await {
-            match event {
-                CommandEvent::Stdout(line) => {
-                    log::info!("[WPPConnect] {}", String::from_utf8_lossy(&line));
-                }
-                CommandEvent::Stderr(line) => {
-                    log::warn!("[WPPConnect] {}", String::from_utf8_lossy(&line));
-                }
-                CommandEvent::Terminated(payload) => {
-                    log::warn!("[WPPConnect] Terminated with code: {:?}", payload.code);
-                    SIDECAR_RUNNING.store(false, Ordering::SeqCst);
-                }
-                _ => {}
-            }
-        }
-    });
-    
-    SIDECAR_RUNNING.store(true, Ordering::SeqCst);
-    Ok(child)
-}
-
-/// Health monitoring loop - restarts sidecar if it crashes
-async fn health_monitor_loop(app: tauri::AppHandle) {
-    let mut consecutive_failures = 0;
-    
-    loop {
-        tokio::time::sleep(Duration::from_secs(5)).await;
-        
-        if !SIDECAR_RUNNING.load(Ordering::SeqCst) {
-            // Sidecar not running, try to start it
-            log::info!("Sidecar not running, attempting to start...");
-            match start_sidecar(&app) {
-                Ok(_) => {
-                    log::info!("Sidecar started successfully");
-                    consecutive_failures = 0;
-                    let _ = app.emit("sidecar-status", "started");
-                }
-                Err(e) => {
-                    log::error!("Failed to start sidecar: {}", e);
-                    consecutive_failures += 1;
-                    let _ = app.emit("sidecar-status", "error");
-                }
-            }
-            continue;
-        }
-        
-        // Check health
-        if check_sidecar_health().await {
-            consecutive_failures = 0;
-            let _ = app.emit("sidecar-status", "healthy");
-        } else {
-            consecutive_failures += 1;
-            log::warn!("Sidecar health check failed ({} consecutive)", consecutive_failures);
-            
-            if consecutive_failures >= 3 {
-                log::error!("Sidecar appears to be dead, marking for restart");
-                SIDECAR_RUNNING.store(false, Ordering::SeqCst);
-                let _ = app.emit("sidecar-status", "restarting");
-            }
-        }
-    }
-}
-
-/// Tauri command to get sidecar status
-#[tauri::command]
-async fn get_sidecar_status() -> Result<serde_json::Value, String> {
-    let is_running = SIDECAR_RUNNING.load(Ordering::SeqCst);
-    let is_healthy = if is_running {
-        check_sidecar_health().await
-    } else {
-        false
-    };
-    
-    Ok(serde_json::json!({
-        "running": is_running,
-        "healthy": is_healthy
-    }))
-}
-
-/// Tauri command to restart sidecar
-#[tauri::command]
-async fn restart_sidecar(app: tauri::AppHandle) -> Result<String, String> {
-    SIDECAR_RUNNING.store(false, Ordering::SeqCst);
-    
-    // Give it a moment to stop
-    tokio::time::sleep(Duration::from_secs(2)).await;
-    
-    match start_sidecar(&app) {
-        Ok(_) => Ok("Sidecar restarted".to_string()),
-        Err(e) => Err(e),
-    }
-}
+use backup::Backups;
+use db::LocalDb;
+use order_parser::OrderParser;
+use pocketbase::PocketBaseClient;
+use pos::PosJournal;
+use settings::SettingsStore;
+use sidecar::SidecarSupervisor;
+use whatsapp::{
+    Campaigns, CatalogSync, Commander, EventStore, Outbox, SessionRegistry, WhatsAppClient,
+};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -135,27 +38,162 @@ pub fn run() {
                         .build(),
                 )?;
             }
-            
-            // Start sidecar on app launch
-            let app_handle = app.handle().clone();
-            match start_sidecar(&app_handle) {
-                Ok(_) => log::info!("WPPConnect sidecar started"),
-                Err(e) => log::warn!("Failed to start sidecar: {} (will retry)", e),
+
+            // Local store for queues that must survive sidecar outages
+            let db = Arc::new(LocalDb::open(
+                &app.path().app_data_dir()?.join("luminila.db"),
+            )?);
+            app.manage(Outbox::new(db.clone(), whatsapp::RateLimits::default()));
+            app.manage(EventStore::new(db.clone()));
+            app.manage(Commander::new(db.clone()));
+            app.manage(Campaigns::new(db.clone()));
+            app.manage(PosJournal::new(db.clone()));
+            app.manage(db.clone());
+
+            // Register sidecars. PocketBase goes first so the UI has a
+            // backend as soon as possible.
+            let supervisor = SidecarSupervisor::new(
+                app.path().app_data_dir()?.join("sidecars"),
+                app.path().app_log_dir()?.join("sidecars"),
+            );
+            supervisor.register(pocketbase::sidecar_spec(app.handle())?);
+            app.manage(PocketBaseClient::new(
+                supervisor.http_client(),
+                pocketbase::ADDR,
+            ));
+            app.manage(OrderParser::default());
+            let settings_store = SettingsStore::open(settings::path(app.handle())?);
+            let settings = settings_store.get();
+            app.manage(settings_store);
+            let backups = Backups::new(
+                db.clone(),
+                settings.backup.clone(),
+                pocketbase::data_dir(app.handle())?,
+                app.path().app_data_dir()?.join("backups"),
+                app.path().app_config_dir()?.join("backup.key"),
+                supervisor.http_client(),
+            );
+            if let Err(e) = backups.migrate_secrets(&app.state::<SettingsStore>()) {
+                log::warn!("Failed to move S3 secret keys out of the settings: {}", e);
             }
-            
-            // Start health monitoring loop
-            let app_handle = app.handle().clone();
-            tauri::async_runtime::spawn(async move {
-                health_monitor_loop(app_handle).await;
-            });
-            
+            // Ship PocketBase's log only while it runs, and let go of its
+            // database before it is stopped for a restore
+            let wal = backups.wal();
+            supervisor.on_lifecycle(pocketbase::SIDECAR_NAME, move |event| {
+                wal.on_lifecycle(event)
+            })?;
+            app.manage(backups);
+            let whatsapp_port = match settings.whatsapp_port {
+                Some(port) => port,
+                None => sidecar::pick_port(whatsapp::DEFAULT_PORT)?,
+            };
+            let whatsapp_token = whatsapp::generate_token();
+            supervisor.register(whatsapp::sidecar_spec(
+                whatsapp_port,
+                &whatsapp_token,
+                whatsapp::data_dir(app.handle())?,
+            ));
+            let handle = app.handle().clone();
+            supervisor.on_details(whatsapp::SIDECAR_NAME, move || {
+                whatsapp::sidecar_details(&handle)
+            })?;
+            app.manage(WhatsAppClient::new(
+                supervisor.http_client(),
+                whatsapp_port,
+                &whatsapp_token,
+            ));
+            app.manage(CatalogSync::new(
+                db,
+                settings.whatsapp_catalog,
+                supervisor.http_client(),
+            ));
+            app.manage(SessionRegistry::new(settings.whatsapp_sessions));
+            app.manage(supervisor);
+
+            // Start sidecars and their health monitoring loops
+            sidecar::start_all(app.handle());
+            tauri::async_runtime::spawn(pos::run_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(backup::run_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(backup::run_wal_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(whatsapp::run_outbox_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(whatsapp::run_event_stream(app.handle().clone()));
+            tauri::async_runtime::spawn(whatsapp::run_campaign_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(whatsapp::run_catalog_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(whatsapp::run_session_manager(app.handle().clone()));
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
-            get_sidecar_status,
-            restart_sidecar
+            sidecar::commands::get_sidecar_status,
+            sidecar::commands::wait_for_sidecar_ready,
+            sidecar::commands::get_sidecar_url,
+            sidecar::commands::list_sidecars,
+            sidecar::commands::restart_sidecar,
+            sidecar::commands::reset_sidecar_breaker,
+            sidecar::commands::tail_sidecar_log,
+            sidecar::commands::search_sidecar_log,
+            sidecar::commands::export_sidecar_logs,
+            whatsapp::commands::whatsapp_request,
+            whatsapp::commands::whatsapp_start_session,
+            whatsapp::commands::whatsapp_session_status,
+            whatsapp::commands::whatsapp_session_qr,
+            whatsapp::commands::whatsapp_send_message,
+            whatsapp::commands::whatsapp_send_file,
+            whatsapp::commands::whatsapp_get_chats,
+            whatsapp::commands::whatsapp_get_messages,
+            whatsapp::commands::whatsapp_check_number,
+            whatsapp::commands::whatsapp_enqueue_message,
+            whatsapp::commands::whatsapp_enqueue_file,
+            whatsapp::commands::whatsapp_outbox_list,
+            whatsapp::commands::whatsapp_outbox_pending_count,
+            whatsapp::commands::whatsapp_outbox_retry,
+            whatsapp::commands::whatsapp_outbox_cancel,
+            whatsapp::commands::whatsapp_stored_messages,
+            whatsapp::commands::whatsapp_command_audit,
+            whatsapp::commands::whatsapp_campaign_preview,
+            whatsapp::commands::whatsapp_campaign_create,
+            whatsapp::commands::whatsapp_campaign_list,
+            whatsapp::commands::whatsapp_campaign_report,
+            whatsapp::commands::whatsapp_campaign_cancel,
+            whatsapp::commands::whatsapp_opt_outs,
+            whatsapp::commands::whatsapp_remove_opt_out,
+            whatsapp::commands::whatsapp_catalog_sync,
+            whatsapp::commands::whatsapp_catalog_status,
+            whatsapp::commands::whatsapp_catalog_runs,
+            whatsapp::commands::whatsapp_list_sessions,
+            whatsapp::commands::whatsapp_save_session,
+            whatsapp::commands::whatsapp_remove_session,
+            whatsapp::commands::whatsapp_backup_sessions,
+            whatsapp::commands::whatsapp_inspect_session_backup,
+            whatsapp::commands::whatsapp_restore_sessions,
+            pocketbase::commands::pocketbase_set_auth,
+            pos::commands::pos_record_sale,
+            pos::commands::pos_journal_list,
+            pos::commands::pos_journal_pending_count,
+            pos::commands::pos_journal_uninvoiced,
+            pos::commands::pos_journal_set_invoice,
+            pos::commands::pos_journal_retry,
+            pos::commands::pos_journal_discard,
+            backup::commands::backup_now,
+            backup::commands::backup_list,
+            backup::commands::backup_status,
+            backup::commands::backup_copies,
+            backup::commands::backup_save_settings,
+            backup::commands::backup_test_destination,
+            backup::commands::backup_set_passphrase,
+            backup::commands::backup_inspect,
+            backup::commands::backup_verify,
+            backup::commands::backup_restore,
+            backup::commands::backup_restore_point_in_time,
+            order_parser::commands::parse_order_message
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Don't leave sidecars holding their ports after we exit
+            if let RunEvent::Exit = event {
+                let supervisor = app.state::<SidecarSupervisor>();
+                tauri::async_runtime::block_on(supervisor.stop_all(app));
+            }
+        });
 }
-
@@ -0,0 +1,18 @@
+use tauri::State;
+
+use super::{DraftOrder, OrderParser};
+use crate::pocketbase::{PocketBaseClient, PocketBaseError};
+
+/// Tauri command to read a draft order out of a customer message, matched
+/// against the product catalog
+#[tauri::command]
+pub async fn parse_order_message(
+    parser: State<'_, OrderParser>,
+    client: State<'_, PocketBaseClient>,
+    message: String,
+    refresh: Option<bool>,
+) -> Result<DraftOrder, PocketBaseError> {
+    parser
+        .parse(&client, &message, refresh.unwrap_or(false))
+        .await
+}
@@ -0,0 +1,240 @@
+//! Word lists for English and Hinglish order messages
+//!
+//! Hinglish spellings vary a lot ("chahiye", "chaiye", "chahie"), so common
+//! variants are listed explicitly; anything else is left to fuzzy matching.
+
+/// Spelled-out quantities
+pub fn number_word(word: &str) -> Option<u32> {
+    Some(match word {
+        "one" | "single" | "ek" | "1pc" => 1,
+        "two" | "do" | "dono" | "couple" => 2,
+        "three" | "teen" | "tin" => 3,
+        "four" | "char" | "chaar" | "chār" => 4,
+        "five" | "paanch" | "panch" | "pach" => 5,
+        "six" | "chhe" | "che" | "chah" | "chhah" => 6,
+        "seven" | "saat" | "sat" => 7,
+        "eight" | "aath" | "ath" => 8,
+        "nine" | "nau" => 9,
+        "ten" | "das" => 10,
+        "dozen" | "darjan" | "dazan" => 12,
+        _ => return None,
+    })
+}
+
+/// Words that mark the number before or after them as a quantity
+pub fn is_quantity_unit(word: &str) -> bool {
+    matches!(
+        word,
+        "pc" | "pcs"
+            | "piece"
+            | "pieces"
+            | "peice"
+            | "peices"
+            | "nos"
+            | "qty"
+            | "quantity"
+            | "units"
+            | "unit"
+            | "set"
+            | "sets"
+            | "pair"
+            | "pairs"
+            | "jodi"
+            | "jode"
+            | "joda"
+            | "x"
+    )
+}
+
+/// Words that mark the following number as a price, not a quantity
+pub fn is_price_marker(word: &str) -> bool {
+    matches!(
+        word,
+        "rs" | "inr" | "₹" | "rupees" | "rupaye" | "rupay" | "price" | "mrp"
+    )
+}
+
+/// Words that introduce a size
+pub fn is_size_marker(word: &str) -> bool {
+    matches!(word, "size" | "sz" | "saiz" | "length" | "no.")
+}
+
+/// Units that follow a size value ("18 inch")
+pub fn is_size_unit(word: &str) -> bool {
+    matches!(word, "inch" | "inches" | "in" | "cm" | "mm")
+}
+
+/// Canonical English colour for an English or Hindi colour word
+pub fn colour(word: &str) -> Option<&'static str> {
+    Some(match word {
+        "red" | "laal" | "lal" => "red",
+        "green" | "hara" | "hari" | "hare" => "green",
+        "blue" | "neela" | "nila" | "neeli" | "nili" => "blue",
+        "black" | "kala" | "kaala" | "kaali" | "kali" => "black",
+        "white" | "safed" | "sufaid" | "safaid" => "white",
+        "gold" | "golden" | "sona" | "sunehra" | "sunhara" => "gold",
+        "silver" | "chandi" | "chaandi" => "silver",
+        "pink" | "gulabi" => "pink",
+        "yellow" | "peela" | "pila" | "peeli" | "pili" => "yellow",
+        "maroon" | "mehroon" => "maroon",
+        "purple" | "jamuni" | "baingani" => "purple",
+        "orange" | "narangi" | "kesari" => "orange",
+        "brown" | "bhura" => "brown",
+        "oxidised" | "oxidized" | "oxide" => "oxidised",
+        "multicolor" | "multicolour" | "multi" => "multicolour",
+        "rosegold" => "rose gold",
+        _ => return None,
+    })
+}
+
+/// Canonical product word for Hindi names and spelling variants, so
+/// "jhumki", "jhumka" and "jhumkas" all match a "Jhumka" product
+pub fn canonical_product_word(word: &str) -> &str {
+    match word {
+        "earring" | "earrings" | "earing" | "earings" | "eartop" | "tops" | "studs" => "earring",
+        "jhumka" | "jhumki" | "jhumkas" | "jhumke" | "jhumkey" => "jhumka",
+        "baali" | "bali" | "baliyan" | "hoops" | "hoop" => "hoop",
+        "necklace" | "necklaces" | "neckless" | "necklce" | "haar" | "har" | "mala" => "necklace",
+        "bangle" | "bangles" | "kangan" | "chudi" | "choodi" | "chudiyan" | "churi" => "bangle",
+        "bracelet" | "bracelets" | "braclet" | "kada" | "kadaa" => "bracelet",
+        "anklet" | "anklets" | "payal" | "paayal" | "pajeb" => "anklet",
+        "ring" | "rings" | "angoothi" | "anguthi" | "angothi" => "ring",
+        "chain" | "chains" | "chen" => "chain",
+        "pendant" | "pendants" | "pendent" | "locket" => "pendant",
+        "nosering" | "nath" | "nathni" | "nosepin" | "laung" => "nosepin",
+        "tikka" | "teeka" | "tika" | "maangtikka" => "tikka",
+        "mangalsutra" | "mangalsutr" => "mangalsutra",
+        _ => word,
+    }
+}
+
+/// Filler words that carry no product information
+pub fn is_stopword(word: &str) -> bool {
+    matches!(
+        word,
+        "i" | "me"
+            | "my"
+            | "we"
+            | "you"
+            | "a"
+            | "an"
+            | "the"
+            | "of"
+            | "for"
+            | "to"
+            | "and"
+            | "with"
+            | "in"
+            | "is"
+            | "it"
+            | "this"
+            | "that"
+            | "these"
+            | "those"
+            | "please"
+            | "pls"
+            | "plz"
+            | "kindly"
+            | "want"
+            | "need"
+            | "order"
+            | "buy"
+            | "book"
+            | "send"
+            | "get"
+            | "give"
+            | "also"
+            | "some"
+            | "more"
+            | "each"
+            | "same"
+            | "like"
+            | "hi"
+            | "hello"
+            | "hey"
+            | "sir"
+            | "madam"
+            | "mam"
+            | "maam"
+            | "bhaiya"
+            | "bhai"
+            | "didi"
+            | "ji"
+            | "mujhe"
+            | "muje"
+            | "mereko"
+            | "humko"
+            | "hume"
+            | "chahiye"
+            | "chaiye"
+            | "chahie"
+            | "chahiyeh"
+            | "chiye"
+            | "bhejo"
+            | "bhej"
+            | "bhejdo"
+            | "bhejna"
+            | "dedo"
+            | "de"
+            | "dena"
+            | "lena"
+            | "leni"
+            | "lene"
+            | "hai"
+            | "hain"
+            | "h"
+            | "ka"
+            | "ki"
+            | "ke"
+            | "ko"
+            | "wala"
+            | "wali"
+            | "wale"
+            | "waala"
+            | "waali"
+            | "waale"
+            | "mein"
+            | "mai"
+            | "aur"
+            | "bhi"
+            | "ye"
+            | "yeh"
+            | "vo"
+            | "woh"
+            | "wo"
+            | "kar"
+            | "karo"
+            | "karna"
+            | "karni"
+            | "krdo"
+            | "kardo"
+            | "se"
+            | "thanks"
+            | "thank"
+            | "thx"
+            | "ok"
+            | "okay"
+            | "kya"
+            | "kitna"
+            | "kitne"
+    )
+}
+
+/// Words that signal the customer wants to buy rather than ask
+pub const ORDER_INTENT_WORDS: &[&str] = &[
+    "order", "buy", "purchase", "want", "need", "book", "confirm", "chahiye", "chaiye", "chahie",
+    "chiye", "bhejo", "bhej", "bhejdo", "dedo", "lena", "leni", "lene", "send",
+];
+
+/// Word pairs that read as one token ("rose gold", "maang tikka")
+pub fn join_pair(first: &str, second: &str) -> Option<&'static str> {
+    Some(match (first, second) {
+        ("rose", "gold") => "rosegold",
+        ("maang", "tikka") | ("mang", "tikka") | ("maang", "teeka") => "maangtikka",
+        ("nose", "ring") => "nosering",
+        ("nose", "pin") => "nosepin",
+        ("free", "size") => "freesize",
+        ("ear", "rings") | ("ear", "ring") => "earring",
+        _ => return None,
+    })
+}
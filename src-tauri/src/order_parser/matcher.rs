@@ -0,0 +1,166 @@
+//! Fuzzy matching of requested items against the product catalog
+
+use super::lexicon;
+use super::parser::{tokenize, RequestedItem};
+use super::CatalogEntry;
+
+/// Word similarity needed for two words to count as the same
+const WORD_MATCH: f64 = 0.75;
+const ATTRIBUTE_BONUS: f64 = 0.15;
+const ATTRIBUTE_PENALTY: f64 = 0.2;
+
+/// Catalog entry with its name pre-tokenized for matching
+pub struct IndexedEntry {
+    pub entry: CatalogEntry,
+    words: Vec<String>,
+    sku: String,
+    product_sku: String,
+}
+
+fn normalize_sku(sku: &str) -> String {
+    sku.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn name_words(text: &str) -> Vec<String> {
+    tokenize(&text.to_lowercase())
+        .into_iter()
+        .filter(|word| !lexicon::is_stopword(word) && word.chars().any(char::is_alphabetic))
+        .map(|word| lexicon::canonical_product_word(&word).to_string())
+        .collect()
+}
+
+impl IndexedEntry {
+    pub fn new(entry: CatalogEntry) -> Self {
+        let mut text = entry.name.clone();
+        if let Some(variant) = &entry.variant_name {
+            text.push(' ');
+            text.push_str(variant);
+        }
+        Self {
+            words: name_words(&text),
+            sku: normalize_sku(&entry.sku),
+            product_sku: normalize_sku(&entry.product_sku),
+            entry,
+        }
+    }
+}
+
+/// Normalized Levenshtein similarity in `0.0..=1.0`
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+fn best_word_match(word: &str, candidates: &[String]) -> f64 {
+    candidates
+        .iter()
+        .map(|candidate| similarity(word, candidate))
+        .filter(|score| *score >= WORD_MATCH)
+        .fold(0.0, f64::max)
+}
+
+/// How well the query words cover the name, with fuzzy word matches. Mostly
+/// weighted on the query: customers name a product by one or two of its
+/// words ("jhumka"), so unmentioned name words only cost a little.
+fn words_score(query: &[String], name: &[String]) -> f64 {
+    if query.is_empty() || name.is_empty() {
+        return 0.0;
+    }
+    let from_query: f64 = query.iter().map(|word| best_word_match(word, name)).sum();
+    let from_name: f64 = name.iter().map(|word| best_word_match(word, query)).sum();
+    0.7 * from_query / query.len() as f64 + 0.3 * from_name / name.len() as f64
+}
+
+fn sku_score(item: &RequestedItem, indexed: &IndexedEntry) -> f64 {
+    let Some(sku) = item.sku.as_deref().map(normalize_sku) else {
+        return 0.0;
+    };
+    if sku == indexed.sku {
+        1.0
+    } else if sku == indexed.product_sku {
+        // Product SKU without a variant suffix: right product, variant unknown
+        0.85
+    } else {
+        let score = similarity(&sku, &indexed.sku);
+        if score >= 0.85 {
+            score * 0.9
+        } else {
+            0.0
+        }
+    }
+}
+
+fn normalize_size(size: &str) -> String {
+    size.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '.')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// +bonus if the entry has the requested attribute, -penalty if it has a
+/// different value, 0 if it doesn't say
+fn attribute_adjustment(requested: Option<&str>, actual: Option<&str>, matches: bool) -> f64 {
+    match (requested, actual) {
+        (Some(_), _) if matches => ATTRIBUTE_BONUS,
+        (Some(_), Some(actual)) if !actual.is_empty() => -ATTRIBUTE_PENALTY,
+        _ => 0.0,
+    }
+}
+
+/// Score how well `indexed` matches `item`, in `0.0..=1.0`
+pub fn score(item: &RequestedItem, indexed: &IndexedEntry) -> f64 {
+    let entry = &indexed.entry;
+
+    // Product names often include the colour ("Red Stone Jhumka")
+    let mut with_colour = item.words.clone();
+    if let Some(colour) = &item.colour {
+        with_colour.extend(name_words(colour));
+    }
+    let name =
+        words_score(&item.words, &indexed.words).max(words_score(&with_colour, &indexed.words));
+    let base = sku_score(item, indexed).max(name);
+    if base == 0.0 {
+        return 0.0;
+    }
+
+    let colour_matches = item.colour.as_deref().is_some_and(|colour| {
+        entry
+            .color
+            .as_deref()
+            .is_some_and(|c| c.to_lowercase().contains(colour))
+            || indexed.words.iter().any(|word| word == colour)
+    });
+    let size_matches = item.size.as_deref().is_some_and(|size| {
+        entry
+            .size
+            .as_deref()
+            .is_some_and(|s| normalize_size(s) == normalize_size(size))
+    });
+
+    let adjusted =
+        base + attribute_adjustment(
+            item.colour.as_deref(),
+            entry.color.as_deref(),
+            colour_matches,
+        ) + attribute_adjustment(item.size.as_deref(), entry.size.as_deref(), size_matches);
+    adjusted.clamp(0.0, 1.0)
+}
@@ -0,0 +1,322 @@
+//! Order extraction from WhatsApp messages
+//!
+//! Customers write orders as free text, mixing English and Hinglish ("2 red
+//! jhumka aur ek gold chain 18 inch chahiye"). The message is split into
+//! requested items, each item's quantity, SKU, size and colour are pulled
+//! out, and the rest is fuzzy-matched against the product catalog. The
+//! result is a draft the orders/create page pre-fills for staff to review;
+//! nothing here creates an order.
+
+pub mod commands;
+mod lexicon;
+mod matcher;
+mod parser;
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::pocketbase::{PocketBaseClient, PocketBaseError};
+use matcher::IndexedEntry;
+
+/// Scores below this are not offered as a match
+const MATCH_THRESHOLD: f64 = 0.45;
+/// Scores below this are not offered as an alternative
+const ALTERNATIVE_THRESHOLD: f64 = 0.35;
+const MAX_ALTERNATIVES: usize = 3;
+/// How close a different product must score to make a match ambiguous
+const AMBIGUITY_MARGIN: f64 = 0.05;
+/// How long a loaded catalog is reused before it is fetched again
+const CATALOG_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// One sellable item: a product variant, or a product without variants
+#[derive(Debug, Clone)]
+pub struct CatalogEntry {
+    pub product_id: String,
+    pub variant_id: Option<String>,
+    pub name: String,
+    pub variant_name: Option<String>,
+    /// Base product SKU
+    pub product_sku: String,
+    /// Full SKU, including the variant suffix
+    pub sku: String,
+    pub size: Option<String>,
+    pub color: Option<String>,
+    pub price: f64,
+    pub stock: Option<i64>,
+}
+
+/// Catalog item a draft line was matched to
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineMatch {
+    pub product_id: String,
+    pub variant_id: Option<String>,
+    pub sku: String,
+    pub name: String,
+    pub variant_name: Option<String>,
+    pub size: Option<String>,
+    pub color: Option<String>,
+    pub unit_price: f64,
+    pub stock: Option<i64>,
+    pub score: f64,
+}
+
+impl LineMatch {
+    fn new(entry: &CatalogEntry, score: f64) -> Self {
+        Self {
+            product_id: entry.product_id.clone(),
+            variant_id: entry.variant_id.clone(),
+            sku: entry.sku.clone(),
+            name: entry.name.clone(),
+            variant_name: entry.variant_name.clone(),
+            size: entry.size.clone(),
+            color: entry.color.clone(),
+            unit_price: entry.price,
+            stock: entry.stock,
+            score: round(score),
+        }
+    }
+}
+
+/// One line of a draft order
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftLine {
+    /// The part of the message this line was read from
+    pub text: String,
+    pub quantity: u32,
+    /// False when the customer gave no quantity and 1 was assumed
+    pub quantity_given: bool,
+    pub sku: Option<String>,
+    pub size: Option<String>,
+    pub color: Option<String>,
+    /// Best catalog match, if any scored high enough
+    pub matched: Option<LineMatch>,
+    /// Next best candidates, for staff to pick from
+    pub alternatives: Vec<LineMatch>,
+    /// How sure the parser is about this line, from 0 to 1
+    pub confidence: f64,
+}
+
+/// Draft order read from a message
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftOrder {
+    /// The message asks to buy something we could match
+    pub is_order: bool,
+    /// "purchase" or "inquiry"
+    pub intent: String,
+    pub lines: Vec<DraftLine>,
+    /// Lowest line confidence, 0 without lines
+    pub confidence: f64,
+}
+
+fn round(score: f64) -> f64 {
+    (score * 100.0).round() / 100.0
+}
+
+fn match_item(item: &parser::RequestedItem, catalog: &[IndexedEntry]) -> DraftLine {
+    let mut scored: Vec<(f64, &CatalogEntry)> = catalog
+        .iter()
+        .map(|indexed| (matcher::score(item, indexed), &indexed.entry))
+        .filter(|(score, _)| *score >= ALTERNATIVE_THRESHOLD)
+        .collect();
+    // Best first; among equals prefer what is in stock
+    scored.sort_by(|(a, a_entry), (b, b_entry)| {
+        b.total_cmp(a).then_with(|| {
+            let in_stock = |entry: &CatalogEntry| entry.stock.map_or(true, |stock| stock > 0);
+            in_stock(b_entry).cmp(&in_stock(a_entry))
+        })
+    });
+
+    let mut line = DraftLine {
+        text: item.text.clone(),
+        quantity: item.quantity.unwrap_or(1),
+        quantity_given: item.quantity.is_some(),
+        sku: item.sku.clone(),
+        size: item.size.clone(),
+        color: item.colour.clone(),
+        matched: None,
+        alternatives: Vec::new(),
+        confidence: 0.0,
+    };
+
+    let Some(&(best, best_entry)) = scored
+        .first()
+        .filter(|(score, _)| *score >= MATCH_THRESHOLD)
+    else {
+        line.alternatives = scored
+            .iter()
+            .take(MAX_ALTERNATIVES)
+            .map(|(score, entry)| LineMatch::new(entry, *score))
+            .collect();
+        return line;
+    };
+
+    let mut confidence = best;
+    if !line.quantity_given {
+        confidence *= 0.9;
+    }
+    if let Some((runner_up, runner_up_entry)) = scored.get(1) {
+        if best - runner_up <= AMBIGUITY_MARGIN {
+            // A close different product is a real doubt; a close variant of
+            // the same product only means size or colour wasn't given
+            confidence *= if runner_up_entry.product_id != best_entry.product_id {
+                0.8
+            } else {
+                0.9
+            };
+        }
+    }
+
+    line.matched = Some(LineMatch::new(best_entry, best));
+    line.alternatives = scored[1..]
+        .iter()
+        .take(MAX_ALTERNATIVES)
+        .map(|(score, entry)| LineMatch::new(entry, *score))
+        .collect();
+    line.confidence = round(confidence);
+    line
+}
+
+fn index(entries: Vec<CatalogEntry>) -> Vec<IndexedEntry> {
+    entries.into_iter().map(IndexedEntry::new).collect()
+}
+
+fn draft(message: &str, catalog: &[IndexedEntry]) -> DraftOrder {
+    let lines: Vec<DraftLine> = parser::parse_items(message)
+        .iter()
+        .map(|item| match_item(item, catalog))
+        .collect();
+    let intent = parser::has_order_intent(message);
+    let confidence = lines
+        .iter()
+        .map(|line| line.confidence)
+        .reduce(f64::min)
+        .unwrap_or(0.0);
+
+    DraftOrder {
+        is_order: intent && lines.iter().any(|line| line.matched.is_some()),
+        intent: if intent { "purchase" } else { "inquiry" }.to_string(),
+        lines,
+        confidence,
+    }
+}
+
+fn string_field(record: &Value, field: &str) -> Option<String> {
+    record
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn number_field(record: &Value, field: &str) -> Option<f64> {
+    record.get(field).and_then(Value::as_f64)
+}
+
+/// Build catalog entries from `products` and `product_variants` records.
+/// Products with variants are sold by variant; the rest as themselves.
+fn catalog_from_records(products: &[Value], variants: &[Value]) -> Vec<CatalogEntry> {
+    let mut entries = Vec::new();
+    for product in products {
+        let Some(product_id) = string_field(product, "id") else {
+            continue;
+        };
+        let name = string_field(product, "name").unwrap_or_default();
+        let product_sku = string_field(product, "sku").unwrap_or_default();
+        let base_price = number_field(product, "base_price").unwrap_or(0.0);
+
+        let mut has_variants = false;
+        for variant in variants
+            .iter()
+            .filter(|variant| string_field(variant, "product").as_deref() == Some(&product_id))
+        {
+            has_variants = true;
+            let sku = match string_field(variant, "sku_suffix") {
+                Some(suffix) => format!("{}-{}", product_sku, suffix),
+                None => product_sku.clone(),
+            };
+            entries.push(CatalogEntry {
+                product_id: product_id.clone(),
+                variant_id: string_field(variant, "id"),
+                name: name.clone(),
+                variant_name: string_field(variant, "variant_name"),
+                product_sku: product_sku.clone(),
+                sku,
+                size: string_field(variant, "size"),
+                color: string_field(variant, "color"),
+                price: base_price + number_field(variant, "price_adjustment").unwrap_or(0.0),
+                stock: variant.get("stock_level").and_then(Value::as_i64),
+            });
+        }
+
+        if !has_variants {
+            entries.push(CatalogEntry {
+                product_id,
+                variant_id: None,
+                name,
+                variant_name: None,
+                sku: product_sku.clone(),
+                product_sku,
+                size: None,
+                color: None,
+                price: base_price,
+                stock: None,
+            });
+        }
+    }
+    entries
+}
+
+/// Fetch the active catalog from PocketBase
+async fn load_catalog(client: &PocketBaseClient) -> Result<Vec<CatalogEntry>, PocketBaseError> {
+    let products = client
+        .list_all("products", Some("is_active = true"), None)
+        .await?;
+    let variants = client.list_all("product_variants", None, None).await?;
+    Ok(catalog_from_records(&products, &variants))
+}
+
+/// Parses messages against a cached copy of the catalog
+#[derive(Default)]
+pub struct OrderParser {
+    catalog: tokio::sync::Mutex<Option<(Instant, Arc<Vec<IndexedEntry>>)>>,
+}
+
+impl OrderParser {
+    async fn catalog(
+        &self,
+        client: &PocketBaseClient,
+        refresh: bool,
+    ) -> Result<Arc<Vec<IndexedEntry>>, PocketBaseError> {
+        // Held across the fetch so concurrent parses share one load
+        let mut cached = self.catalog.lock().await;
+        if let Some((loaded_at, catalog)) = cached.as_ref() {
+            if !refresh && loaded_at.elapsed() < CATALOG_TTL {
+                return Ok(catalog.clone());
+            }
+        }
+        let catalog = Arc::new(index(load_catalog(client).await?));
+        *cached = Some((Instant::now(), catalog.clone()));
+        Ok(catalog)
+    }
+
+    /// Draft an order from `message`. `refresh` reloads the catalog first,
+    /// e.g. right after products were edited.
+    pub async fn parse(
+        &self,
+        client: &PocketBaseClient,
+        message: &str,
+        refresh: bool,
+    ) -> Result<DraftOrder, PocketBaseError> {
+        let catalog = self.catalog(client, refresh).await?;
+        Ok(draft(message, &catalog))
+    }
+}
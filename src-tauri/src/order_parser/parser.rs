@@ -0,0 +1,237 @@
+//! Split a message into order lines and pull out quantity, SKU, size and
+//! colour from each
+
+use super::lexicon;
+
+/// What one line of a message asks for, before catalog matching
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestedItem {
+    /// The part of the message this was read from
+    pub text: String,
+    pub quantity: Option<u32>,
+    pub sku: Option<String>,
+    pub size: Option<String>,
+    pub colour: Option<String>,
+    /// Remaining descriptive words, canonicalized
+    pub words: Vec<String>,
+}
+
+impl RequestedItem {
+    fn describes_product(&self) -> bool {
+        self.sku.is_some() || !self.words.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.describes_product()
+            && self.quantity.is_none()
+            && self.size.is_none()
+            && self.colour.is_none()
+    }
+}
+
+/// Quantities above this are read as prices ("500 wala") unless a unit follows
+const MAX_BARE_QUANTITY: u32 = 50;
+
+const SEGMENT_WORDS: &[&str] = &["and", "aur", "plus", "also", "n"];
+
+/// Break a message into the parts that each describe one item
+fn segments(message: &str) -> Vec<String> {
+    let normalized = message
+        .to_lowercase()
+        .replace('₹', " rs ")
+        .replace("/-", " ");
+
+    let mut segments = Vec::new();
+    for part in normalized.split(['\n', ',', ';', '+', '&', '|']) {
+        let mut current: Vec<&str> = Vec::new();
+        for word in part.split_whitespace() {
+            if SEGMENT_WORDS.contains(&word) {
+                if !current.is_empty() {
+                    segments.push(current.join(" "));
+                    current.clear();
+                }
+            } else {
+                current.push(word);
+            }
+        }
+        if !current.is_empty() {
+            segments.push(current.join(" "));
+        }
+    }
+    segments
+}
+
+fn is_sku_like(token: &str) -> bool {
+    token.contains('-')
+        && token.len() >= 5
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().any(|c| c.is_ascii_alphabetic())
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn is_decimal(token: &str) -> bool {
+    token.contains('.') && token.parse::<f64>().is_ok()
+}
+
+/// Split on punctuation and at letter/digit boundaries ("2pcs" -> "2 pcs"),
+/// keeping SKUs ("lum-ear-001") and decimals ("2.4") whole
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for raw in text.split(|c: char| c.is_whitespace() || "()[]{}!?:\"'*#@".contains(c)) {
+        let raw = raw.trim_matches(|c: char| c == '.' || c == '-' || c == '/');
+        if raw.is_empty() {
+            continue;
+        }
+        if is_sku_like(raw) || is_decimal(raw) {
+            tokens.push(raw.to_string());
+            continue;
+        }
+
+        let mut current = String::new();
+        let mut current_is_digit = None;
+        for c in raw.chars() {
+            if !c.is_alphanumeric() && c != '₹' {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                current_is_digit = None;
+                continue;
+            }
+            let is_digit = c.is_ascii_digit();
+            if current_is_digit.is_some_and(|was| was != is_digit) {
+                tokens.push(std::mem::take(&mut current));
+            }
+            current.push(c);
+            current_is_digit = Some(is_digit);
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+    }
+
+    // Merge two-word terms ("rose gold")
+    let mut merged: Vec<String> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        if let Some(last) = merged.last_mut() {
+            if let Some(joined) = lexicon::join_pair(last, &token) {
+                *last = joined.to_string();
+                continue;
+            }
+        }
+        merged.push(token);
+    }
+    merged
+}
+
+const LETTER_SIZES: &[&str] = &["xs", "xl", "xxl", "xxxl", "freesize"];
+
+fn parse_segment(text: &str) -> RequestedItem {
+    let tokens = tokenize(text);
+    let mut item = RequestedItem {
+        text: text.trim().to_string(),
+        ..Default::default()
+    };
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i].as_str();
+        let next = tokens.get(i + 1).map(String::as_str);
+
+        if is_sku_like(token) {
+            item.sku = Some(token.to_uppercase());
+        } else if lexicon::is_price_marker(token) {
+            // Skip the amount
+            if next.is_some_and(|n| n.parse::<f64>().is_ok()) {
+                i += 1;
+            }
+        } else if lexicon::is_size_marker(token) {
+            if let Some(value) = next {
+                item.size = Some(match tokens.get(i + 2) {
+                    Some(unit) if lexicon::is_size_unit(unit) => {
+                        i += 1;
+                        format!("{} {}", value, unit)
+                    }
+                    _ => value.to_string(),
+                });
+                i += 1;
+            }
+        } else if is_decimal(token) {
+            item.size = Some(token.to_string());
+        } else if let Ok(number) = token.parse::<u32>() {
+            match next {
+                Some(unit) if lexicon::is_size_unit(unit) => {
+                    item.size = Some(format!("{} {}", number, unit));
+                    i += 1;
+                }
+                Some(word) if lexicon::is_price_marker(word) => i += 1,
+                Some(word) if lexicon::is_quantity_unit(word) => {
+                    item.quantity = Some(number);
+                    i += 1;
+                }
+                _ if number > 0 && number <= MAX_BARE_QUANTITY && item.quantity.is_none() => {
+                    item.quantity = Some(number);
+                }
+                _ => {}
+            }
+        } else if let Some(number) = lexicon::number_word(token) {
+            // "do" is also the Hindi imperative ("bhej do"), so it only
+            // counts when something other than filler follows
+            let counts = token != "do" || next.is_some_and(|n| !lexicon::is_stopword(n));
+            if counts && item.quantity.is_none() {
+                item.quantity = Some(number);
+            }
+        } else if let Some(colour) = lexicon::colour(token) {
+            item.colour = Some(colour.to_string());
+        } else if LETTER_SIZES.contains(&token) {
+            item.size = Some(token.to_string());
+        } else if lexicon::is_quantity_unit(token) {
+            // "x 2", "qty 3"
+            if let Some(number) = next.and_then(|n| n.parse::<u32>().ok()) {
+                if item.quantity.is_none() {
+                    item.quantity = Some(number);
+                }
+                i += 1;
+            }
+        } else if !lexicon::is_stopword(token) && token.chars().any(char::is_alphabetic) {
+            item.words
+                .push(lexicon::canonical_product_word(token).to_string());
+        }
+        i += 1;
+    }
+    item
+}
+
+/// Requested items in message order. A part naming only a quantity, colour
+/// or size ("jhumka 2 red, 1 green") refers to the product named before it.
+pub fn parse_items(message: &str) -> Vec<RequestedItem> {
+    let mut items: Vec<RequestedItem> = Vec::new();
+    for segment in segments(message) {
+        let mut item = parse_segment(&segment);
+        if item.is_empty() {
+            continue;
+        }
+        if !item.describes_product() {
+            let Some(previous) = items.last_mut() else {
+                continue;
+            };
+            if item.colour.is_none() && item.size.is_none() {
+                // A lone quantity after the product ("jhumka, 3 pcs")
+                if previous.quantity.is_none() {
+                    previous.quantity = item.quantity;
+                }
+                continue;
+            }
+            item.sku = previous.sku.clone();
+            item.words = previous.words.clone();
+        }
+        items.push(item);
+    }
+    items
+}
+
+/// Whether the message reads as a purchase rather than a question
+pub fn has_order_intent(message: &str) -> bool {
+    tokenize(&message.to_lowercase())
+        .iter()
+        .any(|token| lexicon::ORDER_INTENT_WORDS.contains(&token.as_str()))
+}
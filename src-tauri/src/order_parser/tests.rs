@@ -0,0 +1,269 @@
+//! Corpus of message shapes seen on the shop's WhatsApp, run against a small
+//! fixture catalog
+
+use serde_json::json;
+
+use super::*;
+
+fn catalog() -> Vec<IndexedEntry> {
+    let products = vec![
+        json!({"id": "p1", "name": "Kundan Jhumka", "sku": "LUM-EAR-001", "base_price": 850}),
+        json!({"id": "p2", "name": "Gold Plated Chain", "sku": "LUM-NEC-002", "base_price": 1200}),
+        json!({"id": "p3", "name": "Oxidised Silver Anklet", "sku": "LUM-ANK-003", "base_price": 450}),
+        json!({"id": "p4", "name": "Pearl Necklace Set", "sku": "LUM-NEC-004", "base_price": 2500}),
+        json!({"id": "p5", "name": "Stone Bangle", "sku": "LUM-BAN-005", "base_price": 600}),
+        json!({"id": "p6", "name": "Rose Gold Bracelet", "sku": "LUM-BRA-006", "base_price": 700}),
+    ];
+    let variants = vec![
+        json!({"id": "v1", "product": "p1", "variant_name": "Red", "sku_suffix": "RED",
+               "color": "Red", "price_adjustment": 0, "stock_level": 5}),
+        json!({"id": "v2", "product": "p1", "variant_name": "Green", "sku_suffix": "GRN",
+               "color": "Green", "price_adjustment": 50, "stock_level": 3}),
+        json!({"id": "v3", "product": "p2", "variant_name": "16 inch", "sku_suffix": "16",
+               "size": "16 inch", "color": "Gold", "price_adjustment": 0, "stock_level": 4}),
+        json!({"id": "v4", "product": "p2", "variant_name": "18 inch", "sku_suffix": "18",
+               "size": "18 inch", "color": "Gold", "price_adjustment": 100, "stock_level": 2}),
+        json!({"id": "v5", "product": "p5", "variant_name": "2.4", "sku_suffix": "24",
+               "size": "2.4", "price_adjustment": 0, "stock_level": 0}),
+        json!({"id": "v6", "product": "p5", "variant_name": "2.6", "sku_suffix": "26",
+               "size": "2.6", "price_adjustment": 0, "stock_level": 6}),
+    ];
+    index(catalog_from_records(&products, &variants))
+}
+
+/// (matched SKU, quantity) per line
+fn lines(message: &str) -> Vec<(Option<String>, u32)> {
+    draft(message, &catalog())
+        .lines
+        .into_iter()
+        .map(|line| (line.matched.map(|m| m.sku), line.quantity))
+        .collect()
+}
+
+fn sku(sku: &str, quantity: u32) -> (Option<String>, u32) {
+    (Some(sku.to_string()), quantity)
+}
+
+#[test]
+fn catalog_prices_variants_and_keeps_plain_products() {
+    let catalog = catalog();
+    assert_eq!(catalog.len(), 9);
+
+    let green = catalog
+        .iter()
+        .find(|e| e.entry.variant_id.as_deref() == Some("v2"))
+        .unwrap();
+    assert_eq!(green.entry.sku, "LUM-EAR-001-GRN");
+    assert_eq!(green.entry.price, 900.0);
+
+    let anklet = catalog.iter().find(|e| e.entry.product_id == "p3").unwrap();
+    assert_eq!(anklet.entry.variant_id, None);
+    assert_eq!(anklet.entry.sku, "LUM-ANK-003");
+}
+
+#[test]
+fn english_order_with_quantity_and_colour() {
+    assert_eq!(
+        lines("Hi, I want to order 2 kundan jhumka in red"),
+        vec![sku("LUM-EAR-001-RED", 2)]
+    );
+}
+
+#[test]
+fn hinglish_order_with_hindi_colour_and_plural() {
+    assert_eq!(
+        lines("mujhe 2 laal jhumke chahiye"),
+        vec![sku("LUM-EAR-001-RED", 2)]
+    );
+}
+
+#[test]
+fn full_sku_with_multiplier() {
+    let order = draft("LUM-NEC-002-18 x 2", &catalog());
+    assert_eq!(order.lines.len(), 1);
+    let line = &order.lines[0];
+    assert_eq!(line.quantity, 2);
+    let matched = line.matched.as_ref().unwrap();
+    assert_eq!(matched.sku, "LUM-NEC-002-18");
+    assert_eq!(matched.unit_price, 1300.0);
+    assert_eq!(matched.score, 1.0);
+}
+
+#[test]
+fn base_sku_picks_variant_by_colour() {
+    assert_eq!(
+        lines("LUM-EAR-001 green 1 pc"),
+        vec![sku("LUM-EAR-001-GRN", 1)]
+    );
+}
+
+#[test]
+fn lowercase_sku() {
+    assert_eq!(lines("lum-ank-003"), vec![sku("LUM-ANK-003", 1)]);
+}
+
+#[test]
+fn several_items_joined_with_aur() {
+    assert_eq!(
+        lines("gold chain 18 inch aur ek payal bhej do"),
+        vec![sku("LUM-NEC-002-18", 1), sku("LUM-ANK-003", 1)]
+    );
+}
+
+#[test]
+fn one_item_per_line() {
+    assert_eq!(
+        lines("Order:\nPearl necklace set 1\nstone bangle size 2.6 - 2 pcs"),
+        vec![sku("LUM-NEC-004", 1), sku("LUM-BAN-005-26", 2)]
+    );
+}
+
+#[test]
+fn colours_listed_after_one_product() {
+    assert_eq!(
+        lines("jhumka 2 red, 1 green"),
+        vec![sku("LUM-EAR-001-RED", 2), sku("LUM-EAR-001-GRN", 1)]
+    );
+}
+
+#[test]
+fn quantity_in_its_own_part_applies_to_previous_item() {
+    assert_eq!(
+        lines("rose gold bracelet, 3 pcs"),
+        vec![sku("LUM-BRA-006", 3)]
+    );
+}
+
+#[test]
+fn quantity_glued_to_unit() {
+    assert_eq!(
+        lines("kundan jhumka red 3pcs"),
+        vec![sku("LUM-EAR-001-RED", 3)]
+    );
+}
+
+#[test]
+fn hindi_number_words() {
+    assert_eq!(
+        lines("do laal jhumki aur teen payal"),
+        vec![sku("LUM-EAR-001-RED", 2), sku("LUM-ANK-003", 3)]
+    );
+}
+
+#[test]
+fn do_as_verb_is_not_a_quantity() {
+    let order = draft("payal bhej do", &catalog());
+    assert_eq!(order.lines.len(), 1);
+    assert_eq!(order.lines[0].quantity, 1);
+    assert!(!order.lines[0].quantity_given);
+}
+
+#[test]
+fn prices_are_not_quantities() {
+    for message in [
+        "jhumka 850 wala chahiye",
+        "₹850 wali jhumki",
+        "rs 1200 chain",
+        "chain 1200/-",
+        "jhumka 850rs",
+    ] {
+        let order = draft(message, &catalog());
+        assert_eq!(order.lines.len(), 1, "{}", message);
+        assert!(!order.lines[0].quantity_given, "{}", message);
+    }
+}
+
+#[test]
+fn bangle_size_as_decimal() {
+    assert_eq!(lines("2 stone bangles 2.4"), vec![sku("LUM-BAN-005-24", 2)]);
+}
+
+#[test]
+fn misspelt_product_names() {
+    assert_eq!(lines("kundun jhumkaa red"), vec![sku("LUM-EAR-001-RED", 1)]);
+    assert_eq!(lines("pearl neckless"), vec![sku("LUM-NEC-004", 1)]);
+    assert_eq!(lines("braclet rose gold"), vec![sku("LUM-BRA-006", 1)]);
+}
+
+#[test]
+fn unknown_product_is_not_matched() {
+    let order = draft("2 saree chahiye", &catalog());
+    assert!(!order.is_order);
+    assert_eq!(order.lines.len(), 1);
+    assert!(order.lines[0].matched.is_none());
+}
+
+#[test]
+fn question_is_an_inquiry() {
+    let order = draft("jhumka ka price kya hai?", &catalog());
+    assert_eq!(order.intent, "inquiry");
+    assert!(!order.is_order);
+    assert_eq!(order.lines.len(), 1);
+    assert_eq!(order.lines[0].matched.as_ref().unwrap().product_id, "p1");
+}
+
+#[test]
+fn greeting_has_no_lines() {
+    let order = draft("Hello ji, thank you", &catalog());
+    assert!(order.lines.is_empty());
+    assert!(!order.is_order);
+    assert_eq!(order.confidence, 0.0);
+}
+
+#[test]
+fn order_intent() {
+    assert!(draft("2 red jhumka chahiye", &catalog()).is_order);
+    assert!(draft("I want to buy the pearl necklace", &catalog()).is_order);
+    assert!(!draft("pearl necklace available?", &catalog()).is_order);
+}
+
+#[test]
+fn exact_match_is_more_confident_than_guess() {
+    let exact = draft("2 pcs LUM-EAR-001-RED", &catalog());
+    let vague = draft("jhumka", &catalog());
+    assert!(exact.confidence > vague.confidence);
+    assert!(exact.confidence >= 0.95);
+
+    // Either colour fits, so the other is offered as an alternative
+    let line = &vague.lines[0];
+    assert!(line.matched.is_some());
+    assert!(line
+        .alternatives
+        .iter()
+        .any(|alternative| alternative.product_id == "p1"));
+}
+
+#[test]
+fn wrong_colour_scores_lower() {
+    let order = draft("green jhumka", &catalog());
+    let line = &order.lines[0];
+    assert_eq!(line.matched.as_ref().unwrap().sku, "LUM-EAR-001-GRN");
+    let red = line
+        .alternatives
+        .iter()
+        .find(|alternative| alternative.sku == "LUM-EAR-001-RED")
+        .unwrap();
+    assert!(red.score < line.matched.as_ref().unwrap().score);
+}
+
+#[test]
+fn tie_prefers_stock() {
+    // Both bangle sizes fit, only 2.6 is in stock
+    assert_eq!(lines("stone bangle"), vec![sku("LUM-BAN-005-26", 1)]);
+}
+
+#[test]
+fn similarity_bounds() {
+    assert_eq!(matcher::similarity("jhumka", "jhumka"), 1.0);
+    assert_eq!(matcher::similarity("", ""), 1.0);
+    assert_eq!(matcher::similarity("abc", "xyz"), 0.0);
+    assert!(matcher::similarity("kundun", "kundan") >= 0.75);
+}
+
+#[test]
+fn tokenize_splits_units_and_keeps_skus() {
+    assert_eq!(
+        parser::tokenize("lum-ear-001 x2 3pcs 2.4 rose gold"),
+        vec!["lum-ear-001", "x", "2", "3", "pcs", "2.4", "rosegold"]
+    );
+}
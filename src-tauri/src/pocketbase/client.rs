@@ -0,0 +1,193 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::{Method, Url};
+use serde_json::Value;
+
+use super::error::PocketBaseError;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Largest page PocketBase serves
+const PAGE_SIZE: u32 = 500;
+
+/// Quote `value` as a string literal for a PocketBase filter expression
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Random id in PocketBase's default record id format (15 of `[a-z0-9]`),
+/// for records whose id must be known before PocketBase has seen them
+pub fn new_record_id() -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    let mut rng = rand::thread_rng();
+    (0..15)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect()
+}
+
+/// REST client for the bundled PocketBase, acting as the signed-in user
+pub struct PocketBaseClient {
+    base_url: Url,
+    http: reqwest::Client,
+    /// Auth token handed over by the webview after sign-in
+    token: Mutex<Option<String>>,
+}
+
+impl PocketBaseClient {
+    /// `http` is shared with the sidecar supervisor
+    pub fn new(http: reqwest::Client, addr: &str) -> Self {
+        Self {
+            base_url: Url::parse(&format!("http://{}", addr)).expect("localhost URL is valid"),
+            http,
+            token: Mutex::new(None),
+        }
+    }
+
+    pub fn set_token(&self, token: Option<String>) {
+        *self.token.lock().unwrap() = token.filter(|token| !token.is_empty());
+    }
+
+    /// Whether the webview has handed over a token
+    pub fn is_signed_in(&self) -> bool {
+        self.token.lock().unwrap().is_some()
+    }
+
+    fn records_url(&self, collection: &str, id: Option<&str>) -> Url {
+        let mut url = self.base_url.clone();
+        {
+            let mut segments = url.path_segments_mut().expect("base URL has a path");
+            segments.extend(["api", "collections", collection, "records"]);
+            if let Some(id) = id {
+                segments.push(id);
+            }
+        }
+        url
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<&Value>,
+    ) -> Result<Value, PocketBaseError> {
+        let token = self.token.lock().unwrap().clone();
+        let mut request = self.http.request(method, url).timeout(REQUEST_TIMEOUT);
+        if let Some(token) = token {
+            // PocketBase expects the bare token, without a scheme
+            request = request.header(reqwest::header::AUTHORIZATION, token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+        let body: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+        if (200..300).contains(&status) {
+            return Ok(body);
+        }
+
+        let message = body
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or(text);
+        Err(match status {
+            401 | 403 => PocketBaseError::Unauthorized { message },
+            404 => PocketBaseError::NotFound { message },
+            _ => PocketBaseError::Api {
+                status,
+                message,
+                data: body.get("data").cloned().unwrap_or(Value::Null),
+            },
+        })
+    }
+
+    /// First `limit` records of `collection` matching `filter`
+    pub async fn list(
+        &self,
+        collection: &str,
+        filter: Option<&str>,
+        sort: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<Value>, PocketBaseError> {
+        let mut url = self.records_url(collection, None);
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("perPage", &limit.min(PAGE_SIZE).to_string())
+                .append_pair("skipTotal", "true");
+            if let Some(filter) = filter {
+                query.append_pair("filter", filter);
+            }
+            if let Some(sort) = sort {
+                query.append_pair("sort", sort);
+            }
+        }
+
+        let body = self.send(Method::GET, url, None).await?;
+        Ok(body
+            .get("items")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    pub async fn create(&self, collection: &str, record: &Value) -> Result<Value, PocketBaseError> {
+        let url = self.records_url(collection, None);
+        self.send(Method::POST, url, Some(record)).await
+    }
+
+    /// Apply the fields in `changes` to record `id`
+    pub async fn update(
+        &self,
+        collection: &str,
+        id: &str,
+        changes: &Value,
+    ) -> Result<Value, PocketBaseError> {
+        let url = self.records_url(collection, Some(id));
+        self.send(Method::PATCH, url, Some(changes)).await
+    }
+
+    /// Every record of `collection` matching `filter`
+    pub async fn list_all(
+        &self,
+        collection: &str,
+        filter: Option<&str>,
+        sort: Option<&str>,
+    ) -> Result<Vec<Value>, PocketBaseError> {
+        let mut records = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut url = self.records_url(collection, None);
+            {
+                let mut query = url.query_pairs_mut();
+                query
+                    .append_pair("page", &page.to_string())
+                    .append_pair("perPage", &PAGE_SIZE.to_string())
+                    .append_pair("skipTotal", "true");
+                if let Some(filter) = filter {
+                    query.append_pair("filter", filter);
+                }
+                if let Some(sort) = sort {
+                    query.append_pair("sort", sort);
+                }
+            }
+
+            let body = self.send(Method::GET, url, None).await?;
+            let items = body
+                .get("items")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            let done = items.len() < PAGE_SIZE as usize;
+            records.extend(items);
+            if done {
+                return Ok(records);
+            }
+            page += 1;
+        }
+    }
+}
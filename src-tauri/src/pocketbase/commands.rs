@@ -0,0 +1,10 @@
+use tauri::State;
+
+use super::PocketBaseClient;
+
+/// Tauri command to hand the webview's PocketBase auth token to the Rust
+/// side, so background tasks act as the signed-in user. `None` signs out.
+#[tauri::command]
+pub fn pocketbase_set_auth(client: State<'_, PocketBaseClient>, token: Option<String>) {
+    client.set_token(token);
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Error returned by [`super::PocketBaseClient`], tagged by `kind` for the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PocketBaseError {
+    /// PocketBase is not running or refused the connection
+    Unavailable {
+        message: String,
+    },
+    /// No user is signed in, or the token expired
+    Unauthorized {
+        message: String,
+    },
+    NotFound {
+        message: String,
+    },
+    /// PocketBase answered with another error status. `data` holds
+    /// per-field validation errors.
+    Api {
+        status: u16,
+        message: String,
+        data: Value,
+    },
+}
+
+impl From<reqwest::Error> for PocketBaseError {
+    fn from(e: reqwest::Error) -> Self {
+        PocketBaseError::Unavailable {
+            message: e.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PocketBaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PocketBaseError::Unavailable { message } => {
+                write!(f, "PocketBase unavailable: {}", message)
+            }
+            PocketBaseError::Unauthorized { message } => {
+                write!(f, "Not signed in to PocketBase: {}", message)
+            }
+            PocketBaseError::NotFound { message } => write!(f, "Not found: {}", message),
+            PocketBaseError::Api {
+                status, message, ..
+            } => write!(f, "PocketBase error ({}): {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for PocketBaseError {}
+
+impl From<PocketBaseError> for String {
+    fn from(e: PocketBaseError) -> Self {
+        e.to_string()
+    }
+}
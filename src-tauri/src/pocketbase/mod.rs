@@ -0,0 +1,68 @@
+//! Bundled PocketBase backend
+//!
+//! Besides running PocketBase as a sidecar, the Rust side reads and writes
+//! records through [`PocketBaseClient`], authenticated with the token of the
+//! user signed in to the webview.
+
+mod client;
+pub mod commands;
+mod error;
+
+use std::path::PathBuf;
+
+use tauri::Manager;
+
+use crate::sidecar::SidecarSpec;
+
+pub use client::{new_record_id, quote, PocketBaseClient};
+pub use error::PocketBaseError;
+
+/// Registry name of the PocketBase sidecar
+pub const SIDECAR_NAME: &str = "pocketbase";
+
+/// Port PocketBase listens on (matches NEXT_PUBLIC_POCKETBASE_URL default)
+pub const PORT: u16 = 8090;
+
+/// Address PocketBase listens on
+pub const ADDR: &str = "127.0.0.1:8090";
+
+/// Per-user PocketBase data directory (created on first use)
+pub fn data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?
+        .join("pb_data");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// Migrations bundled with the app, if present
+pub fn migrations_dir(app: &tauri::AppHandle) -> Option<PathBuf> {
+    let dir = app.path().resource_dir().ok()?.join("pb_migrations");
+    dir.is_dir().then_some(dir)
+}
+
+/// Sidecar spec for the bundled PocketBase binary
+pub fn sidecar_spec(app: &tauri::AppHandle) -> Result<SidecarSpec, String> {
+    let data_dir = data_dir(app)?;
+    let mut args = vec![
+        "serve".to_string(),
+        format!("--http={}", ADDR),
+        format!("--dir={}", data_dir.display()),
+    ];
+
+    // Apply the migrations shipped with the app, if bundled
+    if let Some(migrations_dir) = migrations_dir(app) {
+        args.push(format!("--migrationsDir={}", migrations_dir.display()));
+    }
+
+    Ok(SidecarSpec::new(
+        SIDECAR_NAME,
+        "pocketbase",
+        &format!("http://{}/api/health", ADDR),
+    )
+    .args(args)
+    .port(PORT))
+}
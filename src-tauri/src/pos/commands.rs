@@ -0,0 +1,78 @@
+use tauri::{AppHandle, State};
+
+use super::replay::{self, emit_entry};
+use super::{JournalEntry, JournalStatus, NewSale, PosJournal};
+use crate::pocketbase::PocketBaseClient;
+use crate::sidecar::SidecarSupervisor;
+
+/// Tauri command to journal a POS sale and, if PocketBase is up, push it
+/// straight away. Once journalled the sale is never lost, so replay errors
+/// leave it pending instead of failing the command.
+#[tauri::command]
+pub async fn pos_record_sale(
+    app: AppHandle,
+    journal: State<'_, PosJournal>,
+    client: State<'_, PocketBaseClient>,
+    supervisor: State<'_, SidecarSupervisor>,
+    sale: NewSale,
+) -> Result<JournalEntry, String> {
+    let entry = journal.record(&sale)?;
+    if replay::pocketbase_healthy(&supervisor) {
+        if let Err(e) = replay::replay_now(&journal, &client, &entry.id).await {
+            log::error!("Failed to replay POS sale {}: {}", entry.id, e);
+        }
+    }
+    emit_entry(&app, &journal, &entry.id);
+    journal.get(&entry.id).or(Ok(entry))
+}
+
+/// Tauri command to list journalled sales, newest first
+#[tauri::command]
+pub fn pos_journal_list(
+    journal: State<'_, PosJournal>,
+    status: Option<JournalStatus>,
+    limit: Option<u32>,
+) -> Result<Vec<JournalEntry>, String> {
+    journal.list(status, limit.unwrap_or(100))
+}
+
+/// Tauri command to count sales not yet in PocketBase
+#[tauri::command]
+pub fn pos_journal_pending_count(journal: State<'_, PosJournal>) -> Result<u32, String> {
+    journal.pending_count()
+}
+
+/// Tauri command to list synced sales that still need an invoice
+#[tauri::command]
+pub fn pos_journal_uninvoiced(journal: State<'_, PosJournal>) -> Result<Vec<JournalEntry>, String> {
+    journal.uninvoiced()
+}
+
+/// Tauri command to record the invoice issued for a synced sale
+#[tauri::command]
+pub fn pos_journal_set_invoice(
+    journal: State<'_, PosJournal>,
+    id: String,
+    invoice_id: String,
+    invoice_number: String,
+) -> Result<JournalEntry, String> {
+    journal.set_invoice(&id, &invoice_id, &invoice_number)
+}
+
+/// Tauri command to replay a conflicting sale again
+#[tauri::command]
+pub fn pos_journal_retry(
+    journal: State<'_, PosJournal>,
+    id: String,
+) -> Result<JournalEntry, String> {
+    journal.retry(&id)
+}
+
+/// Tauri command to give up on a conflicting sale
+#[tauri::command]
+pub fn pos_journal_discard(
+    journal: State<'_, PosJournal>,
+    id: String,
+) -> Result<JournalEntry, String> {
+    journal.discard(&id)
+}
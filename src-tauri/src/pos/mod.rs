@@ -0,0 +1,654 @@
+//! Offline-first POS sales journal
+//!
+//! A POS sale is written to the local database before anything is sent to
+//! PocketBase, as the ordered list of writes that make it up: the sale, its
+//! items and stock movements, the variant stock decrements and the register
+//! shift totals. Record ids are generated here rather than by PocketBase, so
+//! replaying a write whose response was lost finds the record already there
+//! instead of creating a duplicate. Stock and shift totals are changed with
+//! PocketBase's `field+` / `field-` modifiers, so concurrent writes are not
+//! lost. Each change is kept in the journal with the values it leads to
+//! before it is sent, so a replay tells its own earlier write apart from
+//! one that never happened.
+//!
+//! The till keeps working while PocketBase is down. A background worker
+//! replays the backlog in the order the sales were made once it is back, and
+//! stops a sale at the first write PocketBase rejects, reporting it as a
+//! conflict for staff to resolve. Invoices are still issued by the webview,
+//! once the sale has reached PocketBase.
+
+pub mod commands;
+mod replay;
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::db::{self, LocalDb};
+use crate::pocketbase;
+
+pub use replay::run_worker;
+
+const PAYMENT_METHODS: &[&str] = &["cash", "card", "upi", "phonepe"];
+
+/// Sale as rung up at the till
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSale {
+    /// Receipt reference shown to the customer (`TXN-...`)
+    pub transaction_id: String,
+    pub items: Vec<NewSaleItem>,
+    pub subtotal: f64,
+    /// Manual and loyalty discounts combined
+    pub discount: f64,
+    pub total: f64,
+    pub payment_method: String,
+    pub cash_tendered: Option<f64>,
+    pub change_given: Option<f64>,
+    pub customer_id: Option<String>,
+    pub customer_name: Option<String>,
+    pub customer_phone: Option<String>,
+    pub shift_id: String,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSaleItem {
+    pub product_id: Option<String>,
+    pub variant_id: Option<String>,
+    pub quantity: i64,
+    pub unit_price: f64,
+    pub remarks: Option<String>,
+}
+
+/// One PocketBase write of a journalled sale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum JournalOp {
+    /// Create a record under the id in `record`. `owner` names the field
+    /// tying it to this sale, which tells an earlier replay of the same
+    /// write apart from an id clash.
+    Create {
+        collection: String,
+        record: Value,
+        owner: String,
+    },
+    /// Take `quantity` off a variant's stock level. Stock may go negative,
+    /// which records an oversale.
+    DecrementStock { variant: String, quantity: i64 },
+    /// Add the sale to its register shift's totals
+    ShiftTotals {
+        shift: String,
+        payment_method: String,
+        amount: f64,
+    },
+}
+
+impl JournalOp {
+    /// Short description for conflict messages
+    fn describe(&self) -> String {
+        match self {
+            JournalOp::Create { collection, .. } => format!("create {} record", collection),
+            JournalOp::DecrementStock { variant, .. } => {
+                format!("update stock of variant {}", variant)
+            }
+            JournalOp::ShiftTotals { shift, .. } => format!("update totals of shift {}", shift),
+        }
+    }
+}
+
+/// Increment about to be written, kept until PocketBase confirms it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct Planned {
+    /// `updated` of the record when the increment was planned
+    pub updated: String,
+    /// `field+` / `field-` modifiers sent to PocketBase
+    pub deltas: Map<String, Value>,
+    /// Values the fields have once the increment is written, unless
+    /// something else changed them too
+    pub changes: Map<String, Value>,
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalStatus {
+    /// Waiting to be (fully) replayed
+    Pending,
+    /// Every write reached PocketBase
+    Synced,
+    /// PocketBase rejected a write; needs a retry or discard from staff
+    Conflict,
+    /// Given up on by staff
+    Discarded,
+}
+
+impl JournalStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            JournalStatus::Pending => "pending",
+            JournalStatus::Synced => "synced",
+            JournalStatus::Conflict => "conflict",
+            JournalStatus::Discarded => "discarded",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "synced" => JournalStatus::Synced,
+            "conflict" => JournalStatus::Conflict,
+            "discarded" => JournalStatus::Discarded,
+            _ => JournalStatus::Pending,
+        }
+    }
+}
+
+/// Journalled sale as shown to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    /// PocketBase id of the sale record
+    pub id: String,
+    pub transaction_id: String,
+    pub shift_id: String,
+    pub payment_method: String,
+    pub total: f64,
+    pub item_count: u32,
+    pub status: JournalStatus,
+    pub attempts: u32,
+    /// Why the last replay attempt stopped early, while still pending
+    pub last_error: Option<String>,
+    /// Write PocketBase rejected and its answer
+    pub conflict: Option<String>,
+    /// Issues that did not stop the sale, such as overselling a variant
+    pub warnings: Vec<String>,
+    pub ops_done: u32,
+    pub ops_total: u32,
+    pub invoice_id: Option<String>,
+    pub invoice_number: Option<String>,
+    pub created_at: i64,
+    pub next_attempt_at: i64,
+    pub synced_at: Option<i64>,
+}
+
+const ENTRY_COLUMNS: &str = "j.id, j.transaction_id, j.shift_id, j.payment_method, j.total, j.item_count, j.status, j.attempts, j.last_error, j.conflict, j.warnings,
+    (SELECT COUNT(*) FROM pos_journal_ops o WHERE o.sale_id = j.id AND o.done_at IS NOT NULL),
+    (SELECT COUNT(*) FROM pos_journal_ops o WHERE o.sale_id = j.id),
+    j.invoice_id, j.invoice_number, j.created_at, j.next_attempt_at, j.synced_at";
+
+impl JournalEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            transaction_id: row.get(1)?,
+            shift_id: row.get(2)?,
+            payment_method: row.get(3)?,
+            total: row.get(4)?,
+            item_count: row.get(5)?,
+            status: JournalStatus::parse(&row.get::<_, String>(6)?),
+            attempts: row.get(7)?,
+            last_error: row.get(8)?,
+            conflict: row.get(9)?,
+            warnings: serde_json::from_str(&row.get::<_, String>(10)?).unwrap_or_default(),
+            ops_done: row.get(11)?,
+            ops_total: row.get(12)?,
+            invoice_id: row.get(13)?,
+            invoice_number: row.get(14)?,
+            created_at: row.get(15)?,
+            next_attempt_at: row.get(16)?,
+            synced_at: row.get(17)?,
+        })
+    }
+}
+
+/// Sale picked for replay
+struct DueSale {
+    id: String,
+    created_at: i64,
+    attempts: u32,
+}
+
+pub struct PosJournal {
+    db: Arc<LocalDb>,
+    /// Wakes the worker when a sale is journalled or retried
+    wake: tokio::sync::Notify,
+    /// Held while replaying, so the worker and an immediate replay from
+    /// the till never push the same sale at once
+    replaying: tokio::sync::Mutex<()>,
+}
+
+impl PosJournal {
+    pub fn new(db: Arc<LocalDb>) -> Self {
+        Self {
+            db,
+            wake: tokio::sync::Notify::new(),
+            replaying: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Result<JournalEntry, String> {
+        self.db
+            .with(|conn| {
+                conn.query_row(
+                    &format!(
+                        "SELECT {} FROM pos_journal j WHERE j.id = ?1",
+                        ENTRY_COLUMNS
+                    ),
+                    [id],
+                    JournalEntry::from_row,
+                )
+                .optional()
+            })?
+            .ok_or_else(|| format!("Journalled sale not found: {}", id))
+    }
+
+    /// Journal a sale for replay, assigning its record ids
+    pub fn record(&self, sale: &NewSale) -> Result<JournalEntry, String> {
+        validate(sale)?;
+        let id = pocketbase::new_record_id();
+        let ops = plan(&id, sale)
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to encode sale: {}", e))?;
+        let now = db::now_millis();
+
+        self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO pos_journal
+                    (id, transaction_id, shift_id, payment_method, total, item_count, status, created_at, next_attempt_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'pending', ?7, ?7)",
+                params![
+                    id,
+                    sale.transaction_id,
+                    sale.shift_id,
+                    sale.payment_method,
+                    sale.total,
+                    sale.items.len() as u32,
+                    now
+                ],
+            )?;
+            for (seq, op) in ops.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO pos_journal_ops (sale_id, seq, op) VALUES (?1, ?2, ?3)",
+                    params![id, seq as u32, op],
+                )?;
+            }
+            tx.commit()
+        })?;
+
+        self.wake.notify_one();
+        self.get(&id)
+    }
+
+    /// Journalled sales, newest first, optionally filtered by status
+    pub fn list(
+        &self,
+        status: Option<JournalStatus>,
+        limit: u32,
+    ) -> Result<Vec<JournalEntry>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM pos_journal j
+                 WHERE (?1 IS NULL OR j.status = ?1)
+                 ORDER BY j.created_at DESC LIMIT ?2",
+                ENTRY_COLUMNS
+            ))?;
+            let rows = stmt.query_map(
+                params![status.map(JournalStatus::as_str), limit],
+                JournalEntry::from_row,
+            )?;
+            rows.collect()
+        })
+    }
+
+    /// Number of sales not yet in PocketBase, conflicts excluded
+    pub fn pending_count(&self) -> Result<u32, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM pos_journal WHERE status = 'pending'",
+                [],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Synced sales still waiting for their invoice, oldest first
+    pub fn uninvoiced(&self) -> Result<Vec<JournalEntry>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM pos_journal j
+                 WHERE j.status = 'synced' AND j.invoice_id IS NULL
+                 ORDER BY j.created_at",
+                ENTRY_COLUMNS
+            ))?;
+            let rows = stmt.query_map([], JournalEntry::from_row)?;
+            rows.collect()
+        })
+    }
+
+    /// Record the invoice the webview issued for a synced sale
+    pub fn set_invoice(
+        &self,
+        id: &str,
+        invoice_id: &str,
+        invoice_number: &str,
+    ) -> Result<JournalEntry, String> {
+        let updated = self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal SET invoice_id = ?2, invoice_number = ?3
+                 WHERE id = ?1 AND status = 'synced'",
+                params![id, invoice_id, invoice_number],
+            )
+        })?;
+        if updated == 0 {
+            return Err(format!("Journalled sale {} is not synced", id));
+        }
+        self.get(id)
+    }
+
+    /// Replay a conflicting sale again, from the write that was rejected.
+    /// Increments are worked out afresh, from the records as staff left them.
+    pub fn retry(&self, id: &str) -> Result<JournalEntry, String> {
+        let updated = self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            let updated = tx.execute(
+                "UPDATE pos_journal
+                 SET status = 'pending', attempts = 0, last_error = NULL, conflict = NULL, next_attempt_at = ?2
+                 WHERE id = ?1 AND status = 'conflict'",
+                params![id, db::now_millis()],
+            )?;
+            tx.execute(
+                "UPDATE pos_journal_ops SET planned = NULL WHERE sale_id = ?1 AND done_at IS NULL",
+                [id],
+            )?;
+            tx.commit()?;
+            Ok(updated)
+        })?;
+        if updated == 0 {
+            return Err(format!("Journalled sale {} is not in conflict", id));
+        }
+        self.wake.notify_one();
+        self.get(id)
+    }
+
+    /// Give up on a conflicting sale. Writes that already reached
+    /// PocketBase stay there.
+    pub fn discard(&self, id: &str) -> Result<JournalEntry, String> {
+        let updated = self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal SET status = 'discarded' WHERE id = ?1 AND status = 'conflict'",
+                [id],
+            )
+        })?;
+        if updated == 0 {
+            return Err(format!("Journalled sale {} is not in conflict", id));
+        }
+        self.get(id)
+    }
+
+    /// Make every pending sale due now, e.g. once PocketBase is back
+    fn make_due(&self) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal SET next_attempt_at = ?1 WHERE status = 'pending'",
+                [db::now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Pending sales due for replay, oldest first
+    fn due(&self) -> Result<Vec<DueSale>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, created_at, attempts FROM pos_journal
+                 WHERE status = 'pending' AND next_attempt_at <= ?1
+                 ORDER BY created_at LIMIT 50",
+            )?;
+            let rows = stmt.query_map([db::now_millis()], |row| {
+                Ok(DueSale {
+                    id: row.get(0)?,
+                    created_at: row.get(1)?,
+                    attempts: row.get(2)?,
+                })
+            })?;
+            rows.collect()
+        })
+    }
+
+    /// Writes of a sale that have not reached PocketBase yet, in order
+    fn remaining_ops(&self, id: &str) -> Result<Vec<(u32, JournalOp)>, String> {
+        let rows = self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT seq, op FROM pos_journal_ops
+                 WHERE sale_id = ?1 AND done_at IS NULL ORDER BY seq",
+            )?;
+            let rows = stmt.query_map([id], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+        rows.into_iter()
+            .map(|(seq, op)| {
+                serde_json::from_str(&op)
+                    .map(|op| (seq, op))
+                    .map_err(|e| format!("Unreadable journal entry for sale {}: {}", id, e))
+            })
+            .collect()
+    }
+
+    /// Increment planned for a step that has not been confirmed yet
+    fn planned(&self, id: &str, seq: u32) -> Result<Option<Planned>, String> {
+        let planned: Option<String> = self.db.with(|conn| {
+            conn.query_row(
+                "SELECT planned FROM pos_journal_ops WHERE sale_id = ?1 AND seq = ?2",
+                params![id, seq],
+                |row| row.get(0),
+            )
+        })?;
+        planned
+            .map(|planned| {
+                serde_json::from_str(&planned)
+                    .map_err(|e| format!("Unreadable journal entry for sale {}: {}", id, e))
+            })
+            .transpose()
+    }
+
+    /// Keep the values an increment is about to write
+    fn set_planned(&self, id: &str, seq: u32, planned: &Planned) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal_ops SET planned = ?3 WHERE sale_id = ?1 AND seq = ?2",
+                params![id, seq, json!(planned).to_string()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn mark_op_done(&self, id: &str, seq: u32, warning: Option<&str>) -> Result<(), String> {
+        self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "UPDATE pos_journal_ops SET done_at = ?3 WHERE sale_id = ?1 AND seq = ?2",
+                params![id, seq, db::now_millis()],
+            )?;
+            if let Some(warning) = warning {
+                let warnings: String = tx.query_row(
+                    "SELECT warnings FROM pos_journal WHERE id = ?1",
+                    [id],
+                    |row| row.get(0),
+                )?;
+                let mut warnings: Vec<String> = serde_json::from_str(&warnings).unwrap_or_default();
+                warnings.push(warning.to_string());
+                tx.execute(
+                    "UPDATE pos_journal SET warnings = ?2 WHERE id = ?1",
+                    params![id, json!(warnings).to_string()],
+                )?;
+            }
+            tx.commit()
+        })
+    }
+
+    fn mark_synced(&self, id: &str, attempts: u32) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal
+                 SET status = 'synced', attempts = ?2, last_error = NULL, synced_at = ?3
+                 WHERE id = ?1",
+                params![id, attempts, db::now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn mark_conflict(&self, id: &str, attempts: u32, conflict: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal
+                 SET status = 'conflict', attempts = ?2, last_error = NULL, conflict = ?3
+                 WHERE id = ?1",
+                params![id, attempts, conflict],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn reschedule(&self, id: &str, attempts: u32, error: &str) -> Result<(), String> {
+        // 10s, 20s, 40s, ... capped at 5 minutes
+        let delay_secs = (10 * 2u64.pow(attempts.saturating_sub(1).min(5))).min(5 * 60);
+        let next_attempt_at = db::now_millis() + delay_secs as i64 * 1000;
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE pos_journal SET attempts = ?2, last_error = ?3, next_attempt_at = ?4
+                 WHERE id = ?1",
+                params![id, attempts, error, next_attempt_at],
+            )
+        })?;
+        Ok(())
+    }
+}
+
+fn is_record_id(id: &str) -> bool {
+    id.len() == 15
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+}
+
+fn validate(sale: &NewSale) -> Result<(), String> {
+    if sale.transaction_id.trim().is_empty() {
+        return Err("Transaction id is required".to_string());
+    }
+    if !is_record_id(&sale.shift_id) {
+        return Err(format!("Invalid register shift id: {}", sale.shift_id));
+    }
+    if !PAYMENT_METHODS.contains(&sale.payment_method.as_str()) {
+        return Err(format!("Unknown payment method: {}", sale.payment_method));
+    }
+    if sale.items.is_empty() {
+        return Err("A sale needs at least one item".to_string());
+    }
+    let ids = sale
+        .items
+        .iter()
+        .flat_map(|item| [&item.product_id, &item.variant_id])
+        .chain([&sale.customer_id]);
+    for id in ids.flatten() {
+        if !is_record_id(id) {
+            return Err(format!("Invalid record id: {}", id));
+        }
+    }
+    if let Some(item) = sale.items.iter().find(|item| item.quantity <= 0) {
+        return Err(format!("Invalid quantity: {}", item.quantity));
+    }
+    if [sale.subtotal, sale.discount, sale.total]
+        .iter()
+        .chain(sale.items.iter().map(|item| &item.unit_price))
+        .any(|amount| !amount.is_finite() || *amount < 0.0)
+    {
+        return Err("Amounts must be zero or more".to_string());
+    }
+    Ok(())
+}
+
+/// The PocketBase writes that make up `sale`, in the order the POS page
+/// has always made them
+fn plan(sale_id: &str, sale: &NewSale) -> Vec<JournalOp> {
+    let mut ops = vec![JournalOp::Create {
+        collection: "sales".to_string(),
+        record: json!({
+            "id": sale_id,
+            "channel": "pos",
+            "channel_order_id": sale.transaction_id,
+            "customer": sale.customer_id,
+            "customer_name": sale.customer_name.as_deref().unwrap_or("Walk-in Customer"),
+            "customer_phone": sale.customer_phone.as_deref().unwrap_or(""),
+            "customer_address": "",
+            "subtotal": sale.subtotal,
+            "discount": sale.discount,
+            "total": sale.total,
+            "payment_method": sale.payment_method,
+            // POS sales are handed over at the till
+            "status": "delivered",
+            "notes": sale.notes.as_deref().unwrap_or(""),
+            "register_shift_id": sale.shift_id,
+            "cash_tendered": sale.cash_tendered,
+            "change_given": sale.change_given,
+        }),
+        owner: "channel_order_id".to_string(),
+    }];
+
+    for item in &sale.items {
+        let line_total = item.unit_price * item.quantity as f64;
+        ops.push(JournalOp::Create {
+            collection: "sale_items".to_string(),
+            record: json!({
+                "id": pocketbase::new_record_id(),
+                "sale": sale_id,
+                "product": item.product_id,
+                "variant": item.variant_id,
+                "quantity": item.quantity,
+                "unit_price": item.unit_price,
+                "total_price": line_total,
+                "remarks": item.remarks.as_deref().unwrap_or(""),
+                "discount": 0,
+                "total": line_total,
+            }),
+            owner: "sale".to_string(),
+        });
+
+        if let Some(variant) = &item.variant_id {
+            ops.push(JournalOp::Create {
+                collection: "stock_movements".to_string(),
+                record: json!({
+                    "id": pocketbase::new_record_id(),
+                    "variant": variant,
+                    "movement_type": "sale",
+                    "quantity": -item.quantity,
+                    "reference_id": sale_id,
+                    "source": "pos",
+                    "notes": format!("POS Sale: {}", sale.transaction_id),
+                }),
+                owner: "reference_id".to_string(),
+            });
+            ops.push(JournalOp::DecrementStock {
+                variant: variant.clone(),
+                quantity: item.quantity,
+            });
+        }
+    }
+
+    ops.push(JournalOp::ShiftTotals {
+        shift: sale.shift_id.clone(),
+        payment_method: sale.payment_method.clone(),
+        amount: sale.total,
+    });
+    ops
+}
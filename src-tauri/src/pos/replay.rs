@@ -0,0 +1,448 @@
+//! Replay of journalled sales to PocketBase
+
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+use tauri::{Emitter, Manager};
+
+use super::{DueSale, JournalOp, JournalStatus, Planned, PosJournal};
+use crate::db;
+use crate::pocketbase::{self, quote, PocketBaseClient, PocketBaseError};
+use crate::sidecar::{SidecarState, SidecarSupervisor};
+
+/// Sales replayed this long after they were rung up get a note saying
+/// when, as their PocketBase `created` time is the replay time
+const LATE_AFTER_MS: i64 = 60_000;
+
+/// Why a write did not go through
+#[derive(Debug)]
+pub(super) enum OpError {
+    /// PocketBase is down, nobody is signed in, or it failed internally:
+    /// try again later
+    Transient(String),
+    /// PocketBase rejected the write; retrying as is will not help
+    Conflict(String),
+}
+
+impl From<PocketBaseError> for OpError {
+    fn from(e: PocketBaseError) -> Self {
+        match &e {
+            PocketBaseError::Unavailable { .. } | PocketBaseError::Unauthorized { .. } => {
+                OpError::Transient(e.to_string())
+            }
+            PocketBaseError::Api { status, .. } if *status >= 500 || *status == 429 => {
+                OpError::Transient(e.to_string())
+            }
+            PocketBaseError::Api { message, data, .. } => {
+                // Per-field validation errors say which relation is missing
+                let fields: Vec<String> = data
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(field, error)| {
+                        let reason = error
+                            .get("message")
+                            .and_then(Value::as_str)
+                            .unwrap_or("invalid");
+                        format!("{}: {}", field, reason)
+                    })
+                    .collect();
+                if fields.is_empty() {
+                    OpError::Conflict(message.clone())
+                } else {
+                    OpError::Conflict(format!("{} ({})", message, fields.join(", ")))
+                }
+            }
+            PocketBaseError::NotFound { message } => OpError::Conflict(message.clone()),
+        }
+    }
+}
+
+/// Whether the PocketBase sidecar is up, so a replay has a chance
+pub(super) fn pocketbase_healthy(supervisor: &SidecarSupervisor) -> bool {
+    supervisor
+        .status(pocketbase::SIDECAR_NAME)
+        .map(|status| status.state == SidecarState::Healthy)
+        .unwrap_or(false)
+}
+
+async fn find(
+    client: &PocketBaseClient,
+    collection: &str,
+    id: &str,
+) -> Result<Option<Value>, PocketBaseError> {
+    let filter = format!("id = {}", quote(id));
+    Ok(client
+        .list(collection, Some(&filter), None, 1)
+        .await?
+        .into_iter()
+        .next())
+}
+
+fn number(record: &Value, field: &str) -> f64 {
+    record.get(field).and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+/// Append when a late sale was really made to its notes
+pub(super) fn note_late(record: &mut Value, created_at: i64) {
+    if db::now_millis() - created_at < LATE_AFTER_MS {
+        return;
+    }
+    let Some(at) = chrono::DateTime::from_timestamp_millis(created_at) else {
+        return;
+    };
+    let note = format!(
+        "Recorded offline at {}",
+        at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+    );
+    let notes = record["notes"].as_str().unwrap_or_default();
+    record["notes"] = json!(if notes.is_empty() {
+        note
+    } else {
+        format!("{}\n{}", notes, note)
+    });
+}
+
+async fn create(
+    client: &PocketBaseClient,
+    collection: &str,
+    record: &Value,
+    owner: &str,
+    created_at: i64,
+) -> Result<(), OpError> {
+    let mut record = record.clone();
+    if collection == "sales" {
+        note_late(&mut record, created_at);
+    }
+
+    let error = match client.create(collection, &record).await {
+        Ok(_) => return Ok(()),
+        Err(e @ PocketBaseError::Api { status: 400, .. }) => e,
+        Err(e) => return Err(e.into()),
+    };
+
+    // PocketBase also answers 400 when the id is taken, which is expected
+    // if an earlier attempt got through but its response was lost
+    let id = record["id"].as_str().unwrap_or_default();
+    match find(client, collection, id).await? {
+        Some(existing) if existing.get(owner) == record.get(owner) => Ok(()),
+        Some(_) => Err(OpError::Conflict(format!(
+            "Record id {} is already used by another {} record",
+            id, collection
+        ))),
+        None => Err(error.into()),
+    }
+}
+
+/// Where a planned increment stands, judged from the record as it is now
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum PlanState {
+    /// The record is as it was read, so the values were never written
+    Unwritten,
+    /// The record holds the planned values: an earlier write got through
+    Written,
+    /// Something else changed the record since; whether the earlier write
+    /// got through can't be told
+    Overtaken,
+}
+
+fn same_value(current: Option<&Value>, planned: &Value) -> bool {
+    match (current.and_then(Value::as_f64), planned.as_f64()) {
+        (Some(current), Some(planned)) => (current - planned).abs() < 1e-9,
+        _ => current == Some(planned),
+    }
+}
+
+pub(super) fn plan_state(record: &Value, planned: &Planned) -> PlanState {
+    if record.get("updated").and_then(Value::as_str) == Some(planned.updated.as_str()) {
+        PlanState::Unwritten
+    } else if planned
+        .changes
+        .iter()
+        .all(|(field, value)| same_value(record.get(field), value))
+    {
+        PlanState::Written
+    } else {
+        PlanState::Overtaken
+    }
+}
+
+/// The journalled write being replayed
+struct Step<'a> {
+    journal: &'a PosJournal,
+    client: &'a PocketBaseClient,
+    sale: &'a DueSale,
+    seq: u32,
+}
+
+/// Add `deltas` to fields of `record`, unless an earlier attempt already
+/// did. PocketBase applies `field+` / `field-` modifiers to the value it
+/// holds, so writes made since `record` was read are kept. The increment is
+/// kept in the journal under the sale and step before it is sent, so a
+/// retry after a lost response recognises its own write rather than
+/// applying it twice.
+async fn increment(
+    step: &Step<'_>,
+    collection: &str,
+    record: &Value,
+    deltas: &[(&str, f64)],
+    warning: Option<String>,
+) -> Result<Option<String>, OpError> {
+    let planned = step
+        .journal
+        .planned(&step.sale.id, step.seq)
+        .map_err(OpError::Transient)?;
+    let planned = match planned {
+        Some(planned) => match plan_state(record, &planned) {
+            PlanState::Unwritten => planned,
+            PlanState::Written => return Ok(planned.warning),
+            PlanState::Overtaken => {
+                return Err(OpError::Conflict(format!(
+                    "the {} record changed while this sale was syncing, so it may already count the sale; check it, then retry",
+                    collection
+                )))
+            }
+        },
+        None => {
+            let mut planned = Planned {
+                updated: record
+                    .get("updated")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                deltas: Map::new(),
+                changes: Map::new(),
+                warning,
+            };
+            for &(field, delta) in deltas {
+                let (modifier, by) = if delta < 0.0 {
+                    (format!("{}-", field), -delta)
+                } else {
+                    (format!("{}+", field), delta)
+                };
+                planned.deltas.insert(modifier, json!(by));
+                planned
+                    .changes
+                    .insert(field.to_string(), json!(number(record, field) + delta));
+            }
+            step.journal
+                .set_planned(&step.sale.id, step.seq, &planned)
+                .map_err(OpError::Transient)?;
+            planned
+        }
+    };
+
+    let id = record["id"].as_str().unwrap_or_default();
+    step.client
+        .update(collection, id, &Value::Object(planned.deltas.clone()))
+        .await?;
+    Ok(planned.warning)
+}
+
+/// Returns a warning if the variant was oversold while offline. Its stock
+/// then goes negative, so the oversale shows in the stock levels too.
+async fn decrement_stock(
+    step: &Step<'_>,
+    variant: &str,
+    quantity: i64,
+) -> Result<Option<String>, OpError> {
+    let existing = find(step.client, "product_variants", variant)
+        .await?
+        .ok_or_else(|| OpError::Conflict(format!("Variant {} no longer exists", variant)))?;
+    let stock = number(&existing, "stock_level") as i64;
+    let warning = (stock < quantity).then(|| {
+        let name = existing
+            .get("variant_name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .unwrap_or(variant);
+        format!(
+            "Oversold {}: {} sold with {} in stock when the sale synced; stock is now {}",
+            name,
+            quantity,
+            stock,
+            stock - quantity
+        )
+    });
+    increment(
+        step,
+        "product_variants",
+        &existing,
+        &[("stock_level", -(quantity as f64))],
+        warning,
+    )
+    .await
+}
+
+/// Returns a warning if the shift is gone or already closed
+async fn shift_totals(
+    step: &Step<'_>,
+    shift_id: &str,
+    payment_method: &str,
+    amount: f64,
+) -> Result<Option<String>, OpError> {
+    let Some(shift) = find(step.client, "cash_register_shifts", shift_id).await? else {
+        return Ok(Some(format!(
+            "Register shift {} no longer exists; its totals were not updated",
+            shift_id
+        )));
+    };
+
+    let deltas: &[(&str, f64)] = match payment_method {
+        // Cash sales also add to what the drawer should hold
+        "cash" => &[("total_cash_sales", amount), ("expected_balance", amount)],
+        "card" => &[("total_card_sales", amount)],
+        _ => &[("total_upi_sales", amount)],
+    };
+    let open = shift.get("status").and_then(Value::as_str) == Some("open");
+    let warning = (!open).then(|| {
+        format!(
+            "Register shift {} was closed before this sale synced; its totals were updated",
+            shift_id
+        )
+    });
+    increment(step, "cash_register_shifts", &shift, deltas, warning).await
+}
+
+/// Make one write
+async fn apply(step: &Step<'_>, op: &JournalOp) -> Result<Option<String>, OpError> {
+    match op {
+        JournalOp::Create {
+            collection,
+            record,
+            owner,
+        } => create(step.client, collection, record, owner, step.sale.created_at)
+            .await
+            .map(|()| None),
+        JournalOp::DecrementStock { variant, quantity } => {
+            decrement_stock(step, variant, *quantity).await
+        }
+        JournalOp::ShiftTotals {
+            shift,
+            payment_method,
+            amount,
+        } => shift_totals(step, shift, payment_method, *amount).await,
+    }
+}
+
+/// Push the writes of `sale` that have not gone through yet, stopping at
+/// the first failure. Returns the sale's status afterwards.
+async fn replay_sale(
+    journal: &PosJournal,
+    client: &PocketBaseClient,
+    sale: &DueSale,
+) -> Result<JournalStatus, String> {
+    let attempts = sale.attempts + 1;
+    let ops = match journal.remaining_ops(&sale.id) {
+        Ok(ops) => ops,
+        Err(e) => {
+            journal.mark_conflict(&sale.id, attempts, &e)?;
+            return Ok(JournalStatus::Conflict);
+        }
+    };
+
+    for (seq, op) in ops {
+        let step = Step {
+            journal,
+            client,
+            sale,
+            seq,
+        };
+        match apply(&step, &op).await {
+            Ok(warning) => journal.mark_op_done(&sale.id, seq, warning.as_deref())?,
+            Err(OpError::Transient(error)) => {
+                journal.reschedule(&sale.id, attempts, &error)?;
+                return Ok(JournalStatus::Pending);
+            }
+            Err(OpError::Conflict(error)) => {
+                let conflict = format!("Could not {}: {}", op.describe(), error);
+                log::warn!("POS sale {} conflicts: {}", sale.id, conflict);
+                journal.mark_conflict(&sale.id, attempts, &conflict)?;
+                return Ok(JournalStatus::Conflict);
+            }
+        }
+    }
+
+    journal.mark_synced(&sale.id, attempts)?;
+    Ok(JournalStatus::Synced)
+}
+
+/// Replay one sale straight away, for the till waiting on it
+pub(super) async fn replay_now(
+    journal: &PosJournal,
+    client: &PocketBaseClient,
+    id: &str,
+) -> Result<(), String> {
+    let _replaying = journal.replaying.lock().await;
+    let entry = journal.get(id)?;
+    if entry.status != JournalStatus::Pending {
+        return Ok(());
+    }
+    let sale = DueSale {
+        id: entry.id,
+        created_at: entry.created_at,
+        attempts: entry.attempts,
+    };
+    replay_sale(journal, client, &sale).await?;
+    Ok(())
+}
+
+pub(super) fn emit_entry(app: &tauri::AppHandle, journal: &PosJournal, id: &str) {
+    if let Ok(entry) = journal.get(id) {
+        let _ = app.emit("pos-journal", entry);
+    }
+}
+
+/// Background worker replaying journalled sales while PocketBase is up
+pub async fn run_worker(app: tauri::AppHandle) {
+    let journal = app.state::<PosJournal>();
+    let client = app.state::<PocketBaseClient>();
+    let supervisor = app.state::<SidecarSupervisor>();
+    let mut was_healthy = false;
+
+    loop {
+        // Wake when a sale is journalled, or periodically for retries
+        let _ = tokio::time::timeout(Duration::from_secs(5), journal.wake.notified()).await;
+
+        let healthy = pocketbase_healthy(&supervisor);
+        if healthy && !was_healthy {
+            // Retries backed off during the outage are due as soon as it ends
+            if let Err(e) = journal.make_due() {
+                log::error!("Failed to update POS journal: {}", e);
+            }
+        }
+        was_healthy = healthy;
+        if !healthy {
+            continue;
+        }
+
+        let _replaying = journal.replaying.lock().await;
+        let due = match journal.due() {
+            Ok(due) => due,
+            Err(e) => {
+                log::error!("Failed to read POS journal: {}", e);
+                continue;
+            }
+        };
+
+        for sale in &due {
+            let status = replay_sale(&journal, &client, sale).await;
+            emit_entry(&app, &journal, &sale.id);
+            match status {
+                Ok(JournalStatus::Pending) => {
+                    // PocketBase went away again; later sales wait so they
+                    // reach it in the order they were made
+                    break;
+                }
+                Err(e) => {
+                    log::error!("Failed to update POS journal: {}", e);
+                    break;
+                }
+                Ok(_) => {}
+            }
+        }
+        if due.len() == 50 {
+            journal.wake.notify_one();
+        }
+    }
+}
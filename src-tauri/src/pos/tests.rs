@@ -0,0 +1,302 @@
+//! Journal bookkeeping tests; replay against PocketBase is not covered
+
+use std::sync::Arc;
+
+use serde_json::json;
+
+use super::replay::{note_late, plan_state, OpError, PlanState};
+use super::{
+    is_record_id, plan, JournalOp, JournalStatus, NewSale, NewSaleItem, Planned, PosJournal,
+};
+use crate::db::{self, LocalDb};
+use crate::pocketbase::{self, PocketBaseError};
+use crate::testing;
+
+const SHIFT: &str = "shift0000000001";
+const VARIANT: &str = "variant00000001";
+const PRODUCT: &str = "product00000001";
+
+fn journal() -> PosJournal {
+    let dir = testing::temp_dir("pos");
+    PosJournal::new(Arc::new(LocalDb::open(&dir.join("luminila.db")).unwrap()))
+}
+
+fn sale() -> NewSale {
+    NewSale {
+        transaction_id: "TXN-M1ABCDEF".to_string(),
+        items: vec![
+            NewSaleItem {
+                product_id: Some(PRODUCT.to_string()),
+                variant_id: Some(VARIANT.to_string()),
+                quantity: 2,
+                unit_price: 450.0,
+                remarks: None,
+            },
+            NewSaleItem {
+                product_id: Some(PRODUCT.to_string()),
+                variant_id: None,
+                quantity: 1,
+                unit_price: 100.0,
+                remarks: Some("Gift wrap".to_string()),
+            },
+        ],
+        subtotal: 1000.0,
+        discount: 50.0,
+        total: 950.0,
+        payment_method: "cash".to_string(),
+        cash_tendered: Some(1000.0),
+        change_given: Some(50.0),
+        customer_id: None,
+        customer_name: None,
+        customer_phone: None,
+        shift_id: SHIFT.to_string(),
+        notes: None,
+    }
+}
+
+#[test]
+fn record_ids_match_pocketbase_format() {
+    for _ in 0..100 {
+        assert!(is_record_id(&pocketbase::new_record_id()));
+    }
+    assert!(!is_record_id("Shift0000000001"));
+    assert!(!is_record_id("shift"));
+}
+
+#[test]
+fn plan_mirrors_the_online_sale_flow() {
+    let ops = plan("sale00000000001", &sale());
+    let kinds: Vec<&str> = ops
+        .iter()
+        .map(|op| match op {
+            JournalOp::Create { collection, .. } => collection.as_str(),
+            JournalOp::DecrementStock { .. } => "stock",
+            JournalOp::ShiftTotals { .. } => "shift",
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "sales",
+            "sale_items",
+            "stock_movements",
+            "stock",
+            "sale_items",
+            "shift"
+        ]
+    );
+
+    let JournalOp::Create { record, owner, .. } = &ops[0] else {
+        unreachable!()
+    };
+    assert_eq!(record["id"], "sale00000000001");
+    assert_eq!(record["customer_name"], "Walk-in Customer");
+    assert_eq!(record["customer"], json!(null));
+    assert_eq!(owner, "channel_order_id");
+
+    let JournalOp::Create { record, .. } = &ops[2] else {
+        unreachable!()
+    };
+    assert!(is_record_id(record["id"].as_str().unwrap()));
+    assert_eq!(record["quantity"], -2);
+    assert_eq!(record["reference_id"], "sale00000000001");
+    assert_eq!(
+        ops[3],
+        JournalOp::DecrementStock {
+            variant: VARIANT.to_string(),
+            quantity: 2
+        }
+    );
+}
+
+#[test]
+fn recorded_sale_is_pending_until_replayed() {
+    let journal = journal();
+    let entry = journal.record(&sale()).unwrap();
+    assert!(is_record_id(&entry.id));
+    assert_eq!(entry.status, JournalStatus::Pending);
+    assert_eq!((entry.ops_done, entry.ops_total), (0, 6));
+    assert_eq!(entry.item_count, 2);
+    assert_eq!(journal.pending_count().unwrap(), 1);
+
+    let ops = journal.remaining_ops(&entry.id).unwrap();
+    assert_eq!(ops.len(), 6);
+    journal
+        .mark_op_done(&entry.id, ops[0].0, Some("First warning"))
+        .unwrap();
+    journal
+        .mark_op_done(&entry.id, ops[1].0, Some("Second warning"))
+        .unwrap();
+    let remaining = journal.remaining_ops(&entry.id).unwrap();
+    assert_eq!(remaining, ops[2..]);
+
+    let entry = journal.get(&entry.id).unwrap();
+    assert_eq!(entry.ops_done, 2);
+    assert_eq!(entry.warnings, ["First warning", "Second warning"]);
+}
+
+#[test]
+fn invalid_sales_are_not_journalled() {
+    let journal = journal();
+    let cases: [fn(&mut NewSale); 5] = [
+        |sale| sale.items.clear(),
+        |sale| sale.items[0].quantity = 0,
+        |sale| sale.payment_method = "cheque".to_string(),
+        |sale| sale.shift_id = String::new(),
+        |sale| sale.customer_id = Some("walk-in".to_string()),
+    ];
+    for break_sale in cases {
+        let mut sale = sale();
+        break_sale(&mut sale);
+        assert!(journal.record(&sale).is_err());
+    }
+    assert_eq!(journal.pending_count().unwrap(), 0);
+}
+
+#[test]
+fn conflicts_can_be_retried_or_discarded() {
+    let journal = journal();
+    let first = journal.record(&sale()).unwrap();
+    let second = journal.record(&sale()).unwrap();
+    assert!(journal.retry(&first.id).is_err());
+    assert!(journal.discard(&first.id).is_err());
+
+    for entry in [&first, &second] {
+        journal
+            .mark_conflict(&entry.id, 1, "Could not create sales record")
+            .unwrap();
+    }
+    assert_eq!(journal.pending_count().unwrap(), 0);
+    let conflicts = journal.list(Some(JournalStatus::Conflict), 10).unwrap();
+    assert_eq!(conflicts.len(), 2);
+
+    let retried = journal.retry(&first.id).unwrap();
+    assert_eq!(retried.status, JournalStatus::Pending);
+    assert_eq!(retried.conflict, None);
+    assert_eq!(retried.attempts, 0);
+    assert_eq!(journal.pending_count().unwrap(), 1);
+
+    let discarded = journal.discard(&second.id).unwrap();
+    assert_eq!(discarded.status, JournalStatus::Discarded);
+}
+
+fn planned_stock(stock: i64) -> Planned {
+    Planned {
+        updated: "2024-01-01 10:00:00.000Z".to_string(),
+        deltas: json!({ "stock_level-": 2 }).as_object().unwrap().clone(),
+        changes: json!({ "stock_level": stock }).as_object().unwrap().clone(),
+        warning: None,
+    }
+}
+
+#[test]
+fn planned_increments_survive_until_retried() {
+    let journal = journal();
+    let entry = journal.record(&sale()).unwrap();
+    let (seq, _) = journal.remaining_ops(&entry.id).unwrap()[1].clone();
+    assert_eq!(journal.planned(&entry.id, seq).unwrap(), None);
+
+    journal
+        .set_planned(&entry.id, seq, &planned_stock(3))
+        .unwrap();
+    assert_eq!(
+        journal.planned(&entry.id, seq).unwrap(),
+        Some(planned_stock(3))
+    );
+
+    // After a conflict staff decide, so the retry plans afresh
+    journal.mark_conflict(&entry.id, 1, "Changed").unwrap();
+    journal.retry(&entry.id).unwrap();
+    assert_eq!(journal.planned(&entry.id, seq).unwrap(), None);
+}
+
+#[test]
+fn planned_increments_are_written_once() {
+    let planned = planned_stock(3);
+    let unwritten = json!({ "updated": "2024-01-01 10:00:00.000Z", "stock_level": 5 });
+    assert_eq!(plan_state(&unwritten, &planned), PlanState::Unwritten);
+
+    let written = json!({ "updated": "2024-01-01 10:00:01.000Z", "stock_level": 3.0 });
+    assert_eq!(plan_state(&written, &planned), PlanState::Written);
+
+    let overtaken = json!({ "updated": "2024-01-01 10:00:02.000Z", "stock_level": 4 });
+    assert_eq!(plan_state(&overtaken, &planned), PlanState::Overtaken);
+}
+
+#[test]
+fn synced_sales_wait_for_their_invoice() {
+    let journal = journal();
+    let entry = journal.record(&sale()).unwrap();
+    assert!(journal.set_invoice(&entry.id, "inv", "INV-1").is_err());
+
+    journal.mark_synced(&entry.id, 1).unwrap();
+    assert_eq!(journal.pending_count().unwrap(), 0);
+    assert_eq!(journal.uninvoiced().unwrap().len(), 1);
+
+    let entry = journal.set_invoice(&entry.id, "inv", "INV-1").unwrap();
+    assert_eq!(entry.invoice_number.as_deref(), Some("INV-1"));
+    assert!(journal.uninvoiced().unwrap().is_empty());
+}
+
+#[test]
+fn backed_off_sales_are_due_again_after_an_outage() {
+    let journal = journal();
+    let entry = journal.record(&sale()).unwrap();
+    assert_eq!(journal.due().unwrap().len(), 1);
+
+    journal
+        .reschedule(&entry.id, 1, "PocketBase unavailable")
+        .unwrap();
+    assert!(journal.due().unwrap().is_empty());
+    assert_eq!(
+        journal.get(&entry.id).unwrap().last_error.as_deref(),
+        Some("PocketBase unavailable")
+    );
+
+    journal.make_due().unwrap();
+    let due = journal.due().unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].attempts, 1);
+}
+
+#[test]
+fn errors_are_split_into_transient_and_conflicts() {
+    let transient = [
+        PocketBaseError::Unavailable {
+            message: "connection refused".to_string(),
+        },
+        PocketBaseError::Unauthorized {
+            message: "token expired".to_string(),
+        },
+        PocketBaseError::Api {
+            status: 503,
+            message: "busy".to_string(),
+            data: json!(null),
+        },
+    ];
+    for error in transient {
+        assert!(matches!(OpError::from(error), OpError::Transient(_)));
+    }
+
+    let error = PocketBaseError::Api {
+        status: 400,
+        message: "Failed to create record.".to_string(),
+        data: json!({ "variant": { "code": "validation_missing_rel_records", "message": "Failed to find all relation records with the provided ids." } }),
+    };
+    match OpError::from(error) {
+        OpError::Conflict(message) => assert!(message.contains("variant: Failed to find")),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn late_sales_are_noted() {
+    let mut record = json!({ "notes": "" });
+    note_late(&mut record, db::now_millis());
+    assert_eq!(record["notes"], "");
+
+    let mut record = json!({ "notes": "PhonePe: T123" });
+    note_late(&mut record, db::now_millis() - 2 * 60 * 60 * 1000);
+    let notes = record["notes"].as_str().unwrap();
+    assert!(notes.starts_with("PhonePe: T123\nRecorded offline at "));
+}
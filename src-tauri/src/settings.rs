@@ -0,0 +1,122 @@
+//! Desktop-side settings stored as JSON in the app config directory
+//!
+//! These cover things the Rust side needs before the webview is up (ports,
+//! sidecar options). Business settings stay in PocketBase.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::backup::BackupSettings;
+use crate::whatsapp::{CatalogSyncSettings, SessionConfig};
+
+const FILE_NAME: &str = "settings.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Fixed port for the WPPConnect sidecar. When unset a free port is
+    /// picked at launch, preferring the historical default.
+    pub whatsapp_port: Option<u16>,
+    /// WhatsApp numbers to start on the sidecar
+    pub whatsapp_sessions: Vec<SessionConfig>,
+    /// Background sync of products to the WhatsApp Business catalog
+    pub whatsapp_catalog: CatalogSyncSettings,
+    /// Scheduled backups of PocketBase's data
+    pub backup: BackupSettings,
+}
+
+/// Where settings are stored
+pub fn path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(app
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to resolve app config dir: {}", e))?
+        .join(FILE_NAME))
+}
+
+/// The settings file and its current contents, managed in Tauri state.
+/// Every change goes through [`SettingsStore::update`], so subsystems saving
+/// their own section don't undo each other's changes.
+pub struct SettingsStore {
+    path: PathBuf,
+    current: Mutex<Settings>,
+    /// Why the file on disk could not be read. It is left alone rather than
+    /// overwritten with defaults.
+    unreadable: Option<String>,
+}
+
+impl SettingsStore {
+    /// Load settings, falling back to defaults if the file is missing or
+    /// invalid
+    pub fn open(path: PathBuf) -> Self {
+        let (settings, unreadable) = match std::fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(settings) => (settings, None),
+                Err(e) => {
+                    log::warn!("Ignoring invalid {}: {}", path.display(), e);
+                    (Settings::default(), Some(e.to_string()))
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Settings::default(), None),
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                (Settings::default(), Some(e.to_string()))
+            }
+        };
+        Self {
+            path,
+            current: Mutex::new(settings),
+            unreadable,
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Change settings and write them to disk. Nothing changes if the file
+    /// could not be read at launch or can't be written now.
+    pub fn update(&self, change: impl FnOnce(&mut Settings)) -> Result<(), String> {
+        if let Some(e) = &self.unreadable {
+            return Err(format!(
+                "Not saving settings over {}, which could not be read ({}). Fix or remove it and restart the app.",
+                self.path.display(),
+                e
+            ));
+        }
+        let mut current = self.current.lock().unwrap();
+        let mut settings = current.clone();
+        change(&mut settings);
+        write(&self.path, &settings)?;
+        *current = settings;
+        Ok(())
+    }
+}
+
+/// Write through a temporary file renamed into place, so a crash mid-write
+/// never leaves a truncated file
+fn write(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let contents = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let written = File::create(&temp).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| std::fs::rename(&temp, path)) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Restart pacing: exponential backoff with jitter and a crash-loop breaker
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+/// Tuning for how quickly a failing sidecar is restarted
+#[derive(Debug, Clone)]
+pub struct BackoffPolicy {
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between retries
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Random spread applied to each delay, as a fraction (0.2 = ±20%)
+    pub jitter: f64,
+    /// Terminations within `crash_loop_window` that trip the breaker
+    pub crash_loop_limit: usize,
+    pub crash_loop_window: Duration,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(300),
+            multiplier: 2.0,
+            jitter: 0.2,
+            crash_loop_limit: 5,
+            crash_loop_window: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Per-sidecar restart bookkeeping
+#[derive(Debug)]
+pub struct RestartTracker {
+    policy: BackoffPolicy,
+    /// Failed start attempts since the sidecar was last healthy
+    attempt: u32,
+    /// Recent unexpected terminations, oldest first
+    terminations: VecDeque<Instant>,
+    /// Set when the breaker trips; holds the reason
+    tripped: Option<String>,
+    next_retry_at: Option<Instant>,
+}
+
+impl RestartTracker {
+    pub fn new(policy: BackoffPolicy) -> Self {
+        Self {
+            policy,
+            attempt: 0,
+            terminations: VecDeque::new(),
+            tripped: None,
+            next_retry_at: None,
+        }
+    }
+
+    /// Delay for the current attempt, without jitter
+    fn base_delay(&self) -> Duration {
+        let factor = self.policy.multiplier.powi(self.attempt.min(32) as i32);
+        let delay = self.policy.initial_delay.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.policy.max_delay.as_secs_f64()))
+    }
+
+    /// Schedule the next retry after a failure and return its delay
+    pub fn schedule_retry(&mut self, now: Instant) -> Duration {
+        let base = self.base_delay().as_secs_f64();
+        let spread = base * self.policy.jitter;
+        let delay = if spread > 0.0 {
+            base + rand::thread_rng().gen_range(-spread..=spread)
+        } else {
+            base
+        };
+        let delay = Duration::from_secs_f64(delay.max(0.0));
+
+        self.attempt = self.attempt.saturating_add(1);
+        self.next_retry_at = Some(now + delay);
+        delay
+    }
+
+    /// Whether a start attempt is allowed at `now`
+    pub fn can_retry(&self, now: Instant) -> bool {
+        self.tripped.is_none() && self.next_retry_at.map_or(true, |at| now >= at)
+    }
+
+    pub fn next_retry_at(&self) -> Option<Instant> {
+        self.next_retry_at
+    }
+
+    /// Record an unexpected exit. Returns true if this trips the breaker.
+    pub fn record_termination(&mut self, now: Instant) -> bool {
+        self.terminations.push_back(now);
+        while let Some(&oldest) = self.terminations.front() {
+            if now.duration_since(oldest) > self.policy.crash_loop_window {
+                self.terminations.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.tripped.is_none() && self.terminations.len() >= self.policy.crash_loop_limit {
+            self.tripped = Some(format!(
+                "Crashed {} times within {} minutes",
+                self.terminations.len(),
+                self.policy.crash_loop_window.as_secs() / 60
+            ));
+            self.next_retry_at = None;
+            return true;
+        }
+        false
+    }
+
+    /// Reason the breaker tripped, if it has
+    pub fn tripped(&self) -> Option<&str> {
+        self.tripped.as_deref()
+    }
+
+    /// Called once the sidecar passes a health check
+    pub fn mark_healthy(&mut self) {
+        self.attempt = 0;
+        self.next_retry_at = None;
+    }
+
+    /// Clear the breaker and crash history so the sidecar can start right away
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.terminations.clear();
+        self.tripped = None;
+        self.next_retry_at = None;
+    }
+}
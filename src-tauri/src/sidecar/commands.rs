@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tauri::{Manager, State};
+
+use super::{logs, LogLine, SidecarStatus, SidecarSupervisor};
+
+/// Tauri command to get the status of one sidecar
+#[tauri::command]
+pub async fn get_sidecar_status(
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+) -> Result<SidecarStatus, String> {
+    supervisor.detailed_status(&name)
+}
+
+/// Tauri command that resolves once a sidecar is healthy, or fails after
+/// `timeout_ms` (default 30s) or as soon as the sidecar is parked in `failed`
+#[tauri::command]
+pub async fn wait_for_sidecar_ready(
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+    timeout_ms: Option<u64>,
+) -> Result<SidecarStatus, String> {
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(30_000));
+    supervisor.wait_ready(&name, timeout).await
+}
+
+/// Tauri command to get the base URL (`http://127.0.0.1:<port>`) of a sidecar
+#[tauri::command]
+pub async fn get_sidecar_url(
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+) -> Result<String, String> {
+    supervisor.url(&name)
+}
+
+/// Tauri command to get the status of every registered sidecar
+#[tauri::command]
+pub async fn list_sidecars(
+    supervisor: State<'_, SidecarSupervisor>,
+) -> Result<Vec<SidecarStatus>, String> {
+    supervisor
+        .names()
+        .iter()
+        .map(|name| supervisor.detailed_status(name))
+        .collect()
+}
+
+/// Tauri command to restart a sidecar
+#[tauri::command]
+pub async fn restart_sidecar(
+    app: tauri::AppHandle,
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+) -> Result<String, String> {
+    supervisor.restart(&app, &name).await?;
+    Ok(format!("{} restarted", name))
+}
+
+/// Tauri command to clear a sidecar's crash-loop breaker after it was parked
+/// in the `failed` state
+#[tauri::command]
+pub async fn reset_sidecar_breaker(
+    app: tauri::AppHandle,
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+) -> Result<(), String> {
+    supervisor.reset_breaker(&app, &name)
+}
+
+/// Tauri command to get the most recent output lines of a sidecar
+#[tauri::command]
+pub async fn tail_sidecar_log(
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+    lines: Option<usize>,
+) -> Result<Vec<LogLine>, String> {
+    supervisor.tail_log(&name, lines.unwrap_or(200))
+}
+
+/// Tauri command to search a sidecar's log files (case-insensitive)
+#[tauri::command]
+pub async fn search_sidecar_log(
+    supervisor: State<'_, SidecarSupervisor>,
+    name: String,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<String>, String> {
+    supervisor
+        .search_log(&name, &query, limit.unwrap_or(0))
+        .await
+}
+
+/// Where to write a log export: `path` if it is a zip file directly in the
+/// log folder or the Downloads folder, and a timestamped file in the log
+/// folder when no path is given
+fn export_destination(
+    app: &tauri::AppHandle,
+    log_dir: &Path,
+    path: Option<String>,
+) -> Result<PathBuf, String> {
+    let Some(path) = path else {
+        return Ok(log_dir.join(format!(
+            "luminila-sidecar-logs-{}.zip",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        )));
+    };
+
+    let path = PathBuf::from(path);
+    let is_zip = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    let (Some(dir), Some(file_name), true) = (path.parent(), path.file_name(), is_zip) else {
+        return Err(format!("Not a zip file path: {}", path.display()));
+    };
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Failed to find {}: {}", dir.display(), e))?;
+    let allowed = [Some(log_dir.to_path_buf()), app.path().download_dir().ok()]
+        .into_iter()
+        .flatten()
+        .filter_map(|allowed| allowed.canonicalize().ok())
+        .any(|allowed| allowed == dir);
+    if !allowed {
+        return Err("Logs can only be exported to the log folder or Downloads".to_string());
+    }
+    Ok(dir.join(file_name))
+}
+
+/// Tauri command to export all sidecar logs as a zip for support, into the
+/// log folder or Downloads. Returns the path of the written archive.
+#[tauri::command]
+pub async fn export_sidecar_logs(
+    app: tauri::AppHandle,
+    supervisor: State<'_, SidecarSupervisor>,
+    path: Option<String>,
+) -> Result<String, String> {
+    let dest = export_destination(&app, supervisor.log_dir(), path)?;
+
+    let files = supervisor.log_files();
+    tauri::async_runtime::spawn_blocking({
+        let dest = dest.clone();
+        move || logs::export_zip(&files, &dest)
+    })
+    .await
+    .map_err(|e| format!("Export task failed: {}", e))??;
+
+    Ok(dest.display().to_string())
+}
@@ -0,0 +1,220 @@
+//! Persistent capture of sidecar stdout/stderr
+//!
+//! Each sidecar writes to `<app log dir>/sidecars/<name>.log`, rotated by size
+//! into `<name>.log.1` … `<name>.log.N`, and keeps its most recent lines in
+//! memory for quick tailing from the UI.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// Rotate once the active file exceeds this size
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Rotated files kept per sidecar
+const MAX_ROTATED_FILES: usize = 5;
+/// Lines kept in memory per sidecar
+const RING_CAPACITY: usize = 1000;
+/// Matches a search returns when no limit is given
+const DEFAULT_SEARCH_LIMIT: usize = 500;
+/// Most matches a search returns, whatever limit is asked for
+const MAX_SEARCH_LIMIT: usize = 5000;
+
+/// Output stream a line came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    /// Lines written by the supervisor itself (spawn, exit, ...)
+    Supervisor,
+}
+
+impl LogStream {
+    fn as_str(self) -> &'static str {
+        match self {
+            LogStream::Stdout => "stdout",
+            LogStream::Stderr => "stderr",
+            LogStream::Supervisor => "supervisor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    pub stream: LogStream,
+    pub text: String,
+}
+
+struct LogFile {
+    file: Option<File>,
+    size: u64,
+}
+
+/// Rotating log file plus in-memory ring buffer for one sidecar
+pub struct SidecarLog {
+    path: PathBuf,
+    file: Mutex<LogFile>,
+    recent: Mutex<VecDeque<LogLine>>,
+}
+
+impl SidecarLog {
+    pub fn new(dir: &Path, name: &str) -> Self {
+        Self {
+            path: dir.join(format!("{}.log", name)),
+            file: Mutex::new(LogFile {
+                file: None,
+                size: 0,
+            }),
+            recent: Mutex::new(VecDeque::with_capacity(RING_CAPACITY)),
+        }
+    }
+
+    /// Append a chunk of output. Sidecars may emit several lines at once.
+    pub fn append(&self, stream: LogStream, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            self.append_line(stream, line.trim_end());
+        }
+    }
+
+    fn append_line(&self, stream: LogStream, text: &str) {
+        let line = LogLine {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            stream,
+            text: text.to_string(),
+        };
+        let formatted = format!("{} [{}] {}\n", line.timestamp, stream.as_str(), line.text);
+
+        if let Err(e) = self.write(formatted.as_bytes()) {
+            log::warn!("Failed to write {}: {}", self.path.display(), e);
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RING_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(line);
+    }
+
+    fn write(&self, bytes: &[u8]) -> std::io::Result<()> {
+        let mut log_file = self.file.lock().unwrap();
+
+        if log_file.file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            log_file.size = file.metadata()?.len();
+            log_file.file = Some(file);
+        }
+
+        if log_file.size + bytes.len() as u64 > MAX_FILE_BYTES {
+            log_file.file = None;
+            self.rotate()?;
+            log_file.file = Some(File::create(&self.path)?);
+            log_file.size = 0;
+        }
+
+        if let Some(file) = log_file.file.as_mut() {
+            file.write_all(bytes)?;
+            log_file.size += bytes.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Shift `name.log.N-1` → `name.log.N` … `name.log` → `name.log.1`
+    fn rotate(&self) -> std::io::Result<()> {
+        let _ = std::fs::remove_file(self.rotated_path(MAX_ROTATED_FILES));
+        for index in (1..MAX_ROTATED_FILES).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    /// Log files on disk, oldest first
+    pub fn files(&self) -> Vec<PathBuf> {
+        (1..=MAX_ROTATED_FILES)
+            .rev()
+            .map(|index| self.rotated_path(index))
+            .chain(std::iter::once(self.path.clone()))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// The last `count` lines captured since the app started
+    pub fn tail(&self, count: usize) -> Vec<LogLine> {
+        let recent = self.recent.lock().unwrap();
+        let skip = recent.len().saturating_sub(count);
+        recent.iter().skip(skip).cloned().collect()
+    }
+
+    /// Case-insensitive search across all log files, newest matches last.
+    /// A `limit` of 0 means the default; larger ones are capped.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<String>, String> {
+        let limit = match limit {
+            0 => DEFAULT_SEARCH_LIMIT,
+            limit => limit.min(MAX_SEARCH_LIMIT),
+        };
+        let needle = query.to_lowercase();
+        let mut matches = VecDeque::new();
+
+        for path in self.files() {
+            let file = File::open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if line.to_lowercase().contains(&needle) {
+                    if matches.len() == limit {
+                        matches.pop_front();
+                    }
+                    matches.push_back(line);
+                }
+            }
+        }
+
+        Ok(matches.into())
+    }
+}
+
+/// Bundle log files into a zip archive for support
+pub fn export_zip(files: &[PathBuf], dest: &Path) -> Result<(), String> {
+    use zip::write::SimpleFileOptions;
+
+    let file =
+        File::create(dest).map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for path in files {
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        let contents =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        zip.start_file(file_name.to_string_lossy(), options)
+            .map_err(|e| format!("Failed to add {} to archive: {}", path.display(), e))?;
+        zip.write_all(&contents)
+            .map_err(|e| format!("Failed to add {} to archive: {}", path.display(), e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finish archive: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Sidecar process supervision
+//!
+//! Every bundled helper process (WPPConnect, PocketBase, ...) is described by a
+//! [`SidecarSpec`] and registered with the [`SidecarSupervisor`], which is kept
+//! in Tauri state. The supervisor spawns each sidecar, polls its health URL and
+//! restarts it according to its [`RestartPolicy`].
+
+mod backoff;
+pub mod commands;
+mod logs;
+mod process;
+mod status;
+mod supervisor;
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub use backoff::BackoffPolicy;
+pub use logs::LogLine;
+pub use process::pick_port;
+pub use status::{SidecarState, SidecarStatus};
+pub use supervisor::{start_all, SidecarSupervisor};
+
+/// What to do when a sidecar exits or stops answering health checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Restart whenever the process dies or becomes unhealthy
+    Always,
+    /// Start once and leave it alone afterwards
+    Never,
+}
+
+/// Points in a sidecar's life that code outside the supervisor can hook,
+/// see [`SidecarSupervisor::on_lifecycle`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    /// A freshly spawned process passed its first health check
+    Ready,
+    /// The process exited, whether it was stopped or crashed. On a stop,
+    /// hooks run before the stop returns, so files the sidecar shares with
+    /// a hook are let go of before its data directory is touched.
+    Exited,
+}
+
+/// How to ask a sidecar to exit before resorting to a kill
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShutdownMethod {
+    /// Send SIGTERM (falls back to a kill where signals are unsupported)
+    Signal,
+    /// POST to this URL
+    Http(String),
+}
+
+/// Static description of a managed sidecar
+#[derive(Debug, Clone)]
+pub struct SidecarSpec {
+    /// Registry key used by the frontend, e.g. `"wppconnect"`
+    pub name: String,
+    /// Name of the bundled binary (see `bundle.externalBin` in tauri.conf.json)
+    pub binary: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    /// Working directory, created before spawning. Defaults to the app's.
+    pub current_dir: Option<PathBuf>,
+    /// URL that returns a 2xx response while the sidecar is healthy
+    pub health_url: String,
+    /// Sent as `Authorization: Bearer` on supervisor requests (health, shutdown)
+    pub bearer_token: Option<String>,
+    pub restart_policy: RestartPolicy,
+    /// Delay between health checks
+    pub health_interval: Duration,
+    /// How long a freshly spawned sidecar may take to pass its first health check
+    pub startup_timeout: Duration,
+    /// Consecutive failed health checks before the sidecar is restarted
+    pub unhealthy_threshold: u32,
+    /// Port the sidecar binds, checked for leftovers before spawning
+    pub port: Option<u16>,
+    pub shutdown: ShutdownMethod,
+    /// How long to wait for a graceful exit before killing the process
+    pub shutdown_timeout: Duration,
+    pub backoff: BackoffPolicy,
+}
+
+impl SidecarSpec {
+    pub fn new(name: &str, binary: &str, health_url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            binary: binary.to_string(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            current_dir: None,
+            health_url: health_url.to_string(),
+            bearer_token: None,
+            restart_policy: RestartPolicy::Always,
+            health_interval: Duration::from_secs(5),
+            startup_timeout: Duration::from_secs(30),
+            unhealthy_threshold: 3,
+            port: None,
+            shutdown: ShutdownMethod::Signal,
+            shutdown_timeout: Duration::from_secs(10),
+            backoff: BackoffPolicy::default(),
+        }
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn current_dir(mut self, dir: PathBuf) -> Self {
+        self.current_dir = Some(dir);
+        self
+    }
+
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    pub fn bearer_token(mut self, token: &str) -> Self {
+        self.bearer_token = Some(token.to_string());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn shutdown_url(mut self, url: &str) -> Self {
+        self.shutdown = ShutdownMethod::Http(url.to_string());
+        self
+    }
+}
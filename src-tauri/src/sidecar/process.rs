@@ -0,0 +1,146 @@
+//! OS-level helpers for sidecar processes: pid files, signals and port probes
+
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::Duration;
+
+use sysinfo::{Pid, ProcessesToUpdate, Signal, System};
+
+/// Whether nothing is listening on `127.0.0.1:port`
+pub fn is_port_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// Use `preferred` if it is free, otherwise let the OS pick a free port
+pub fn pick_port(preferred: u16) -> Result<u16, String> {
+    if is_port_free(preferred) {
+        return Ok(preferred);
+    }
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .map_err(|e| format!("Failed to find a free port: {}", e))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to find a free port: {}", e))?
+        .port();
+    log::info!("Port {} is busy, using {} instead", preferred, port);
+    Ok(port)
+}
+
+/// Poll until `port` is released or `timeout` elapses
+pub async fn wait_for_port_free(port: u16, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if is_port_free(port) {
+            return true;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+pub fn read_pid_file(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn write_pid_file(path: &Path, pid: u32) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Err(e) = std::fs::write(path, pid.to_string()) {
+        log::warn!("Failed to write pid file {}: {}", path.display(), e);
+    }
+}
+
+pub fn remove_pid_file(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+/// Whether `pid` is alive and looks like an instance of `binary`.
+///
+/// The name check guards against the OS having reused the pid for an
+/// unrelated process since the pid file was written.
+pub fn is_sidecar_process(pid: u32, binary: &str) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+
+    let Some(process) = system.process(pid) else {
+        return false;
+    };
+
+    let exe_stem = process
+        .exe()
+        .and_then(|exe| exe.file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned());
+    let name = process.name().to_string_lossy().into_owned();
+    let name = name.trim_end_matches(".exe");
+
+    // Linux truncates process names to 15 characters
+    exe_stem.is_some_and(|stem| stem.starts_with(binary))
+        || (!name.is_empty() && binary.starts_with(name))
+}
+
+/// Ask a process to exit. Returns false if the platform has no such signal
+/// (SIGTERM on Windows) or the process is gone.
+pub fn terminate(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    system
+        .process(pid)
+        .and_then(|process| process.kill_with(Signal::Term))
+        .unwrap_or(false)
+}
+
+/// Forcefully kill a process
+pub fn kill(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    system.process(pid).is_some_and(|process| process.kill())
+}
+
+/// Whether `pid` still exists
+pub fn is_alive(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    system.process(pid).is_some()
+}
+
+/// Kill a sidecar left behind by a previous app run.
+///
+/// `pid_file` is the file written when that run spawned the sidecar. Returns
+/// an error if `port` is still taken afterwards, since spawning would then
+/// fail to bind.
+pub async fn reap_orphan(pid_file: &Path, binary: &str, port: Option<u16>) -> Result<(), String> {
+    if let Some(pid) = read_pid_file(pid_file) {
+        if is_sidecar_process(pid, binary) {
+            log::warn!("Found orphaned {} (pid {}), stopping it", binary, pid);
+            terminate(pid);
+            for _ in 0..25 {
+                if !is_alive(pid) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            if is_alive(pid) {
+                kill(pid);
+            }
+        }
+        remove_pid_file(pid_file);
+    }
+
+    if let Some(port) = port {
+        if !wait_for_port_free(port, Duration::from_secs(5)).await {
+            return Err(format!(
+                "Port {} is already in use by another program",
+                port
+            ));
+        }
+    }
+
+    Ok(())
+}
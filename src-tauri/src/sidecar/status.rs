@@ -0,0 +1,105 @@
+//! Typed sidecar status shared by the `sidecar-status` event and the status commands
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Lifecycle state of a sidecar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SidecarState {
+    /// Not running and no start is pending
+    Stopped,
+    /// Process spawned, waiting for its first successful health check
+    Starting,
+    Healthy,
+    /// Running but failing health checks
+    Unhealthy,
+    /// Down and waiting for the next restart attempt
+    Restarting,
+    /// Crash-looping; parked until the breaker is reset
+    Failed,
+}
+
+/// Snapshot of a sidecar's status, as sent to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SidecarStatus {
+    pub name: String,
+    pub state: SidecarState,
+    pub pid: Option<u32>,
+    /// Localhost port the sidecar listens on, if it has one
+    pub port: Option<u16>,
+    /// Seconds since the current process was spawned
+    pub uptime_secs: Option<u64>,
+    /// Number of times the sidecar has been respawned
+    pub restart_count: u32,
+    /// Exit code of the last process that terminated
+    pub last_exit_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub last_health_latency_ms: Option<u64>,
+    /// Unix timestamp (ms) of the next restart attempt, if one is scheduled
+    pub next_retry_at: Option<u64>,
+    /// Whatever the sidecar's owner reports through
+    /// [`SidecarSupervisor::on_details`](super::SidecarSupervisor::on_details),
+    /// e.g. the WhatsApp sessions WPPConnect hosts. Only filled in by
+    /// `get_sidecar_status` and `list_sidecars`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Mutable bookkeeping behind [`SidecarStatus`]
+#[derive(Debug)]
+pub(super) struct StatusInfo {
+    pub state: SidecarState,
+    pub started_at: Option<Instant>,
+    pub spawn_count: u32,
+    pub last_exit_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub last_health_latency: Option<Duration>,
+}
+
+impl Default for StatusInfo {
+    fn default() -> Self {
+        Self {
+            state: SidecarState::Stopped,
+            started_at: None,
+            spawn_count: 0,
+            last_exit_code: None,
+            last_error: None,
+            last_health_latency: None,
+        }
+    }
+}
+
+impl StatusInfo {
+    pub fn snapshot(
+        &self,
+        name: &str,
+        pid: Option<u32>,
+        port: Option<u16>,
+        next_retry_at: Option<Instant>,
+    ) -> SidecarStatus {
+        SidecarStatus {
+            name: name.to_string(),
+            state: self.state,
+            pid,
+            port,
+            uptime_secs: self.started_at.map(|at| at.elapsed().as_secs()),
+            restart_count: self.spawn_count.saturating_sub(1),
+            last_exit_code: self.last_exit_code,
+            last_error: self.last_error.clone(),
+            last_health_latency_ms: self.last_health_latency.map(|d| d.as_millis() as u64),
+            next_retry_at: next_retry_at.map(unix_millis),
+            details: None,
+        }
+    }
+}
+
+/// Convert a monotonic instant into a Unix timestamp in milliseconds
+pub(super) fn unix_millis(at: Instant) -> u64 {
+    let wall = SystemTime::now() + at.saturating_duration_since(Instant::now());
+    wall.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,717 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tauri_plugin_shell::process::CommandChild;
+
+use super::backoff::RestartTracker;
+use super::logs::{LogLine, LogStream, SidecarLog};
+use super::process;
+use super::status::{SidecarState, SidecarStatus, StatusInfo};
+use super::{Lifecycle, RestartPolicy, ShutdownMethod, SidecarSpec};
+
+/// Timeout for health checks and shutdown requests
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(3);
+
+type LifecycleHook = Arc<dyn Fn(Lifecycle) + Send + Sync>;
+type DetailsHook = Arc<dyn Fn() -> serde_json::Value + Send + Sync>;
+
+/// Runtime state of one registered sidecar
+struct ManagedSidecar {
+    spec: SidecarSpec,
+    running: AtomicBool,
+    /// Handle of the process we spawned, if it is still alive
+    child: Mutex<Option<CommandChild>>,
+    /// Serializes start/stop so the monitor and commands don't race
+    lifecycle: tokio::sync::Mutex<()>,
+    /// Set while we are deliberately stopping the process, so its exit is
+    /// not counted as a crash
+    stopping: AtomicBool,
+    /// Set by [`SidecarSupervisor::pause`] to keep the monitor from
+    /// restarting a sidecar that was stopped for maintenance
+    paused: AtomicBool,
+    restarts: Mutex<RestartTracker>,
+    status: Mutex<StatusInfo>,
+    /// Broadcasts state changes to `wait_ready` callers
+    state_tx: tokio::sync::watch::Sender<SidecarState>,
+    log: SidecarLog,
+    pid_file: PathBuf,
+    hooks: Mutex<Vec<LifecycleHook>>,
+    details: Mutex<Option<DetailsHook>>,
+}
+
+impl ManagedSidecar {
+    fn pid(&self) -> Option<u32> {
+        self.child.lock().unwrap().as_ref().map(CommandChild::pid)
+    }
+
+    fn snapshot(&self) -> SidecarStatus {
+        let next_retry_at = self.restarts.lock().unwrap().next_retry_at();
+        self.status.lock().unwrap().snapshot(
+            &self.spec.name,
+            self.pid(),
+            self.spec.port,
+            next_retry_at,
+        )
+    }
+
+    /// Apply `update` to the status and emit `sidecar-status` if the state changed
+    fn update<R: Runtime>(&self, app: &AppHandle<R>, update: impl FnOnce(&mut StatusInfo)) {
+        let changed = {
+            let mut status = self.status.lock().unwrap();
+            let before = status.state;
+            update(&mut status);
+            (status.state != before).then_some(status.state)
+        };
+
+        if let Some(state) = changed {
+            self.state_tx.send_replace(state);
+            let _ = app.emit("sidecar-status", self.snapshot());
+        }
+    }
+
+    fn set_state<R: Runtime>(&self, app: &AppHandle<R>, state: SidecarState) {
+        self.update(app, |status| status.state = state);
+    }
+
+    /// Run the hooks for `event` off the async runtime, as they may block
+    async fn run_hooks(&self, event: Lifecycle) {
+        let hooks = self.hooks.lock().unwrap().clone();
+        if hooks.is_empty() {
+            return;
+        }
+        let ran = tauri::async_runtime::spawn_blocking(move || {
+            for hook in hooks {
+                hook(event);
+            }
+        })
+        .await;
+        if let Err(e) = ran {
+            log::error!("{} {:?} hook failed: {}", self.spec.name, event, e);
+        }
+    }
+
+    /// How long the monitor should sleep before its next iteration
+    fn monitor_delay(&self) -> Duration {
+        let interval = self.spec.health_interval;
+        if self.running.load(Ordering::SeqCst) {
+            return interval;
+        }
+        match self.restarts.lock().unwrap().next_retry_at() {
+            Some(at) => at
+                .saturating_duration_since(Instant::now())
+                .clamp(Duration::from_millis(100), interval),
+            None => interval,
+        }
+    }
+
+    /// Record an unexpected exit and move to `restarting` or `failed`
+    fn record_crash<R: Runtime>(&self, app: &AppHandle<R>, error: Option<String>) {
+        let now = Instant::now();
+        let tripped = {
+            let mut restarts = self.restarts.lock().unwrap();
+            if restarts.record_termination(now) {
+                restarts.tripped().map(str::to_string)
+            } else {
+                restarts.schedule_retry(now);
+                None
+            }
+        };
+
+        self.update(app, |status| {
+            status.started_at = None;
+            match tripped {
+                Some(reason) => {
+                    log::error!("{} is crash-looping, giving up: {}", self.spec.name, reason);
+                    status.state = SidecarState::Failed;
+                    status.last_error = Some(reason);
+                }
+                None => {
+                    status.state = SidecarState::Restarting;
+                    if error.is_some() {
+                        status.last_error = error;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Registry of named sidecars, managed in Tauri state
+pub struct SidecarSupervisor {
+    sidecars: Mutex<HashMap<String, Arc<ManagedSidecar>>>,
+    client: reqwest::Client,
+    /// Directory holding one pid file per sidecar
+    state_dir: PathBuf,
+    /// Directory holding the rotating sidecar log files
+    log_dir: PathBuf,
+}
+
+impl SidecarSupervisor {
+    pub fn new(state_dir: PathBuf, log_dir: PathBuf) -> Self {
+        Self {
+            sidecars: Mutex::new(HashMap::new()),
+            client: reqwest::Client::new(),
+            state_dir,
+            log_dir,
+        }
+    }
+
+    /// Register a sidecar. Registering the same name twice replaces the spec.
+    pub fn register(&self, spec: SidecarSpec) {
+        let managed = Arc::new(ManagedSidecar {
+            pid_file: self.state_dir.join(format!("{}.pid", spec.name)),
+            restarts: Mutex::new(RestartTracker::new(spec.backoff.clone())),
+            log: SidecarLog::new(&self.log_dir, &spec.name),
+            spec,
+            running: AtomicBool::new(false),
+            child: Mutex::new(None),
+            lifecycle: tokio::sync::Mutex::new(()),
+            stopping: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            status: Mutex::new(StatusInfo::default()),
+            state_tx: tokio::sync::watch::channel(SidecarState::Stopped).0,
+            hooks: Mutex::new(Vec::new()),
+            details: Mutex::new(None),
+        });
+        self.sidecars
+            .lock()
+            .unwrap()
+            .insert(managed.spec.name.clone(), managed);
+    }
+
+    /// Call `hook` whenever the sidecar becomes ready or exits. Hooks run on
+    /// a blocking thread and should return quickly.
+    pub fn on_lifecycle(
+        &self,
+        name: &str,
+        hook: impl Fn(Lifecycle) + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        self.get(name)?.hooks.lock().unwrap().push(Arc::new(hook));
+        Ok(())
+    }
+
+    /// Have `details` fill in [`SidecarStatus::details`] for detailed
+    /// status requests, so the status can say more than the supervisor
+    /// knows about, e.g. the sessions a sidecar hosts
+    pub fn on_details(
+        &self,
+        name: &str,
+        details: impl Fn() -> serde_json::Value + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        *self.get(name)?.details.lock().unwrap() = Some(Arc::new(details));
+        Ok(())
+    }
+
+    /// HTTP client shared with code that talks to the sidecars
+    pub fn http_client(&self) -> reqwest::Client {
+        self.client.clone()
+    }
+
+    /// Names of all registered sidecars
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.sidecars.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn get(&self, name: &str) -> Result<Arc<ManagedSidecar>, String> {
+        self.sidecars
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown sidecar: {}", name))
+    }
+
+    /// Current status of a sidecar
+    pub fn status(&self, name: &str) -> Result<SidecarStatus, String> {
+        Ok(self.get(name)?.snapshot())
+    }
+
+    /// Current status of a sidecar, with the details its owner reports
+    pub fn detailed_status(&self, name: &str) -> Result<SidecarStatus, String> {
+        let sidecar = self.get(name)?;
+        let mut status = sidecar.snapshot();
+        let details = sidecar.details.lock().unwrap().clone();
+        status.details = details.map(|details| details());
+        Ok(status)
+    }
+
+    /// Base URL of a sidecar's localhost HTTP server
+    pub fn url(&self, name: &str) -> Result<String, String> {
+        let sidecar = self.get(name)?;
+        let port = sidecar
+            .spec
+            .port
+            .ok_or_else(|| format!("{} does not listen on a port", name))?;
+        Ok(format!("http://127.0.0.1:{}", port))
+    }
+
+    /// Most recent captured output lines of a sidecar
+    pub fn tail_log(&self, name: &str, count: usize) -> Result<Vec<LogLine>, String> {
+        Ok(self.get(name)?.log.tail(count))
+    }
+
+    /// Search a sidecar's log files, off the async runtime
+    pub async fn search_log(
+        &self,
+        name: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, String> {
+        let sidecar = self.get(name)?;
+        let query = query.to_string();
+        tauri::async_runtime::spawn_blocking(move || sidecar.log.search(&query, limit))
+            .await
+            .map_err(|e| format!("Search task failed: {}", e))?
+    }
+
+    /// Log files of every sidecar, for export
+    pub fn log_files(&self) -> Vec<PathBuf> {
+        let sidecars: Vec<_> = self.sidecars.lock().unwrap().values().cloned().collect();
+        sidecars
+            .iter()
+            .flat_map(|sidecar| sidecar.log.files())
+            .collect()
+    }
+
+    pub fn log_dir(&self) -> &std::path::Path {
+        &self.log_dir
+    }
+
+    /// Clear the crash-loop breaker so the monitor starts the sidecar again
+    pub fn reset_breaker<R: Runtime>(&self, app: &AppHandle<R>, name: &str) -> Result<(), String> {
+        let sidecar = self.get(name)?;
+        sidecar.restarts.lock().unwrap().reset();
+        sidecar.update(app, |status| {
+            if status.state == SidecarState::Failed {
+                status.state = SidecarState::Stopped;
+                status.last_error = None;
+            }
+        });
+        log::info!("{} breaker cleared", name);
+        Ok(())
+    }
+
+    /// Check if a sidecar answers its health URL, recording the latency
+    pub async fn check_health(&self, name: &str) -> Result<bool, String> {
+        let sidecar = self.get(name)?;
+        let started = Instant::now();
+        let mut request = self
+            .client
+            .get(&sidecar.spec.health_url)
+            .timeout(HEALTH_TIMEOUT);
+        if let Some(token) = &sidecar.spec.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        let healthy = match request.send().await {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        };
+
+        if healthy {
+            sidecar.status.lock().unwrap().last_health_latency = Some(started.elapsed());
+        }
+        Ok(healthy)
+    }
+
+    /// Receiver that observes every state change of a sidecar
+    pub fn watch_state(
+        &self,
+        name: &str,
+    ) -> Result<tokio::sync::watch::Receiver<SidecarState>, String> {
+        Ok(self.get(name)?.state_tx.subscribe())
+    }
+
+    /// Wait until a sidecar is healthy, failing early if it is parked in
+    /// `failed` and with an error once `timeout` elapses
+    pub async fn wait_ready(&self, name: &str, timeout: Duration) -> Result<SidecarStatus, String> {
+        let sidecar = self.get(name)?;
+        let mut state_rx = sidecar.state_tx.subscribe();
+
+        let ready = tokio::time::timeout(timeout, async {
+            loop {
+                let state = *state_rx.borrow_and_update();
+                match state {
+                    SidecarState::Healthy => return Ok(()),
+                    SidecarState::Failed => {
+                        let error = sidecar.status.lock().unwrap().last_error.clone();
+                        return Err(format!(
+                            "{} failed: {}",
+                            name,
+                            error.unwrap_or_else(|| "unknown error".to_string())
+                        ));
+                    }
+                    _ => {}
+                }
+                if state_rx.changed().await.is_err() {
+                    return Err(format!("{} was unregistered", name));
+                }
+            }
+        })
+        .await;
+
+        match ready {
+            Ok(Ok(())) => Ok(sidecar.snapshot()),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(format!(
+                "{} not ready after {}ms",
+                name,
+                timeout.as_millis()
+            )),
+        }
+    }
+
+    /// Spawn a sidecar process unless it is already running, then wait for it
+    /// to pass a health check within its startup timeout. A process someone
+    /// else spawned first, such as the monitor, is waited for the same way.
+    ///
+    /// A sidecar that never becomes healthy is stopped and counted as a crash.
+    pub async fn start<R: Runtime>(&self, app: &AppHandle<R>, name: &str) -> Result<(), String> {
+        let sidecar = self.get(name)?;
+        match self.spawn(app, &sidecar).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return self
+                    .wait_ready(name, sidecar.spec.startup_timeout)
+                    .await
+                    .map(|_| ())
+            }
+            Err(e) => {
+                // A binary that can't be spawned fails the same way every
+                // time, so it counts toward the breaker like a crash
+                log::error!("{}", e);
+                sidecar.record_crash(app, Some(e.clone()));
+                return Err(e);
+            }
+        }
+
+        let spec = &sidecar.spec;
+        let deadline = tokio::time::Instant::now() + spec.startup_timeout;
+        loop {
+            if !sidecar.running.load(Ordering::SeqCst) {
+                // Exited during startup; the Terminated handler recorded it
+                return Err(format!("{} exited during startup", spec.name));
+            }
+            if self.check_health(name).await? {
+                sidecar.restarts.lock().unwrap().mark_healthy();
+                sidecar.set_state(app, SidecarState::Healthy);
+                sidecar.run_hooks(Lifecycle::Ready).await;
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+
+        let error = format!(
+            "{} did not become ready within {}s",
+            spec.name,
+            spec.startup_timeout.as_secs()
+        );
+        log::error!("{}", error);
+        self.stop(app, name).await?;
+        sidecar.record_crash(app, Some(error.clone()));
+        Err(error)
+    }
+
+    /// Spawn the process. Returns false if it was already running.
+    ///
+    /// Any process left over from a previous app run is stopped first so the
+    /// new one can bind its port.
+    async fn spawn<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        sidecar: &Arc<ManagedSidecar>,
+    ) -> Result<bool, String> {
+        use tauri_plugin_shell::ShellExt;
+
+        let _guard = sidecar.lifecycle.lock().await;
+        if sidecar.running.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+
+        let spec = &sidecar.spec;
+        process::reap_orphan(&sidecar.pid_file, &spec.binary, spec.port).await?;
+
+        let mut command = app
+            .shell()
+            .sidecar(&spec.binary)
+            .map_err(|e| format!("Failed to create {} command: {}", spec.name, e))?
+            .args(&spec.args)
+            .envs(spec.env.clone());
+        if let Some(dir) = &spec.current_dir {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            command = command.current_dir(dir);
+        }
+
+        let (mut rx, child) = command
+            .spawn()
+            .map_err(|e| format!("Failed to spawn {}: {}", spec.name, e))?;
+
+        let pid = child.pid();
+        sidecar.log.append(
+            LogStream::Supervisor,
+            format!("Spawned {} (pid {})", spec.binary, pid).as_bytes(),
+        );
+        process::write_pid_file(&sidecar.pid_file, pid);
+        *sidecar.child.lock().unwrap() = Some(child);
+        sidecar.running.store(true, Ordering::SeqCst);
+        sidecar.update(app, |status| {
+            status.state = SidecarState::Starting;
+            status.started_at = Some(Instant::now());
+            status.spawn_count += 1;
+        });
+
+        // Capture sidecar output in background
+        let watched = sidecar.clone();
+        let app_handle = app.clone();
+        tauri::async_runtime::spawn(async move {
+            use tauri_plugin_shell::process::CommandEvent;
+            let name = &watched.spec.name;
+            while let Some(event) = rx.recv().await {
+                match event {
+                    CommandEvent::Stdout(line) => {
+                        log::info!("[{}] {}", name, String::from_utf8_lossy(&line));
+                        watched.log.append(LogStream::Stdout, &line);
+                    }
+                    CommandEvent::Stderr(line) => {
+                        log::warn!("[{}] {}", name, String::from_utf8_lossy(&line));
+                        watched.log.append(LogStream::Stderr, &line);
+                    }
+                    CommandEvent::Terminated(payload) => {
+                        log::warn!("[{}] Terminated with code: {:?}", name, payload.code);
+                        watched.log.append(
+                            LogStream::Supervisor,
+                            format!(
+                                "Terminated with code {:?} (signal {:?})",
+                                payload.code, payload.signal
+                            )
+                            .as_bytes(),
+                        );
+
+                        // Only clear state if it still refers to this process
+                        let current = {
+                            let mut child = watched.child.lock().unwrap();
+                            let current = child.as_ref().map(CommandChild::pid) == Some(pid);
+                            if current {
+                                *child = None;
+                            }
+                            current
+                        };
+                        if !current {
+                            continue;
+                        }
+
+                        watched.running.store(false, Ordering::SeqCst);
+                        process::remove_pid_file(&watched.pid_file);
+                        watched.status.lock().unwrap().last_exit_code = payload.code;
+
+                        // A deliberate stop runs the hooks itself once it is done
+                        if !watched.stopping.load(Ordering::SeqCst) {
+                            let error = format!("Exited unexpectedly with code {:?}", payload.code);
+                            watched.record_crash(&app_handle, Some(error));
+                            watched.run_hooks(Lifecycle::Exited).await;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(true)
+    }
+
+    /// Stop a sidecar: ask it to exit, wait up to its shutdown timeout, then kill it
+    pub async fn stop<R: Runtime>(&self, app: &AppHandle<R>, name: &str) -> Result<(), String> {
+        let sidecar = self.get(name)?;
+        let _guard = sidecar.lifecycle.lock().await;
+        let spec = &sidecar.spec;
+
+        let Some(pid) = sidecar.pid() else {
+            sidecar.running.store(false, Ordering::SeqCst);
+            return Ok(());
+        };
+
+        log::info!("Stopping {} (pid {})", spec.name, pid);
+        sidecar.stopping.store(true, Ordering::SeqCst);
+        let requested = match &spec.shutdown {
+            ShutdownMethod::Http(url) => {
+                let mut request = self.client.post(url).timeout(HEALTH_TIMEOUT);
+                if let Some(token) = &spec.bearer_token {
+                    request = request.bearer_auth(token);
+                }
+                request.send().await.is_ok()
+            }
+            ShutdownMethod::Signal => process::terminate(pid),
+        };
+
+        if requested && wait_for_exit(&sidecar, spec.shutdown_timeout).await {
+            log::info!("{} exited gracefully", spec.name);
+        } else {
+            log::warn!("{} did not exit in time, killing it", spec.name);
+            let child = sidecar.child.lock().unwrap().take();
+            if let Some(child) = child {
+                if let Err(e) = child.kill() {
+                    log::error!("Failed to kill {}: {}", spec.name, e);
+                }
+            }
+        }
+
+        *sidecar.child.lock().unwrap() = None;
+        sidecar.running.store(false, Ordering::SeqCst);
+        sidecar.stopping.store(false, Ordering::SeqCst);
+        process::remove_pid_file(&sidecar.pid_file);
+        sidecar.update(app, |status| {
+            status.state = SidecarState::Stopped;
+            status.started_at = None;
+        });
+        sidecar.run_hooks(Lifecycle::Exited).await;
+
+        if let Some(port) = spec.port {
+            if !process::wait_for_port_free(port, Duration::from_secs(5)).await {
+                log::warn!("{} port {} is still in use after stop", spec.name, port);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop a sidecar and spawn it again
+    pub async fn restart<R: Runtime>(&self, app: &AppHandle<R>, name: &str) -> Result<(), String> {
+        self.stop(app, name).await?;
+        self.start(app, name).await
+    }
+
+    /// Stop a sidecar and keep it down until [`Self::resume`], e.g. while its
+    /// data directory is snapshotted or replaced
+    pub async fn pause<R: Runtime>(&self, app: &AppHandle<R>, name: &str) -> Result<(), String> {
+        let sidecar = self.get(name)?;
+        sidecar.paused.store(true, Ordering::SeqCst);
+        log::info!("Pausing {}", name);
+        if let Err(e) = self.stop(app, name).await {
+            sidecar.paused.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Start a sidecar stopped with [`Self::pause`] and hand it back to the monitor
+    pub async fn resume<R: Runtime>(&self, app: &AppHandle<R>, name: &str) -> Result<(), String> {
+        let sidecar = self.get(name)?;
+        sidecar.paused.store(false, Ordering::SeqCst);
+        log::info!("Resuming {}", name);
+        self.start(app, name).await
+    }
+
+    /// Stop every sidecar, used when the app exits
+    pub async fn stop_all<R: Runtime>(&self, app: &AppHandle<R>) {
+        for name in self.names() {
+            if let Err(e) = self.stop(app, &name).await {
+                log::error!("Failed to stop {}: {}", name, e);
+            }
+        }
+    }
+}
+
+/// Wait for the Terminated event to clear `running`
+async fn wait_for_exit(sidecar: &ManagedSidecar, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while sidecar.running.load(Ordering::SeqCst) {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    true
+}
+
+/// Start every registered sidecar and spawn a health monitor for each
+pub fn start_all<R: Runtime>(app: &AppHandle<R>) {
+    let supervisor = app.state::<SidecarSupervisor>();
+
+    for name in supervisor.names() {
+        let app_handle = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let supervisor = app_handle.state::<SidecarSupervisor>();
+            match supervisor.start(&app_handle, &name).await {
+                Ok(_) => log::info!("{} sidecar started", name),
+                Err(e) => log::warn!("Failed to start {} sidecar: {} (will retry)", name, e),
+            }
+
+            health_monitor_loop(app_handle.clone(), name).await;
+        });
+    }
+}
+
+/// Health monitoring loop - restarts the sidecar if it crashes, backing off
+/// between attempts and parking it in `failed` if it keeps crashing
+async fn health_monitor_loop<R: Runtime>(app: AppHandle<R>, name: String) {
+    let supervisor = app.state::<SidecarSupervisor>();
+    let Ok(sidecar) = supervisor.get(&name) else {
+        return;
+    };
+    let spec = &sidecar.spec;
+    let mut consecutive_failures = 0;
+
+    loop {
+        tokio::time::sleep(sidecar.monitor_delay()).await;
+
+        if !sidecar.running.load(Ordering::SeqCst) {
+            if spec.restart_policy == RestartPolicy::Never || sidecar.paused.load(Ordering::SeqCst)
+            {
+                continue;
+            }
+
+            let now = Instant::now();
+            if !sidecar.restarts.lock().unwrap().can_retry(now) {
+                continue;
+            }
+
+            // Sidecar not running, try to start it
+            log::info!("{} not running, attempting to start...", name);
+            match supervisor.start(&app, &name).await {
+                Ok(_) => {
+                    log::info!("{} started successfully", name);
+                    consecutive_failures = 0;
+                }
+                Err(e) => log::error!("Failed to start {}: {}", name, e),
+            }
+            continue;
+        }
+
+        // Check health
+        if supervisor.check_health(&name).await.unwrap_or(false) {
+            consecutive_failures = 0;
+            sidecar.restarts.lock().unwrap().mark_healthy();
+            sidecar.set_state(&app, SidecarState::Healthy);
+        } else {
+            consecutive_failures += 1;
+            log::warn!(
+                "{} health check failed ({} consecutive)",
+                name,
+                consecutive_failures
+            );
+            sidecar.set_state(&app, SidecarState::Unhealthy);
+
+            if consecutive_failures >= spec.unhealthy_threshold
+                && spec.restart_policy == RestartPolicy::Always
+            {
+                // A hung process keeps its port, so stop it for real before
+                // the next iteration respawns it
+                log::error!("{} appears to be dead, restarting", name);
+                consecutive_failures = 0;
+                if let Err(e) = supervisor.stop(&app, &name).await {
+                    log::error!("Failed to stop {}: {}", name, e);
+                }
+                sidecar.record_crash(&app, Some("Health check failed".to_string()));
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Supervisor tests against fake WPPConnect processes
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tauri::test::MockRuntime;
+use tauri::{App, Manager};
+
+use super::{
+    start_all, BackoffPolicy, Lifecycle, SidecarSpec, SidecarState, SidecarStatus,
+    SidecarSupervisor,
+};
+use crate::testing::fake_wppconnect::{self, CRASH_EXIT_CODE};
+use crate::testing::{self, block_on, eventually, Fault};
+use crate::whatsapp::SIDECAR_NAME;
+
+const TOKEN: &str = "supervisor-test-token";
+const WAIT: Duration = Duration::from_secs(30);
+
+struct Fixture {
+    app: App<MockRuntime>,
+    port: u16,
+    dir: PathBuf,
+}
+
+impl Fixture {
+    /// Register a fake sidecar with fast health checks and backoff
+    fn new(fault: Option<Fault>, tune: impl FnOnce(&mut SidecarSpec)) -> Self {
+        let dir = testing::temp_dir("supervisor");
+        let port = testing::free_port();
+        let mut spec = fake_wppconnect::sidecar_spec(port, TOKEN, dir.join("whatsapp"), fault);
+        spec.health_interval = Duration::from_millis(200);
+        spec.startup_timeout = Duration::from_secs(10);
+        spec.shutdown_timeout = Duration::from_secs(5);
+        spec.backoff = BackoffPolicy {
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(200),
+            ..BackoffPolicy::default()
+        };
+        tune(&mut spec);
+
+        let supervisor = SidecarSupervisor::new(dir.join("sidecars"), dir.join("logs"));
+        supervisor.register(spec);
+        let app = testing::mock_app();
+        app.manage(supervisor);
+        Self { app, port, dir }
+    }
+
+    fn supervisor(&self) -> tauri::State<'_, SidecarSupervisor> {
+        self.app.state::<SidecarSupervisor>()
+    }
+
+    fn status(&self) -> SidecarStatus {
+        self.supervisor().status(SIDECAR_NAME).unwrap()
+    }
+
+    /// Stop the sidecar for good; a plain stop would be undone by the monitor
+    async fn finish(self) {
+        self.supervisor()
+            .pause(self.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn start_waits_for_health_and_stop_is_graceful() {
+    let fixture = Fixture::new(None, |_| {});
+    block_on(async {
+        let supervisor = fixture.supervisor();
+        supervisor
+            .start(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Healthy);
+        assert!(status.pid.is_some());
+        assert_eq!(status.port, Some(fixture.port));
+        assert!(supervisor.check_health(SIDECAR_NAME).await.unwrap());
+
+        // Answered by POST /api/shutdown, which the fake exits on with 0
+        supervisor
+            .stop(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Stopped);
+        assert_eq!(status.pid, None);
+        assert_eq!(status.last_exit_code, Some(0));
+        assert!(super::process::is_port_free(fixture.port));
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn crashed_sidecar_is_restarted() {
+    let fixture = Fixture::new(None, |_| {});
+    block_on(async {
+        start_all(fixture.app.handle());
+        fixture
+            .supervisor()
+            .wait_ready(SIDECAR_NAME, WAIT)
+            .await
+            .unwrap();
+
+        fake_wppconnect::inject_remote(fixture.port, Fault::Crash).await;
+        eventually("a restart after the crash", WAIT, || {
+            let status = fixture.status();
+            status.restart_count == 1 && status.state == SidecarState::Healthy
+        })
+        .await;
+
+        let status = fixture.status();
+        assert_eq!(status.last_exit_code, Some(CRASH_EXIT_CODE));
+        assert!(status
+            .last_error
+            .is_some_and(|error| error.contains("Exited unexpectedly")));
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn hung_sidecar_is_killed_and_replaced() {
+    let fixture = Fixture::new(None, |spec| spec.unhealthy_threshold = 2);
+    block_on(async {
+        start_all(fixture.app.handle());
+        fixture
+            .supervisor()
+            .wait_ready(SIDECAR_NAME, WAIT)
+            .await
+            .unwrap();
+        let hung_pid = fixture.status().pid;
+
+        // The hung process keeps its port, so the replacement can only
+        // become healthy if the supervisor killed it
+        fake_wppconnect::inject_remote(fixture.port, Fault::Hang).await;
+        eventually("the hung sidecar to be replaced", WAIT, || {
+            let status = fixture.status();
+            status.restart_count == 1 && status.state == SidecarState::Healthy
+        })
+        .await;
+
+        let status = fixture.status();
+        assert_ne!(status.pid, hung_pid);
+        assert_eq!(status.last_error.as_deref(), Some("Health check failed"));
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn startup_times_out_when_health_never_answers() {
+    let fixture = Fixture::new(Some(Fault::Hang), |spec| {
+        spec.startup_timeout = Duration::from_secs(1);
+    });
+    block_on(async {
+        let error = fixture
+            .supervisor()
+            .start(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap_err();
+        assert!(error.contains("did not become ready"), "{}", error);
+
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Restarting);
+        assert_eq!(status.pid, None);
+        assert!(status.next_retry_at.is_some());
+        assert!(super::process::is_port_free(fixture.port));
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn crash_loop_trips_the_breaker() {
+    let fixture = Fixture::new(Some(Fault::Crash), |spec| {
+        spec.backoff.crash_loop_limit = 3;
+        spec.backoff.crash_loop_window = Duration::from_secs(60);
+    });
+    block_on(async {
+        start_all(fixture.app.handle());
+        let error = fixture
+            .supervisor()
+            .wait_ready(SIDECAR_NAME, WAIT)
+            .await
+            .unwrap_err();
+        assert!(error.contains("failed"), "{}", error);
+
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Failed);
+        assert_eq!(status.last_exit_code, Some(CRASH_EXIT_CODE));
+
+        // Parked: the monitor leaves it alone until the breaker is reset
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(fixture.status().state, SidecarState::Failed);
+        fixture
+            .supervisor()
+            .reset_breaker(fixture.app.handle(), SIDECAR_NAME)
+            .unwrap();
+        assert_eq!(fixture.status().state, SidecarState::Stopped);
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn spawn_failures_trip_the_breaker() {
+    let fixture = Fixture::new(None, |spec| {
+        spec.binary = "missing-sidecar".to_string();
+        spec.backoff.crash_loop_limit = 3;
+        spec.backoff.crash_loop_window = Duration::from_secs(60);
+    });
+    block_on(async {
+        start_all(fixture.app.handle());
+        let error = fixture
+            .supervisor()
+            .wait_ready(SIDECAR_NAME, WAIT)
+            .await
+            .unwrap_err();
+        assert!(error.contains("failed"), "{}", error);
+
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Failed);
+        assert_eq!(status.pid, None);
+        assert!(status
+            .last_error
+            .is_some_and(|e| e.contains("Crashed 3 times")));
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn slow_health_checks_are_tolerated() {
+    let delay = Duration::from_millis(500);
+    let fixture = Fixture::new(Some(Fault::SlowHealth(delay)), |_| {});
+    block_on(async {
+        fixture
+            .supervisor()
+            .start(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Healthy);
+        assert!(status
+            .last_health_latency_ms
+            .is_some_and(|latency| latency >= delay.as_millis() as u64));
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn start_waits_for_a_start_already_under_way() {
+    let fixture = Fixture::new(Some(Fault::SlowHealth(Duration::from_secs(1))), |_| {});
+    block_on(async {
+        let app = fixture.app.handle().clone();
+        let first = tauri::async_runtime::spawn(async move {
+            let supervisor = app.state::<SidecarSupervisor>();
+            supervisor.start(&app, SIDECAR_NAME).await
+        });
+        eventually("the first start to spawn", WAIT, || {
+            fixture.status().pid.is_some()
+        })
+        .await;
+
+        // As when a restart races the monitor to spawn the process
+        fixture
+            .supervisor()
+            .start(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+        assert_eq!(fixture.status().state, SidecarState::Healthy);
+        first.await.unwrap().unwrap();
+        assert_eq!(fixture.status().restart_count, 0);
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn paused_sidecar_is_not_restarted() {
+    let fixture = Fixture::new(None, |_| {});
+    block_on(async {
+        start_all(fixture.app.handle());
+        let supervisor = fixture.supervisor();
+        supervisor.wait_ready(SIDECAR_NAME, WAIT).await.unwrap();
+
+        supervisor
+            .pause(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(fixture.status().state, SidecarState::Stopped);
+
+        supervisor
+            .resume(fixture.app.handle(), SIDECAR_NAME)
+            .await
+            .unwrap();
+        let status = fixture.status();
+        assert_eq!(status.state, SidecarState::Healthy);
+        assert_eq!(status.restart_count, 1);
+
+        fixture.finish().await;
+    });
+}
+
+#[test]
+fn lifecycle_hooks_follow_the_process() {
+    let fixture = Fixture::new(None, |_| {});
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    fixture
+        .supervisor()
+        .on_lifecycle(SIDECAR_NAME, move |event| seen.lock().unwrap().push(event))
+        .unwrap();
+    assert!(fixture
+        .supervisor()
+        .on_lifecycle("missing", |_| {})
+        .is_err());
+
+    block_on(async {
+        start_all(fixture.app.handle());
+        eventually("the ready hook", WAIT, || {
+            *events.lock().unwrap() == [Lifecycle::Ready]
+        })
+        .await;
+
+        // A crash is an exit too, and the restarted process is ready again
+        fake_wppconnect::inject_remote(fixture.port, Fault::Crash).await;
+        eventually("hooks for the crash and restart", WAIT, || {
+            events.lock().unwrap().len() == 3
+        })
+        .await;
+        assert_eq!(
+            *events.lock().unwrap(),
+            [Lifecycle::Ready, Lifecycle::Exited, Lifecycle::Ready]
+        );
+
+        // Exit hooks have run by the time a stop returns
+        fixture.finish().await;
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                Lifecycle::Ready,
+                Lifecycle::Exited,
+                Lifecycle::Ready,
+                Lifecycle::Exited
+            ]
+        );
+    });
+}
@@ -0,0 +1,677 @@
+//! Fake S3-compatible server
+//!
+//! Holds one bucket in memory and serves the requests backup uploads make,
+//! addressed path-style as with MinIO: object PUT, GET, HEAD and DELETE,
+//! ListObjectsV2, and multipart uploads (create, upload part, list parts,
+//! complete, abort).
+//! Every request must carry a valid Signature Version 4 for the configured
+//! keys, and parts sent with a SHA-256 are checked against it. Part uploads
+//! can be made to fail to exercise resuming, or be damaged on the way in.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::backup::destination::s3;
+
+pub const ACCESS_KEY_ID: &str = "minioadmin";
+pub const SECRET_ACCESS_KEY: &str = "minioadmin-secret";
+pub const REGION: &str = "us-east-1";
+
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub body: Vec<u8>,
+    /// `x-amz-meta-sha256` it was stored with
+    pub sha256: Option<String>,
+}
+
+struct Part {
+    body: Vec<u8>,
+    /// Base64 SHA-256 the part was sent with
+    checksum: Option<String>,
+}
+
+struct Upload {
+    key: String,
+    sha256: Option<String>,
+    parts: BTreeMap<u32, Part>,
+}
+
+struct Shared {
+    port: u16,
+    bucket: String,
+    stopped: AtomicBool,
+    objects: Mutex<HashMap<String, Object>>,
+    uploads: Mutex<HashMap<String, Upload>>,
+    next_upload: Mutex<u32>,
+    /// Part uploads to accept before failing the rest, if set
+    parts_before_failing: Mutex<Option<u32>>,
+    /// Part uploads to damage a byte of before they are checked
+    parts_to_corrupt: Mutex<u32>,
+    /// Neither check nor report part checksums
+    without_checksums: AtomicBool,
+    /// `METHOD /path?query` of every request, in order
+    requests: Mutex<Vec<String>>,
+}
+
+impl Shared {
+    fn stop(&self) {
+        if !self.stopped.swap(true, Ordering::SeqCst) {
+            let _ = TcpStream::connect(("127.0.0.1", self.port));
+        }
+    }
+}
+
+/// In-process fake S3 server, stopped when dropped
+pub struct FakeS3 {
+    shared: Arc<Shared>,
+}
+
+impl FakeS3 {
+    /// Listen on a free localhost port, serving `bucket`
+    pub fn start(bucket: &str) -> Self {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("fake S3 can bind");
+        let port = listener
+            .local_addr()
+            .expect("bound socket has an address")
+            .port();
+        let shared = Arc::new(Shared {
+            port,
+            bucket: bucket.to_string(),
+            stopped: AtomicBool::new(false),
+            objects: Mutex::new(HashMap::new()),
+            uploads: Mutex::new(HashMap::new()),
+            next_upload: Mutex::new(1),
+            parts_before_failing: Mutex::new(None),
+            parts_to_corrupt: Mutex::new(0),
+            without_checksums: AtomicBool::new(false),
+            requests: Mutex::new(Vec::new()),
+        });
+        let serving = shared.clone();
+        std::thread::spawn(move || serve(listener, serving));
+        Self { shared }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://127.0.0.1:{}", self.shared.port)
+    }
+
+    /// Target for this server's bucket with the right keys
+    pub fn target(&self, prefix: &str) -> s3::S3Target {
+        s3::S3Target {
+            endpoint: self.endpoint(),
+            region: REGION.to_string(),
+            bucket: self.shared.bucket.clone(),
+            prefix: prefix.to_string(),
+            access_key_id: ACCESS_KEY_ID.to_string(),
+            secret_access_key: SECRET_ACCESS_KEY.to_string(),
+            path_style: true,
+        }
+    }
+
+    /// Accept `count` more part uploads, then answer the rest with a 500.
+    /// `None` accepts all of them again.
+    pub fn fail_parts_after(&self, count: Option<u32>) {
+        *self.shared.parts_before_failing.lock().unwrap() = count;
+    }
+
+    /// Flip a byte in the next `count` part uploads, as a bad connection
+    /// might
+    pub fn corrupt_parts(&self, count: u32) {
+        *self.shared.parts_to_corrupt.lock().unwrap() = count;
+    }
+
+    /// Behave like a service without checksum support, which ignores the
+    /// checksums sent and lists parts without them
+    pub fn without_checksums(&self) {
+        self.shared.without_checksums.store(true, Ordering::SeqCst);
+    }
+
+    pub fn object(&self, key: &str) -> Option<Object> {
+        self.shared.objects.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .shared
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Multipart uploads neither completed nor aborted
+    pub fn open_uploads(&self) -> usize {
+        self.shared.uploads.lock().unwrap().len()
+    }
+
+    /// Requests whose `METHOD /path?query` contains `pattern`
+    pub fn request_count(&self, pattern: &str) -> usize {
+        self.shared
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|seen| seen.contains(pattern))
+            .count()
+    }
+}
+
+impl Drop for FakeS3 {
+    fn drop(&mut self) {
+        self.shared.stop();
+    }
+}
+
+fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let shared = shared.clone();
+        std::thread::spawn(move || handle(stream, &shared));
+    }
+}
+
+struct Request {
+    method: String,
+    /// Encoded path, as sent
+    path: String,
+    /// Decoded query parameters
+    query: Vec<(String, String)>,
+    /// Lowercase names
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body,
+    })
+}
+
+/// Recompute the request's signature with the server's copy of the secret
+fn signature_matches(request: &Request) -> bool {
+    let Some(authorization) = request.headers.get("authorization") else {
+        return false;
+    };
+    let Some(signed_headers) = authorization
+        .split(", ")
+        .find_map(|part| part.strip_prefix("SignedHeaders="))
+    else {
+        return false;
+    };
+    let (Some(amz_date), Some(payload_hash)) = (
+        request.headers.get("x-amz-date"),
+        request.headers.get("x-amz-content-sha256"),
+    ) else {
+        return false;
+    };
+    if *payload_hash != hex::encode(Sha256::digest(&request.body)) {
+        return false;
+    }
+
+    let headers: Vec<(String, String)> = signed_headers
+        .split(';')
+        .map(|name| {
+            let value = request.headers.get(name).cloned().unwrap_or_default();
+            (name.to_string(), value)
+        })
+        .collect();
+    let query: Vec<(&str, &str)> = request
+        .query
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    let expected = s3::authorization(
+        ACCESS_KEY_ID,
+        SECRET_ACCESS_KEY,
+        REGION,
+        amz_date,
+        &s3::Canonical {
+            method: &request.method,
+            path: &request.path,
+            query: &s3::canonical_query(&query),
+            headers: &headers,
+            payload_hash,
+        },
+    );
+    expected == *authorization
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn xml(status: u16, body: String) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/xml".to_string())],
+            body: body.into_bytes(),
+        }
+    }
+
+    fn error(status: u16, code: &str, message: &str) -> Self {
+        Self::xml(
+            status,
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code><Message>{}</Message></Error>",
+                code, message
+            ),
+        )
+    }
+}
+
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", &hex::encode(Sha256::digest(body))[..32])
+}
+
+fn checksum(body: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(Sha256::digest(body))
+}
+
+fn handle(mut stream: TcpStream, shared: &Shared) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let Some(request) = read_request(&stream) else {
+        return;
+    };
+    let query: Vec<String> = request
+        .query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    shared.requests.lock().unwrap().push(format!(
+        "{} {}?{}",
+        request.method,
+        request.path,
+        query.join("&")
+    ));
+
+    let head = request.method == "HEAD";
+    let response = route(shared, &request);
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str("\r\n");
+    let _ = stream.write_all(out.as_bytes());
+    if !head {
+        let _ = stream.write_all(&response.body);
+    }
+    let _ = stream.flush();
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    }
+}
+
+fn route(shared: &Shared, request: &Request) -> Response {
+    if !signature_matches(request) {
+        return Response::error(
+            403,
+            "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided.",
+        );
+    }
+    let path = percent_decode(&request.path);
+    let Some((bucket, key)) = path.trim_start_matches('/').split_once('/') else {
+        return Response::error(400, "InvalidRequest", "Object key is required");
+    };
+    if bucket != shared.bucket {
+        return Response::error(404, "NoSuchBucket", "The specified bucket does not exist");
+    }
+    let key = key.to_string();
+    let upload_id = request.param("uploadId").map(str::to_string);
+
+    match (request.method.as_str(), upload_id) {
+        ("POST", None) if request.param("uploads").is_some() => {
+            let mut next = shared.next_upload.lock().unwrap();
+            let upload_id = format!("upload-{}", *next);
+            *next += 1;
+            shared.uploads.lock().unwrap().insert(
+                upload_id.clone(),
+                Upload {
+                    key: key.clone(),
+                    sha256: request.headers.get("x-amz-meta-sha256").cloned(),
+                    parts: BTreeMap::new(),
+                },
+            );
+            Response::xml(
+                200,
+                format!(
+                    "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                    bucket, key, upload_id
+                ),
+            )
+        }
+        ("PUT", Some(upload_id)) => {
+            let Some(number) = request
+                .param("partNumber")
+                .and_then(|number| number.parse().ok())
+            else {
+                return Response::error(400, "InvalidArgument", "Part number is required");
+            };
+            {
+                let mut remaining = shared.parts_before_failing.lock().unwrap();
+                match remaining.as_mut() {
+                    Some(0) => {
+                        return Response::error(500, "InternalError", "Scripted part failure")
+                    }
+                    Some(count) => *count -= 1,
+                    None => {}
+                }
+            }
+            let mut body = request.body.clone();
+            {
+                let mut remaining = shared.parts_to_corrupt.lock().unwrap();
+                if *remaining > 0 && !body.is_empty() {
+                    *remaining -= 1;
+                    body[0] ^= 0xff;
+                }
+            }
+            let sent = request
+                .headers
+                .get("x-amz-checksum-sha256")
+                .filter(|_| !shared.without_checksums.load(Ordering::SeqCst))
+                .cloned();
+            if sent.as_ref().is_some_and(|sent| *sent != checksum(&body)) {
+                return Response::error(
+                    400,
+                    "BadDigest",
+                    "The SHA256 you specified did not match the calculated checksum.",
+                );
+            }
+            let mut uploads = shared.uploads.lock().unwrap();
+            let Some(upload) = uploads.get_mut(&upload_id).filter(|u| u.key == key) else {
+                return Response::error(404, "NoSuchUpload", "The specified upload does not exist");
+            };
+            let mut headers = vec![("ETag", etag(&body))];
+            headers.extend(
+                sent.iter()
+                    .map(|sent| ("x-amz-checksum-sha256", sent.clone())),
+            );
+            upload.parts.insert(
+                number,
+                Part {
+                    body,
+                    checksum: sent,
+                },
+            );
+            Response {
+                status: 200,
+                headers,
+                body: Vec::new(),
+            }
+        }
+        ("GET", Some(upload_id)) => {
+            let uploads = shared.uploads.lock().unwrap();
+            let Some(upload) = uploads.get(&upload_id).filter(|u| u.key == key) else {
+                return Response::error(404, "NoSuchUpload", "The specified upload does not exist");
+            };
+            let parts: String = upload
+                .parts
+                .iter()
+                .map(|(number, part)| {
+                    let checksum = part
+                        .checksum
+                        .as_ref()
+                        .map(|checksum| format!("<ChecksumSHA256>{}</ChecksumSHA256>", checksum))
+                        .unwrap_or_default();
+                    format!(
+                        "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag><Size>{}</Size>{}</Part>",
+                        number,
+                        etag(&part.body).replace('"', "&quot;"),
+                        part.body.len(),
+                        checksum
+                    )
+                })
+                .collect();
+            Response::xml(
+                200,
+                format!(
+                    "<ListPartsResult><UploadId>{}</UploadId>{}</ListPartsResult>",
+                    upload_id, parts
+                ),
+            )
+        }
+        ("POST", Some(upload_id)) => {
+            let mut uploads = shared.uploads.lock().unwrap();
+            let Some(upload) = uploads.get(&upload_id).filter(|u| u.key == key) else {
+                return Response::error(404, "NoSuchUpload", "The specified upload does not exist");
+            };
+            // Parts must be listed in order, each with its ETag and the
+            // checksum it was sent with
+            let without_checksums = shared.without_checksums.load(Ordering::SeqCst);
+            let manifest = String::from_utf8_lossy(&request.body)
+                .replace("&quot;", "\"")
+                .to_string();
+            let mut body = Vec::new();
+            let mut digests = Sha256::new();
+            let mut count = 0;
+            let mut previous = 0;
+            for part in manifest.split("<Part>").skip(1) {
+                let field = |name: &str| {
+                    part.split_once(&format!("<{}>", name))
+                        .and_then(|(_, rest)| rest.split_once(&format!("</{}>", name)))
+                        .map(|(value, _)| value.to_string())
+                };
+                let number: u32 = field("PartNumber")
+                    .and_then(|number| number.parse().ok())
+                    .unwrap_or(0);
+                let matches = upload.parts.get(&number).is_some_and(|part| {
+                    Some(etag(&part.body)) == field("ETag")
+                        && (without_checksums || part.checksum == field("ChecksumSHA256"))
+                });
+                if number <= previous || !matches {
+                    return Response::error(
+                        400,
+                        "InvalidPart",
+                        "One or more of the specified parts could not be found",
+                    );
+                }
+                previous = number;
+                body.extend_from_slice(&upload.parts[&number].body);
+                digests.update(Sha256::digest(&upload.parts[&number].body));
+                count += 1;
+            }
+            let upload = uploads.remove(&upload_id).expect("upload was found");
+            let checksum = format!(
+                "{}-{}",
+                base64::engine::general_purpose::STANDARD.encode(digests.finalize()),
+                count
+            );
+            shared.objects.lock().unwrap().insert(
+                key.clone(),
+                Object {
+                    body,
+                    sha256: upload.sha256,
+                },
+            );
+            let checksum = if without_checksums {
+                String::new()
+            } else {
+                format!("<ChecksumSHA256>{}</ChecksumSHA256>", checksum)
+            };
+            Response::xml(
+                200,
+                format!(
+                    "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key>{}</CompleteMultipartUploadResult>",
+                    bucket, key, checksum
+                ),
+            )
+        }
+        ("DELETE", Some(upload_id)) => match shared.uploads.lock().unwrap().remove(&upload_id) {
+            Some(_) => Response::empty(204),
+            None => Response::error(404, "NoSuchUpload", "The specified upload does not exist"),
+        },
+        ("PUT", None) => {
+            shared.objects.lock().unwrap().insert(
+                key,
+                Object {
+                    body: request.body.clone(),
+                    sha256: request.headers.get("x-amz-meta-sha256").cloned(),
+                },
+            );
+            Response {
+                status: 200,
+                headers: vec![("ETag", etag(&request.body))],
+                body: Vec::new(),
+            }
+        }
+        ("GET", None) if key.is_empty() && request.param("list-type") == Some("2") => {
+            let prefix = request.param("prefix").unwrap_or_default();
+            let mut keys: Vec<String> = shared
+                .objects
+                .lock()
+                .unwrap()
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect();
+            keys.sort();
+            let contents: String = keys
+                .iter()
+                .map(|key| format!("<Contents><Key>{}</Key></Contents>", key))
+                .collect();
+            Response::xml(
+                200,
+                format!(
+                    "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                    bucket, prefix, contents
+                ),
+            )
+        }
+        ("GET", None) => match shared.objects.lock().unwrap().get(&key) {
+            Some(object) => Response {
+                status: 200,
+                headers: Vec::new(),
+                body: object.body.clone(),
+            },
+            None => Response::error(404, "NoSuchKey", "The specified key does not exist."),
+        },
+        ("HEAD", None) => match shared.objects.lock().unwrap().get(&key) {
+            Some(object) => Response {
+                status: 200,
+                headers: object
+                    .sha256
+                    .iter()
+                    .map(|sha256| ("x-amz-meta-sha256", sha256.clone()))
+                    .collect(),
+                // HEAD answers carry the object's length with no body
+                body: object.body.clone(),
+            },
+            None => Response::empty(404),
+        },
+        ("DELETE", None) => {
+            shared.objects.lock().unwrap().remove(&key);
+            Response::empty(204)
+        }
+        _ => Response::error(400, "NotImplemented", "Not supported by the fake"),
+    }
+}
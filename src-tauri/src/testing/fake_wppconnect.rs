@@ -0,0 +1,528 @@
+//! Fake WPPConnect server
+//!
+//! Serves the routes the app depends on (`/health`, session start, status
+//! and QR code, send-message, chats and `/api/shutdown`) with the real
+//! sidecar's response shapes and bearer token check, and can be scripted to
+//! fail with a [`Fault`].
+//!
+//! It runs either in-process ([`FakeWppConnect::start`]), for client and
+//! command tests, or as a separate process for supervisor tests. In the
+//! latter case the test binary runs itself with only
+//! [`fake_sidecar_process`] selected ([`sidecar_spec`] sets that up), which
+//! reads `WPPCONNECT_PORT` and `WPPCONNECT_SECRET` like the real sidecar.
+//! Faults can be injected at runtime with `POST /__fake/fault`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::sidecar::SidecarSpec;
+use crate::whatsapp;
+
+/// libtest filter selecting [`fake_sidecar_process`]
+const PROCESS_TEST: &str = "testing::fake_wppconnect::fake_sidecar_process";
+/// Fault applied when the fake process starts, see [`Fault::parse`]
+const FAULT_ENV: &str = "FAKE_WPPCONNECT_FAULT";
+/// A fake process exits on its own after this long, so a failed test
+/// doesn't leave it running
+const PROCESS_TTL: Duration = Duration::from_secs(120);
+/// Exit code of a scripted crash
+pub const CRASH_EXIT_CODE: i32 = 101;
+/// QR code handed out for sessions that are not paired yet
+pub const QR_CODE: &str = "data:image/png;base64,RkFLRQ==";
+
+/// Scripted failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Exit the process (or, in-process, stop listening)
+    Crash,
+    /// Accept requests but never answer them
+    Hang,
+    /// Answer `/health` only after this delay
+    SlowHealth(Duration),
+    /// Answer the next `n` API requests with a 500
+    ServerErrors(u32),
+}
+
+impl Fault {
+    /// `crash`, `hang`, `slow-health:<ms>` or `500:<count>`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.split_once(':') {
+            None if value == "crash" => Some(Fault::Crash),
+            None if value == "hang" => Some(Fault::Hang),
+            Some(("slow-health", ms)) => ms
+                .parse()
+                .ok()
+                .map(|ms| Fault::SlowHealth(Duration::from_millis(ms))),
+            Some(("500", count)) => count.parse().ok().map(Fault::ServerErrors),
+            _ => None,
+        }
+    }
+
+    pub fn as_arg(&self) -> String {
+        match self {
+            Fault::Crash => "crash".to_string(),
+            Fault::Hang => "hang".to_string(),
+            Fault::SlowHealth(delay) => format!("slow-health:{}", delay.as_millis()),
+            Fault::ServerErrors(count) => format!("500:{}", count),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Session {
+    connected: bool,
+    chats: Vec<Value>,
+}
+
+/// What to do once a response has been written
+enum After {
+    Nothing,
+    Exit(i32),
+    StopListening,
+}
+
+struct Shared {
+    port: u16,
+    token: Option<String>,
+    /// Running as its own process, so crashes and shutdowns exit it
+    standalone: bool,
+    stopped: AtomicBool,
+    hang: AtomicBool,
+    slow_health: Mutex<Duration>,
+    server_errors: Mutex<u32>,
+    sessions: Mutex<HashMap<String, Session>>,
+    sent: Mutex<Vec<Value>>,
+    /// `METHOD /path` of every request, in order
+    requests: Mutex<Vec<String>>,
+}
+
+impl Shared {
+    fn new(port: u16, token: Option<String>, standalone: bool) -> Self {
+        Self {
+            port,
+            token,
+            standalone,
+            stopped: AtomicBool::new(false),
+            hang: AtomicBool::new(false),
+            slow_health: Mutex::new(Duration::ZERO),
+            server_errors: Mutex::new(0),
+            sessions: Mutex::new(HashMap::new()),
+            sent: Mutex::new(Vec::new()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn apply(&self, fault: Fault) -> After {
+        match fault {
+            Fault::Crash if self.standalone => return After::Exit(CRASH_EXIT_CODE),
+            Fault::Crash => return After::StopListening,
+            Fault::Hang => self.hang.store(true, Ordering::SeqCst),
+            Fault::SlowHealth(delay) => *self.slow_health.lock().unwrap() = delay,
+            Fault::ServerErrors(count) => *self.server_errors.lock().unwrap() = count,
+        }
+        After::Nothing
+    }
+
+    fn stop(&self) {
+        if !self.stopped.swap(true, Ordering::SeqCst) {
+            // Wake the accept loop so it sees the flag
+            let _ = TcpStream::connect(("127.0.0.1", self.port));
+        }
+    }
+}
+
+/// In-process fake sidecar, stopped when dropped
+pub struct FakeWppConnect {
+    shared: Arc<Shared>,
+}
+
+impl FakeWppConnect {
+    /// Listen on a free localhost port, requiring `token` on API routes
+    pub fn start(token: &str) -> Self {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("fake sidecar can bind");
+        let port = listener
+            .local_addr()
+            .expect("bound socket has an address")
+            .port();
+        let shared = Arc::new(Shared::new(port, Some(token.to_string()), false));
+        let serving = shared.clone();
+        std::thread::spawn(move || serve(listener, serving));
+        Self { shared }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.shared.port
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        if let After::StopListening = self.shared.apply(fault) {
+            self.shared.stop();
+        }
+    }
+
+    /// Mark a session as paired, as if its QR code had been scanned
+    pub fn connect(&self, session: &str) {
+        self.shared
+            .sessions
+            .lock()
+            .unwrap()
+            .entry(session.to_string())
+            .or_default()
+            .connected = true;
+    }
+
+    pub fn add_chat(&self, session: &str, id: &str, name: &str) {
+        self.shared
+            .sessions
+            .lock()
+            .unwrap()
+            .entry(session.to_string())
+            .or_default()
+            .chats
+            .push(json!({
+                "id": { "_serialized": id },
+                "name": name,
+                "isGroup": id.ends_with("@g.us"),
+                "unreadCount": 0,
+            }));
+    }
+
+    /// Bodies of the send-message requests that went through
+    pub fn sent(&self) -> Vec<Value> {
+        self.shared.sent.lock().unwrap().clone()
+    }
+
+    /// Number of requests received for `METHOD /path`
+    pub fn request_count(&self, request: &str) -> usize {
+        self.shared
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|seen| *seen == request)
+            .count()
+    }
+}
+
+impl Drop for FakeWppConnect {
+    fn drop(&mut self) {
+        self.shared.stop();
+    }
+}
+
+/// Spec that makes the supervisor spawn a fake process in place of the real
+/// sidecar, with `fault` applied from the start
+pub fn sidecar_spec(
+    port: u16,
+    token: &str,
+    data_dir: PathBuf,
+    fault: Option<Fault>,
+) -> SidecarSpec {
+    let exe = std::env::current_exe().expect("test binary path is known");
+    let mut spec = whatsapp::sidecar_spec(port, token, data_dir);
+    // Sidecars are resolved next to the running executable
+    spec.binary = exe
+        .file_name()
+        .expect("test binary has a file name")
+        .to_string_lossy()
+        .into_owned();
+    spec.args = [PROCESS_TEST, "--exact", "--ignored", "--nocapture"]
+        .map(str::to_string)
+        .to_vec();
+    match fault {
+        Some(fault) => spec.env(FAULT_ENV, &fault.as_arg()),
+        None => spec,
+    }
+}
+
+/// Inject a fault into a fake process through its control route
+pub async fn inject_remote(port: u16, fault: Fault) {
+    // The process may exit before answering a crash
+    let _ = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/__fake/fault", port))
+        .json(&json!({ "fault": fault.as_arg() }))
+        .timeout(Duration::from_secs(3))
+        .send()
+        .await;
+}
+
+/// Entry point of a fake sidecar process, see the module docs
+#[test]
+#[ignore = "runs only as a fake sidecar spawned by supervisor tests"]
+fn fake_sidecar_process() {
+    let Some(port) = std::env::var("WPPCONNECT_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+    else {
+        return;
+    };
+    // Like the real sidecar, never serve without a token
+    let Some(token) = std::env::var("WPPCONNECT_SECRET")
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        eprintln!("WPPCONNECT_SECRET is not set; refusing to start without an access token");
+        std::process::exit(1);
+    };
+    let shared = Arc::new(Shared::new(port, Some(token), true));
+    if let Some(fault) = std::env::var(FAULT_ENV).ok().and_then(|f| Fault::parse(&f)) {
+        if let After::Exit(code) = shared.apply(fault) {
+            std::process::exit(code);
+        }
+    }
+
+    std::thread::spawn(|| {
+        std::thread::sleep(PROCESS_TTL);
+        std::process::exit(2);
+    });
+    let listener = TcpListener::bind(("127.0.0.1", port)).expect("fake sidecar can bind its port");
+    println!("Fake WPPConnect listening on port {}", port);
+    serve(listener, shared);
+}
+
+fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let shared = shared.clone();
+        std::thread::spawn(move || handle(stream, &shared));
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Value,
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut authorization = None;
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "authorization" => authorization = Some(value.to_string()),
+            "content-length" => length = value.parse().ok()?,
+            _ => {}
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        method,
+        path,
+        authorization,
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    })
+}
+
+fn handle(mut stream: TcpStream, shared: &Shared) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let Some(request) = read_request(&stream) else {
+        return;
+    };
+    shared
+        .requests
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", request.method, request.path));
+
+    let control = request.path.starts_with("/__fake/");
+    if !control && shared.hang.load(Ordering::SeqCst) {
+        // Hold the connection open until the client gives up
+        while !shared.stopped.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        return;
+    }
+
+    let (status, body, after) = route(shared, &request);
+    let body = body.to_string();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    );
+    let _ = stream.flush();
+
+    match after {
+        After::Nothing => {}
+        After::Exit(code) => std::process::exit(code),
+        After::StopListening => shared.stop(),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    }
+}
+
+fn route(shared: &Shared, request: &Request) -> (u16, Value, After) {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+
+    if request.path == "/__fake/fault" {
+        let Some(fault) = request
+            .body
+            .get("fault")
+            .and_then(Value::as_str)
+            .and_then(Fault::parse)
+        else {
+            return (400, json!({ "error": "Unknown fault" }), After::Nothing);
+        };
+        return (200, json!({ "success": true }), shared.apply(fault));
+    }
+
+    if request.path == "/health" {
+        std::thread::sleep(*shared.slow_health.lock().unwrap());
+        let sessions = shared.sessions.lock().unwrap().len();
+        return (
+            200,
+            json!({ "status": "ok", "sessions": sessions }),
+            After::Nothing,
+        );
+    }
+
+    if let Some(token) = &shared.token {
+        if request.authorization.as_deref() != Some(format!("Bearer {}", token).as_str()) {
+            let body = json!({ "success": false, "error": "Unauthorized" });
+            return (401, body, After::Nothing);
+        }
+    }
+
+    {
+        let mut server_errors = shared.server_errors.lock().unwrap();
+        if *server_errors > 0 {
+            *server_errors -= 1;
+            let body = json!({ "success": false, "error": "Injected failure" });
+            return (500, body, After::Nothing);
+        }
+    }
+
+    let mut sessions = shared.sessions.lock().unwrap();
+    let (status, body) = match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["api", "shutdown"]) => {
+            let after = if shared.standalone {
+                After::Exit(0)
+            } else {
+                After::StopListening
+            };
+            return (
+                200,
+                json!({ "success": true, "message": "Shutting down" }),
+                after,
+            );
+        }
+        ("GET", ["api", "status"]) => {
+            let list: Vec<Value> = sessions
+                .iter()
+                .map(|(name, session)| {
+                    json!({
+                        "name": name,
+                        "connected": session.connected,
+                        "qrReady": !session.connected,
+                    })
+                })
+                .collect();
+            (200, json!({ "sessions": list }))
+        }
+        ("POST", ["api", name, "start"]) => {
+            if sessions.contains_key(*name) {
+                (
+                    200,
+                    json!({ "success": true, "message": "Session already exists" }),
+                )
+            } else {
+                sessions.insert(name.to_string(), Session::default());
+                (
+                    200,
+                    json!({ "success": true, "message": "Session starting" }),
+                )
+            }
+        }
+        ("GET", ["api", name, "status"]) => match sessions.get(*name) {
+            Some(session) => (
+                200,
+                json!({
+                    "exists": true,
+                    "connected": session.connected,
+                    "qrReady": !session.connected,
+                }),
+            ),
+            None => (200, json!({ "exists": false, "connected": false })),
+        },
+        ("GET", ["api", name, "qrcode"]) => match sessions.get(*name) {
+            None => (
+                404,
+                json!({ "success": false, "error": "Session not found" }),
+            ),
+            Some(session) if session.connected => (
+                202,
+                json!({ "success": true, "message": "QR code not ready yet" }),
+            ),
+            Some(_) => (200, json!({ "success": true, "qrCode": QR_CODE })),
+        },
+        ("POST", ["api", name, "send-message"]) => {
+            match sessions.get(*name).filter(|session| session.connected) {
+                None => (
+                    404,
+                    json!({ "success": false, "error": "Session not connected" }),
+                ),
+                Some(_) => {
+                    let mut sent = shared.sent.lock().unwrap();
+                    sent.push(request.body.clone());
+                    let phone = request.body.get("phone").and_then(Value::as_str);
+                    let id = format!("true_{}@c.us_FAKE{}", phone.unwrap_or_default(), sent.len());
+                    (200, json!({ "success": true, "result": { "id": id } }))
+                }
+            }
+        }
+        ("GET", ["api", name, "chats"]) => {
+            match sessions.get(*name).filter(|session| session.connected) {
+                None => (
+                    404,
+                    json!({ "success": false, "error": "Session not connected" }),
+                ),
+                Some(session) => (200, json!({ "success": true, "chats": session.chats })),
+            }
+        }
+        _ => (404, json!({ "success": false, "error": "Not found" })),
+    };
+    (status, body, After::Nothing)
+}
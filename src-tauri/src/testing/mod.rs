@@ -0,0 +1,61 @@
+//! Test support: stand-ins for the WPPConnect sidecar and an S3-compatible
+//! store, and Tauri app fixtures
+
+pub mod fake_s3;
+pub mod fake_wppconnect;
+
+use std::future::Future;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rand::distributions::{Alphanumeric, DistString};
+use tauri::test::MockRuntime;
+
+pub use fake_s3::FakeS3;
+pub use fake_wppconnect::{FakeWppConnect, Fault};
+
+/// Fresh, empty directory under the system temp dir
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "luminila-test-{}-{}",
+        name,
+        Alphanumeric.sample_string(&mut rand::thread_rng(), 8)
+    ));
+    std::fs::create_dir_all(&dir).expect("temp dir is writable");
+    dir
+}
+
+/// Port nothing is listening on right now
+pub fn free_port() -> u16 {
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .expect("a free port is available")
+        .port()
+}
+
+/// App on the mock runtime with the plugins the supervisor needs
+pub fn mock_app() -> tauri::App<MockRuntime> {
+    tauri::test::mock_builder()
+        .plugin(tauri_plugin_shell::init())
+        .build(tauri::test::mock_context(tauri::test::noop_assets()))
+        .expect("mock app builds")
+}
+
+/// Run a test body on Tauri's async runtime, which the code under test
+/// spawns its background tasks on
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tauri::async_runtime::block_on(future)
+}
+
+/// Poll `check` every 50ms until it holds, failing the test with `what`
+/// after `timeout`
+pub async fn eventually(what: &str, timeout: Duration, mut check: impl FnMut() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !check() {
+        if tokio::time::Instant::now() >= deadline {
+            panic!("timed out after {:?} waiting for {}", timeout, what);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
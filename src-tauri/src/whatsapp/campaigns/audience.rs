@@ -0,0 +1,85 @@
+//! Campaign audiences built from the customers collection
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::pocketbase::{quote, PocketBaseClient, PocketBaseError};
+
+/// Country code added to ten-digit numbers, which is how the shop stores them
+const DEFAULT_COUNTRY_CODE: &str = "91";
+
+/// Which customers a campaign goes to. Empty fields don't filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Audience {
+    /// Customer tiers to include ("gold", "vip", ...)
+    pub tiers: Vec<String>,
+    pub min_total_spent: Option<f64>,
+    pub max_total_spent: Option<f64>,
+    /// Hand-picked customers; when set, only these are considered
+    pub customer_ids: Vec<String>,
+}
+
+impl Audience {
+    fn filter(&self) -> String {
+        let mut conditions = vec!["phone != \"\"".to_string()];
+        if !self.customer_ids.is_empty() {
+            conditions.push(any_of("id", &self.customer_ids));
+        }
+        if !self.tiers.is_empty() {
+            conditions.push(any_of("tier", &self.tiers));
+        }
+        if let Some(min) = self.min_total_spent {
+            conditions.push(format!("total_spent >= {}", min));
+        }
+        if let Some(max) = self.max_total_spent {
+            conditions.push(format!("total_spent <= {}", max));
+        }
+        conditions.join(" && ")
+    }
+
+    /// Customer records in the audience that have a phone number
+    pub async fn customers(
+        &self,
+        client: &PocketBaseClient,
+    ) -> Result<Vec<Value>, PocketBaseError> {
+        client
+            .list_all("customers", Some(&self.filter()), Some("name"))
+            .await
+    }
+}
+
+fn any_of(field: &str, values: &[String]) -> String {
+    let options = values
+        .iter()
+        .map(|value| format!("{} = {}", field, quote(value)))
+        .collect::<Vec<_>>()
+        .join(" || ");
+    format!("({})", options)
+}
+
+/// Last ten digits, which identify a number with or without its country
+/// code (same rule as `normalizePhone` in the webview)
+pub fn phone_key(phone: &str) -> String {
+    let digits: String = phone
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    digits[digits.len().saturating_sub(10)..].to_string()
+}
+
+/// Number to send to, with the country code added where it is missing.
+/// `None` if it is too short to be a mobile number.
+pub fn send_address(phone: &str) -> Option<String> {
+    let digits: String = phone.chars().filter(char::is_ascii_digit).collect();
+    // Trunk prefix, as in 098765 43210
+    let digits = digits.trim_start_matches('0');
+    match digits.len() {
+        0..=9 => None,
+        10 => Some(format!("{}{}", DEFAULT_COUNTRY_CODE, digits)),
+        _ => Some(digits.to_string()),
+    }
+}
@@ -0,0 +1,941 @@
+//! Scheduled broadcast campaigns
+//!
+//! A campaign is a message template sent to a customer audience at a set
+//! time. When it comes due, the audience is resolved against PocketBase and
+//! each customer gets their own rendered message. The worker feeds these to
+//! the outbox one at a time, waiting for each to leave the queue and pausing
+//! between sends, so a large campaign never crowds out order confirmations
+//! and doesn't look like spam to WhatsApp.
+//!
+//! Customers who reply STOP are added to a local opt-out list, which every
+//! campaign checks before sending. START removes them again.
+
+mod audience;
+mod template;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{Emitter, Manager};
+
+use super::events::{ack_status, InboundMessage};
+use super::outbox::{Outbox, OutboxPayload};
+use crate::db::{self, LocalDb};
+use crate::pocketbase::PocketBaseClient;
+use audience::phone_key;
+use template::Template;
+
+pub use audience::{send_address, Audience};
+
+/// Gap between two messages of a campaign, before jitter
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(15);
+const MIN_INTERVAL: Duration = Duration::from_secs(5);
+/// Wait before retrying a campaign whose audience could not be loaded
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Messages shown by a preview
+const PREVIEW_SAMPLES: usize = 3;
+
+const OPT_OUT_WORDS: &[&str] = &["STOP", "STOP ALL", "UNSUBSCRIBE", "OPT OUT", "OPTOUT"];
+const OPT_IN_WORDS: &[&str] = &["START", "SUBSCRIBE", "UNSTOP"];
+/// Error recorded for recipients skipped because of the opt-out list
+const OPTED_OUT: &str = "Opted out";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CampaignStatus {
+    /// Waiting for its start time
+    Scheduled,
+    Sending,
+    Completed,
+    Cancelled,
+}
+
+impl CampaignStatus {
+    fn parse(value: &str) -> Self {
+        match value {
+            "sending" => CampaignStatus::Sending,
+            "completed" => CampaignStatus::Completed,
+            "cancelled" => CampaignStatus::Cancelled,
+            _ => CampaignStatus::Scheduled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecipientStatus {
+    /// Not handed to the outbox yet
+    Pending,
+    /// In the outbox
+    Queued,
+    Sent,
+    Failed,
+    /// Opted out or no usable number
+    Skipped,
+    Cancelled,
+}
+
+impl RecipientStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            RecipientStatus::Pending => "pending",
+            RecipientStatus::Queued => "queued",
+            RecipientStatus::Sent => "sent",
+            RecipientStatus::Failed => "failed",
+            RecipientStatus::Skipped => "skipped",
+            RecipientStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "queued" => RecipientStatus::Queued,
+            "sent" => RecipientStatus::Sent,
+            "failed" => RecipientStatus::Failed,
+            "skipped" => RecipientStatus::Skipped,
+            "cancelled" => RecipientStatus::Cancelled,
+            _ => RecipientStatus::Pending,
+        }
+    }
+}
+
+/// Recipients of a campaign by status. `delivered` and `read` are subsets
+/// of `sent`, from WhatsApp's delivery acks.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientCounts {
+    pub total: u32,
+    pub pending: u32,
+    pub queued: u32,
+    pub sent: u32,
+    pub delivered: u32,
+    pub read: u32,
+    pub failed: u32,
+    pub skipped: u32,
+    pub cancelled: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Campaign {
+    pub id: String,
+    pub name: String,
+    pub session: String,
+    pub template: String,
+    pub audience: Audience,
+    pub interval_secs: u32,
+    pub status: CampaignStatus,
+    /// Unix timestamp (ms) the campaign starts at
+    pub scheduled_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    /// Why the audience could not be loaded, while retrying
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub counts: RecipientCounts,
+}
+
+const CAMPAIGN_COLUMNS: &str = "id, name, session, template, audience, interval_secs, status, scheduled_at, started_at, finished_at, last_error, created_at";
+
+impl Campaign {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            session: row.get(2)?,
+            template: row.get(3)?,
+            audience: serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or_default(),
+            interval_secs: row.get(5)?,
+            status: CampaignStatus::parse(&row.get::<_, String>(6)?),
+            scheduled_at: row.get(7)?,
+            started_at: row.get(8)?,
+            finished_at: row.get(9)?,
+            last_error: row.get(10)?,
+            created_at: row.get(11)?,
+            counts: RecipientCounts::default(),
+        })
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.into())
+    }
+}
+
+/// Campaign as submitted by the webview
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewCampaign {
+    pub name: String,
+    pub session: String,
+    pub template: String,
+    #[serde(default)]
+    pub audience: Audience,
+    /// Unix timestamp (ms); now if unset
+    pub scheduled_at: Option<i64>,
+    /// Seconds between two messages; 15 if unset
+    pub interval_secs: Option<u32>,
+}
+
+/// Delivery result for one customer
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientResult {
+    pub customer_id: String,
+    pub customer_name: String,
+    pub phone: String,
+    pub status: RecipientStatus,
+    /// Latest WhatsApp ack ("sent", "delivered", "read", ...) once sent
+    pub delivery: Option<&'static str>,
+    pub error: Option<String>,
+    pub message_id: Option<String>,
+    pub sent_at: Option<i64>,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CampaignReport {
+    pub campaign: Campaign,
+    pub recipients: Vec<RecipientResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewMessage {
+    pub customer_name: String,
+    pub phone: String,
+    pub message: String,
+}
+
+/// What a campaign would send if it started now
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CampaignPreview {
+    /// Customers who would get a message
+    pub recipients: u32,
+    pub opted_out: u32,
+    /// Customers whose number is too short to send to
+    pub invalid_phone: u32,
+    pub samples: Vec<PreviewMessage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptOut {
+    /// Last ten digits of the number
+    pub phone: String,
+    /// Session the STOP was received on
+    pub session: String,
+    pub keyword: String,
+    pub created_at: i64,
+}
+
+/// One customer's message, before it is stored
+struct Recipient {
+    customer_id: String,
+    customer_name: String,
+    /// Send address, or the raw digits when unusable
+    phone: String,
+    message: String,
+    status: RecipientStatus,
+    error: Option<&'static str>,
+}
+
+fn customer_field(customer: &Value, field: &str) -> String {
+    customer
+        .get(field)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub struct Campaigns {
+    db: Arc<LocalDb>,
+    /// Wakes the worker when a campaign is created
+    wake: tokio::sync::Notify,
+}
+
+impl Campaigns {
+    pub fn new(db: Arc<LocalDb>) -> Self {
+        Self {
+            db,
+            wake: tokio::sync::Notify::new(),
+        }
+    }
+
+    /// Validate and store a campaign
+    pub fn create(&self, campaign: NewCampaign) -> Result<Campaign, String> {
+        if campaign.name.trim().is_empty() {
+            return Err("Campaign name is required".to_string());
+        }
+        if campaign.session.is_empty() {
+            return Err("Session name is required".to_string());
+        }
+        Template::parse(&campaign.template)?;
+        let interval = campaign
+            .interval_secs
+            .map(|secs| Duration::from_secs(secs.into()))
+            .unwrap_or(DEFAULT_INTERVAL);
+        if interval < MIN_INTERVAL {
+            return Err(format!(
+                "Send interval must be at least {} seconds",
+                MIN_INTERVAL.as_secs()
+            ));
+        }
+        let audience = serde_json::to_string(&campaign.audience)
+            .map_err(|e| format!("Failed to encode audience: {}", e))?;
+
+        let id = db::new_id();
+        let now = db::now_millis();
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_campaigns
+                    (id, name, session, template, audience, interval_secs, status, scheduled_at, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'scheduled', ?7, ?8)",
+                params![
+                    id,
+                    campaign.name.trim(),
+                    campaign.session,
+                    campaign.template,
+                    audience,
+                    interval.as_secs(),
+                    campaign.scheduled_at.unwrap_or(now),
+                    now
+                ],
+            )
+        })?;
+
+        self.wake.notify_one();
+        self.get(&id)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Campaign, String> {
+        let mut campaign = self
+            .db
+            .with(|conn| {
+                conn.query_row(
+                    &format!(
+                        "SELECT {} FROM whatsapp_campaigns WHERE id = ?1",
+                        CAMPAIGN_COLUMNS
+                    ),
+                    [id],
+                    Campaign::from_row,
+                )
+                .optional()
+            })?
+            .ok_or_else(|| format!("Campaign not found: {}", id))?;
+        campaign.counts = self.counts(id)?;
+        Ok(campaign)
+    }
+
+    /// Campaigns, most recently scheduled first
+    pub fn list(&self, limit: u32) -> Result<Vec<Campaign>, String> {
+        let mut campaigns = self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM whatsapp_campaigns ORDER BY scheduled_at DESC LIMIT ?1",
+                CAMPAIGN_COLUMNS
+            ))?;
+            let rows = stmt.query_map([limit], Campaign::from_row)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+        for campaign in &mut campaigns {
+            campaign.counts = self.counts(&campaign.id)?;
+        }
+        Ok(campaigns)
+    }
+
+    fn counts(&self, id: &str) -> Result<RecipientCounts, String> {
+        let rows = self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT r.status, COUNT(*), SUM(COALESCE(a.ack, 0) >= 2), SUM(COALESCE(a.ack, 0) >= 3)
+                 FROM whatsapp_campaign_recipients r
+                 LEFT JOIN whatsapp_outbox o ON o.id = r.outbox_id
+                 LEFT JOIN whatsapp_acks a ON a.message_id = o.message_id
+                 WHERE r.campaign_id = ?1
+                 GROUP BY r.status",
+            )?;
+            let rows = stmt.query_map([id], |row| {
+                Ok((
+                    RecipientStatus::parse(&row.get::<_, String>(0)?),
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, u32>(3)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+
+        let mut counts = RecipientCounts::default();
+        for (status, count, delivered, read) in rows {
+            counts.total += count;
+            match status {
+                RecipientStatus::Pending => counts.pending += count,
+                RecipientStatus::Queued => counts.queued += count,
+                RecipientStatus::Sent => {
+                    counts.sent += count;
+                    counts.delivered += delivered;
+                    counts.read += read;
+                }
+                RecipientStatus::Failed => counts.failed += count,
+                RecipientStatus::Skipped => counts.skipped += count,
+                RecipientStatus::Cancelled => counts.cancelled += count,
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Campaign with the delivery result of every recipient
+    pub fn report(&self, id: &str) -> Result<CampaignReport, String> {
+        let campaign = self.get(id)?;
+        let recipients = self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT r.customer_id, r.customer_name, r.phone, r.status, a.ack, COALESCE(r.error, o.last_error),
+                        o.message_id, o.sent_at, r.updated_at
+                 FROM whatsapp_campaign_recipients r
+                 LEFT JOIN whatsapp_outbox o ON o.id = r.outbox_id
+                 LEFT JOIN whatsapp_acks a ON a.message_id = o.message_id
+                 WHERE r.campaign_id = ?1
+                 ORDER BY r.customer_name",
+            )?;
+            let rows = stmt.query_map([id], |row| {
+                Ok(RecipientResult {
+                    customer_id: row.get(0)?,
+                    customer_name: row.get(1)?,
+                    phone: row.get(2)?,
+                    status: RecipientStatus::parse(&row.get::<_, String>(3)?),
+                    delivery: row.get::<_, Option<i64>>(4)?.map(ack_status),
+                    error: row.get(5)?,
+                    message_id: row.get(6)?,
+                    sent_at: row.get(7)?,
+                    updated_at: row.get(8)?,
+                })
+            })?;
+            rows.collect()
+        })?;
+        Ok(CampaignReport {
+            campaign,
+            recipients,
+        })
+    }
+
+    /// Stop a campaign. Messages already in the outbox are pulled back if
+    /// they have not been sent.
+    pub fn cancel(&self, outbox: &Outbox, id: &str) -> Result<Campaign, String> {
+        let now = db::now_millis();
+        let queued = self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            let updated = tx.execute(
+                "UPDATE whatsapp_campaigns SET status = 'cancelled', finished_at = ?2
+                 WHERE id = ?1 AND status IN ('scheduled', 'sending')",
+                params![id, now],
+            )?;
+            if updated == 0 {
+                return Ok(None);
+            }
+            tx.execute(
+                "UPDATE whatsapp_campaign_recipients SET status = 'cancelled', updated_at = ?2
+                 WHERE campaign_id = ?1 AND status = 'pending'",
+                params![id, now],
+            )?;
+            let queued = {
+                let mut stmt = tx.prepare(
+                    "SELECT outbox_id FROM whatsapp_campaign_recipients
+                     WHERE campaign_id = ?1 AND status = 'queued'",
+                )?;
+                let rows = stmt.query_map([id], |row| row.get::<_, String>(0))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            tx.commit()?;
+            Ok(Some(queued))
+        })?;
+        let Some(queued) = queued else {
+            return Err(format!("Campaign {} has already finished", id));
+        };
+
+        // Fails for messages already being sent, which is fine
+        for outbox_id in queued {
+            let _ = outbox.cancel(&outbox_id);
+        }
+        self.get(id)
+    }
+
+    /// Resolve the audience and render every message without storing anything
+    pub async fn preview(
+        &self,
+        client: &PocketBaseClient,
+        audience: &Audience,
+        template: &str,
+    ) -> Result<CampaignPreview, String> {
+        let recipients = self.recipients(client, audience, template).await?;
+        let mut preview = CampaignPreview {
+            recipients: 0,
+            opted_out: 0,
+            invalid_phone: 0,
+            samples: Vec::new(),
+        };
+        for recipient in recipients {
+            match recipient.error {
+                Some(OPTED_OUT) => preview.opted_out += 1,
+                Some(_) => preview.invalid_phone += 1,
+                None => {
+                    preview.recipients += 1;
+                    if preview.samples.len() < PREVIEW_SAMPLES {
+                        preview.samples.push(PreviewMessage {
+                            customer_name: recipient.customer_name,
+                            phone: recipient.phone,
+                            message: recipient.message,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(preview)
+    }
+
+    /// One message per distinct number in the audience, with opted-out and
+    /// unusable numbers marked skipped
+    async fn recipients(
+        &self,
+        client: &PocketBaseClient,
+        audience: &Audience,
+        template: &str,
+    ) -> Result<Vec<Recipient>, String> {
+        let template = Template::parse(template)?;
+        let customers = audience.customers(client).await?;
+        let opted_out = self.opted_out_keys()?;
+
+        let mut seen = HashSet::new();
+        let mut recipients = Vec::new();
+        for customer in &customers {
+            let raw_phone = customer_field(customer, "phone");
+            let (phone, status, error) = match send_address(&raw_phone) {
+                Some(phone) if opted_out.contains(&phone_key(&phone)) => {
+                    (phone, RecipientStatus::Skipped, Some(OPTED_OUT))
+                }
+                Some(phone) => (phone, RecipientStatus::Pending, None),
+                None => (
+                    raw_phone.chars().filter(char::is_ascii_digit).collect(),
+                    RecipientStatus::Skipped,
+                    Some("Invalid phone number"),
+                ),
+            };
+            // Family members often share a number; they get one message
+            if !seen.insert(phone.clone()) {
+                continue;
+            }
+            recipients.push(Recipient {
+                customer_id: customer_field(customer, "id"),
+                customer_name: customer_field(customer, "name"),
+                phone,
+                message: template.render(customer),
+                status,
+                error,
+            });
+        }
+        Ok(recipients)
+    }
+
+    /// Store the recipients of a due campaign and mark it sending
+    fn start(&self, id: &str, recipients: &[Recipient]) -> Result<(), String> {
+        let now = db::now_millis();
+        self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR IGNORE INTO whatsapp_campaign_recipients
+                        (campaign_id, phone, customer_id, customer_name, message, status, error, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )?;
+                for recipient in recipients {
+                    insert.execute(params![
+                        id,
+                        recipient.phone,
+                        recipient.customer_id,
+                        recipient.customer_name,
+                        recipient.message,
+                        recipient.status.as_str(),
+                        recipient.error,
+                        now
+                    ])?;
+                }
+            }
+            tx.execute(
+                "UPDATE whatsapp_campaigns SET status = 'sending', started_at = ?2, last_error = NULL
+                 WHERE id = ?1 AND status = 'scheduled'",
+                params![id, now],
+            )?;
+            tx.commit()
+        })
+    }
+
+    fn set_error(&self, id: &str, error: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_campaigns SET last_error = ?2 WHERE id = ?1",
+                params![id, error],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn with_status(&self, status: &str, due_only: bool) -> Result<Vec<Campaign>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM whatsapp_campaigns
+                 WHERE status = ?1 AND (?2 = 0 OR scheduled_at <= ?3)
+                 ORDER BY scheduled_at",
+                CAMPAIGN_COLUMNS
+            ))?;
+            let rows = stmt.query_map(
+                params![status, due_only, db::now_millis()],
+                Campaign::from_row,
+            )?;
+            rows.collect()
+        })
+    }
+
+    /// Move queued recipients whose outbox message has finished to its result
+    fn sync_queued(&self) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_campaign_recipients
+                 SET status = (SELECT o.status FROM whatsapp_outbox o WHERE o.id = outbox_id),
+                     updated_at = ?1
+                 WHERE status = 'queued' AND EXISTS (
+                     SELECT 1 FROM whatsapp_outbox o
+                     WHERE o.id = outbox_id AND o.status IN ('sent', 'failed', 'cancelled')
+                 )",
+                [db::now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn has_queued(&self, id: &str) -> Result<bool, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM whatsapp_campaign_recipients
+                                WHERE campaign_id = ?1 AND status = 'queued')",
+                [id],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Next recipient to send to, as (phone, message)
+    fn next_pending(&self, id: &str) -> Result<Option<(String, String)>, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                "SELECT phone, message FROM whatsapp_campaign_recipients
+                 WHERE campaign_id = ?1 AND status = 'pending'
+                 ORDER BY rowid LIMIT 1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+        })
+    }
+
+    fn mark_recipient(
+        &self,
+        id: &str,
+        phone: &str,
+        status: RecipientStatus,
+        outbox_id: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_campaign_recipients
+                 SET status = ?3, outbox_id = COALESCE(?4, outbox_id), error = ?5, updated_at = ?6
+                 WHERE campaign_id = ?1 AND phone = ?2",
+                params![
+                    id,
+                    phone,
+                    status.as_str(),
+                    outbox_id,
+                    error,
+                    db::now_millis()
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn complete(&self, id: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_campaigns SET status = 'completed', finished_at = ?2
+                 WHERE id = ?1 AND status = 'sending'",
+                params![id, db::now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Numbers that opted out, newest first
+    pub fn opt_outs(&self) -> Result<Vec<OptOut>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT phone, session, keyword, created_at FROM whatsapp_opt_outs
+                 ORDER BY created_at DESC",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(OptOut {
+                    phone: row.get(0)?,
+                    session: row.get(1)?,
+                    keyword: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?;
+            rows.collect()
+        })
+    }
+
+    fn opted_out_keys(&self) -> Result<HashSet<String>, String> {
+        Ok(self
+            .opt_outs()?
+            .into_iter()
+            .map(|opt_out| opt_out.phone)
+            .collect())
+    }
+
+    /// Add `phone` to the opt-out list and withdraw its unsent campaign
+    /// messages. Returns false if it was already on the list.
+    pub fn opt_out(
+        &self,
+        outbox: &Outbox,
+        phone: &str,
+        session: &str,
+        keyword: &str,
+    ) -> Result<bool, String> {
+        let key = phone_key(phone);
+        let now = db::now_millis();
+        let withdrawn = self.db.with(|conn| {
+            let tx = conn.transaction()?;
+            let added = tx.execute(
+                "INSERT OR IGNORE INTO whatsapp_opt_outs (phone, session, keyword, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![key, session, keyword, now],
+            )? > 0;
+            tx.execute(
+                "UPDATE whatsapp_campaign_recipients SET status = 'skipped', error = ?2, updated_at = ?3
+                 WHERE status = 'pending' AND substr(phone, -10) = ?1",
+                params![key, OPTED_OUT, now],
+            )?;
+            let queued = {
+                let mut stmt = tx.prepare(
+                    "SELECT outbox_id FROM whatsapp_campaign_recipients
+                     WHERE status = 'queued' AND substr(phone, -10) = ?1",
+                )?;
+                let rows = stmt.query_map([&key], |row| row.get::<_, String>(0))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            tx.commit()?;
+            Ok((added, queued))
+        })?;
+
+        let (added, queued) = withdrawn;
+        for outbox_id in queued {
+            let _ = outbox.cancel(&outbox_id);
+        }
+        Ok(added)
+    }
+
+    /// Remove `phone` from the opt-out list. Returns false if it wasn't on it.
+    pub fn opt_in(&self, phone: &str) -> Result<bool, String> {
+        let removed = self.db.with(|conn| {
+            conn.execute(
+                "DELETE FROM whatsapp_opt_outs WHERE phone = ?1",
+                [phone_key(phone)],
+            )
+        })?;
+        Ok(removed > 0)
+    }
+}
+
+fn emit_campaign(app: &tauri::AppHandle, campaigns: &Campaigns, id: &str) {
+    if let Ok(campaign) = campaigns.get(id) {
+        let _ = app.emit("whatsapp-campaign", campaign);
+    }
+}
+
+/// Resolve the audience of every due campaign and mark it sending
+async fn start_due(
+    app: &tauri::AppHandle,
+    campaigns: &Campaigns,
+    client: &PocketBaseClient,
+    retry_at: &mut HashMap<String, Instant>,
+) -> Result<(), String> {
+    retry_at.retain(|_, at| *at > Instant::now());
+    for campaign in campaigns.with_status("scheduled", true)? {
+        if retry_at.contains_key(&campaign.id) {
+            continue;
+        }
+        match campaigns
+            .recipients(client, &campaign.audience, &campaign.template)
+            .await
+        {
+            Ok(recipients) => {
+                campaigns.start(&campaign.id, &recipients)?;
+                log::info!(
+                    "Started campaign '{}' with {} recipients",
+                    campaign.name,
+                    recipients.len()
+                );
+            }
+            Err(e) => {
+                log::warn!("Campaign '{}' could not start: {}", campaign.name, e);
+                campaigns.set_error(&campaign.id, &e)?;
+                retry_at.insert(campaign.id.clone(), Instant::now() + RETRY_DELAY);
+            }
+        }
+        emit_campaign(app, campaigns, &campaign.id);
+    }
+    Ok(())
+}
+
+/// Hand the next message of the oldest sending campaign to the outbox
+fn send_next(
+    app: &tauri::AppHandle,
+    campaigns: &Campaigns,
+    outbox: &Outbox,
+    next_send_at: &mut HashMap<String, Instant>,
+) -> Result<(), String> {
+    campaigns.sync_queued()?;
+    let Some(campaign) = campaigns.with_status("sending", false)?.into_iter().next() else {
+        return Ok(());
+    };
+    // One message in the outbox at a time
+    if campaigns.has_queued(&campaign.id)? {
+        return Ok(());
+    }
+    if next_send_at
+        .get(&campaign.id)
+        .is_some_and(|at| *at > Instant::now())
+    {
+        return Ok(());
+    }
+
+    let Some((phone, message)) = campaigns.next_pending(&campaign.id)? else {
+        campaigns.complete(&campaign.id)?;
+        next_send_at.remove(&campaign.id);
+        log::info!("Finished campaign '{}'", campaign.name);
+        emit_campaign(app, campaigns, &campaign.id);
+        return Ok(());
+    };
+    match outbox.enqueue(&campaign.session, &phone, OutboxPayload::Text { message }) {
+        Ok(entry) => campaigns.mark_recipient(
+            &campaign.id,
+            &phone,
+            RecipientStatus::Queued,
+            Some(&entry.id),
+            None,
+        )?,
+        Err(e) => campaigns.mark_recipient(
+            &campaign.id,
+            &phone,
+            RecipientStatus::Failed,
+            None,
+            Some(&e),
+        )?,
+    }
+    // Up to 50% jitter so the sends don't arrive on a fixed beat
+    let interval = campaign.interval();
+    let jitter = interval.mul_f64(rand::thread_rng().gen_range(0.0..0.5));
+    next_send_at.insert(campaign.id.clone(), Instant::now() + interval + jitter);
+    emit_campaign(app, campaigns, &campaign.id);
+    Ok(())
+}
+
+/// Background worker starting due campaigns and pacing their messages
+pub async fn run_worker(app: tauri::AppHandle) {
+    let campaigns = app.state::<Campaigns>();
+    let outbox = app.state::<Outbox>();
+    let client = app.state::<PocketBaseClient>();
+
+    let mut retry_at = HashMap::new();
+    let mut next_send_at = HashMap::new();
+    loop {
+        let _ = tokio::time::timeout(Duration::from_secs(2), campaigns.wake.notified()).await;
+
+        if let Err(e) = start_due(&app, &campaigns, &client, &mut retry_at).await {
+            log::error!("Failed to start WhatsApp campaigns: {}", e);
+        }
+        if let Err(e) = send_next(&app, &campaigns, &outbox, &mut next_send_at) {
+            log::error!("Failed to send WhatsApp campaign message: {}", e);
+        }
+    }
+}
+
+/// Opt-out or opt-in keyword a message consists of
+fn subscription_keyword(message: &InboundMessage) -> Option<(bool, String)> {
+    if message.message.from_me || message.message.is_group_msg {
+        return None;
+    }
+    let word = message
+        .message
+        .body
+        .trim()
+        .trim_end_matches(['.', '!'])
+        .to_uppercase();
+    if OPT_OUT_WORDS.contains(&word.as_str()) {
+        Some((true, word))
+    } else if OPT_IN_WORDS.contains(&word.as_str()) {
+        Some((false, word))
+    } else {
+        None
+    }
+}
+
+/// Whether `message` is a STOP or START reply
+pub fn is_subscription_reply(message: &InboundMessage) -> bool {
+    subscription_keyword(message).is_some()
+}
+
+/// Update the opt-out list from a STOP or START reply and confirm it
+pub fn handle_subscription_reply(app: &tauri::AppHandle, inbound: &InboundMessage) {
+    let Some((opt_out, keyword)) = subscription_keyword(inbound) else {
+        return;
+    };
+    let campaigns = app.state::<Campaigns>();
+    let outbox = app.state::<Outbox>();
+    let sender = &inbound.message.from;
+
+    let result = if opt_out {
+        campaigns
+            .opt_out(&outbox, sender, &inbound.session, &keyword)
+            .map(|changed| {
+                changed.then_some(
+                    "You won't get any more offers from us on WhatsApp. Reply START to subscribe again.",
+                )
+            })
+    } else {
+        campaigns.opt_in(sender).map(|changed| {
+            changed.then_some("You're subscribed again. Reply STOP at any time to unsubscribe.")
+        })
+    };
+    match result {
+        // Only confirm a change, so repeated STOPs don't start a conversation
+        Ok(Some(confirmation)) => {
+            log::info!("WhatsApp number {} sent {}", phone_key(sender), keyword);
+            let queued = outbox.enqueue(
+                &inbound.session,
+                &inbound.chat_id,
+                OutboxPayload::Text {
+                    message: confirmation.to_string(),
+                },
+            );
+            if let Err(e) = queued {
+                log::error!("Failed to queue opt-out confirmation: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to update WhatsApp opt-outs: {}", e),
+    }
+}
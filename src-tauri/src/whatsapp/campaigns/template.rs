@@ -0,0 +1,135 @@
+//! Message templates with per-customer variables
+//!
+//! `{{first_name}}` is replaced with the customer's value; `{{first_name|there}}`
+//! falls back to "there" when the customer has none.
+
+use serde_json::Value;
+
+/// Variables a template may use, with the customers field each comes from
+const VARIABLES: &[(&str, &str)] = &[
+    ("name", "name"),
+    ("first_name", "name"),
+    ("phone", "phone"),
+    ("tier", "tier"),
+    ("loyalty_points", "loyalty_points"),
+    ("total_spent", "total_spent"),
+];
+
+/// Appended to every message unless the template already says how to opt out
+const OPT_OUT_FOOTER: &str = "Reply STOP to stop these messages.";
+
+enum Segment {
+    Text(String),
+    Variable {
+        name: String,
+        fallback: Option<String>,
+    },
+}
+
+/// Parsed, validated template
+pub struct Template {
+    segments: Vec<Segment>,
+    has_opt_out: bool,
+}
+
+impl Template {
+    /// Parse `source`, rejecting unknown variables and unclosed `{{`
+    pub fn parse(source: &str) -> Result<Self, String> {
+        if source.trim().is_empty() {
+            return Err("Message template is empty".to_string());
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| "Unclosed {{ in message template".to_string())?;
+            let (name, fallback) = match after[..end].split_once('|') {
+                Some((name, fallback)) => (name.trim(), Some(fallback.trim().to_string())),
+                None => (after[..end].trim(), None),
+            };
+            if !VARIABLES.iter().any(|(known, _)| *known == name) {
+                return Err(format!(
+                    "Unknown template variable {{{{{}}}}}. Available: {}",
+                    name,
+                    VARIABLES
+                        .iter()
+                        .map(|(known, _)| *known)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            segments.push(Segment::Variable {
+                name: name.to_string(),
+                fallback,
+            });
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(Self {
+            segments,
+            has_opt_out: source
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|word| word.eq_ignore_ascii_case("stop")),
+        })
+    }
+
+    /// Message for one customer record
+    pub fn render(&self, customer: &Value) -> String {
+        let mut message = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => message.push_str(text),
+                Segment::Variable { name, fallback } => {
+                    let value = variable(customer, name);
+                    if value.is_empty() {
+                        message.push_str(fallback.as_deref().unwrap_or_default());
+                    } else {
+                        message.push_str(&value);
+                    }
+                }
+            }
+        }
+        if !self.has_opt_out {
+            message = format!("{}\n\n{}", message.trim_end(), OPT_OUT_FOOTER);
+        }
+        message
+    }
+}
+
+fn variable(customer: &Value, name: &str) -> String {
+    let field = VARIABLES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, field)| *field)
+        .unwrap_or(name);
+    let value = match customer.get(field) {
+        Some(Value::String(text)) => text.trim().to_string(),
+        Some(Value::Number(number)) => {
+            let number = number.as_f64().unwrap_or_default();
+            if name == "total_spent" {
+                format!("₹{:.0}", number)
+            } else {
+                format!("{}", number)
+            }
+        }
+        _ => String::new(),
+    };
+    if name == "first_name" {
+        value
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        value
+    }
+}
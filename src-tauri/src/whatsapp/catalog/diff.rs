@@ -0,0 +1,243 @@
+//! Work out what to push by comparing products, what we last pushed and the
+//! live catalog
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::super::client::ProductFields;
+use super::super::types::CatalogProduct;
+
+const CURRENCY: &str = "INR";
+
+/// A product as it should appear in the catalog
+#[derive(Debug, Clone)]
+pub struct Desired {
+    pub product_id: String,
+    pub fields: ProductFields,
+    pub image_url: Option<String>,
+}
+
+impl Desired {
+    /// Catalog entry for an active PocketBase product with a SKU. Products
+    /// that are out of stock stay listed but hidden. `stock` is what its
+    /// variants have in stock, for products with variants.
+    pub fn from_product(product: &Value, stock: Option<f64>) -> Option<Self> {
+        let text = |field: &str| {
+            product
+                .get(field)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let flag = |field: &str| product.get(field).and_then(Value::as_bool) == Some(true);
+
+        let sku = text("sku");
+        if sku.is_empty() || !flag("is_active") {
+            return None;
+        }
+        let stock = stock.unwrap_or_else(|| {
+            product
+                .get("stock_quantity")
+                .and_then(Value::as_f64)
+                .unwrap_or_default()
+        });
+        let image_url = text("image_url");
+        Some(Self {
+            product_id: text("id"),
+            fields: ProductFields {
+                name: text("name"),
+                description: text("description"),
+                price: product
+                    .get("base_price")
+                    .and_then(Value::as_f64)
+                    .unwrap_or_default(),
+                currency: CURRENCY,
+                is_hidden: flag("track_stock") && stock <= 0.0,
+                retailer_id: sku,
+            },
+            image_url: (!image_url.is_empty()).then_some(image_url),
+        })
+    }
+
+    /// Hash of the fields pushed with an edit
+    pub fn content_hash(&self) -> String {
+        let fields = &self.fields;
+        let mut hasher = Sha256::new();
+        for part in [
+            fields.name.as_str(),
+            fields.description.as_str(),
+            &format!("{:.2}", fields.price),
+            fields.currency,
+            if fields.is_hidden { "hidden" } else { "listed" },
+            fields.retailer_id.as_str(),
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Whether someone changed the product in the WhatsApp app
+    fn drifted(&self, remote: &CatalogProduct) -> bool {
+        let fields = &self.fields;
+        remote.name != fields.name
+            || remote.is_hidden != fields.is_hidden
+            || remote
+                .price
+                .is_some_and(|price| (price - fields.price).abs() > 0.005)
+    }
+}
+
+/// What we last pushed for a product
+#[derive(Debug, Clone)]
+pub struct Tracked {
+    pub product_id: String,
+    pub catalog_id: Option<String>,
+    pub content_hash: Option<String>,
+    pub image_url: Option<String>,
+    /// Unix timestamp (ms) before which a failed product is not retried
+    pub next_attempt_at: i64,
+}
+
+#[derive(Debug, Clone)]
+pub enum Action {
+    Create(Desired),
+    Update {
+        desired: Desired,
+        catalog_id: String,
+        /// Name, price, description or visibility changed
+        content: bool,
+        /// Image URL changed; uploaded only if the bytes differ too
+        image: bool,
+    },
+    /// Remove from the catalog. `product_id` is set for products we track.
+    Delete {
+        product_id: Option<String>,
+        catalog_id: String,
+    },
+    /// Stop tracking a product whose catalog entry is already gone
+    Forget {
+        product_id: String,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+    pub unchanged: u32,
+    /// Failed recently and waiting for their retry time
+    pub deferred: u32,
+}
+
+/// Compare the wanted catalog with the tracked state and the live catalog.
+///
+/// Live products are matched by the id we recorded, then by SKU (retailer
+/// id), so a product created by an earlier sync is adopted rather than
+/// duplicated. Live products we don't track are only removed when their SKU
+/// belongs to a product that has been deactivated; anything else was added
+/// by hand in WhatsApp and is left alone.
+pub fn plan(
+    desired: &[Desired],
+    tracked: &[Tracked],
+    remote: &[CatalogProduct],
+    inactive_skus: &HashSet<String>,
+    now: i64,
+) -> Plan {
+    let tracked: HashMap<&str, &Tracked> = tracked
+        .iter()
+        .map(|item| (item.product_id.as_str(), item))
+        .collect();
+    let remote_by_id: HashMap<&str, &CatalogProduct> = remote
+        .iter()
+        .map(|product| (product.id.as_str(), product))
+        .collect();
+    let remote_by_sku: HashMap<&str, &CatalogProduct> = remote
+        .iter()
+        .filter_map(|product| Some((product.retailer_id.as_deref()?, product)))
+        .collect();
+
+    let mut plan = Plan::default();
+    let mut claimed = HashSet::new();
+    let mut wanted = HashSet::new();
+
+    for product in desired {
+        wanted.insert(product.product_id.as_str());
+        let item = tracked.get(product.product_id.as_str());
+        let live = item
+            .and_then(|item| item.catalog_id.as_deref())
+            .and_then(|id| remote_by_id.get(id))
+            .or_else(|| remote_by_sku.get(product.fields.retailer_id.as_str()))
+            .copied();
+        if let Some(live) = live {
+            claimed.insert(live.id.as_str());
+        }
+        if item.is_some_and(|item| item.next_attempt_at > now) {
+            plan.deferred += 1;
+            continue;
+        }
+
+        let Some(live) = live else {
+            plan.actions.push(Action::Create(product.clone()));
+            continue;
+        };
+        let adopted = item.and_then(|item| item.catalog_id.as_deref()) != Some(live.id.as_str());
+        let content = adopted
+            || item.and_then(|item| item.content_hash.as_deref())
+                != Some(product.content_hash().as_str())
+            || product.drifted(live);
+        let image = product.image_url.is_some()
+            && item.and_then(|item| item.image_url.as_deref()) != product.image_url.as_deref();
+        if content || image {
+            plan.actions.push(Action::Update {
+                desired: product.clone(),
+                catalog_id: live.id.clone(),
+                content,
+                image,
+            });
+        } else {
+            plan.unchanged += 1;
+        }
+    }
+
+    // Tracked products that were deleted or deactivated
+    for item in tracked.values() {
+        if wanted.contains(item.product_id.as_str()) {
+            continue;
+        }
+        match item
+            .catalog_id
+            .as_deref()
+            .filter(|id| remote_by_id.contains_key(id) && !claimed.contains(id))
+        {
+            Some(id) => {
+                claimed.insert(id);
+                plan.actions.push(Action::Delete {
+                    product_id: Some(item.product_id.clone()),
+                    catalog_id: id.to_string(),
+                });
+            }
+            None => plan.actions.push(Action::Forget {
+                product_id: item.product_id.clone(),
+            }),
+        }
+    }
+
+    // Untracked live products of deactivated SKUs
+    for product in remote {
+        let inactive = product
+            .retailer_id
+            .as_ref()
+            .is_some_and(|sku| inactive_skus.contains(sku));
+        if inactive && !claimed.contains(product.id.as_str()) {
+            plan.actions.push(Action::Delete {
+                product_id: None,
+                catalog_id: product.id.clone(),
+            });
+        }
+    }
+
+    plan
+}
@@ -0,0 +1,835 @@
+//! Incremental sync of the product catalog to WhatsApp Business
+//!
+//! Each run compares the active products in PocketBase with what the last
+//! run pushed (kept as content hashes in the local database) and with the
+//! live catalog from the sidecar's `get-products`, then pushes only the
+//! difference. Images are downloaded and uploaded only when a product's
+//! image URL changes and the new bytes differ from the old ones.
+//!
+//! Runs happen on a schedule, shortly after products or their variants
+//! change in PocketBase (stock changes included, since sold-out products are
+//! hidden), and on demand from the webview.
+
+mod diff;
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tauri::{Emitter, Manager};
+
+use super::events::SessionState;
+use super::{SessionRegistry, WhatsAppClient, WhatsAppError};
+use crate::db::{self, LocalDb};
+use crate::pocketbase::PocketBaseClient;
+use diff::{Action, Desired, Tracked};
+
+/// How often PocketBase is checked for product changes
+const CHANGE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Attempts per push within a run, for transient sidecar errors
+const PUSH_ATTEMPTS: u32 = 3;
+const PUSH_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// WhatsApp rejects larger product images
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CatalogSyncSettings {
+    pub enabled: bool,
+    /// Session whose business catalog is synced; the first configured
+    /// session when unset
+    pub session: Option<String>,
+    /// Full sync interval
+    pub interval_minutes: u32,
+}
+
+impl Default for CatalogSyncSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            session: None,
+            interval_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Schedule,
+    /// Products changed in PocketBase
+    Change,
+    Manual,
+}
+
+impl Trigger {
+    fn as_str(self) -> &'static str {
+        match self {
+            Trigger::Schedule => "schedule",
+            Trigger::Change => "change",
+            Trigger::Manual => "manual",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "change" => Trigger::Change,
+            "manual" => Trigger::Manual,
+            _ => Trigger::Schedule,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Success,
+    /// Finished, but some products failed to push
+    Partial,
+    /// Could not load products or the live catalog
+    Failed,
+}
+
+impl RunStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            RunStatus::Running => "running",
+            RunStatus::Success => "success",
+            RunStatus::Partial => "partial",
+            RunStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "running" => RunStatus::Running,
+            "success" => RunStatus::Success,
+            "partial" => RunStatus::Partial,
+            _ => RunStatus::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogRun {
+    pub id: String,
+    pub session: String,
+    pub trigger: Trigger,
+    pub status: RunStatus,
+    pub added: u32,
+    pub updated: u32,
+    pub deleted: u32,
+    pub unchanged: u32,
+    /// Products that can't be listed, e.g. without an image
+    pub skipped: u32,
+    pub failed: u32,
+    /// Failed products waiting for their retry time
+    pub deferred: u32,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+const RUN_COLUMNS: &str = "id, session, trigger, status, added, updated, deleted, unchanged, skipped, failed, deferred, error, started_at, finished_at";
+
+impl CatalogRun {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            session: row.get(1)?,
+            trigger: Trigger::parse(&row.get::<_, String>(2)?),
+            status: RunStatus::parse(&row.get::<_, String>(3)?),
+            added: row.get(4)?,
+            updated: row.get(5)?,
+            deleted: row.get(6)?,
+            unchanged: row.get(7)?,
+            skipped: row.get(8)?,
+            failed: row.get(9)?,
+            deferred: row.get(10)?,
+            error: row.get(11)?,
+            started_at: row.get(12)?,
+            finished_at: row.get(13)?,
+        })
+    }
+}
+
+/// Product whose last push failed or that can't be listed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogIssue {
+    pub product_id: String,
+    pub sku: String,
+    pub error: String,
+    pub attempts: u32,
+    /// Unix timestamp (ms) of the next retry
+    pub next_attempt_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogStatus {
+    pub enabled: bool,
+    pub session: Option<String>,
+    pub interval_minutes: u32,
+    pub running: bool,
+    pub last_run: Option<CatalogRun>,
+    /// Products currently in the catalog
+    pub listed: u32,
+    pub issues: Vec<CatalogIssue>,
+}
+
+pub struct CatalogSync {
+    db: Arc<LocalDb>,
+    settings: CatalogSyncSettings,
+    /// Downloads product images
+    http: reqwest::Client,
+    /// Held for the duration of a run
+    running: tokio::sync::Mutex<()>,
+}
+
+impl CatalogSync {
+    /// `http` is shared with the sidecar supervisor
+    pub fn new(db: Arc<LocalDb>, settings: CatalogSyncSettings, http: reqwest::Client) -> Self {
+        Self {
+            db,
+            settings,
+            http,
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn session(&self, registry: &SessionRegistry) -> Option<String> {
+        self.settings.session.clone().or_else(|| {
+            registry
+                .list()
+                .into_iter()
+                .next()
+                .map(|session| session.config.name)
+        })
+    }
+
+    pub fn status(&self, registry: &SessionRegistry) -> Result<CatalogStatus, String> {
+        let session = self.session(registry);
+        let (last_run, listed, issues) = self.db.with(|conn| {
+            let last_run = conn
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM whatsapp_catalog_runs ORDER BY started_at DESC LIMIT 1",
+                        RUN_COLUMNS
+                    ),
+                    [],
+                    CatalogRun::from_row,
+                )
+                .optional()?;
+            let listed = conn.query_row(
+                "SELECT COUNT(*) FROM whatsapp_catalog_items
+                 WHERE session = ?1 AND catalog_id IS NOT NULL",
+                [&session],
+                |row| row.get(0),
+            )?;
+            let mut stmt = conn.prepare(
+                "SELECT product_id, sku, last_error, attempts, next_attempt_at
+                 FROM whatsapp_catalog_items
+                 WHERE session = ?1 AND last_error IS NOT NULL
+                 ORDER BY sku",
+            )?;
+            let issues = stmt
+                .query_map([&session], |row| {
+                    Ok(CatalogIssue {
+                        product_id: row.get(0)?,
+                        sku: row.get(1)?,
+                        error: row.get(2)?,
+                        attempts: row.get(3)?,
+                        next_attempt_at: row.get(4)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((last_run, listed, issues))
+        })?;
+        Ok(CatalogStatus {
+            enabled: self.settings.enabled,
+            session,
+            interval_minutes: self.settings.interval_minutes,
+            running: self.running.try_lock().is_err(),
+            last_run,
+            listed,
+            issues,
+        })
+    }
+
+    /// Past runs, newest first
+    pub fn runs(&self, limit: u32) -> Result<Vec<CatalogRun>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM whatsapp_catalog_runs ORDER BY started_at DESC LIMIT ?1",
+                RUN_COLUMNS
+            ))?;
+            let rows = stmt.query_map([limit], CatalogRun::from_row)?;
+            rows.collect()
+        })
+    }
+
+    fn tracked(&self, session: &str) -> Result<Vec<(Tracked, Option<String>)>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT product_id, catalog_id, content_hash, image_url, next_attempt_at, image_hash
+                 FROM whatsapp_catalog_items WHERE session = ?1",
+            )?;
+            let rows = stmt.query_map([session], |row| {
+                Ok((
+                    Tracked {
+                        product_id: row.get(0)?,
+                        catalog_id: row.get(1)?,
+                        content_hash: row.get(2)?,
+                        image_url: row.get(3)?,
+                        next_attempt_at: row.get(4)?,
+                    },
+                    row.get(5)?,
+                ))
+            })?;
+            rows.collect()
+        })
+    }
+
+    fn start_run(&self, session: &str, trigger: Trigger) -> Result<CatalogRun, String> {
+        let run = CatalogRun {
+            id: db::new_id(),
+            session: session.to_string(),
+            trigger,
+            status: RunStatus::Running,
+            added: 0,
+            updated: 0,
+            deleted: 0,
+            unchanged: 0,
+            skipped: 0,
+            failed: 0,
+            deferred: 0,
+            error: None,
+            started_at: db::now_millis(),
+            finished_at: None,
+        };
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_catalog_runs (id, session, trigger, status, started_at)
+                 VALUES (?1, ?2, ?3, 'running', ?4)",
+                params![run.id, run.session, trigger.as_str(), run.started_at],
+            )
+        })?;
+        Ok(run)
+    }
+
+    fn finish_run(&self, run: &mut CatalogRun) -> Result<(), String> {
+        run.finished_at = Some(db::now_millis());
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_catalog_runs
+                 SET status = ?2, added = ?3, updated = ?4, deleted = ?5, unchanged = ?6,
+                     skipped = ?7, failed = ?8, deferred = ?9, error = ?10, finished_at = ?11
+                 WHERE id = ?1",
+                params![
+                    run.id,
+                    run.status.as_str(),
+                    run.added,
+                    run.updated,
+                    run.deleted,
+                    run.unchanged,
+                    run.skipped,
+                    run.failed,
+                    run.deferred,
+                    run.error,
+                    run.finished_at
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Record a successful push of a product's fields
+    fn record_content(
+        &self,
+        session: &str,
+        desired: &Desired,
+        catalog_id: &str,
+    ) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_catalog_items
+                    (session, product_id, sku, catalog_id, content_hash, attempts, last_error, next_attempt_at, synced_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 0, NULL, 0, ?6)
+                 ON CONFLICT (session, product_id) DO UPDATE SET
+                    sku = excluded.sku, catalog_id = excluded.catalog_id, content_hash = excluded.content_hash,
+                    attempts = 0, last_error = NULL, next_attempt_at = 0, synced_at = excluded.synced_at",
+                params![
+                    session,
+                    desired.product_id,
+                    desired.fields.retailer_id,
+                    catalog_id,
+                    desired.content_hash(),
+                    db::now_millis()
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn record_image(
+        &self,
+        session: &str,
+        product_id: &str,
+        image_url: &str,
+        image_hash: &str,
+    ) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_catalog_items SET image_url = ?3, image_hash = ?4
+                 WHERE session = ?1 AND product_id = ?2",
+                params![session, product_id, image_url, image_hash],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Record a failed push. Retries back off from one minute to six hours.
+    fn record_failure(&self, session: &str, desired: &Desired, error: &str) -> Result<(), String> {
+        let now = db::now_millis();
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_catalog_items
+                    (session, product_id, sku, attempts, last_error, next_attempt_at)
+                 VALUES (?1, ?2, ?3, 1, ?4, ?5 + 60000)
+                 ON CONFLICT (session, product_id) DO UPDATE SET
+                    attempts = attempts + 1, last_error = excluded.last_error,
+                    next_attempt_at = ?5 + MIN(60000 << MIN(attempts, 9), 21600000)",
+                params![
+                    session,
+                    desired.product_id,
+                    desired.fields.retailer_id,
+                    error,
+                    now
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Record why a product can't be listed. It is checked again every run.
+    fn record_skip(&self, session: &str, desired: &Desired, reason: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_catalog_items (session, product_id, sku, last_error)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (session, product_id) DO UPDATE SET last_error = excluded.last_error",
+                params![
+                    session,
+                    desired.product_id,
+                    desired.fields.retailer_id,
+                    reason
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn forget(&self, session: &str, product_id: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "DELETE FROM whatsapp_catalog_items WHERE session = ?1 AND product_id = ?2",
+                params![session, product_id],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Download an image as a data URL, with the hash of its bytes.
+    /// Images too large for WhatsApp are refused without reading them whole.
+    async fn fetch_image(&self, url: &str) -> Result<(String, String), String> {
+        let mut response = self
+            .http
+            .get(url)
+            .timeout(IMAGE_TIMEOUT)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| format!("Failed to download image: {}", e))?;
+        let mime = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("image/jpeg")
+            .to_string();
+        if !mime.starts_with("image/") {
+            return Err(format!("Image URL returned {}", mime));
+        }
+        if let Some(size) = response
+            .content_length()
+            .filter(|&size| size > MAX_IMAGE_BYTES as u64)
+        {
+            return Err(format!(
+                "Image is {} KB, WhatsApp accepts up to {} KB",
+                size / 1024,
+                MAX_IMAGE_BYTES / 1024
+            ));
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to download image: {}", e))?
+        {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > MAX_IMAGE_BYTES {
+                return Err(format!(
+                    "Image is over {} KB, which WhatsApp does not accept",
+                    MAX_IMAGE_BYTES / 1024
+                ));
+            }
+        }
+        let hash = hex::encode(Sha256::digest(&bytes));
+        let data_url = format!(
+            "data:{};base64,{}",
+            mime,
+            base64::engine::general_purpose::STANDARD.encode(&bytes)
+        );
+        Ok((data_url, hash))
+    }
+
+    /// Run one sync of `session`. Fails only if products or the live catalog
+    /// could not be loaded; per-product failures are counted in the run.
+    pub async fn sync(
+        &self,
+        app: &tauri::AppHandle,
+        trigger: Trigger,
+    ) -> Result<CatalogRun, String> {
+        let Ok(_guard) = self.running.try_lock() else {
+            return Err("A catalog sync is already running".to_string());
+        };
+        let session = self
+            .session(&app.state::<SessionRegistry>())
+            .ok_or_else(|| "No WhatsApp session is configured".to_string())?;
+        let mut run = self.start_run(&session, trigger)?;
+
+        let result = self.push_changes(app, &session, &mut run).await;
+        run.status = match result {
+            Err(e) => {
+                run.error = Some(e);
+                RunStatus::Failed
+            }
+            Ok(()) if run.failed > 0 => RunStatus::Partial,
+            Ok(()) => RunStatus::Success,
+        };
+        self.finish_run(&mut run)?;
+        log::info!(
+            "Catalog sync {}: {} added, {} updated, {} deleted, {} failed",
+            run.status.as_str(),
+            run.added,
+            run.updated,
+            run.deleted,
+            run.failed
+        );
+        let _ = app.emit("whatsapp-catalog-sync", &run);
+        Ok(run)
+    }
+
+    async fn push_changes(
+        &self,
+        app: &tauri::AppHandle,
+        session: &str,
+        run: &mut CatalogRun,
+    ) -> Result<(), String> {
+        let client = app.state::<WhatsAppClient>();
+        let pocketbase = app.state::<PocketBaseClient>();
+        let products = pocketbase
+            .list_all("products", None, None)
+            .await
+            .map_err(|e| format!("Failed to load products: {}", e))?;
+        let variants = pocketbase
+            .list_all("product_variants", None, None)
+            .await
+            .map_err(|e| format!("Failed to load product variants: {}", e))?;
+        let stock = variant_stock(&variants);
+        let remote = client
+            .get_products(session)
+            .await
+            .map_err(|e| format!("Failed to load the WhatsApp catalog: {}", e))?;
+
+        let mut desired = Vec::new();
+        let mut inactive_skus = HashSet::new();
+        for product in &products {
+            let id = product
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match Desired::from_product(product, stock.get(id).copied()) {
+                Some(product) => desired.push(product),
+                None => {
+                    if let Some(sku) = product
+                        .get("sku")
+                        .and_then(Value::as_str)
+                        .filter(|sku| !sku.is_empty())
+                    {
+                        inactive_skus.insert(sku.to_string());
+                    }
+                }
+            }
+        }
+        let tracked = self.tracked(session)?;
+        let image_hashes: HashMap<String, Option<String>> = tracked
+            .iter()
+            .map(|(item, hash)| (item.product_id.clone(), hash.clone()))
+            .collect();
+        let tracked: Vec<Tracked> = tracked.into_iter().map(|(item, _)| item).collect();
+
+        let plan = diff::plan(
+            &desired,
+            &tracked,
+            &remote,
+            &inactive_skus,
+            db::now_millis(),
+        );
+        run.unchanged = plan.unchanged;
+        run.deferred = plan.deferred;
+
+        let mut deletions = Vec::new();
+        for action in plan.actions {
+            match action {
+                Action::Create(product) => {
+                    let Some(image_url) = product.image_url.clone() else {
+                        self.record_skip(session, &product, "WhatsApp needs a product image")?;
+                        run.skipped += 1;
+                        continue;
+                    };
+                    match self.create(&client, session, &product, &image_url).await {
+                        Ok(()) => run.added += 1,
+                        Err(e) => {
+                            log::warn!(
+                                "Failed to add {} to the catalog: {}",
+                                product.fields.retailer_id,
+                                e
+                            );
+                            self.record_failure(session, &product, &e)?;
+                            run.failed += 1;
+                        }
+                    }
+                }
+                Action::Update {
+                    desired: product,
+                    catalog_id,
+                    content,
+                    image,
+                } => {
+                    let old_hash = image_hashes.get(&product.product_id).cloned().flatten();
+                    let result = self
+                        .update(
+                            &client,
+                            session,
+                            &product,
+                            &catalog_id,
+                            content,
+                            image.then_some(old_hash),
+                        )
+                        .await;
+                    match result {
+                        Ok(()) => run.updated += 1,
+                        Err(e) => {
+                            log::warn!(
+                                "Failed to update {} in the catalog: {}",
+                                product.fields.retailer_id,
+                                e
+                            );
+                            self.record_failure(session, &product, &e)?;
+                            run.failed += 1;
+                        }
+                    }
+                }
+                Action::Delete {
+                    product_id,
+                    catalog_id,
+                } => deletions.push((product_id, catalog_id)),
+                Action::Forget { product_id } => self.forget(session, &product_id)?,
+            }
+        }
+
+        if !deletions.is_empty() {
+            let ids: Vec<String> = deletions.iter().map(|(_, id)| id.clone()).collect();
+            match with_retry(|| client.delete_products(session, &ids)).await {
+                Ok(()) => {
+                    run.deleted += ids.len() as u32;
+                    for product_id in deletions.iter().filter_map(|(id, _)| id.as_deref()) {
+                        self.forget(session, product_id)?;
+                    }
+                }
+                // Retried on the next run, which will plan the same deletions
+                Err(e) => {
+                    log::warn!("Failed to delete catalog products: {}", e);
+                    run.failed += ids.len() as u32;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn create(
+        &self,
+        client: &WhatsAppClient,
+        session: &str,
+        product: &Desired,
+        image_url: &str,
+    ) -> Result<(), String> {
+        let (image, image_hash) = self.fetch_image(image_url).await?;
+        // Not retried: a timed-out add may have gone through. The next run
+        // finds it by SKU instead.
+        let id = client
+            .add_product(session, &product.fields, &image)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "The sidecar did not return the new product's id".to_string())?;
+        self.record_content(session, product, &id)?;
+        self.record_image(session, &product.product_id, image_url, &image_hash)
+    }
+
+    /// Push changed fields, and the image if it changed. `old_image_hash`
+    /// is set when the image URL changed.
+    async fn update(
+        &self,
+        client: &WhatsAppClient,
+        session: &str,
+        product: &Desired,
+        catalog_id: &str,
+        content: bool,
+        old_image_hash: Option<Option<String>>,
+    ) -> Result<(), String> {
+        if content {
+            with_retry(|| client.edit_product(session, catalog_id, &product.fields))
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        // Keep the link to the catalog product even if only the image changed
+        self.record_content(session, product, catalog_id)?;
+
+        let (Some(old_hash), Some(image_url)) = (old_image_hash, product.image_url.as_deref())
+        else {
+            return Ok(());
+        };
+        let (image, image_hash) = self.fetch_image(image_url).await?;
+        if old_hash.as_deref() != Some(image_hash.as_str()) {
+            with_retry(|| client.change_product_image(session, catalog_id, &image))
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        self.record_image(session, &product.product_id, image_url, &image_hash)
+    }
+}
+
+/// Retry an idempotent sidecar call on transient errors
+async fn with_retry<T, F, Fut>(mut call: F) -> Result<T, WhatsAppError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, WhatsAppError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if e.is_transient() && attempt < PUSH_ATTEMPTS => {
+                tokio::time::sleep(PUSH_RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Stock per product across its variants, which is what sales decrement.
+/// Oversold variants count as empty rather than taking from the others.
+fn variant_stock(variants: &[Value]) -> HashMap<String, f64> {
+    let mut stock = HashMap::new();
+    for variant in variants {
+        let Some(product) = variant.get("product").and_then(Value::as_str) else {
+            continue;
+        };
+        let level = variant
+            .get("stock_level")
+            .and_then(Value::as_f64)
+            .unwrap_or_default();
+        *stock.entry(product.to_string()).or_default() += level.max(0.0);
+    }
+    stock
+}
+
+/// Newest `updated` timestamp in `collection`, as PocketBase formats it
+async fn latest_change(client: &PocketBaseClient, collection: &str) -> Option<String> {
+    match client.list(collection, None, Some("-updated"), 1).await {
+        Ok(records) => records
+            .first()
+            .and_then(|record| record.get("updated"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        Err(e) => {
+            log::debug!("Could not check for {} changes: {}", collection, e);
+            None
+        }
+    }
+}
+
+/// Newest change to products or their variants. PocketBase timestamps sort
+/// as text.
+async fn latest_product_change(client: &PocketBaseClient) -> Option<String> {
+    let products = latest_change(client, "products").await;
+    let variants = latest_change(client, "product_variants").await;
+    products.max(variants)
+}
+
+fn session_connected(app: &tauri::AppHandle, session: &str) -> bool {
+    app.state::<SessionRegistry>()
+        .list()
+        .iter()
+        .any(|info| info.config.name == session && info.state == SessionState::Connected)
+}
+
+/// Background worker running scheduled and change-triggered syncs
+pub async fn run_worker(app: tauri::AppHandle) {
+    let sync = app.state::<CatalogSync>();
+    if !sync.settings.enabled {
+        return;
+    }
+    let client = app.state::<PocketBaseClient>();
+    let interval = Duration::from_secs(u64::from(sync.settings.interval_minutes.max(1)) * 60);
+
+    let mut last_run: Option<Instant> = None;
+    let mut last_change = None;
+    loop {
+        tokio::time::sleep(CHANGE_CHECK_INTERVAL).await;
+
+        let Some(session) = sync.session(&app.state::<SessionRegistry>()) else {
+            continue;
+        };
+        if !session_connected(&app, &session) {
+            continue;
+        }
+
+        let latest = latest_product_change(&client).await;
+        let trigger = if last_run.map_or(true, |at| at.elapsed() >= interval) {
+            Trigger::Schedule
+        } else if latest.is_some() && latest != last_change {
+            Trigger::Change
+        } else {
+            continue;
+        };
+
+        match sync.sync(&app, trigger).await {
+            Ok(_) => {
+                last_run = Some(Instant::now());
+                last_change = latest;
+            }
+            Err(e) => log::warn!("Catalog sync did not run: {}", e),
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+use reqwest::{Method, Url};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::error::WhatsAppError;
+use super::types::{
+    CatalogProduct, Chat, Message, NumberStatus, QrCode, SentMessage, SessionStatus,
+};
+
+/// Timeout for ordinary API calls
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Timeout for media uploads, which are sent base64-encoded
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+/// Extra attempts after the first one for transient failures
+const MAX_RETRIES: u32 = 2;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Sidecar routes the webview can't reach through [`WhatsAppClient::request`]:
+/// the supervisor stops the sidecar, and the app reads its event feed
+const INTERNAL_ROUTES: &[&str] = &["/api/shutdown", "/api/events"];
+
+/// HTTP client for the WPPConnect sidecar that attaches the launch token
+pub struct WhatsAppClient {
+    base_url: Url,
+    token: String,
+    http: reqwest::Client,
+}
+
+/// Raw sidecar response passed back to the webview
+#[derive(Debug, Serialize)]
+pub struct ProxyResponse {
+    pub status: u16,
+    /// Parsed JSON body, or the body as a string if it is not JSON
+    pub body: Value,
+}
+
+/// Keep digits only unless `phone` is already a chat id (`...@c.us`, `...@g.us`)
+pub(super) fn normalize_phone(phone: &str) -> Result<String, WhatsAppError> {
+    if phone.contains('@') {
+        return Ok(phone.to_string());
+    }
+    let digits: String = phone.chars().filter(char::is_ascii_digit).collect();
+    if digits.is_empty() {
+        return Err(WhatsAppError::invalid(format!(
+            "Invalid phone number: {}",
+            phone
+        )));
+    }
+    Ok(digits)
+}
+
+impl WhatsAppClient {
+    /// `http` is shared with the sidecar supervisor
+    pub fn new(http: reqwest::Client, port: u16, token: &str) -> Self {
+        Self {
+            base_url: Url::parse(&format!("http://127.0.0.1:{}", port))
+                .expect("localhost URL is valid"),
+            token: token.to_string(),
+            http,
+        }
+    }
+
+    /// `/api/<session>/<segments...>`, with each segment percent-encoded
+    fn session_url(&self, session: &str, segments: &[&str]) -> Result<Url, WhatsAppError> {
+        if session.is_empty() {
+            return Err(WhatsAppError::invalid("Session name is required"));
+        }
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| WhatsAppError::invalid("Invalid sidecar URL"))?
+            .push("api")
+            .push(session)
+            .extend(segments);
+        Ok(url)
+    }
+
+    async fn send_once(
+        &self,
+        method: &Method,
+        url: &Url,
+        body: Option<&Value>,
+        timeout: Duration,
+    ) -> Result<ProxyResponse, WhatsAppError> {
+        let mut request = self
+            .http
+            .request(method.clone(), url.clone())
+            .bearer_auth(&self.token)
+            .timeout(timeout);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+        let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        Ok(ProxyResponse { status, body })
+    }
+
+    /// Call a session route and return its JSON body.
+    ///
+    /// Transient failures are retried, but only for GETs: a POST that timed
+    /// out may already have sent the message.
+    async fn call(
+        &self,
+        method: Method,
+        session: &str,
+        url: Url,
+        body: Option<Value>,
+        timeout: Duration,
+    ) -> Result<Value, WhatsAppError> {
+        let retries = if method == Method::GET {
+            MAX_RETRIES
+        } else {
+            0
+        };
+
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .send_once(&method, &url, body.as_ref(), timeout)
+                .await
+                .and_then(|response| check_response(session, response));
+
+            match result {
+                Err(e) if e.is_transient() && attempt < retries => {
+                    attempt += 1;
+                    log::warn!("WhatsApp {} failed ({}), retrying", url.path(), e);
+                    tokio::time::sleep(RETRY_DELAY * attempt).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn get(&self, session: &str, segments: &[&str]) -> Result<Value, WhatsAppError> {
+        let url = self.session_url(session, segments)?;
+        self.call(Method::GET, session, url, None, REQUEST_TIMEOUT)
+            .await
+    }
+
+    async fn post(
+        &self,
+        session: &str,
+        segments: &[&str],
+        body: Option<Value>,
+        timeout: Duration,
+    ) -> Result<Value, WhatsAppError> {
+        let url = self.session_url(session, segments)?;
+        self.call(Method::POST, session, url, body, timeout).await
+    }
+
+    /// Forward a request to the sidecar's `/api/...` routes, except the
+    /// ones only the app may call
+    pub async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<ProxyResponse, WhatsAppError> {
+        let invalid = || WhatsAppError::invalid(format!("Invalid WhatsApp API path: {}", path));
+        // Check the route as the sidecar matches it: decoded, in any case
+        // and with or without a trailing slash
+        let route = path.split(['?', '#']).next().unwrap_or_default();
+        let route = percent_decode_str(route)
+            .decode_utf8()
+            .map_err(|_| invalid())?
+            .to_lowercase();
+        let route = route.trim_end_matches('/');
+        if !route.starts_with("/api/")
+            || route.contains('\\')
+            || route
+                .split('/')
+                .any(|segment| segment == "." || segment == "..")
+            || INTERNAL_ROUTES.contains(&route)
+        {
+            return Err(invalid());
+        }
+        let url = self
+            .base_url
+            .join(path)
+            .map_err(|e| WhatsAppError::invalid(e.to_string()))?;
+        self.send_once(&method, &url, body.as_ref(), UPLOAD_TIMEOUT)
+            .await
+    }
+
+    /// Open the sidecar's server-sent event feed. `last_event_id` resumes
+    /// after the last event seen on a previous connection.
+    pub(super) async fn open_events(
+        &self,
+        last_event_id: Option<&str>,
+    ) -> Result<reqwest::Response, WhatsAppError> {
+        let url = self
+            .base_url
+            .join("/api/events")
+            .expect("static path is valid");
+        let mut request = self
+            .http
+            .get(url)
+            .bearer_auth(&self.token)
+            .header(reqwest::header::ACCEPT, "text/event-stream");
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(WhatsAppError::Api {
+                status: response.status().as_u16(),
+                message: "Event feed rejected".to_string(),
+            });
+        }
+        Ok(response)
+    }
+
+    pub async fn start_session(&self, session: &str) -> Result<(), WhatsAppError> {
+        self.post(session, &["start"], None, REQUEST_TIMEOUT)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn session_status(&self, session: &str) -> Result<SessionStatus, WhatsAppError> {
+        let body = self.get(session, &["status"]).await?;
+        Ok(SessionStatus::from_value(&body))
+    }
+
+    pub async fn session_qr(&self, session: &str) -> Result<QrCode, WhatsAppError> {
+        let body = self.get(session, &["qrcode"]).await?;
+        Ok(QrCode {
+            qr_code: body
+                .get("qrCode")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    pub async fn send_message(
+        &self,
+        session: &str,
+        phone: &str,
+        message: &str,
+    ) -> Result<SentMessage, WhatsAppError> {
+        let body = json!({ "phone": normalize_phone(phone)?, "message": message });
+        let body = self
+            .post(session, &["send-message"], Some(body), REQUEST_TIMEOUT)
+            .await?;
+        Ok(SentMessage::from_value(&body))
+    }
+
+    /// Send a document. `base64` may be a data URL.
+    pub async fn send_file(
+        &self,
+        session: &str,
+        phone: &str,
+        base64: &str,
+        filename: &str,
+        caption: &str,
+    ) -> Result<SentMessage, WhatsAppError> {
+        let body = json!({
+            "phone": normalize_phone(phone)?,
+            "base64": base64,
+            "filename": filename,
+            "caption": caption,
+        });
+        let body = self
+            .post(session, &["send-file"], Some(body), UPLOAD_TIMEOUT)
+            .await?;
+        Ok(SentMessage::from_value(&body))
+    }
+
+    pub async fn get_chats(&self, session: &str) -> Result<Vec<Chat>, WhatsAppError> {
+        let body = self.get(session, &["chats"]).await?;
+        Ok(body
+            .get("chats")
+            .and_then(Value::as_array)
+            .map(|chats| chats.iter().filter_map(Chat::from_value).collect())
+            .unwrap_or_default())
+    }
+
+    pub async fn get_messages(
+        &self,
+        session: &str,
+        chat_id: &str,
+        count: u32,
+    ) -> Result<Vec<Message>, WhatsAppError> {
+        let mut url = self.session_url(session, &["messages", chat_id])?;
+        url.query_pairs_mut()
+            .append_pair("count", &count.to_string());
+        let body = self
+            .call(Method::GET, session, url, None, REQUEST_TIMEOUT)
+            .await?;
+        Ok(body
+            .get("messages")
+            .and_then(Value::as_array)
+            .map(|messages| messages.iter().filter_map(Message::from_value).collect())
+            .unwrap_or_default())
+    }
+
+    pub async fn check_number(
+        &self,
+        session: &str,
+        phone: &str,
+    ) -> Result<NumberStatus, WhatsAppError> {
+        let phone = normalize_phone(phone)?;
+        let body = self.get(session, &["check-number", &phone]).await?;
+        Ok(NumberStatus::from_value(&body))
+    }
+
+    /// Products in the session's business catalog
+    pub async fn get_products(&self, session: &str) -> Result<Vec<CatalogProduct>, WhatsAppError> {
+        let body = self.get(session, &["get-products"]).await?;
+        Ok(body
+            .get("products")
+            .and_then(Value::as_array)
+            .map(|products| {
+                products
+                    .iter()
+                    .filter_map(CatalogProduct::from_value)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Add a catalog product and return its WhatsApp id. `image` is a data URL.
+    pub async fn add_product(
+        &self,
+        session: &str,
+        product: &ProductFields,
+        image: &str,
+    ) -> Result<Option<String>, WhatsAppError> {
+        let body = json!({
+            "name": product.name,
+            "description": product.description,
+            "price": product.price,
+            "currency": product.currency,
+            "isHidden": product.is_hidden,
+            "retailerId": product.retailer_id,
+            "image": image,
+        });
+        let body = self
+            .post(session, &["add-product"], Some(body), UPLOAD_TIMEOUT)
+            .await?;
+        Ok(SentMessage::from_value(&body).id)
+    }
+
+    pub async fn edit_product(
+        &self,
+        session: &str,
+        id: &str,
+        product: &ProductFields,
+    ) -> Result<(), WhatsAppError> {
+        let body = json!({
+            "id": id,
+            "options": {
+                "name": product.name,
+                "description": product.description,
+                "price": product.price,
+                "currency": product.currency,
+                "isHidden": product.is_hidden,
+                "retailerId": product.retailer_id,
+            },
+        });
+        self.post(session, &["edit-product"], Some(body), REQUEST_TIMEOUT)
+            .await
+            .map(|_| ())
+    }
+
+    /// Replace a catalog product's main image. `image` is a data URL.
+    pub async fn change_product_image(
+        &self,
+        session: &str,
+        id: &str,
+        image: &str,
+    ) -> Result<(), WhatsAppError> {
+        let body = json!({ "id": id, "base64": image });
+        self.post(
+            session,
+            &["change-product-image"],
+            Some(body),
+            UPLOAD_TIMEOUT,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn delete_products(
+        &self,
+        session: &str,
+        ids: &[String],
+    ) -> Result<(), WhatsAppError> {
+        let body = json!({ "productIds": ids });
+        self.post(session, &["del-products"], Some(body), REQUEST_TIMEOUT)
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Editable fields of a catalog product
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductFields {
+    pub name: String,
+    pub description: String,
+    pub price: f64,
+    pub currency: &'static str,
+    pub is_hidden: bool,
+    pub retailer_id: String,
+}
+
+/// Map sidecar error statuses onto [`WhatsAppError`]
+fn check_response(session: &str, response: ProxyResponse) -> Result<Value, WhatsAppError> {
+    if (200..300).contains(&response.status) {
+        return Ok(response.body);
+    }
+
+    let message = response
+        .body
+        .get("error")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| response.body.to_string());
+
+    if response.status == 404 && message.to_lowercase().contains("not connected") {
+        return Err(WhatsAppError::SessionNotConnected {
+            session: session.to_string(),
+        });
+    }
+    Err(WhatsAppError::Api {
+        status: response.status,
+        message,
+    })
+}
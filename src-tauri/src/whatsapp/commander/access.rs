@@ -0,0 +1,119 @@
+//! Match a sender's phone number to a PocketBase user and their roles
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::pocketbase::{quote, PocketBaseClient, PocketBaseError};
+use crate::whatsapp::campaigns::send_address;
+
+/// Role names that get every permission, as in the webview's `isAdmin`
+const ADMIN_ROLES: &[&str] = &["Admin", "Super Admin"];
+
+/// Staff member allowed to send commands
+#[derive(Debug, Clone)]
+pub struct Operator {
+    pub user_id: String,
+    pub name: String,
+    is_admin: bool,
+    /// Resource -> granted actions, merged over all roles
+    permissions: HashMap<String, HashSet<String>>,
+}
+
+impl Operator {
+    /// Whether a role grants `action` on `resource` (same shape as
+    /// `roles.permissions` in the webview's RBAC)
+    pub fn can(&self, resource: &str, action: &str) -> bool {
+        self.is_admin
+            || self
+                .permissions
+                .get(resource)
+                .is_some_and(|actions| actions.contains(action))
+    }
+}
+
+/// Numbers are stored with or without the country code, so both are
+/// compared in full, with the shop's country code added to numbers that
+/// have none
+fn same_phone(a: &str, b: &str) -> bool {
+    let full = |phone: &str| send_address(phone.split('@').next().unwrap_or_default());
+    full(a).is_some_and(|a| Some(a) == full(b))
+}
+
+/// The active user whose `phone` matches `sender`, with their merged role
+/// permissions. `None` if the sender is not a user or has no role.
+pub async fn authorize(
+    client: &PocketBaseClient,
+    sender: &str,
+) -> Result<Option<Operator>, PocketBaseError> {
+    let users = client
+        .list_all("users", Some("phone != \"\" && is_active = true"), None)
+        .await?;
+    let Some(user) = users.iter().find(|user| {
+        user.get("phone")
+            .and_then(Value::as_str)
+            .is_some_and(|phone| same_phone(phone, sender))
+    }) else {
+        return Ok(None);
+    };
+    let Some(user_id) = user.get("id").and_then(Value::as_str) else {
+        return Ok(None);
+    };
+
+    let role_ids: Vec<String> = client
+        .list_all(
+            "user_roles",
+            Some(&format!("user = {}", quote(user_id))),
+            None,
+        )
+        .await?
+        .iter()
+        .filter_map(|link| link.get("role").and_then(Value::as_str).map(str::to_string))
+        .collect();
+    if role_ids.is_empty() {
+        return Ok(None);
+    }
+    let filter = role_ids
+        .iter()
+        .map(|id| format!("id = {}", quote(id)))
+        .collect::<Vec<_>>()
+        .join(" || ");
+    let roles = client.list_all("roles", Some(&filter), None).await?;
+
+    let mut operator = Operator {
+        user_id: user_id.to_string(),
+        name: user
+            .get("name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .or_else(|| user.get("email").and_then(Value::as_str))
+            .unwrap_or(user_id)
+            .to_string(),
+        is_admin: false,
+        permissions: HashMap::new(),
+    };
+    for role in &roles {
+        if role
+            .get("name")
+            .and_then(Value::as_str)
+            .is_some_and(|name| ADMIN_ROLES.contains(&name))
+        {
+            operator.is_admin = true;
+        }
+        let Some(permissions) = role.get("permissions").and_then(Value::as_object) else {
+            continue;
+        };
+        for (resource, actions) in permissions {
+            let granted = operator.permissions.entry(resource.clone()).or_default();
+            granted.extend(
+                actions
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string),
+            );
+        }
+    }
+    Ok(Some(operator))
+}
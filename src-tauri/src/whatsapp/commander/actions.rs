@@ -0,0 +1,535 @@
+//! What each command reads or changes in PocketBase, and the replies
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::pocketbase::{quote, PocketBaseClient, PocketBaseError};
+
+/// Threshold for variants without their own, as in the webview's alerts
+const DEFAULT_LOW_STOCK: i64 = 5;
+/// Longest list sent in one reply
+const MAX_LINES: usize = 15;
+/// Products shown for a name search
+const MAX_PRODUCTS: u32 = 5;
+/// Order statuses that count as revenue, as on the dashboard
+const REVENUE_STATUSES: &[&str] = &["confirmed", "shipped", "delivered", "invoiced"];
+
+/// Why a command could not run
+pub enum Failure {
+    /// Bad input; the reply explains what to fix
+    Invalid(String),
+    Backend(PocketBaseError),
+}
+
+impl From<PocketBaseError> for Failure {
+    fn from(e: PocketBaseError) -> Self {
+        Failure::Backend(e)
+    }
+}
+
+/// A change waiting for confirmation
+#[derive(Debug, Clone)]
+pub struct PreparedChange {
+    /// What will change, in words, e.g. for the confirmation prompt
+    pub summary: String,
+    /// `activity_logs` entity type
+    pub entity_type: &'static str,
+    pub entity_id: String,
+    change: Change,
+}
+
+#[derive(Debug, Clone)]
+enum Change {
+    ProductPrice { base_price: f64 },
+    VariantPrice { price_adjustment: f64 },
+    OrderStatus { status: &'static str },
+}
+
+impl PreparedChange {
+    fn collection(&self) -> &'static str {
+        match self.change {
+            Change::ProductPrice { .. } => "products",
+            Change::VariantPrice { .. } => "product_variants",
+            Change::OrderStatus { .. } => "sales_orders",
+        }
+    }
+
+    fn fields(&self) -> Value {
+        match &self.change {
+            Change::ProductPrice { base_price } => json!({ "base_price": base_price }),
+            Change::VariantPrice { price_adjustment } => {
+                json!({ "price_adjustment": price_adjustment })
+            }
+            Change::OrderStatus { status } => json!({ "status": status }),
+        }
+    }
+}
+
+/// Write a confirmed change
+pub async fn apply(
+    client: &PocketBaseClient,
+    change: &PreparedChange,
+) -> Result<(), PocketBaseError> {
+    client
+        .update(change.collection(), &change.entity_id, &change.fields())
+        .await
+        .map(|_| ())
+}
+
+/// Mirror an applied change into the app's activity log. Best effort: the
+/// local audit trail already has it.
+pub async fn log_activity(
+    client: &PocketBaseClient,
+    change: &PreparedChange,
+    user_id: &str,
+    user_name: &str,
+    sender: &str,
+) {
+    let record = json!({
+        "action": "update",
+        "entity_type": change.entity_type,
+        "entity_id": change.entity_id,
+        "description": format!("{} (via WhatsApp)", change.summary),
+        "user_id": user_id,
+        "user_name": user_name,
+        "metadata": { "source": "whatsapp", "sender": sender, "changes": change.fields() },
+    });
+    if let Err(e) = client.create("activity_logs", &record).await {
+        log::warn!("Failed to log WhatsApp command activity: {}", e);
+    }
+}
+
+/// Rupees with Indian digit grouping, e.g. `₹1,23,456`
+pub fn format_inr(amount: f64) -> String {
+    let rounded = amount.round() as i64;
+    let digits = rounded.unsigned_abs().to_string();
+    let mut grouped = String::new();
+    if digits.len() > 3 {
+        let (head, tail) = digits.split_at(digits.len() - 3);
+        let chars: Vec<char> = head.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            if i > 0 && (chars.len() - i) % 2 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(*c);
+        }
+        grouped.push(',');
+        grouped.push_str(tail);
+    } else {
+        grouped = digits;
+    }
+    format!("{}₹{}", if rounded < 0 { "-" } else { "" }, grouped)
+}
+
+fn str_field<'a>(record: &'a Value, field: &str) -> &'a str {
+    record
+        .get(field)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn num_field(record: &Value, field: &str) -> f64 {
+    record.get(field).and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+fn variant_label(variant: &Value) -> String {
+    let name = str_field(variant, "variant_name");
+    if !name.is_empty() {
+        return name.to_string();
+    }
+    let parts: Vec<&str> = [str_field(variant, "size"), str_field(variant, "color")]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty() {
+        "Default".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+fn variant_sku(product: &Value, variant: &Value) -> String {
+    let suffix = str_field(variant, "sku_suffix");
+    if suffix.is_empty() {
+        str_field(product, "sku").to_string()
+    } else {
+        format!("{}-{}", str_field(product, "sku"), suffix)
+    }
+}
+
+fn ids_filter(field: &str, ids: &[&str]) -> String {
+    ids.iter()
+        .map(|id| format!("{} = {}", field, quote(id)))
+        .collect::<Vec<_>>()
+        .join(" || ")
+}
+
+/// A SKU resolved to a product, and to one of its variants if the SKU has
+/// a variant suffix
+struct SkuMatch {
+    product: Value,
+    variant: Option<Value>,
+}
+
+/// Variant SKUs are the product SKU plus `-suffix`, and suffixes may contain
+/// dashes themselves, so every dash is tried as the split point
+async fn find_sku(
+    client: &PocketBaseClient,
+    sku: &str,
+) -> Result<Option<SkuMatch>, PocketBaseError> {
+    let sku = sku.trim().to_uppercase();
+    let prefixes: Vec<&str> = std::iter::once(sku.as_str())
+        .chain(sku.match_indices('-').map(|(i, _)| &sku[..i]))
+        .collect();
+    let mut products = client
+        .list_all("products", Some(&ids_filter("sku", &prefixes)), None)
+        .await?;
+
+    // Longest matching product SKU first
+    products.sort_by_key(|product| std::cmp::Reverse(str_field(product, "sku").len()));
+    for product in products {
+        let product_sku = str_field(&product, "sku").to_uppercase();
+        if product_sku == sku {
+            return Ok(Some(SkuMatch {
+                product,
+                variant: None,
+            }));
+        }
+        let Some(suffix) = sku.strip_prefix(&format!("{}-", product_sku)) else {
+            continue;
+        };
+        let filter = format!("product = {}", quote(str_field(&product, "id")));
+        let variant = client
+            .list_all("product_variants", Some(&filter), None)
+            .await?
+            .into_iter()
+            .find(|variant| str_field(variant, "sku_suffix").eq_ignore_ascii_case(suffix));
+        if variant.is_some() {
+            return Ok(Some(SkuMatch { product, variant }));
+        }
+    }
+    Ok(None)
+}
+
+fn stock_lines(product: &Value, variants: &[&Value]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "*{}* ({})",
+        str_field(product, "name"),
+        str_field(product, "sku")
+    )];
+    if variants.is_empty() {
+        lines.push(format!(
+            "  Stock: {}",
+            num_field(product, "stock_quantity") as i64
+        ));
+    }
+    for variant in variants {
+        lines.push(format!(
+            "  {} ({}): {}",
+            variant_label(variant),
+            variant_sku(product, variant),
+            num_field(variant, "stock_level") as i64
+        ));
+    }
+    lines
+}
+
+/// `!stock <sku or name>`
+pub async fn stock(client: &PocketBaseClient, query: &str) -> Result<String, Failure> {
+    if let Some(found) = find_sku(client, query).await? {
+        let variants: Vec<Value> = match &found.variant {
+            Some(variant) => vec![variant.clone()],
+            None => {
+                let filter = format!("product = {}", quote(str_field(&found.product, "id")));
+                client
+                    .list_all("product_variants", Some(&filter), Some("variant_name"))
+                    .await?
+            }
+        };
+        let variants: Vec<&Value> = variants.iter().collect();
+        return Ok(stock_lines(&found.product, &variants).join("\n"));
+    }
+
+    let filter = format!("is_active = true && name ~ {}", quote(query.trim()));
+    let products = client
+        .list("products", Some(&filter), Some("name"), MAX_PRODUCTS)
+        .await?;
+    if products.is_empty() {
+        return Ok(format!("❌ No product matching \"{}\"", query.trim()));
+    }
+    let ids: Vec<&str> = products.iter().map(|p| str_field(p, "id")).collect();
+    let variants = client
+        .list_all(
+            "product_variants",
+            Some(&ids_filter("product", &ids)),
+            Some("variant_name"),
+        )
+        .await?;
+
+    let mut lines = Vec::new();
+    for product in &products {
+        let own: Vec<&Value> = variants
+            .iter()
+            .filter(|variant| str_field(variant, "product") == str_field(product, "id"))
+            .collect();
+        lines.extend(stock_lines(product, &own));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Local midnight as a PocketBase UTC datetime
+fn start_of_today() -> String {
+    let midnight = chrono::Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time");
+    let start = midnight
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .map(|start| start.with_timezone(&chrono::Utc))
+        .unwrap_or_else(chrono::Utc::now);
+    start.format("%Y-%m-%d %H:%M:%S%.3fZ").to_string()
+}
+
+/// `!sales`: counter sales and confirmed orders since midnight
+pub async fn sales_today(client: &PocketBaseClient) -> Result<String, Failure> {
+    let since = quote(&start_of_today());
+    let sales = client
+        .list_all(
+            "sales",
+            Some(&format!("created >= {} && status != \"cancelled\"", since)),
+            None,
+        )
+        .await?;
+    let statuses = REVENUE_STATUSES
+        .iter()
+        .map(|status| format!("status = \"{}\"", status))
+        .collect::<Vec<_>>()
+        .join(" || ");
+    let orders = client
+        .list_all(
+            "sales_orders",
+            Some(&format!("created >= {} && ({})", since, statuses)),
+            None,
+        )
+        .await?;
+
+    let sales_total: f64 = sales.iter().map(|sale| num_field(sale, "total")).sum();
+    let orders_total: f64 = orders.iter().map(|order| num_field(order, "total")).sum();
+    let mut by_method: BTreeMap<&str, f64> = BTreeMap::new();
+    for sale in &sales {
+        let method = match str_field(sale, "payment_method") {
+            "" => "other",
+            method => method,
+        };
+        *by_method.entry(method).or_default() += num_field(sale, "total");
+    }
+
+    let mut lines = vec![
+        format!(
+            "📊 *Sales today* ({})",
+            chrono::Local::now().format("%d %b")
+        ),
+        format!(
+            "Counter: {} sales, {}",
+            sales.len(),
+            format_inr(sales_total)
+        ),
+    ];
+    for (method, amount) in by_method {
+        lines.push(format!("  {}: {}", method, format_inr(amount)));
+    }
+    lines.push(format!(
+        "Orders: {}, {}",
+        orders.len(),
+        format_inr(orders_total)
+    ));
+    lines.push(format!(
+        "*Total: {}*",
+        format_inr(sales_total + orders_total)
+    ));
+    Ok(lines.join("\n"))
+}
+
+/// `!lowstock`: variants at or below their threshold, lowest first
+pub async fn low_stock(client: &PocketBaseClient) -> Result<String, Failure> {
+    let filter = format!(
+        "(low_stock_threshold > 0 && stock_level <= low_stock_threshold) || (low_stock_threshold <= 0 && stock_level <= {})",
+        DEFAULT_LOW_STOCK
+    );
+    let variants = client
+        .list_all("product_variants", Some(&filter), Some("stock_level"))
+        .await?;
+    if variants.is_empty() {
+        return Ok("✅ Nothing is low on stock".to_string());
+    }
+
+    let shown = &variants[..variants.len().min(MAX_LINES)];
+    let mut ids: Vec<&str> = shown.iter().map(|v| str_field(v, "product")).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let products = client
+        .list_all("products", Some(&ids_filter("id", &ids)), None)
+        .await?;
+
+    let mut lines = vec![format!("⚠️ *Low stock* ({} items)", variants.len())];
+    for variant in shown {
+        let product = products
+            .iter()
+            .find(|product| str_field(product, "id") == str_field(variant, "product"));
+        let (name, sku) = match product {
+            Some(product) => (
+                str_field(product, "name").to_string(),
+                variant_sku(product, variant),
+            ),
+            None => (
+                "Unknown".to_string(),
+                str_field(variant, "sku_suffix").to_string(),
+            ),
+        };
+        lines.push(format!(
+            "{} {} ({}): {}",
+            name,
+            variant_label(variant),
+            sku,
+            num_field(variant, "stock_level") as i64
+        ));
+    }
+    if variants.len() > shown.len() {
+        lines.push(format!("…and {} more", variants.len() - shown.len()));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// `!price <sku> <amount>`: look up what would change
+pub async fn prepare_price(
+    client: &PocketBaseClient,
+    sku: &str,
+    amount: f64,
+) -> Result<PreparedChange, Failure> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(Failure::Invalid(
+            "⚠️ Price must be more than zero".to_string(),
+        ));
+    }
+    let Some(found) = find_sku(client, sku).await? else {
+        return Err(Failure::Invalid(format!("❌ No product with SKU {}", sku)));
+    };
+    let product = &found.product;
+    let base_price = num_field(product, "base_price");
+    let name = str_field(product, "name");
+
+    Ok(match &found.variant {
+        Some(variant) => {
+            let old = base_price + num_field(variant, "price_adjustment");
+            PreparedChange {
+                summary: format!(
+                    "Price of {} {} ({}) from {} to {}",
+                    name,
+                    variant_label(variant),
+                    variant_sku(product, variant),
+                    format_inr(old),
+                    format_inr(amount)
+                ),
+                entity_type: "product",
+                entity_id: str_field(variant, "id").to_string(),
+                change: Change::VariantPrice {
+                    price_adjustment: amount - base_price,
+                },
+            }
+        }
+        None => PreparedChange {
+            summary: format!(
+                "Price of {} ({}) from {} to {}",
+                name,
+                str_field(product, "sku"),
+                format_inr(base_price),
+                format_inr(amount)
+            ),
+            entity_type: "product",
+            entity_id: str_field(product, "id").to_string(),
+            change: Change::ProductPrice { base_price: amount },
+        },
+    })
+}
+
+/// Order by number, or by (part of) its id as the old `!status a1b2` did
+async fn find_order(client: &PocketBaseClient, reference: &str) -> Result<Value, Failure> {
+    let reference = reference.trim();
+    let filter = format!(
+        "order_number = {} || id ~ {}",
+        quote(reference),
+        quote(reference)
+    );
+    let mut orders = client.list("sales_orders", Some(&filter), None, 2).await?;
+    match orders.len() {
+        0 => Err(Failure::Invalid(format!(
+            "❌ No order matching \"{}\"",
+            reference
+        ))),
+        1 => Ok(orders.remove(0)),
+        _ => Err(Failure::Invalid(format!(
+            "⚠️ Several orders match \"{}\", send more of the order number",
+            reference
+        ))),
+    }
+}
+
+fn order_label(order: &Value) -> String {
+    match str_field(order, "order_number") {
+        "" => {
+            let id = str_field(order, "id");
+            format!("#{}", id.get(..8).unwrap_or(id))
+        }
+        number => number.to_string(),
+    }
+}
+
+/// `!status <order>`
+pub async fn order_status(client: &PocketBaseClient, reference: &str) -> Result<String, Failure> {
+    let order = find_order(client, reference).await?;
+    Ok(format!(
+        "ℹ️ Order {}\nCustomer: {}\nStatus: {}\nTotal: {}",
+        order_label(&order),
+        str_field(&order, "customer_name"),
+        str_field(&order, "status"),
+        format_inr(num_field(&order, "total"))
+    ))
+}
+
+/// `!ship <order>` / `!cancel <order>`: look up what would change
+pub async fn prepare_order_status(
+    client: &PocketBaseClient,
+    reference: &str,
+    status: &'static str,
+) -> Result<PreparedChange, Failure> {
+    let order = find_order(client, reference).await?;
+    let current = str_field(&order, "status");
+    let allowed = match status {
+        "shipped" => !matches!(current, "shipped" | "delivered" | "cancelled"),
+        _ => !matches!(current, "shipped" | "delivered" | "invoiced" | "cancelled"),
+    };
+    if !allowed {
+        return Err(Failure::Invalid(format!(
+            "⚠️ Order {} is {}, it can't be marked {}",
+            order_label(&order),
+            current,
+            status
+        )));
+    }
+
+    Ok(PreparedChange {
+        summary: format!(
+            "Order {} ({}, {}) from {} to {}",
+            order_label(&order),
+            str_field(&order, "customer_name"),
+            format_inr(num_field(&order, "total")),
+            current,
+            status
+        ),
+        entity_type: "order",
+        entity_id: str_field(&order, "id").to_string(),
+        change: Change::OrderStatus { status },
+    })
+}
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use rusqlite::{params, Row};
+use serde::Serialize;
+
+use super::access::Operator;
+use crate::db::{self, LocalDb};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Executed,
+    /// Understood and allowed, but failed while running
+    Failed,
+    /// Sender unknown or lacking the permission
+    Denied,
+    /// Not a command we know, or malformed arguments
+    Rejected,
+    /// Mutating command waiting for `!confirm`
+    Pending,
+    Cancelled,
+    /// Not confirmed in time
+    Expired,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Executed => "executed",
+            Outcome::Failed => "failed",
+            Outcome::Denied => "denied",
+            Outcome::Rejected => "rejected",
+            Outcome::Pending => "pending",
+            Outcome::Cancelled => "cancelled",
+            Outcome::Expired => "expired",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "executed" => Outcome::Executed,
+            "denied" => Outcome::Denied,
+            "rejected" => Outcome::Rejected,
+            "pending" => Outcome::Pending,
+            "cancelled" => Outcome::Cancelled,
+            "expired" => Outcome::Expired,
+            _ => Outcome::Failed,
+        }
+    }
+}
+
+/// One command received over WhatsApp
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: String,
+    pub session: String,
+    pub message_id: String,
+    /// Sender's phone number
+    pub sender: String,
+    /// PocketBase user the sender was matched to
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
+    pub command: String,
+    /// Message text as received
+    pub body: String,
+    pub outcome: Outcome,
+    /// Reply sent, or why the command failed
+    pub detail: Option<String>,
+    /// Unix timestamp (ms)
+    pub created_at: i64,
+    /// Unix timestamp (ms) of the last outcome change
+    pub updated_at: i64,
+}
+
+impl AuditEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            session: row.get(1)?,
+            message_id: row.get(2)?,
+            sender: row.get(3)?,
+            user_id: row.get(4)?,
+            user_name: row.get(5)?,
+            command: row.get(6)?,
+            body: row.get(7)?,
+            outcome: Outcome::parse(&row.get::<_, String>(8)?),
+            detail: row.get(9)?,
+            created_at: row.get(10)?,
+            updated_at: row.get(11)?,
+        })
+    }
+}
+
+/// Fields of a new audit record
+#[derive(Clone, Copy)]
+pub struct NewEntry<'a> {
+    pub session: &'a str,
+    pub message_id: &'a str,
+    pub sender: &'a str,
+    pub user_id: Option<&'a str>,
+    pub user_name: Option<&'a str>,
+    pub command: &'a str,
+    pub body: &'a str,
+}
+
+impl<'a> NewEntry<'a> {
+    /// The same entry, attributed to `operator`
+    pub fn by(self, operator: &'a Operator) -> Self {
+        Self {
+            user_id: Some(&operator.user_id),
+            user_name: Some(&operator.name),
+            ..self
+        }
+    }
+}
+
+/// Append-only record of every command received, kept in the local database
+/// so denied attempts are logged even while PocketBase is down
+pub struct AuditLog {
+    db: Arc<LocalDb>,
+}
+
+impl AuditLog {
+    pub fn new(db: Arc<LocalDb>) -> Self {
+        Self { db }
+    }
+
+    /// Record a command and return the record id
+    pub fn record(
+        &self,
+        entry: NewEntry,
+        outcome: Outcome,
+        detail: Option<&str>,
+    ) -> Result<String, String> {
+        let id = db::new_id();
+        let now = db::now_millis();
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_command_audit
+                    (id, session, message_id, sender, user_id, user_name, command, body, outcome, detail, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?11)",
+                params![
+                    id,
+                    entry.session,
+                    entry.message_id,
+                    entry.sender,
+                    entry.user_id,
+                    entry.user_name,
+                    entry.command,
+                    entry.body,
+                    outcome.as_str(),
+                    detail,
+                    now
+                ],
+            )
+        })?;
+        Ok(id)
+    }
+
+    /// Record how a pending command ended
+    pub fn resolve(&self, id: &str, outcome: Outcome, detail: Option<&str>) -> Result<(), String> {
+        let now = db::now_millis();
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_command_audit SET outcome = ?2, detail = ?3, updated_at = ?4 WHERE id = ?1",
+                params![id, outcome.as_str(), detail, now],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Most recent commands first
+    pub fn list(&self, limit: u32) -> Result<Vec<AuditEntry>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, session, message_id, sender, user_id, user_name, command, body, outcome, detail, created_at, updated_at
+                 FROM whatsapp_command_audit ORDER BY created_at DESC LIMIT ?1",
+            )?;
+            let rows = stmt.query_map(params![limit], AuditEntry::from_row)?;
+            rows.collect()
+        })
+    }
+}
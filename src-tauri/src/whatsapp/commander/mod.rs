@@ -0,0 +1,502 @@
+//! Owner commands over WhatsApp
+//!
+//! Staff send `!`-prefixed commands ("!stock LUM-EAR-001", "!sales") to the
+//! shop's number. New inbound messages from the event stream are routed
+//! here, whether or not the webview is open. The sender's phone is matched
+//! to a PocketBase user's `phone` field, and that user's roles decide which
+//! commands they may run. Lookups and changes are made as the user signed in
+//! to the app, so commands are refused while nobody is. Commands that change data first reply with what
+//! would change and a code, and only run after `!confirm <code>`.
+//!
+//! Every command, allowed or not, goes to a local audit trail. Applied
+//! changes are also written to the app's activity log.
+
+mod access;
+mod actions;
+mod audit;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tauri::Manager;
+
+use super::events::InboundMessage;
+use super::outbox::{Outbox, OutboxPayload};
+use crate::db::LocalDb;
+use crate::pocketbase::{PocketBaseClient, PocketBaseError};
+use access::Operator;
+use actions::{Failure, PreparedChange};
+use audit::{AuditLog, NewEntry, Outcome};
+
+pub use audit::AuditEntry;
+
+/// How long a mutating command waits for `!confirm`
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Help,
+    Stock(String),
+    Sales,
+    LowStock,
+    Price { sku: String, amount: f64 },
+    Order(String),
+    Ship(String),
+    Cancel(String),
+    Confirm(String),
+    Abort,
+}
+
+/// Resource and action a role must grant, in the webview's RBAC terms
+type Permission = (&'static str, &'static str);
+
+/// Usage, description and required permission of each command
+const HELP: &[(&str, &str, Permission)] = &[
+    (
+        "!stock <SKU or name>",
+        "stock on hand",
+        ("inventory", "read"),
+    ),
+    ("!lowstock", "items to reorder", ("inventory", "read")),
+    ("!sales", "today's sales", ("sales", "read")),
+    ("!status <order>", "order details", ("sales", "read")),
+    (
+        "!price <SKU> <amount>",
+        "change a price",
+        ("products", "update"),
+    ),
+    (
+        "!ship <order>",
+        "mark an order shipped",
+        ("sales", "update"),
+    ),
+    ("!cancel <order>", "cancel an order", ("sales", "update")),
+];
+
+fn parse_amount(text: &str) -> Option<f64> {
+    let cleaned: String = text
+        .trim_start_matches('₹')
+        .trim_end_matches("/-")
+        .chars()
+        .filter(|c| *c != ',')
+        .collect();
+    cleaned.parse().ok()
+}
+
+impl Command {
+    /// Parse a `!command`, or return the reply explaining what is wrong
+    fn parse(body: &str) -> Result<Self, String> {
+        let body = body.trim().trim_start_matches('!').trim();
+        let (name, rest) = body
+            .split_once(char::is_whitespace)
+            .map(|(name, rest)| (name, rest.trim()))
+            .unwrap_or((body, ""));
+        let required = |usage: &str| {
+            if rest.is_empty() {
+                Err(format!("Usage: {}", usage))
+            } else {
+                Ok(rest.to_string())
+            }
+        };
+
+        Ok(match name.to_lowercase().as_str() {
+            "help" | "commands" => Command::Help,
+            "stock" => Command::Stock(required("!stock <SKU or product name>")?),
+            "lowstock" | "low" => Command::LowStock,
+            "sales" | "today" => Command::Sales,
+            "status" | "order" => Command::Order(required("!status <order number>")?),
+            "ship" | "shipped" => Command::Ship(required("!ship <order number>")?),
+            "cancel" => Command::Cancel(required("!cancel <order number>")?),
+            "confirm" | "yes" => Command::Confirm(rest.to_string()),
+            "no" | "abort" => Command::Abort,
+            "price" => {
+                let mut parts = rest.split_whitespace();
+                match (
+                    parts.next(),
+                    parts.next().and_then(parse_amount),
+                    parts.next(),
+                ) {
+                    (Some(sku), Some(amount), None) => Command::Price {
+                        sku: sku.to_string(),
+                        amount,
+                    },
+                    _ => return Err("Usage: !price <SKU> <amount>".to_string()),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown command !{}. Send !help for the list.",
+                    name
+                ))
+            }
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Help => "help",
+            Command::Stock(_) => "stock",
+            Command::Sales => "sales",
+            Command::LowStock => "lowstock",
+            Command::Price { .. } => "price",
+            Command::Order(_) => "status",
+            Command::Ship(_) => "ship",
+            Command::Cancel(_) => "cancel",
+            Command::Confirm(_) => "confirm",
+            Command::Abort => "abort",
+        }
+    }
+
+    /// `None` for commands anyone known may send
+    fn permission(&self) -> Option<Permission> {
+        match self {
+            Command::Stock(_) | Command::LowStock => Some(("inventory", "read")),
+            Command::Sales | Command::Order(_) => Some(("sales", "read")),
+            Command::Price { .. } => Some(("products", "update")),
+            Command::Ship(_) | Command::Cancel(_) => Some(("sales", "update")),
+            Command::Help | Command::Confirm(_) | Command::Abort => None,
+        }
+    }
+}
+
+/// Mutating command waiting for `!confirm`
+struct Pending {
+    audit_id: String,
+    code: String,
+    change: PreparedChange,
+    permission: Permission,
+    expires_at: Instant,
+}
+
+/// Routes staff commands and remembers which ones await confirmation
+pub struct Commander {
+    audit: AuditLog,
+    /// Keyed by sender phone; one pending command per sender
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+/// Result of running a command, before it is audited and answered
+enum Step {
+    Done(Outcome, String),
+    Prompt(Pending),
+}
+
+impl Step {
+    fn from_failure(failure: Failure) -> Self {
+        match failure {
+            Failure::Invalid(reply) => Step::Done(Outcome::Rejected, reply),
+            Failure::Backend(e) => {
+                Step::Done(Outcome::Failed, format!("⚠️ Couldn't complete that: {}", e))
+            }
+        }
+    }
+
+    fn from_result(result: Result<String, Failure>) -> Self {
+        match result {
+            Ok(reply) => Step::Done(Outcome::Executed, reply),
+            Err(failure) => Step::from_failure(failure),
+        }
+    }
+}
+
+/// Sender's phone digits from a `number@c.us` JID
+fn sender_phone(jid: &str) -> String {
+    jid.split('@').next().unwrap_or_default().to_string()
+}
+
+/// Whether `message` should go to the command router: a `!` message sent to
+/// us directly, not in a group and not by the shop itself
+pub fn is_command(message: &InboundMessage) -> bool {
+    !message.message.from_me
+        && !message.message.is_group_msg
+        && message.message.body.trim_start().starts_with('!')
+}
+
+impl Commander {
+    pub fn new(db: Arc<LocalDb>) -> Self {
+        Self {
+            audit: AuditLog::new(db),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Most recent commands first
+    pub fn history(&self, limit: u32) -> Result<Vec<AuditEntry>, String> {
+        self.audit.list(limit)
+    }
+
+    fn help(operator: &Operator) -> String {
+        let mut lines = vec!["🤖 *Commands*".to_string()];
+        for (usage, description, permission) in HELP {
+            let (resource, action) = permission;
+            if operator.can(resource, action) {
+                lines.push(format!("{} - {}", usage, description));
+            }
+        }
+        lines.push("Changes need !confirm <code> to apply.".to_string());
+        lines.join("\n")
+    }
+
+    fn prompt(change: PreparedChange, permission: Permission) -> Pending {
+        Pending {
+            audit_id: String::new(),
+            code: format!("{:04}", rand::thread_rng().gen_range(0..10_000)),
+            change,
+            permission,
+            expires_at: Instant::now() + CONFIRM_TIMEOUT,
+        }
+    }
+
+    async fn confirm(
+        &self,
+        client: &PocketBaseClient,
+        operator: &Operator,
+        sender: &str,
+        code: &str,
+    ) -> Step {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(sender) {
+                None => {
+                    return Step::Done(Outcome::Rejected, "Nothing to confirm".to_string());
+                }
+                Some(waiting) if waiting.expires_at > Instant::now() && waiting.code != code => {
+                    return Step::Done(
+                        Outcome::Rejected,
+                        "❌ Wrong code, use the one from the last prompt".to_string(),
+                    );
+                }
+                Some(_) => pending.remove(sender).expect("entry checked above"),
+            }
+        };
+
+        if pending.expires_at <= Instant::now() {
+            self.resolve(&pending.audit_id, Outcome::Expired, None);
+            return Step::Done(
+                Outcome::Rejected,
+                "⌛ That confirmation expired, send the command again".to_string(),
+            );
+        }
+        // Roles may have changed since the prompt
+        let (resource, action) = pending.permission;
+        if !operator.can(resource, action) {
+            self.resolve(&pending.audit_id, Outcome::Denied, None);
+            return Step::Done(
+                Outcome::Denied,
+                "⛔ You are no longer allowed to do that".to_string(),
+            );
+        }
+
+        match actions::apply(client, &pending.change).await {
+            Ok(()) => {
+                let reply = format!("✅ Done: {}", pending.change.summary);
+                self.resolve(&pending.audit_id, Outcome::Executed, Some(&reply));
+                actions::log_activity(
+                    client,
+                    &pending.change,
+                    &operator.user_id,
+                    &operator.name,
+                    sender,
+                )
+                .await;
+                Step::Done(Outcome::Executed, reply)
+            }
+            Err(e) => {
+                let reply = format!("⚠️ Couldn't apply the change: {}", e);
+                self.resolve(&pending.audit_id, Outcome::Failed, Some(&reply));
+                Step::Done(Outcome::Failed, reply)
+            }
+        }
+    }
+
+    fn abort(&self, sender: &str) -> Step {
+        match self.pending.lock().unwrap().remove(sender) {
+            Some(pending) => {
+                self.resolve(&pending.audit_id, Outcome::Cancelled, None);
+                Step::Done(Outcome::Executed, "🚫 Cancelled".to_string())
+            }
+            None => Step::Done(Outcome::Rejected, "Nothing to cancel".to_string()),
+        }
+    }
+
+    async fn run(
+        &self,
+        client: &PocketBaseClient,
+        operator: &Operator,
+        sender: &str,
+        command: &Command,
+    ) -> Step {
+        let prepared = match command {
+            Command::Help => return Step::Done(Outcome::Executed, Self::help(operator)),
+            Command::Stock(query) => return Step::from_result(actions::stock(client, query).await),
+            Command::Sales => return Step::from_result(actions::sales_today(client).await),
+            Command::LowStock => return Step::from_result(actions::low_stock(client).await),
+            Command::Order(reference) => {
+                return Step::from_result(actions::order_status(client, reference).await)
+            }
+            Command::Confirm(code) => return self.confirm(client, operator, sender, code).await,
+            Command::Abort => return self.abort(sender),
+            Command::Price { sku, amount } => actions::prepare_price(client, sku, *amount).await,
+            Command::Ship(reference) => {
+                actions::prepare_order_status(client, reference, "shipped").await
+            }
+            Command::Cancel(reference) => {
+                actions::prepare_order_status(client, reference, "cancelled").await
+            }
+        };
+
+        match prepared {
+            Ok(change) => {
+                let permission = command
+                    .permission()
+                    .expect("mutating commands declare a permission");
+                Step::Prompt(Self::prompt(change, permission))
+            }
+            Err(failure) => Step::from_failure(failure),
+        }
+    }
+
+    fn record(&self, entry: NewEntry, outcome: Outcome, detail: Option<&str>) -> Option<String> {
+        match self.audit.record(entry, outcome, detail) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                log::error!("Failed to audit WhatsApp command: {}", e);
+                None
+            }
+        }
+    }
+
+    fn resolve(&self, audit_id: &str, outcome: Outcome, detail: Option<&str>) {
+        if let Err(e) = self.audit.resolve(audit_id, outcome, detail) {
+            log::error!("Failed to audit WhatsApp command: {}", e);
+        }
+    }
+}
+
+fn reply(app: &tauri::AppHandle, inbound: &InboundMessage, message: String) {
+    let result = app.state::<Outbox>().enqueue(
+        &inbound.session,
+        &inbound.chat_id,
+        OutboxPayload::Text { message },
+    );
+    if let Err(e) = result {
+        log::error!("Failed to queue WhatsApp command reply: {}", e);
+    }
+}
+
+fn refuse_signed_out(
+    app: &tauri::AppHandle,
+    inbound: &InboundMessage,
+    commander: &Commander,
+    entry: NewEntry,
+) {
+    let refused = "⚠️ Commands only work while someone is signed in to the app".to_string();
+    commander.record(entry, Outcome::Failed, Some(&refused));
+    reply(app, inbound, refused);
+}
+
+/// Authorize, run, audit and answer one command message
+pub async fn handle_message(app: tauri::AppHandle, inbound: InboundMessage) {
+    let commander = app.state::<Commander>();
+    let client = app.state::<PocketBaseClient>();
+    let body = inbound.message.body.trim();
+    let sender = sender_phone(&inbound.message.from);
+    let parsed = Command::parse(body);
+    let command_name = parsed.as_ref().map(Command::name).unwrap_or("unknown");
+    let entry = NewEntry {
+        session: &inbound.session,
+        message_id: &inbound.message.id,
+        sender: &sender,
+        user_id: None,
+        user_name: None,
+        command: command_name,
+        body,
+    };
+
+    // Without a signed-in user there is no one to look roles up as
+    if !client.is_signed_in() {
+        refuse_signed_out(&app, &inbound, &commander, entry);
+        return;
+    }
+    // Strangers get no reply, so the shop number doesn't advertise commands
+    let operator = match access::authorize(&client, &sender).await {
+        Ok(Some(operator)) => operator,
+        Ok(None) => {
+            commander.record(
+                entry,
+                Outcome::Denied,
+                Some("Sender is not an active user with a role"),
+            );
+            return;
+        }
+        Err(PocketBaseError::Unauthorized { .. }) => {
+            refuse_signed_out(&app, &inbound, &commander, entry);
+            return;
+        }
+        Err(e) => {
+            log::warn!("Could not authorize WhatsApp command sender: {}", e);
+            commander.record(entry, Outcome::Failed, Some(&e.to_string()));
+            return;
+        }
+    };
+
+    let command = match parsed {
+        Ok(command) => command,
+        Err(hint) => {
+            commander.record(entry.by(&operator), Outcome::Rejected, Some(&hint));
+            reply(&app, &inbound, hint);
+            return;
+        }
+    };
+    if let Some((resource, action)) = command.permission() {
+        if !operator.can(resource, action) {
+            let denied = format!("⛔ Your role doesn't allow !{}", command.name());
+            commander.record(entry.by(&operator), Outcome::Denied, Some(&denied));
+            reply(&app, &inbound, denied);
+            return;
+        }
+    }
+
+    match commander.run(&client, &operator, &sender, &command).await {
+        Step::Done(outcome, text) => {
+            commander.record(entry.by(&operator), outcome, Some(&text));
+            reply(&app, &inbound, text);
+        }
+        Step::Prompt(mut pending) => {
+            let prompt = format!(
+                "{}?\nReply !confirm {} within {} minutes to apply, or !no to cancel.",
+                pending.change.summary,
+                pending.code,
+                CONFIRM_TIMEOUT.as_secs() / 60
+            );
+            let Some(audit_id) =
+                commander.record(entry.by(&operator), Outcome::Pending, Some(&prompt))
+            else {
+                // Never apply a change that isn't on the audit trail
+                reply(
+                    &app,
+                    &inbound,
+                    "⚠️ Couldn't record the command, nothing was changed".to_string(),
+                );
+                return;
+            };
+            pending.audit_id = audit_id;
+            // A new command replaces an unconfirmed one
+            if let Some(replaced) = commander
+                .pending
+                .lock()
+                .unwrap()
+                .insert(sender.clone(), pending)
+            {
+                commander.resolve(
+                    &replaced.audit_id,
+                    Outcome::Cancelled,
+                    Some("Replaced by a newer command"),
+                );
+            }
+            reply(&app, &inbound, prompt);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use reqwest::Method;
+use tauri::{AppHandle, State};
+
+use super::campaigns::{
+    Audience, Campaign, CampaignPreview, CampaignReport, Campaigns, NewCampaign, OptOut,
+};
+use super::catalog::{CatalogRun, CatalogStatus, CatalogSync, Trigger};
+use super::client::ProxyResponse;
+use super::commander::{AuditEntry, Commander};
+use super::events::{EventStore, InboundMessage};
+use super::outbox::{Outbox, OutboxEntry, OutboxPayload, OutboxStatus};
+use super::sessions::{self, SessionConfig, SessionInfo, SessionRegistry};
+use super::snapshot::{self, SnapshotInfo};
+use super::types::{Chat, Message, NumberStatus, QrCode, SentMessage, SessionStatus};
+use super::{WhatsAppClient, WhatsAppError};
+use crate::pocketbase::PocketBaseClient;
+
+/// Tauri command to call a WPPConnect `/api/...` route with the launch token.
+/// Prefer the typed commands below; this covers the long tail of endpoints.
+#[tauri::command]
+pub async fn whatsapp_request(
+    client: State<'_, WhatsAppClient>,
+    method: String,
+    path: String,
+    body: Option<serde_json::Value>,
+) -> Result<ProxyResponse, WhatsAppError> {
+    let method = match method.to_uppercase().as_str() {
+        "GET" => Method::GET,
+        "POST" => Method::POST,
+        "PUT" => Method::PUT,
+        "DELETE" => Method::DELETE,
+        other => {
+            return Err(WhatsAppError::invalid(format!(
+                "Unsupported method: {}",
+                other
+            )))
+        }
+    };
+    client.request(method, &path, body).await
+}
+
+/// Tauri command to start (or resume) a WhatsApp session
+#[tauri::command]
+pub async fn whatsapp_start_session(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+) -> Result<(), WhatsAppError> {
+    client.start_session(&session).await
+}
+
+/// Tauri command to get a session's connection state
+#[tauri::command]
+pub async fn whatsapp_session_status(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+) -> Result<SessionStatus, WhatsAppError> {
+    client.session_status(&session).await
+}
+
+/// Tauri command to get the pairing QR code of a session
+#[tauri::command]
+pub async fn whatsapp_session_qr(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+) -> Result<QrCode, WhatsAppError> {
+    client.session_qr(&session).await
+}
+
+/// Tauri command to send a text message
+#[tauri::command]
+pub async fn whatsapp_send_message(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+    phone: String,
+    message: String,
+) -> Result<SentMessage, WhatsAppError> {
+    client.send_message(&session, &phone, &message).await
+}
+
+/// Tauri command to send a document (invoice PDF, catalog, ...)
+#[tauri::command]
+pub async fn whatsapp_send_file(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+    phone: String,
+    base64: String,
+    filename: String,
+    caption: Option<String>,
+) -> Result<SentMessage, WhatsAppError> {
+    client
+        .send_file(
+            &session,
+            &phone,
+            &base64,
+            &filename,
+            caption.as_deref().unwrap_or_default(),
+        )
+        .await
+}
+
+/// Tauri command to list chats
+#[tauri::command]
+pub async fn whatsapp_get_chats(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+) -> Result<Vec<Chat>, WhatsAppError> {
+    client.get_chats(&session).await
+}
+
+/// Tauri command to get the latest messages of a chat
+#[tauri::command]
+pub async fn whatsapp_get_messages(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+    chat_id: String,
+    count: Option<u32>,
+) -> Result<Vec<Message>, WhatsAppError> {
+    client
+        .get_messages(&session, &chat_id, count.unwrap_or(20))
+        .await
+}
+
+/// Tauri command to check whether a phone number is on WhatsApp
+#[tauri::command]
+pub async fn whatsapp_check_number(
+    client: State<'_, WhatsAppClient>,
+    session: String,
+    phone: String,
+) -> Result<NumberStatus, WhatsAppError> {
+    client.check_number(&session, &phone).await
+}
+
+/// Tauri command to queue a text message for delivery by the outbox worker
+#[tauri::command]
+pub fn whatsapp_enqueue_message(
+    outbox: State<'_, Outbox>,
+    session: String,
+    phone: String,
+    message: String,
+) -> Result<OutboxEntry, String> {
+    outbox.enqueue(&session, &phone, OutboxPayload::Text { message })
+}
+
+/// Tauri command to queue a document for delivery by the outbox worker
+#[tauri::command]
+pub fn whatsapp_enqueue_file(
+    outbox: State<'_, Outbox>,
+    session: String,
+    phone: String,
+    base64: String,
+    filename: String,
+    caption: Option<String>,
+) -> Result<OutboxEntry, String> {
+    outbox.enqueue(
+        &session,
+        &phone,
+        OutboxPayload::File {
+            base64,
+            filename,
+            caption: caption.unwrap_or_default(),
+        },
+    )
+}
+
+/// Tauri command to list queued messages, newest first
+#[tauri::command]
+pub fn whatsapp_outbox_list(
+    outbox: State<'_, Outbox>,
+    status: Option<OutboxStatus>,
+    limit: Option<u32>,
+) -> Result<Vec<OutboxEntry>, String> {
+    outbox.list(status, limit.unwrap_or(100))
+}
+
+/// Tauri command to count messages not yet sent
+#[tauri::command]
+pub fn whatsapp_outbox_pending_count(outbox: State<'_, Outbox>) -> Result<u32, String> {
+    outbox.pending_count()
+}
+
+/// Tauri command to requeue a failed or cancelled message
+#[tauri::command]
+pub fn whatsapp_outbox_retry(outbox: State<'_, Outbox>, id: String) -> Result<OutboxEntry, String> {
+    outbox.retry(&id)
+}
+
+/// Tauri command to cancel a pending message
+#[tauri::command]
+pub fn whatsapp_outbox_cancel(
+    outbox: State<'_, Outbox>,
+    id: String,
+) -> Result<OutboxEntry, String> {
+    outbox.cancel(&id)
+}
+
+/// Tauri command to read messages received through the event stream,
+/// newest first
+#[tauri::command]
+pub fn whatsapp_stored_messages(
+    store: State<'_, EventStore>,
+    session: String,
+    chat_id: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<InboundMessage>, String> {
+    store.messages(&session, chat_id.as_deref(), limit.unwrap_or(50))
+}
+
+/// Tauri command to read the audit trail of admin commands received over
+/// WhatsApp, newest first
+#[tauri::command]
+pub fn whatsapp_command_audit(
+    commander: State<'_, Commander>,
+    limit: Option<u32>,
+) -> Result<Vec<AuditEntry>, String> {
+    commander.history(limit.unwrap_or(100))
+}
+
+/// Tauri command to show how many customers a campaign would reach and
+/// what a few of their messages would look like
+#[tauri::command]
+pub async fn whatsapp_campaign_preview(
+    campaigns: State<'_, Campaigns>,
+    client: State<'_, PocketBaseClient>,
+    audience: Audience,
+    template: String,
+) -> Result<CampaignPreview, String> {
+    campaigns.preview(&client, &audience, &template).await
+}
+
+/// Tauri command to schedule a broadcast campaign
+#[tauri::command]
+pub fn whatsapp_campaign_create(
+    campaigns: State<'_, Campaigns>,
+    campaign: NewCampaign,
+) -> Result<Campaign, String> {
+    campaigns.create(campaign)
+}
+
+/// Tauri command to list campaigns with their recipient counts, latest first
+#[tauri::command]
+pub fn whatsapp_campaign_list(
+    campaigns: State<'_, Campaigns>,
+    limit: Option<u32>,
+) -> Result<Vec<Campaign>, String> {
+    campaigns.list(limit.unwrap_or(50))
+}
+
+/// Tauri command to get the delivery result of every recipient of a campaign
+#[tauri::command]
+pub fn whatsapp_campaign_report(
+    campaigns: State<'_, Campaigns>,
+    id: String,
+) -> Result<CampaignReport, String> {
+    campaigns.report(&id)
+}
+
+/// Tauri command to cancel a scheduled or running campaign
+#[tauri::command]
+pub fn whatsapp_campaign_cancel(
+    campaigns: State<'_, Campaigns>,
+    outbox: State<'_, Outbox>,
+    id: String,
+) -> Result<Campaign, String> {
+    campaigns.cancel(&outbox, &id)
+}
+
+/// Tauri command to list numbers that opted out of campaigns
+#[tauri::command]
+pub fn whatsapp_opt_outs(campaigns: State<'_, Campaigns>) -> Result<Vec<OptOut>, String> {
+    campaigns.opt_outs()
+}
+
+/// Tauri command to take a number off the opt-out list, e.g. when the
+/// customer asks in the shop
+#[tauri::command]
+pub fn whatsapp_remove_opt_out(
+    campaigns: State<'_, Campaigns>,
+    phone: String,
+) -> Result<bool, String> {
+    campaigns.opt_in(&phone)
+}
+
+/// Tauri command to sync products to the WhatsApp Business catalog now
+#[tauri::command]
+pub async fn whatsapp_catalog_sync(
+    app: AppHandle,
+    catalog: State<'_, CatalogSync>,
+) -> Result<CatalogRun, String> {
+    catalog.sync(&app, Trigger::Manual).await
+}
+
+/// Tauri command to get the catalog sync settings, last run and failing products
+#[tauri::command]
+pub fn whatsapp_catalog_status(
+    catalog: State<'_, CatalogSync>,
+    registry: State<'_, SessionRegistry>,
+) -> Result<CatalogStatus, String> {
+    catalog.status(&registry)
+}
+
+/// Tauri command to list recent catalog sync runs, newest first
+#[tauri::command]
+pub fn whatsapp_catalog_runs(
+    catalog: State<'_, CatalogSync>,
+    limit: Option<u32>,
+) -> Result<Vec<CatalogRun>, String> {
+    catalog.runs(limit.unwrap_or(20))
+}
+
+/// Tauri command to list configured WhatsApp sessions with their state
+#[tauri::command]
+pub fn whatsapp_list_sessions(registry: State<'_, SessionRegistry>) -> Vec<SessionInfo> {
+    registry.list()
+}
+
+/// Tauri command to add or update a WhatsApp session and start it
+#[tauri::command]
+pub async fn whatsapp_save_session(
+    app: AppHandle,
+    registry: State<'_, SessionRegistry>,
+    config: SessionConfig,
+) -> Result<Vec<SessionInfo>, String> {
+    let name = config.name.clone();
+    registry.save(&app, config)?;
+    sessions::start_session(&app, &name).await;
+    Ok(registry.list())
+}
+
+/// Tauri command to remove a WhatsApp session from the configuration
+#[tauri::command]
+pub fn whatsapp_remove_session(
+    app: AppHandle,
+    registry: State<'_, SessionRegistry>,
+    name: String,
+) -> Result<Vec<SessionInfo>, String> {
+    registry.remove(&app, &name)?;
+    Ok(registry.list())
+}
+
+/// Tauri command to export the sidecar's WhatsApp logins to an encrypted
+/// `.lmwa` archive in the app data folder or Downloads. The sidecar is
+/// briefly stopped while the archive is written.
+#[tauri::command]
+pub async fn whatsapp_backup_sessions(
+    app: AppHandle,
+    path: String,
+    passphrase: String,
+) -> Result<SnapshotInfo, String> {
+    let dest = snapshot::export_destination(&app, &path)?;
+    snapshot::backup(&app, dest, passphrase).await
+}
+
+/// Tauri command to read what a session snapshot contains without decrypting it
+#[tauri::command]
+pub fn whatsapp_inspect_session_backup(path: String) -> Result<SnapshotInfo, String> {
+    snapshot::inspect(path.as_ref())
+}
+
+/// Tauri command to restore WhatsApp logins from an encrypted archive.
+/// Archives from an incompatible sidecar build are refused.
+#[tauri::command]
+pub async fn whatsapp_restore_sessions(
+    app: AppHandle,
+    path: String,
+    passphrase: String,
+) -> Result<SnapshotInfo, String> {
+    snapshot::restore(&app, path.into(), passphrase).await
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// Error returned by every WhatsApp command, tagged by `kind` for the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WhatsAppError {
+    /// The sidecar is not running or refused the connection
+    SidecarUnavailable { message: String },
+    /// The sidecar did not answer in time
+    Timeout { message: String },
+    /// The session has not been started or is not logged in
+    SessionNotConnected { session: String },
+    /// The sidecar answered with an error status
+    Api { status: u16, message: String },
+    /// The request was rejected before being sent
+    InvalidRequest { message: String },
+}
+
+impl WhatsAppError {
+    pub(super) fn invalid(message: impl Into<String>) -> Self {
+        WhatsAppError::InvalidRequest {
+            message: message.into(),
+        }
+    }
+
+    /// Whether retrying the same request may succeed
+    pub(super) fn is_transient(&self) -> bool {
+        match self {
+            WhatsAppError::SidecarUnavailable { .. } | WhatsAppError::Timeout { .. } => true,
+            WhatsAppError::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for WhatsAppError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            WhatsAppError::Timeout {
+                message: e.to_string(),
+            }
+        } else {
+            WhatsAppError::SidecarUnavailable {
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
+impl fmt::Display for WhatsAppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WhatsAppError::SidecarUnavailable { message } => {
+                write!(f, "WhatsApp sidecar unavailable: {}", message)
+            }
+            WhatsAppError::Timeout { message } => {
+                write!(f, "WhatsApp sidecar timed out: {}", message)
+            }
+            WhatsAppError::SessionNotConnected { session } => {
+                write!(f, "WhatsApp session '{}' is not connected", session)
+            }
+            WhatsAppError::Api { status, message } => {
+                write!(f, "WhatsApp sidecar error ({}): {}", status, message)
+            }
+            WhatsAppError::InvalidRequest { message } => write!(f, "Invalid request: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for WhatsAppError {}
+
+impl From<WhatsAppError> for String {
+    fn from(e: WhatsAppError) -> Self {
+        e.to_string()
+    }
+}
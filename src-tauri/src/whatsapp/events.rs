@@ -0,0 +1,412 @@
+//! Inbound event stream from the WPPConnect sidecar
+//!
+//! One long-lived subscription to the sidecar's `/api/events` feed replaces
+//! polling from the webview. Incoming messages, delivery acks and session
+//! state changes are normalized, stored in the local database and re-emitted
+//! as `whatsapp-message`, `whatsapp-ack` and `whatsapp-state` events.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rusqlite::{params, Row};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{Emitter, Manager};
+
+use super::sessions::SessionRegistry;
+use super::types::{id_string, Message};
+use super::{campaigns, commander};
+use super::{WhatsAppClient, WhatsAppError, SIDECAR_NAME};
+use crate::db::{self, LocalDb};
+use crate::sidecar::SidecarSupervisor;
+
+/// The sidecar sends a heartbeat every 15s; silence for longer than this
+/// means the connection is dead even if the socket is still open
+const IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Message received on a session
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboundMessage {
+    pub session: String,
+    /// Chat the message belongs to (the sender, or the group)
+    pub chat_id: String,
+    #[serde(flatten)]
+    pub message: Message,
+}
+
+/// Delivery status of an outgoing message
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageAck {
+    pub session: String,
+    pub message_id: String,
+    /// Raw WhatsApp ack level, -1 (error) to 4 (played)
+    pub ack: i64,
+    pub status: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// Not started on the sidecar
+    Stopped,
+    /// Start requested, WhatsApp Web still loading
+    Starting,
+    /// Waiting for the QR code to be scanned
+    QrReady,
+    /// Waiting for the pairing code to be entered on the phone
+    Pairing,
+    Connected,
+    Disconnected,
+    /// The sidecar refused to start the session
+    Error,
+}
+
+impl SessionState {
+    pub(super) fn as_str(self) -> &'static str {
+        match self {
+            SessionState::Stopped => "stopped",
+            SessionState::Starting => "starting",
+            SessionState::QrReady => "qr_ready",
+            SessionState::Pairing => "pairing",
+            SessionState::Connected => "connected",
+            SessionState::Disconnected => "disconnected",
+            SessionState::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStateChange {
+    pub session: String,
+    pub state: SessionState,
+    /// Data URL of the pairing QR code when `state` is `qr_ready`
+    pub qr_code: Option<String>,
+    /// Code to enter on the phone when `state` is `pairing`
+    pub pairing_code: Option<String>,
+}
+
+/// Normalized sidecar event
+enum Event {
+    Message(InboundMessage),
+    Ack(MessageAck),
+    State(SessionStateChange),
+}
+
+pub(super) fn ack_status(ack: i64) -> &'static str {
+    match ack {
+        i64::MIN..=-1 => "error",
+        0 => "pending",
+        1 => "sent",
+        2 => "delivered",
+        3 => "read",
+        _ => "played",
+    }
+}
+
+impl Event {
+    /// Map a raw SSE event onto a typed one, `None` for unknown or malformed events
+    fn parse(name: &str, data: &Value) -> Option<Self> {
+        let session = data.get("session").and_then(Value::as_str)?.to_string();
+        match name {
+            "message" => {
+                let message = Message::from_value(data.get("message")?)?;
+                let chat_id = if message.from_me {
+                    message.to.clone()
+                } else {
+                    message.from.clone()
+                };
+                Some(Event::Message(InboundMessage {
+                    session,
+                    chat_id,
+                    message,
+                }))
+            }
+            "ack" => {
+                let ack = data.get("ack")?;
+                let level = ack.get("ack").and_then(Value::as_i64)?;
+                Some(Event::Ack(MessageAck {
+                    session,
+                    message_id: ack.get("id").and_then(id_string)?,
+                    ack: level,
+                    status: ack_status(level),
+                }))
+            }
+            "qrcode" => Some(Event::State(SessionStateChange {
+                session,
+                state: SessionState::QrReady,
+                qr_code: data
+                    .get("qrCode")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                pairing_code: None,
+            })),
+            "paircode" => Some(Event::State(SessionStateChange {
+                session,
+                state: SessionState::Pairing,
+                qr_code: None,
+                pairing_code: data.get("code").and_then(Value::as_str).map(str::to_string),
+            })),
+            "connected" | "disconnected" => Some(Event::State(SessionStateChange {
+                session,
+                state: if name == "connected" {
+                    SessionState::Connected
+                } else {
+                    SessionState::Disconnected
+                },
+                qr_code: None,
+                pairing_code: None,
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// Stores inbound events and answers queries about them
+pub struct EventStore {
+    db: Arc<LocalDb>,
+}
+
+impl EventStore {
+    pub fn new(db: Arc<LocalDb>) -> Self {
+        Self { db }
+    }
+
+    /// Store `event`. Returns false for a message that was already stored,
+    /// e.g. replayed after a reconnect.
+    fn persist(&self, event: &Event) -> Result<bool, String> {
+        let now = db::now_millis();
+        let changed = self.db.with(|conn| match event {
+            Event::Message(inbound) => {
+                let message = &inbound.message;
+                conn.execute(
+                    "INSERT OR IGNORE INTO whatsapp_messages
+                        (session, id, chat_id, from_jid, to_jid, sender_name, body, kind, from_me, is_group, timestamp, received_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    params![
+                        inbound.session,
+                        message.id,
+                        inbound.chat_id,
+                        message.from,
+                        message.to,
+                        message.sender_name,
+                        message.body,
+                        message.kind,
+                        message.from_me,
+                        message.is_group_msg,
+                        message.timestamp,
+                        now
+                    ],
+                )
+            }
+            Event::Ack(ack) => conn.execute(
+                "INSERT INTO whatsapp_acks (message_id, session, ack, updated_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (message_id) DO UPDATE SET ack = MAX(ack, excluded.ack), updated_at = excluded.updated_at",
+                params![ack.message_id, ack.session, ack.ack, now],
+            ),
+            Event::State(change) => conn.execute(
+                "INSERT INTO whatsapp_session_state (session, state, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT (session) DO UPDATE SET state = excluded.state, updated_at = excluded.updated_at",
+                params![change.session, change.state.as_str(), now],
+            ),
+        })?;
+        Ok(changed > 0)
+    }
+
+    /// Stored messages of a session, newest first, optionally for one chat
+    pub fn messages(
+        &self,
+        session: &str,
+        chat_id: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<InboundMessage>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT session, id, chat_id, from_jid, to_jid, sender_name, body, kind, from_me, is_group, timestamp
+                 FROM whatsapp_messages
+                 WHERE session = ?1 AND (?2 IS NULL OR chat_id = ?2)
+                 ORDER BY received_at DESC LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![session, chat_id, limit], message_from_row)?;
+            rows.collect()
+        })
+    }
+}
+
+fn message_from_row(row: &Row) -> rusqlite::Result<InboundMessage> {
+    Ok(InboundMessage {
+        session: row.get(0)?,
+        chat_id: row.get(2)?,
+        message: Message {
+            id: row.get(1)?,
+            from: row.get(3)?,
+            to: row.get(4)?,
+            sender_name: row.get(5)?,
+            body: row.get(6)?,
+            kind: row.get(7)?,
+            from_me: row.get(8)?,
+            is_group_msg: row.get(9)?,
+            timestamp: row.get(10)?,
+        },
+    })
+}
+
+/// Incremental parser for `text/event-stream` bodies
+#[derive(Default)]
+pub(super) struct SseParser {
+    /// Bytes of the line being received, decoded once it is complete so a
+    /// character split across chunks stays whole
+    buffer: Vec<u8>,
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+}
+
+/// One complete server-sent event
+pub(super) struct SseEvent {
+    pub id: Option<String>,
+    pub event: String,
+    pub data: String,
+}
+
+impl SseParser {
+    /// Feed a chunk of the body and return the events it completed
+    pub(super) fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let bytes: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&bytes);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        id: self.id.take(),
+                        event: self.event.take().unwrap_or_else(|| "message".to_string()),
+                        data: std::mem::take(&mut self.data),
+                    });
+                }
+                self.id = None;
+                self.event = None;
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "id" => self.id = Some(value.to_string()),
+                "event" => self.event = Some(value.to_string()),
+                "data" => {
+                    if !self.data.is_empty() {
+                        self.data.push('\n');
+                    }
+                    self.data.push_str(value);
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+fn dispatch(app: &tauri::AppHandle, store: &EventStore, event: Event) {
+    let is_new = store.persist(&event).unwrap_or_else(|e| {
+        log::error!("Failed to store WhatsApp event: {}", e);
+        false
+    });
+    match &event {
+        Event::State(change) => {
+            app.state::<SessionRegistry>()
+                .set_state(&change.session, change.state, None);
+        }
+        // Only on first sight, so a replayed command doesn't run twice
+        Event::Message(inbound) if is_new && commander::is_command(inbound) => {
+            tauri::async_runtime::spawn(commander::handle_message(app.clone(), inbound.clone()));
+        }
+        Event::Message(inbound) if is_new && campaigns::is_subscription_reply(inbound) => {
+            campaigns::handle_subscription_reply(app, inbound);
+        }
+        _ => {}
+    }
+    let result = match event {
+        Event::Message(message) => app.emit("whatsapp-message", message),
+        Event::Ack(ack) => app.emit("whatsapp-ack", ack),
+        Event::State(change) => app.emit("whatsapp-state", change),
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to emit WhatsApp event: {}", e);
+    }
+}
+
+/// Read the feed until it ends, fails or goes quiet
+async fn consume(
+    app: &tauri::AppHandle,
+    client: &WhatsAppClient,
+    store: &EventStore,
+    last_event_id: &mut Option<String>,
+) -> Result<(), WhatsAppError> {
+    let mut response = client.open_events(last_event_id.as_deref()).await?;
+    log::info!("Subscribed to WhatsApp events");
+
+    let mut parser = SseParser::default();
+    loop {
+        let chunk = tokio::time::timeout(IDLE_TIMEOUT, response.chunk())
+            .await
+            .map_err(|_| WhatsAppError::Timeout {
+                message: "No events or heartbeat from the sidecar".to_string(),
+            })??;
+        let Some(chunk) = chunk else {
+            return Ok(());
+        };
+
+        for sse in parser.push(&chunk) {
+            if sse.id.is_some() {
+                *last_event_id = sse.id;
+            }
+            let Ok(data) = serde_json::from_str::<Value>(&sse.data) else {
+                log::warn!("Ignoring malformed WhatsApp event '{}'", sse.event);
+                continue;
+            };
+            if let Some(event) = Event::parse(&sse.event, &data) {
+                dispatch(app, store, event);
+            }
+        }
+    }
+}
+
+/// Keep a subscription to the sidecar's event feed open for the app's lifetime
+pub async fn run_event_stream(app: tauri::AppHandle) {
+    let client = app.state::<WhatsAppClient>();
+    let store = app.state::<EventStore>();
+    let supervisor = app.state::<SidecarSupervisor>();
+    let mut last_event_id = None;
+
+    loop {
+        // Only subscribe once the sidecar is up; a failed sidecar is retried
+        // after its breaker is reset
+        if supervisor
+            .wait_ready(SIDECAR_NAME, Duration::from_secs(60))
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        match consume(&app, &client, &store, &mut last_event_id).await {
+            Ok(()) => log::info!("WhatsApp event feed closed, reconnecting"),
+            Err(e) => log::warn!("WhatsApp event feed lost: {}", e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
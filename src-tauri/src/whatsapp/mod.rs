@@ -0,0 +1,80 @@
+//! WhatsApp integration backed by the WPPConnect sidecar
+//!
+//! The sidecar only accepts requests carrying a per-launch bearer token, which
+//! lives in [`WhatsAppClient`] on the Rust side. The webview reaches the
+//! sidecar through Tauri commands and never sees the token.
+
+mod campaigns;
+mod catalog;
+mod client;
+mod commander;
+pub mod commands;
+mod error;
+mod events;
+mod outbox;
+mod sessions;
+mod snapshot;
+#[cfg(test)]
+mod tests;
+pub mod types;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rand::distributions::{Alphanumeric, DistString};
+use tauri::Manager;
+
+use crate::sidecar::SidecarSpec;
+
+pub use campaigns::{run_worker as run_campaign_worker, Campaigns};
+pub use catalog::{run_worker as run_catalog_worker, CatalogSync, CatalogSyncSettings};
+pub use client::WhatsAppClient;
+pub use commander::Commander;
+pub use error::WhatsAppError;
+pub use events::{run_event_stream, EventStore};
+pub use outbox::{run_worker as run_outbox_worker, Outbox, RateLimits};
+pub use sessions::{run_session_manager, SessionConfig, SessionRegistry};
+
+/// Registry name of the WPPConnect sidecar
+pub const SIDECAR_NAME: &str = "wppconnect";
+
+/// Port used when it is free and none is configured in settings
+pub const DEFAULT_PORT: u16 = 21465;
+
+/// Random secret shared with the sidecar for this app run
+pub fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 48)
+}
+
+/// Working directory of the sidecar. WPPConnect keeps its login tokens and
+/// browser profiles in `tokens/` below it.
+pub fn data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?
+        .join("whatsapp"))
+}
+
+/// What the WPPConnect sidecar's detailed status reports: the sessions it
+/// hosts
+pub fn sidecar_details(app: &tauri::AppHandle) -> serde_json::Value {
+    serde_json::json!({ "sessions": app.state::<SessionRegistry>().list() })
+}
+
+/// Sidecar spec for the bundled `wppconnect-server` listening on `port`
+pub fn sidecar_spec(port: u16, token: &str, data_dir: PathBuf) -> SidecarSpec {
+    SidecarSpec::new(
+        SIDECAR_NAME,
+        "wppconnect-server",
+        &format!("http://127.0.0.1:{}/health", port),
+    )
+    .env("WPPCONNECT_PORT", &port.to_string())
+    .env("WPPCONNECT_SECRET", token)
+    .bearer_token(token)
+    .port(port)
+    .current_dir(data_dir)
+    // Node + Puppeteer bootstrap is slow on older shop PCs
+    .startup_timeout(Duration::from_secs(60))
+    .shutdown_url(&format!("http://127.0.0.1:{}/api/shutdown", port))
+}
@@ -0,0 +1,480 @@
+//! Durable outbound message queue
+//!
+//! Messages are written to the local database first and sent by a background
+//! worker, so order confirmations survive sidecar restarts and app restarts.
+//! The worker paces sends globally and per recipient to stay clear of
+//! WhatsApp's spam detection.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use super::client::normalize_phone;
+use super::{WhatsAppClient, WhatsAppError, SIDECAR_NAME};
+use crate::db::{self, LocalDb};
+use crate::sidecar::{SidecarState, SidecarSupervisor};
+
+/// Pacing and retry limits for the outbox worker
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    /// Minimum gap between any two sends
+    pub global_interval: Duration,
+    /// Minimum gap between two sends to the same recipient
+    pub per_recipient_interval: Duration,
+    /// Attempts before a message is marked failed
+    pub max_attempts: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            global_interval: Duration::from_secs(4),
+            per_recipient_interval: Duration::from_secs(20),
+            max_attempts: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OutboxPayload {
+    Text {
+        message: String,
+    },
+    File {
+        base64: String,
+        filename: String,
+        caption: String,
+    },
+}
+
+impl OutboxPayload {
+    fn kind(&self) -> &'static str {
+        match self {
+            OutboxPayload::Text { .. } => "text",
+            OutboxPayload::File { .. } => "file",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxStatus {
+    Pending,
+    Sending,
+    Sent,
+    Failed,
+    Cancelled,
+}
+
+impl OutboxStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Sending => "sending",
+            OutboxStatus::Sent => "sent",
+            OutboxStatus::Failed => "failed",
+            OutboxStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "sending" => OutboxStatus::Sending,
+            "sent" => OutboxStatus::Sent,
+            "failed" => OutboxStatus::Failed,
+            "cancelled" => OutboxStatus::Cancelled,
+            _ => OutboxStatus::Pending,
+        }
+    }
+}
+
+/// Queue entry as shown to the frontend (without the message payload)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxEntry {
+    pub id: String,
+    pub session: String,
+    pub recipient: String,
+    pub kind: String,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// WhatsApp message id once sent
+    pub message_id: Option<String>,
+    pub created_at: i64,
+    pub next_attempt_at: i64,
+    pub sent_at: Option<i64>,
+}
+
+const ENTRY_COLUMNS: &str = "id, session, recipient, kind, status, attempts, last_error, message_id, created_at, next_attempt_at, sent_at";
+
+impl OutboxEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            session: row.get(1)?,
+            recipient: row.get(2)?,
+            kind: row.get(3)?,
+            status: OutboxStatus::parse(&row.get::<_, String>(4)?),
+            attempts: row.get(5)?,
+            last_error: row.get(6)?,
+            message_id: row.get(7)?,
+            created_at: row.get(8)?,
+            next_attempt_at: row.get(9)?,
+            sent_at: row.get(10)?,
+        })
+    }
+}
+
+/// Message picked by the worker for sending
+struct DueMessage {
+    id: String,
+    session: String,
+    recipient: String,
+    payload: OutboxPayload,
+    attempts: u32,
+}
+
+pub struct Outbox {
+    db: Arc<LocalDb>,
+    limits: RateLimits,
+    /// Wakes the worker when something is enqueued
+    wake: tokio::sync::Notify,
+}
+
+impl Outbox {
+    pub fn new(db: Arc<LocalDb>, limits: RateLimits) -> Self {
+        Self {
+            db,
+            limits,
+            wake: tokio::sync::Notify::new(),
+        }
+    }
+
+    fn get(&self, id: &str) -> Result<OutboxEntry, String> {
+        self.db
+            .with(|conn| {
+                conn.query_row(
+                    &format!(
+                        "SELECT {} FROM whatsapp_outbox WHERE id = ?1",
+                        ENTRY_COLUMNS
+                    ),
+                    [id],
+                    OutboxEntry::from_row,
+                )
+                .optional()
+            })?
+            .ok_or_else(|| format!("Outbox message not found: {}", id))
+    }
+
+    pub fn enqueue(
+        &self,
+        session: &str,
+        recipient: &str,
+        payload: OutboxPayload,
+    ) -> Result<OutboxEntry, String> {
+        if session.is_empty() {
+            return Err("Session name is required".to_string());
+        }
+        let recipient = normalize_phone(recipient).map_err(String::from)?;
+        let id = db::new_id();
+        let now = db::now_millis();
+        let payload_json = serde_json::to_string(&payload)
+            .map_err(|e| format!("Failed to encode message: {}", e))?;
+
+        self.db.with(|conn| {
+            conn.execute(
+                "INSERT INTO whatsapp_outbox
+                    (id, session, recipient, kind, payload, status, attempts, created_at, next_attempt_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 'pending', 0, ?6, ?6)",
+                params![id, session, recipient, payload.kind(), payload_json, now],
+            )
+        })?;
+
+        self.wake.notify_one();
+        self.get(&id)
+    }
+
+    /// Queue entries, newest first, optionally filtered by status
+    pub fn list(
+        &self,
+        status: Option<OutboxStatus>,
+        limit: u32,
+    ) -> Result<Vec<OutboxEntry>, String> {
+        self.db.with(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM whatsapp_outbox
+                 WHERE (?1 IS NULL OR status = ?1)
+                 ORDER BY created_at DESC LIMIT ?2",
+                ENTRY_COLUMNS
+            ))?;
+            let rows = stmt.query_map(
+                params![status.map(OutboxStatus::as_str), limit],
+                OutboxEntry::from_row,
+            )?;
+            rows.collect()
+        })
+    }
+
+    /// Number of messages still waiting to be sent
+    pub fn pending_count(&self) -> Result<u32, String> {
+        self.db.with(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM whatsapp_outbox WHERE status IN ('pending', 'sending')",
+                [],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Put a failed or cancelled message back in the queue
+    pub fn retry(&self, id: &str) -> Result<OutboxEntry, String> {
+        let updated = self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox
+                 SET status = 'pending', attempts = 0, last_error = NULL, next_attempt_at = ?2
+                 WHERE id = ?1 AND status IN ('failed', 'cancelled')",
+                params![id, db::now_millis()],
+            )
+        })?;
+        if updated == 0 {
+            return Err(format!("Outbox message {} is not failed or cancelled", id));
+        }
+        self.wake.notify_one();
+        self.get(id)
+    }
+
+    /// Drop a message that has not been sent yet
+    pub fn cancel(&self, id: &str) -> Result<OutboxEntry, String> {
+        let updated = self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox SET status = 'cancelled' WHERE id = ?1 AND status = 'pending'",
+                [id],
+            )
+        })?;
+        if updated == 0 {
+            return Err(format!("Outbox message {} is not pending", id));
+        }
+        self.get(id)
+    }
+
+    /// Messages left in `sending` by a crash go back to `pending`. This can
+    /// resend a message whose delivery was not recorded, which is preferable
+    /// to silently dropping it.
+    fn requeue_in_flight(&self) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox SET status = 'pending' WHERE status = 'sending'",
+                [],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Oldest due message whose recipient is not rate-limited
+    fn next_due(&self, is_blocked: impl Fn(&str) -> bool) -> Result<Option<DueMessage>, String> {
+        let candidates = self.db.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, session, recipient, payload, attempts FROM whatsapp_outbox
+                 WHERE status = 'pending' AND next_attempt_at <= ?1
+                 ORDER BY created_at LIMIT 50",
+            )?;
+            let rows = stmt.query_map([db::now_millis()], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, u32>(4)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+
+        for (id, session, recipient, payload, attempts) in candidates {
+            if is_blocked(&recipient) {
+                continue;
+            }
+            match serde_json::from_str(&payload) {
+                Ok(payload) => {
+                    return Ok(Some(DueMessage {
+                        id,
+                        session,
+                        recipient,
+                        payload,
+                        attempts,
+                    }))
+                }
+                Err(e) => {
+                    self.mark_failed(&id, attempts, &format!("Unreadable payload: {}", e))?;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn mark_sending(&self, id: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox SET status = 'sending' WHERE id = ?1",
+                [id],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn mark_sent(&self, id: &str, attempts: u32, message_id: Option<&str>) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox
+                 SET status = 'sent', attempts = ?2, message_id = ?3, sent_at = ?4, last_error = NULL
+                 WHERE id = ?1",
+                params![id, attempts, message_id, db::now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn mark_failed(&self, id: &str, attempts: u32, error: &str) -> Result<(), String> {
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox SET status = 'failed', attempts = ?2, last_error = ?3 WHERE id = ?1",
+                params![id, attempts, error],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn reschedule(&self, id: &str, attempts: u32, error: &str) -> Result<(), String> {
+        // 30s, 1m, 2m, ... capped at 30 minutes
+        let delay = Duration::from_secs(30 * 2u64.pow(attempts.saturating_sub(1).min(6)))
+            .min(Duration::from_secs(30 * 60));
+        let next_attempt_at = db::now_millis() + delay.as_millis() as i64;
+        self.db.with(|conn| {
+            conn.execute(
+                "UPDATE whatsapp_outbox
+                 SET status = 'pending', attempts = ?2, last_error = ?3, next_attempt_at = ?4
+                 WHERE id = ?1",
+                params![id, attempts, error, next_attempt_at],
+            )
+        })?;
+        Ok(())
+    }
+}
+
+fn emit_entry(app: &tauri::AppHandle, outbox: &Outbox, id: &str) {
+    if let Ok(entry) = outbox.get(id) {
+        let _ = app.emit("whatsapp-outbox", entry);
+    }
+}
+
+async fn deliver(
+    client: &WhatsAppClient,
+    message: &DueMessage,
+) -> Result<Option<String>, WhatsAppError> {
+    let sent = match &message.payload {
+        OutboxPayload::Text { message: text } => {
+            client
+                .send_message(&message.session, &message.recipient, text)
+                .await?
+        }
+        OutboxPayload::File {
+            base64,
+            filename,
+            caption,
+        } => {
+            client
+                .send_file(
+                    &message.session,
+                    &message.recipient,
+                    base64,
+                    filename,
+                    caption,
+                )
+                .await?
+        }
+    };
+    Ok(sent.id)
+}
+
+/// Background worker draining the outbox while the sidecar is healthy
+pub async fn run_worker(app: tauri::AppHandle) {
+    let outbox = app.state::<Outbox>();
+    let client = app.state::<WhatsAppClient>();
+    let supervisor = app.state::<SidecarSupervisor>();
+    let limits = outbox.limits.clone();
+
+    if let Err(e) = outbox.requeue_in_flight() {
+        log::error!("Failed to requeue in-flight WhatsApp messages: {}", e);
+    }
+
+    let mut last_send: Option<Instant> = None;
+    let mut last_send_to: HashMap<String, Instant> = HashMap::new();
+
+    loop {
+        // Wake on enqueue, or periodically for retries that became due
+        let _ = tokio::time::timeout(Duration::from_secs(2), outbox.wake.notified()).await;
+
+        let healthy = supervisor
+            .status(SIDECAR_NAME)
+            .map(|status| status.state == SidecarState::Healthy)
+            .unwrap_or(false);
+        if !healthy {
+            continue;
+        }
+
+        if let Some(last) = last_send {
+            let elapsed = last.elapsed();
+            if elapsed < limits.global_interval {
+                tokio::time::sleep(limits.global_interval - elapsed).await;
+            }
+        }
+
+        last_send_to.retain(|_, at| at.elapsed() < limits.per_recipient_interval);
+        let message = match outbox.next_due(|recipient| last_send_to.contains_key(recipient)) {
+            Ok(Some(message)) => message,
+            Ok(None) => continue,
+            Err(e) => {
+                log::error!("Failed to read WhatsApp outbox: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = outbox.mark_sending(&message.id) {
+            log::error!("Failed to update WhatsApp outbox: {}", e);
+            continue;
+        }
+        emit_entry(&app, &outbox, &message.id);
+
+        let attempts = message.attempts + 1;
+        let result = deliver(&client, &message).await;
+        last_send = Some(Instant::now());
+        last_send_to.insert(message.recipient.clone(), Instant::now());
+
+        let update = match result {
+            Ok(message_id) => outbox.mark_sent(&message.id, attempts, message_id.as_deref()),
+            Err(e) => {
+                let retryable =
+                    e.is_transient() || matches!(e, WhatsAppError::SessionNotConnected { .. });
+                log::warn!("WhatsApp message {} failed: {}", message.id, e);
+                if retryable && attempts < limits.max_attempts {
+                    outbox.reschedule(&message.id, attempts, &e.to_string())
+                } else {
+                    outbox.mark_failed(&message.id, attempts, &e.to_string())
+                }
+            }
+        };
+        if let Err(e) = update {
+            log::error!("Failed to update WhatsApp outbox: {}", e);
+        }
+        emit_entry(&app, &outbox, &message.id);
+
+        // More messages may already be due
+        outbox.wake.notify_one();
+    }
+}
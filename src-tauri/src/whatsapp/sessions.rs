@@ -0,0 +1,216 @@
+//! Registry of the WhatsApp numbers the shop runs (retail, wholesale, ...)
+//!
+//! Sessions are configured in the desktop settings and started on the
+//! sidecar every time it comes up. WPPConnect keeps each session's login
+//! tokens on disk, so a start after a restart resumes without a new QR scan.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use super::events::SessionState;
+use super::{WhatsAppClient, SIDECAR_NAME};
+use crate::db;
+use crate::settings::SettingsStore;
+use crate::sidecar::{SidecarState, SidecarSupervisor};
+
+/// Session used when none is configured, matching the frontend's historical id
+const DEFAULT_SESSION: &str = "luminila";
+
+/// Suffix the sidecar appends to sessions logged in with a pairing code
+const PHONE_LOGIN_SUFFIX: &str = "_phone";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionConfig {
+    /// Session id on the sidecar
+    pub name: String,
+    /// Number the session is logged in with, for display
+    #[serde(default)]
+    pub phone: Option<String>,
+    /// What the number is used for, e.g. `retail` or `wholesale`
+    #[serde(default)]
+    pub purpose: String,
+}
+
+/// Configured session with its live state
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub config: SessionConfig,
+    pub state: SessionState,
+    pub last_error: Option<String>,
+    /// Unix timestamp (ms) of the last state change
+    pub updated_at: i64,
+}
+
+impl SessionInfo {
+    fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            state: SessionState::Stopped,
+            last_error: None,
+            updated_at: db::now_millis(),
+        }
+    }
+}
+
+pub struct SessionRegistry {
+    sessions: Mutex<Vec<SessionInfo>>,
+}
+
+impl SessionRegistry {
+    pub fn new(mut configs: Vec<SessionConfig>) -> Self {
+        if configs.is_empty() {
+            configs.push(SessionConfig {
+                name: DEFAULT_SESSION.to_string(),
+                phone: None,
+                purpose: "retail".to_string(),
+            });
+        }
+        Self {
+            sessions: Mutex::new(configs.into_iter().map(SessionInfo::new).collect()),
+        }
+    }
+
+    /// All sessions in configuration order
+    pub fn list(&self) -> Vec<SessionInfo> {
+        self.sessions.lock().unwrap().clone()
+    }
+
+    fn configs(&self) -> Vec<SessionConfig> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|info| info.config.clone())
+            .collect()
+    }
+
+    /// Record a session's state. Events for a session's phone-login twin
+    /// (`<name>_phone`) are attributed to the configured session.
+    pub fn set_state(&self, session: &str, state: SessionState, error: Option<String>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let base = session.strip_suffix(PHONE_LOGIN_SUFFIX);
+        let index = sessions
+            .iter()
+            .position(|info| info.config.name == session)
+            .or_else(|| {
+                sessions
+                    .iter()
+                    .position(|info| Some(info.config.name.as_str()) == base)
+            });
+        let Some(info) = index.map(|index| &mut sessions[index]) else {
+            return;
+        };
+        info.state = state;
+        info.last_error = error;
+        info.updated_at = db::now_millis();
+    }
+
+    fn set_all(&self, state: SessionState) {
+        for config in self.configs() {
+            self.set_state(&config.name, state, None);
+        }
+    }
+
+    /// Add or update a session and persist the configuration
+    pub fn save(&self, app: &tauri::AppHandle, config: SessionConfig) -> Result<(), String> {
+        if config.name.is_empty()
+            || !config
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid session name: {}", config.name));
+        }
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            match sessions
+                .iter_mut()
+                .find(|info| info.config.name == config.name)
+            {
+                Some(info) => info.config = config,
+                None => sessions.push(SessionInfo::new(config)),
+            }
+        }
+        self.persist(app)
+    }
+
+    /// Remove a session from the configuration. Its login stays on the
+    /// sidecar until logged out.
+    pub fn remove(&self, app: &tauri::AppHandle, name: &str) -> Result<(), String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|info| info.config.name != name);
+        self.persist(app)
+    }
+
+    fn persist(&self, app: &tauri::AppHandle) -> Result<(), String> {
+        app.state::<SettingsStore>()
+            .update(|settings| settings.whatsapp_sessions = self.configs())
+    }
+}
+
+/// Start (or resume) one session and record the state the sidecar reports
+pub async fn start_session(app: &tauri::AppHandle, name: &str) {
+    let registry = app.state::<SessionRegistry>();
+    let client = app.state::<WhatsAppClient>();
+
+    registry.set_state(name, SessionState::Starting, None);
+    if let Err(e) = client.start_session(name).await {
+        log::warn!("Failed to start WhatsApp session {}: {}", name, e);
+        registry.set_state(name, SessionState::Error, Some(e.to_string()));
+        return;
+    }
+
+    // An already running session won't replay its events, so ask for its state
+    match client.session_status(name).await {
+        Ok(status) if status.connected => {
+            registry.set_state(name, SessionState::Connected, None);
+        }
+        Ok(status) if status.qr_ready => registry.set_state(name, SessionState::QrReady, None),
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to get status of WhatsApp session {}: {}", name, e),
+    }
+}
+
+/// Start every configured session whenever the sidecar becomes healthy, and
+/// mark them stopped while it is down
+pub async fn run_session_manager(app: tauri::AppHandle) {
+    let registry = app.state::<SessionRegistry>();
+    let mut state_rx = match app.state::<SidecarSupervisor>().watch_state(SIDECAR_NAME) {
+        Ok(rx) => rx,
+        Err(e) => {
+            log::error!("WhatsApp session manager not started: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        if state_rx
+            .wait_for(|state| *state == SidecarState::Healthy)
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        for config in registry.configs() {
+            start_session(&app, &config.name).await;
+        }
+
+        // Unhealthy is usually a slow health check; sessions survive it
+        if state_rx
+            .wait_for(|state| !matches!(state, SidecarState::Healthy | SidecarState::Unhealthy))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        registry.set_all(SessionState::Stopped);
+    }
+}
@@ -0,0 +1,450 @@
+//! Encrypted snapshots of the sidecar's WhatsApp logins
+//!
+//! WPPConnect keeps each session's tokens and browser profile under
+//! `tokens/` in its working directory. Losing that directory (reinstall, new
+//! PC) means scanning every QR code again, so it can be exported to a
+//! passphrase-protected archive and restored later. The sidecar is paused
+//! while the directory is read or replaced so the browser profiles are
+//! consistent, and a restore that the sidecar doesn't start with is rolled
+//! back.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use super::SIDECAR_NAME;
+use crate::crypto;
+use crate::db;
+use crate::sidecar::SidecarSupervisor;
+
+const MAGIC: &[u8; 8] = b"LMWASNAP";
+/// Extension snapshots are exported with
+const EXTENSION: &str = "lmwa";
+/// Bump when the archive contents change shape
+const FORMAT: u32 = 1;
+const SIDECAR_VERSION: &str = env!("WPPCONNECT_SIDECAR_VERSION");
+/// Version of the WPPConnect library bundled in the sidecar, which decides
+/// the token and profile layout
+const LIB_VERSION: &str = env!("WPPCONNECT_LIB_VERSION");
+
+/// Browser caches, rebuilt on the next start and not worth archiving
+const SKIPPED_DIRS: &[&str] = &[
+    "Cache",
+    "Code Cache",
+    "GPUCache",
+    "DawnCache",
+    "GrShaderCache",
+    "ShaderCache",
+    "CacheStorage",
+    "Crashpad",
+];
+
+/// Cleartext description of a snapshot, stored in the archive header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub format: u32,
+    /// Unix timestamp (ms)
+    pub created_at: i64,
+    pub sidecar_version: String,
+    pub wppconnect_version: String,
+    /// Sessions contained in the snapshot
+    pub sessions: Vec<String>,
+    /// Size of the unencrypted payload in bytes
+    pub size: u64,
+    /// SHA-256 of the unencrypted payload
+    pub sha256: String,
+}
+
+fn tokens_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("tokens")
+}
+
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// A snapshot is restorable into this build if it uses the same archive
+/// format and was written by the same WPPConnect major version, not newer
+/// than ours. Browser profiles don't survive downgrades.
+fn check_compatible(info: &SnapshotInfo) -> Result<(), String> {
+    if info.format != FORMAT {
+        return Err(format!(
+            "Snapshot format {} is not supported by this version of the app",
+            info.format
+        ));
+    }
+    let (Some(theirs), Some(ours)) = (
+        parse_version(&info.wppconnect_version),
+        parse_version(LIB_VERSION),
+    ) else {
+        return Err(format!(
+            "Cannot verify that a snapshot from WPPConnect {} is compatible with {}",
+            info.wppconnect_version, LIB_VERSION
+        ));
+    };
+    if theirs.0 != ours.0 || theirs > ours {
+        return Err(format!(
+            "Snapshot was taken with WPPConnect {}, which is incompatible with the bundled {}",
+            info.wppconnect_version, LIB_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// Add `dir` to the archive below `prefix`, skipping caches and symlinks
+fn zip_dir(
+    zip: &mut zip::ZipWriter<File>,
+    dir: &Path,
+    prefix: &str,
+    options: zip::write::SimpleFileOptions,
+) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let archive_name = format!("{}{}", prefix, name);
+
+        if file_type.is_dir() {
+            if SKIPPED_DIRS.contains(&name.as_str()) {
+                continue;
+            }
+            zip.add_directory(archive_name.as_str(), options)
+                .map_err(|e| format!("Failed to add {} to snapshot: {}", path.display(), e))?;
+            zip_dir(zip, &path, &format!("{}/", archive_name), options)?;
+        } else if file_type.is_file() {
+            let mut file = File::open(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            zip.start_file(archive_name.as_str(), options)
+                .map_err(|e| format!("Failed to add {} to snapshot: {}", path.display(), e))?;
+            std::io::copy(&mut file, zip)
+                .map_err(|e| format!("Failed to add {} to snapshot: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Session names are the top-level directories of `tokens/`
+fn session_names(tokens: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(tokens)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn write_snapshot(data_dir: &Path, dest: &Path, passphrase: &str) -> Result<SnapshotInfo, String> {
+    let tokens = tokens_dir(data_dir);
+    let sessions = session_names(&tokens);
+    if sessions.is_empty() {
+        return Err("There are no WhatsApp sessions to back up".to_string());
+    }
+
+    let payload = data_dir.join("snapshot.zip.tmp");
+    let result = (|| {
+        let file = File::create(&payload)
+            .map_err(|e| format!("Failed to create {}: {}", payload.display(), e))?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        zip_dir(&mut zip, &tokens, "", options)?;
+        zip.finish()
+            .map_err(|e| format!("Failed to finish snapshot: {}", e))?;
+
+        let open_payload = || {
+            File::open(&payload)
+                .map(BufReader::new)
+                .map_err(|e| format!("Failed to read {}: {}", payload.display(), e))
+        };
+        let info = SnapshotInfo {
+            format: FORMAT,
+            created_at: db::now_millis(),
+            sidecar_version: SIDECAR_VERSION.to_string(),
+            wppconnect_version: LIB_VERSION.to_string(),
+            sessions,
+            size: std::fs::metadata(&payload).map(|m| m.len()).unwrap_or(0),
+            sha256: crypto::sha256_hex(open_payload()?)?,
+        };
+
+        // Write next to the destination and rename, so a failed export never
+        // leaves a half-written archive under the requested name
+        let partial = dest.with_extension("partial");
+        let out = File::create(&partial)
+            .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+        let mut writer = BufWriter::new(out);
+        crypto::seal(
+            MAGIC,
+            info.clone(),
+            passphrase,
+            open_payload()?,
+            &mut writer,
+        )
+        .and_then(|_| {
+            writer
+                .into_inner()
+                .map_err(|e| e.to_string())?
+                .sync_all()
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&partial);
+            format!("Failed to write snapshot: {}", e)
+        })?;
+        std::fs::rename(&partial, dest)
+            .map_err(|e| format!("Failed to move snapshot to {}: {}", dest.display(), e))?;
+        Ok(info)
+    })();
+
+    let _ = std::fs::remove_file(&payload);
+    result
+}
+
+/// Decrypt, verify and unpack a snapshot into `staging`
+fn unpack_snapshot(
+    src: &Path,
+    passphrase: &str,
+    data_dir: &Path,
+    staging: &Path,
+) -> Result<SnapshotInfo, String> {
+    let mut reader = BufReader::new(
+        File::open(src).map_err(|e| format!("Failed to open {}: {}", src.display(), e))?,
+    );
+    let (header, header_bytes) = crypto::read_header::<SnapshotInfo>(MAGIC, &mut reader)?;
+    check_compatible(&header.metadata)?;
+
+    let payload = data_dir.join("restore.zip.tmp");
+    let result = (|| {
+        let mut out = File::create(&payload)
+            .map_err(|e| format!("Failed to create {}: {}", payload.display(), e))?;
+        crypto::open(&header, &header_bytes, passphrase, reader, &mut out)?;
+        out.flush()
+            .map_err(|e| format!("Failed to write {}: {}", payload.display(), e))?;
+
+        let open_payload = || {
+            File::open(&payload).map_err(|e| format!("Failed to read {}: {}", payload.display(), e))
+        };
+        if crypto::sha256_hex(BufReader::new(open_payload()?))? != header.metadata.sha256 {
+            return Err("Snapshot checksum does not match, the archive is corrupted".to_string());
+        }
+
+        if staging.exists() {
+            std::fs::remove_dir_all(staging)
+                .map_err(|e| format!("Failed to clear {}: {}", staging.display(), e))?;
+        }
+        zip::ZipArchive::new(open_payload()?)
+            .and_then(|mut archive| archive.extract(staging))
+            .map_err(|e| format!("Failed to unpack snapshot: {}", e))?;
+        Ok(header.metadata)
+    })();
+
+    let _ = std::fs::remove_file(&payload);
+    result
+}
+
+fn previous_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("tokens.previous")
+}
+
+fn remove_dir(dir: &Path) -> Result<(), String> {
+    match std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to clear {}: {}", dir.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Swap the unpacked snapshot in place of the live tokens. The previous
+/// tokens are kept in `tokens.previous` until the next restore.
+fn swap_in(data_dir: &Path, staging: &Path) -> Result<(), String> {
+    let tokens = tokens_dir(data_dir);
+    let previous = previous_dir(data_dir);
+
+    remove_dir(&previous)?;
+    let had_tokens = tokens.exists();
+    if had_tokens {
+        std::fs::rename(&tokens, &previous)
+            .map_err(|e| format!("Failed to move current sessions aside: {}", e))?;
+    }
+    if let Err(e) = std::fs::rename(staging, &tokens) {
+        let error = format!("Failed to move restored sessions in place: {}", e);
+        if had_tokens {
+            if let Err(back) = std::fs::rename(&previous, &tokens) {
+                return Err(format!(
+                    "{}, and putting the current sessions back failed: {}. They are in {}.",
+                    error,
+                    back,
+                    previous.display()
+                ));
+            }
+        }
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Put the sessions [`swap_in`] moved aside back. The restored ones are
+/// kept in `tokens.failed` to look into.
+fn roll_back(data_dir: &Path) -> Result<(), String> {
+    let tokens = tokens_dir(data_dir);
+    let previous = previous_dir(data_dir);
+    let failed = data_dir.join("tokens.failed");
+
+    remove_dir(&failed)?;
+    std::fs::rename(&tokens, &failed)
+        .map_err(|e| format!("Failed to move restored sessions aside: {}", e))?;
+    if previous.exists() {
+        std::fs::rename(&previous, &tokens)
+            .map_err(|e| format!("Failed to put previous sessions back: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Where to export a snapshot: `path` if it is a `.lmwa` file directly in
+/// the app data folder or the Downloads folder
+pub fn export_destination(app: &tauri::AppHandle, path: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(path);
+    let has_extension = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(EXTENSION));
+    let (Some(dir), Some(file_name), true) = (path.parent(), path.file_name(), has_extension)
+    else {
+        return Err(format!(
+            "Not a .{} file path: {}",
+            EXTENSION,
+            path.display()
+        ));
+    };
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Failed to find {}: {}", dir.display(), e))?;
+    let allowed = [
+        app.path().app_data_dir().ok(),
+        app.path().download_dir().ok(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|allowed| allowed.canonicalize().ok())
+    .any(|allowed| allowed == dir);
+    if !allowed {
+        return Err(
+            "Sessions can only be exported to the app data folder or Downloads".to_string(),
+        );
+    }
+    Ok(dir.join(file_name))
+}
+
+/// Read a snapshot's header without decrypting it
+pub fn inspect(src: &Path) -> Result<SnapshotInfo, String> {
+    let mut reader = BufReader::new(
+        File::open(src).map_err(|e| format!("Failed to open {}: {}", src.display(), e))?,
+    );
+    let (header, _) = crypto::read_header::<SnapshotInfo>(MAGIC, &mut reader)?;
+    Ok(header.metadata)
+}
+
+/// Pause the sidecar, archive its sessions to `dest`, and start it again
+pub async fn backup(
+    app: &tauri::AppHandle,
+    dest: PathBuf,
+    passphrase: String,
+) -> Result<SnapshotInfo, String> {
+    let data_dir = super::data_dir(app)?;
+    let supervisor = app.state::<SidecarSupervisor>();
+
+    supervisor.pause(app, SIDECAR_NAME).await?;
+    let result =
+        tauri::async_runtime::spawn_blocking(move || write_snapshot(&data_dir, &dest, &passphrase))
+            .await
+            .map_err(|e| format!("Snapshot task failed: {}", e))
+            .and_then(|result| result);
+    if let Err(e) = supervisor.resume(app, SIDECAR_NAME).await {
+        log::error!("Failed to restart WhatsApp sidecar after snapshot: {}", e);
+    }
+    result
+}
+
+/// Verify and unpack a snapshot, then pause the sidecar, swap the sessions
+/// in and start it again
+pub async fn restore(
+    app: &tauri::AppHandle,
+    src: PathBuf,
+    passphrase: String,
+) -> Result<SnapshotInfo, String> {
+    let data_dir = super::data_dir(app)?;
+    std::fs::create_dir_all(&data_dir)
+        .map_err(|e| format!("Failed to create {}: {}", data_dir.display(), e))?;
+    let staging = data_dir.join("tokens.restore");
+
+    // Everything that can fail on a bad archive happens before the sidecar
+    // is touched
+    let info = tauri::async_runtime::spawn_blocking({
+        let data_dir = data_dir.clone();
+        let staging = staging.clone();
+        move || unpack_snapshot(&src, &passphrase, &data_dir, &staging)
+    })
+    .await
+    .map_err(|e| format!("Restore task failed: {}", e))??;
+
+    let supervisor = app.state::<SidecarSupervisor>();
+    if let Err(e) = supervisor.pause(app, SIDECAR_NAME).await {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    if let Err(e) = swap_in(&data_dir, &staging) {
+        let _ = std::fs::remove_dir_all(&staging);
+        if let Err(e) = supervisor.resume(app, SIDECAR_NAME).await {
+            log::error!(
+                "Failed to restart WhatsApp sidecar after a failed restore: {}",
+                e
+            );
+        }
+        return Err(e);
+    }
+
+    // Starting waits for the sidecar to pass its health check
+    let started = match supervisor.resume(app, SIDECAR_NAME).await {
+        Ok(()) => return Ok(info),
+        Err(e) => e,
+    };
+
+    log::error!(
+        "WhatsApp sidecar failed to start with restored sessions, rolling back: {}",
+        started
+    );
+    if let Err(e) = supervisor.pause(app, SIDECAR_NAME).await {
+        log::error!("Failed to stop WhatsApp sidecar for rollback: {}", e);
+    }
+    let rolled_back = roll_back(&data_dir);
+    if let Err(e) = supervisor.resume(app, SIDECAR_NAME).await {
+        log::error!("Failed to restart WhatsApp sidecar after rollback: {}", e);
+    }
+    match rolled_back {
+        Ok(()) => Err(format!(
+            "The WhatsApp sidecar did not start with the restored sessions ({}). The previous sessions were put back.",
+            started
+        )),
+        Err(e) => Err(format!(
+            "The WhatsApp sidecar did not start with the restored sessions ({}), and rolling back failed: {}. The previous sessions are in {}.",
+            started,
+            e,
+            previous_dir(&data_dir).display()
+        )),
+    }
+}
@@ -0,0 +1,250 @@
+//! WhatsApp client and command tests against an in-process fake WPPConnect
+
+use tauri::Manager;
+
+use super::events::SseParser;
+use super::{commands, WhatsAppClient, WhatsAppError};
+use crate::testing::fake_wppconnect::QR_CODE;
+use crate::testing::{self, block_on, FakeWppConnect, Fault};
+
+const TOKEN: &str = "whatsapp-test-token";
+const SESSION: &str = "shop";
+
+fn client(fake: &FakeWppConnect, token: &str) -> WhatsAppClient {
+    WhatsAppClient::new(reqwest::Client::new(), fake.port(), token)
+}
+
+#[test]
+fn session_goes_from_qr_code_to_connected() {
+    let fake = FakeWppConnect::start(TOKEN);
+    let client = client(&fake, TOKEN);
+    block_on(async {
+        let status = client.session_status(SESSION).await.unwrap();
+        assert!(!status.exists);
+
+        client.start_session(SESSION).await.unwrap();
+        let status = client.session_status(SESSION).await.unwrap();
+        assert!(status.exists && status.qr_ready && !status.connected);
+        let qr = client.session_qr(SESSION).await.unwrap();
+        assert_eq!(qr.qr_code.as_deref(), Some(QR_CODE));
+
+        fake.connect(SESSION);
+        let status = client.session_status(SESSION).await.unwrap();
+        assert!(status.connected && !status.qr_ready);
+        let qr = client.session_qr(SESSION).await.unwrap();
+        assert_eq!(qr.qr_code, None);
+    });
+}
+
+#[test]
+fn send_message_normalizes_the_phone_number() {
+    let fake = FakeWppConnect::start(TOKEN);
+    fake.connect(SESSION);
+    let client = client(&fake, TOKEN);
+    block_on(async {
+        let sent = client
+            .send_message(SESSION, "+91 98765-43210", "Your order is ready")
+            .await
+            .unwrap();
+        assert_eq!(sent.id.as_deref(), Some("true_919876543210@c.us_FAKE1"));
+
+        let error = client.send_message(SESSION, "n/a", "Hi").await.unwrap_err();
+        assert!(matches!(error, WhatsAppError::InvalidRequest { .. }));
+    });
+
+    let sent = fake.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["phone"], "919876543210");
+    assert_eq!(sent[0]["message"], "Your order is ready");
+}
+
+#[test]
+fn unpaired_session_is_reported_as_not_connected() {
+    let fake = FakeWppConnect::start(TOKEN);
+    let client = client(&fake, TOKEN);
+    block_on(async {
+        client.start_session(SESSION).await.unwrap();
+        let error = client
+            .send_message(SESSION, "919876543210", "Hi")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, WhatsAppError::SessionNotConnected { session } if session == SESSION),
+            "{:?}",
+            error
+        );
+        assert!(!error.is_transient());
+    });
+    assert!(fake.sent().is_empty());
+}
+
+#[test]
+fn wrong_token_is_rejected() {
+    let fake = FakeWppConnect::start(TOKEN);
+    let client = client(&fake, "not-the-token");
+    block_on(async {
+        let error = client.start_session(SESSION).await.unwrap_err();
+        assert!(
+            matches!(error, WhatsAppError::Api { status: 401, .. }),
+            "{:?}",
+            error
+        );
+    });
+}
+
+#[test]
+fn reads_are_retried_on_server_errors() {
+    let fake = FakeWppConnect::start(TOKEN);
+    fake.connect(SESSION);
+    fake.add_chat(SESSION, "919876543210@c.us", "Asha");
+    let client = client(&fake, TOKEN);
+    block_on(async {
+        fake.inject(Fault::ServerErrors(2));
+        let chats = client.get_chats(SESSION).await.unwrap();
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats[0].name, "Asha");
+        assert_eq!(fake.request_count("GET /api/shop/chats"), 3);
+
+        // One more failure than there are retries
+        fake.inject(Fault::ServerErrors(3));
+        let error = client.get_chats(SESSION).await.unwrap_err();
+        assert!(matches!(error, WhatsAppError::Api { status: 500, .. }));
+        assert_eq!(fake.request_count("GET /api/shop/chats"), 6);
+    });
+}
+
+#[test]
+fn sends_are_not_retried() {
+    let fake = FakeWppConnect::start(TOKEN);
+    fake.connect(SESSION);
+    let client = client(&fake, TOKEN);
+    block_on(async {
+        fake.inject(Fault::ServerErrors(1));
+        let error = client
+            .send_message(SESSION, "919876543210", "Hi")
+            .await
+            .unwrap_err();
+        assert!(error.is_transient());
+    });
+    assert_eq!(fake.request_count("POST /api/shop/send-message"), 1);
+    assert!(fake.sent().is_empty());
+}
+
+#[test]
+fn stopped_sidecar_is_unavailable() {
+    let fake = FakeWppConnect::start(TOKEN);
+    let client = client(&fake, TOKEN);
+    fake.inject(Fault::Crash);
+    block_on(async {
+        let error = client.session_status(SESSION).await.unwrap_err();
+        assert!(
+            matches!(error, WhatsAppError::SidecarUnavailable { .. }),
+            "{:?}",
+            error
+        );
+    });
+}
+
+#[test]
+fn request_command_proxies_raw_responses() {
+    let fake = FakeWppConnect::start(TOKEN);
+    let app = testing::mock_app();
+    app.manage(client(&fake, TOKEN));
+    block_on(async {
+        let request = |method: &str, path: &str| {
+            commands::whatsapp_request(
+                app.state::<WhatsAppClient>(),
+                method.to_string(),
+                path.to_string(),
+                None,
+            )
+        };
+
+        // Error statuses are passed through, not mapped to errors
+        let response = request("GET", "/api/shop/qrcode").await.unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body["error"], "Session not found");
+
+        request("post", "/api/shop/start").await.unwrap();
+        let response = request("GET", "/api/shop/qrcode").await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["qrCode"], QR_CODE);
+
+        for (method, path) in [
+            ("GET", "/health"),
+            ("GET", "/api/../health"),
+            ("GET", "/api/%2e%2e/health"),
+            ("GET", "/api/shop/%2E%2E/%2e%2E/health"),
+            ("POST", "/api/shutdown"),
+            ("POST", "/API/Shutdown/"),
+            ("POST", "/api/%73hutdown?now=1"),
+            ("GET", "/api/events"),
+            ("PATCH", "/api/status"),
+        ] {
+            let error = request(method, path).await.unwrap_err();
+            assert!(
+                matches!(error, WhatsAppError::InvalidRequest { .. }),
+                "{} {}: {:?}",
+                method,
+                path,
+                error
+            );
+        }
+    });
+}
+
+#[test]
+fn typed_commands_use_the_managed_client() {
+    let fake = FakeWppConnect::start(TOKEN);
+    fake.add_chat(SESSION, "120363000000000000@g.us", "Wholesale buyers");
+    let app = testing::mock_app();
+    app.manage(client(&fake, TOKEN));
+    block_on(async {
+        let state = || app.state::<WhatsAppClient>();
+        commands::whatsapp_start_session(state(), SESSION.to_string())
+            .await
+            .unwrap();
+        let qr = commands::whatsapp_session_qr(state(), SESSION.to_string())
+            .await
+            .unwrap();
+        assert!(qr.qr_code.is_some());
+
+        fake.connect(SESSION);
+        let status = commands::whatsapp_session_status(state(), SESSION.to_string())
+            .await
+            .unwrap();
+        assert!(status.connected);
+
+        let chats = commands::whatsapp_get_chats(state(), SESSION.to_string())
+            .await
+            .unwrap();
+        assert_eq!(chats.len(), 1);
+        assert!(chats[0].is_group);
+
+        let sent = commands::whatsapp_send_message(
+            state(),
+            SESSION.to_string(),
+            "120363000000000000@g.us".to_string(),
+            "New stock arrived".to_string(),
+        )
+        .await
+        .unwrap();
+        assert!(sent.id.is_some());
+    });
+    assert_eq!(fake.sent()[0]["phone"], "120363000000000000@g.us");
+}
+
+#[test]
+fn event_stream_keeps_characters_split_across_chunks() {
+    let body = "id: 7\nevent: message\ndata: {\"body\":\"Namasté 💍\"}\n\n".as_bytes();
+    // Split inside the four bytes of the emoji
+    let split = body.iter().position(|&byte| byte == 0xF0).unwrap() + 2;
+
+    let mut parser = SseParser::default();
+    assert!(parser.push(&body[..split]).is_empty());
+    let events = parser.push(&body[split..]);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id.as_deref(), Some("7"));
+    assert_eq!(events[0].event, "message");
+    assert_eq!(events[0].data, "{\"body\":\"Namasté 💍\"}");
+}
@@ -0,0 +1,215 @@
+//! Typed views over WPPConnect responses
+//!
+//! WPPConnect returns raw WhatsApp Web objects whose shape varies between
+//! versions, so these are extracted field by field instead of deserialized.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// WPPConnect ids are either plain strings or `{ _serialized: "..." }` objects
+pub(super) fn id_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.clone()),
+        Value::Object(map) => map
+            .get("_serialized")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+fn str_field(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStatus {
+    pub exists: bool,
+    pub connected: bool,
+    pub qr_ready: bool,
+}
+
+impl SessionStatus {
+    pub(super) fn from_value(value: &Value) -> Self {
+        Self {
+            exists: value
+                .get("exists")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            connected: value
+                .get("connected")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            qr_ready: value
+                .get("qrReady")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QrCode {
+    /// Data URL of the QR image, `None` while WhatsApp Web is still loading
+    pub qr_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SentMessage {
+    pub id: Option<String>,
+}
+
+impl SentMessage {
+    pub(super) fn from_value(value: &Value) -> Self {
+        let id = value.get("result").and_then(|result| {
+            result
+                .get("id")
+                .and_then(id_string)
+                .or_else(|| id_string(result))
+        });
+        Self { id }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chat {
+    pub id: String,
+    pub name: String,
+    pub is_group: bool,
+    pub unread_count: u64,
+    pub timestamp: Option<i64>,
+    pub last_message: Option<String>,
+}
+
+impl Chat {
+    pub(super) fn from_value(chat: &Value) -> Option<Self> {
+        let id = chat.get("id").and_then(id_string)?;
+        let name = str_field(chat, "name")
+            .or_else(|| chat.get("contact").and_then(|c| str_field(c, "name")))
+            .or_else(|| chat.get("contact").and_then(|c| str_field(c, "pushname")))
+            .unwrap_or_else(|| id.split('@').next().unwrap_or_default().to_string());
+
+        Some(Self {
+            is_group: chat
+                .get("isGroup")
+                .and_then(Value::as_bool)
+                .unwrap_or(id.ends_with("@g.us")),
+            unread_count: chat.get("unreadCount").and_then(Value::as_u64).unwrap_or(0),
+            timestamp: chat.get("t").and_then(Value::as_i64),
+            last_message: chat.get("lastMessage").and_then(|m| str_field(m, "body")),
+            id,
+            name,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub body: String,
+    /// chat, image, video, document, ptt, audio, sticker, ...
+    pub kind: String,
+    pub timestamp: Option<i64>,
+    pub from_me: bool,
+    pub is_group_msg: bool,
+    pub sender_name: Option<String>,
+}
+
+impl Message {
+    pub(super) fn from_value(message: &Value) -> Option<Self> {
+        let sender = message.get("sender");
+        Some(Self {
+            id: message.get("id").and_then(id_string)?,
+            from: message.get("from").and_then(id_string).unwrap_or_default(),
+            to: message.get("to").and_then(id_string).unwrap_or_default(),
+            body: str_field(message, "body")
+                .or_else(|| str_field(message, "caption"))
+                .unwrap_or_default(),
+            kind: str_field(message, "type").unwrap_or_else(|| "chat".to_string()),
+            timestamp: message.get("timestamp").and_then(Value::as_i64),
+            from_me: message
+                .get("fromMe")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            is_group_msg: message
+                .get("isGroupMsg")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            sender_name: sender
+                .and_then(|s| str_field(s, "name").or_else(|| str_field(s, "pushname")))
+                .or_else(|| str_field(message, "notifyName")),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberStatus {
+    pub exists: bool,
+    /// WhatsApp chat id (`<number>@c.us`) when the number is registered
+    pub jid: Option<String>,
+}
+
+impl NumberStatus {
+    pub(super) fn from_value(value: &Value) -> Self {
+        let result = value.get("result").unwrap_or(value);
+        Self {
+            exists: result
+                .get("numberExists")
+                .or_else(|| result.get("exists"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            jid: result.get("id").and_then(id_string),
+        }
+    }
+}
+
+/// Product in the session's WhatsApp Business catalog
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogProduct {
+    pub id: String,
+    /// Our SKU, when the product was created with one
+    pub retailer_id: Option<String>,
+    pub name: String,
+    pub price: Option<f64>,
+    pub is_hidden: bool,
+}
+
+impl CatalogProduct {
+    pub(super) fn from_value(product: &Value) -> Option<Self> {
+        // WhatsApp stores prices in thousandths of the currency unit
+        let price = product
+            .get("priceAmount1000")
+            .and_then(Value::as_f64)
+            .map(|price| price / 1000.0)
+            .or_else(|| match product.get("price") {
+                Some(Value::Number(price)) => price.as_f64(),
+                Some(Value::String(price)) => price.parse().ok(),
+                _ => None,
+            });
+        Some(Self {
+            id: product.get("id").and_then(id_string)?,
+            retailer_id: str_field(product, "retailerId")
+                .or_else(|| str_field(product, "retailer_id")),
+            name: str_field(product, "name").unwrap_or_default(),
+            price,
+            is_hidden: product
+                .get("isHidden")
+                .or_else(|| product.get("is_hidden"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
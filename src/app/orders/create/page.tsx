@@ -13,13 +13,15 @@ import {
     User,
     ChevronLeft,
     CheckCircle,
-    FileText
+    FileText,
+    MessageSquare
 } from "lucide-react";
 import { toast } from "sonner";
 import { formatPrice } from "@/lib/utils";
 import { createOrder, OrderType, OrderStatus, OrderItemInput } from "@/lib/orders";
 import { searchCustomers, Customer } from "@/lib/customers";
 import { pb } from "@/lib/pocketbase";
+import { parseOrderDraft } from "@/lib/whatsapp";
 
 export default function CreateOrderPage() {
     const router = useRouter();
@@ -52,6 +54,10 @@ export default function CreateOrderPage() {
     // Notes
     const [notes, setNotes] = useState("");
 
+    // Pre-fill from a customer's WhatsApp message
+    const [orderMessage, setOrderMessage] = useState("");
+    const [parsingMessage, setParsingMessage] = useState(false);
+
     // Calculate totals whenever items change
     useEffect(() => {
         let sub = 0;
@@ -151,6 +157,51 @@ export default function CreateOrderPage() {
         setShowProductResults(false);
     };
 
+    const handleFillFromMessage = async () => {
+        if (!orderMessage.trim()) return;
+
+        setParsingMessage(true);
+        try {
+            const draft = await parseOrderDraft(orderMessage);
+            if (!draft) {
+                toast.error("Reading orders from messages needs the desktop app");
+                return;
+            }
+
+            const matched = draft.lines.filter(line => line.matched);
+            const newItems: OrderItemInput[] = matched.map(line => {
+                const match = line.matched!;
+                const price = match.unitPrice;
+                const details = [match.size, match.color].filter(Boolean).join(' ');
+                return {
+                    product_id: match.productId,
+                    variant_id: match.variantId || '',
+                    description: details ? `${match.name} - ${details}` : match.name,
+                    quantity: line.quantity,
+                    unit_price: price,
+                    tax_rate: 3, // Default gold tax 3%
+                    discount_amount: 0,
+                    total: (line.quantity * price) * 1.03
+                };
+            });
+            setItems([...items, ...newItems]);
+
+            const unsure = draft.lines.filter(line => !line.matched || line.confidence < 0.6);
+            if (unsure.length > 0) {
+                toast.warning(`Please check: ${unsure.map(line => `"${line.text}"`).join(', ')}`);
+            } else if (newItems.length > 0) {
+                toast.success(`Added ${newItems.length} item${newItems.length === 1 ? '' : 's'} from message`);
+            } else {
+                toast.error("No products found in the message");
+            }
+        } catch (e) {
+            console.error('Failed to parse order message:', e);
+            toast.error("Could not read the message");
+        } finally {
+            setParsingMessage(false);
+        }
+    };
+
     const updateItem = (index: number, field: keyof OrderItemInput, value: any) => {
         const newItems = [...items];
         const item = { ...newItems[index], [field]: value };
@@ -351,6 +402,28 @@ export default function CreateOrderPage() {
                             )}
                         </div>
 
+                        {/* From WhatsApp message */}
+                        <div className="bg-surface-navy p-6 rounded-xl border border-surface-hover">
+                            <h3 className="text-white font-bold mb-4 flex items-center gap-2">
+                                <MessageSquare size={18} className="text-primary" />
+                                From Message
+                            </h3>
+                            <textarea
+                                value={orderMessage}
+                                onChange={(e) => setOrderMessage(e.target.value)}
+                                placeholder="Paste the customer's WhatsApp message..."
+                                className="input bg-bg-navy border-surface-hover w-full text-white h-24 resize-none"
+                            />
+                            <Button
+                                variant="ghost"
+                                onClick={handleFillFromMessage}
+                                disabled={parsingMessage || !orderMessage.trim()}
+                                className="mt-3 w-full"
+                            >
+                                {parsingMessage ? "Reading..." : "Add Items"}
+                            </Button>
+                        </div>
+
                         {/* Notes */}
                         <div className="bg-surface-navy p-6 rounded-xl border border-surface-hover">
                             <h3 className="text-white font-bold mb-4">Notes</h3>
@@ -25,6 +25,7 @@ import {
     LogOut,
     Gift,
     Star,
+    CloudOff,
 } from "lucide-react";
 import {
     Dialog,
@@ -53,7 +54,7 @@ import {
     redeemPoints,
     type LoyaltyAccount,
 } from "@/lib/loyalty";
-import { createPOSSale } from "@/lib/pos-sales";
+import { createPOSSale, getPendingSaleCount, issuePendingInvoices, onPOSJournal } from "@/lib/pos-sales";
 import { getTypeAheadProducts, getProducts, type ProductWithVariant, type Product } from "@/lib/products";
 import { pb } from "@/lib/pocketbase";
 
@@ -122,6 +123,7 @@ export default function POSPage() {
         changeGiven?: number;
     } | null>(null);
     const [showReceipt, setShowReceipt] = useState(false);
+    const [pendingSales, setPendingSales] = useState(0);
     const [showPhonePeModal, setShowPhonePeModal] = useState(false);
     const receiptRef = useRef<HTMLDivElement>(null);
 
@@ -168,6 +170,19 @@ export default function POSPage() {
         loadUserAndShift();
     }, [user]);
 
+    // Track sales saved offline and invoice them once they reach PocketBase
+    useEffect(() => {
+        const refresh = () => {
+            getPendingSaleCount().then(setPendingSales).catch(() => {});
+            issuePendingInvoices().catch((err) => console.error("Error issuing invoices:", err));
+        };
+        refresh();
+        const unlisten = onPOSJournal(refresh);
+        return () => {
+            unlisten.then((fn) => fn());
+        };
+    }, []);
+
     // Handle opening a new shift
     const handleOpenShift = async () => {
         const balance = parseFloat(openingBalance);
@@ -475,11 +490,11 @@ export default function POSPage() {
                 pointsEarned: pointsToEarn,
             });
 
-            // Handle loyalty points
-            if (selectedCustomerId && pointsToRedeem > 0) {
+            // Handle loyalty points (needs the sale in PocketBase)
+            if (!result.pending && selectedCustomerId && pointsToRedeem > 0) {
                 await redeemPoints(selectedCustomerId, pointsToRedeem, result.saleId, 'POS redemption');
             }
-            if (selectedCustomerId && pointsToEarn > 0) {
+            if (!result.pending && selectedCustomerId && pointsToEarn > 0) {
                 await earnPoints(selectedCustomerId, total, result.saleId, 'POS purchase');
             }
 
@@ -496,9 +511,14 @@ export default function POSPage() {
                 changeGiven: paymentMethod === 'cash' ? changeAmount : undefined,
             });
 
-            // Reload shift to get updated totals
-            const updatedShift = await getCurrentShift(userId);
-            setCurrentShift(updatedShift);
+            if (result.pending) {
+                // Saved locally; PocketBase is out of reach for now
+                setPendingSales((count) => count + 1);
+            } else {
+                // Reload shift to get updated totals
+                const updatedShift = await getCurrentShift(userId);
+                setCurrentShift(updatedShift);
+            }
 
             setShowReceipt(true);
             clearCart();
@@ -543,11 +563,11 @@ export default function POSPage() {
                 pointsEarned: pointsToEarn,
             });
 
-            // Handle loyalty points
-            if (selectedCustomerId && pointsToRedeem > 0) {
+            // Handle loyalty points (needs the sale in PocketBase)
+            if (!result.pending && selectedCustomerId && pointsToRedeem > 0) {
                 await redeemPoints(selectedCustomerId, pointsToRedeem, result.saleId, 'POS redemption');
             }
-            if (selectedCustomerId && pointsToEarn > 0) {
+            if (!result.pending && selectedCustomerId && pointsToEarn > 0) {
                 await earnPoints(selectedCustomerId, total, result.saleId, 'POS purchase');
             }
 
@@ -561,9 +581,14 @@ export default function POSPage() {
                 paymentMethod: 'phonepe',
             });
 
-            // Reload shift to get updated totals
-            const updatedShift = await getCurrentShift(userId);
-            setCurrentShift(updatedShift);
+            if (result.pending) {
+                // Saved locally; PocketBase is out of reach for now
+                setPendingSales((count) => count + 1);
+            } else {
+                // Reload shift to get updated totals
+                const updatedShift = await getCurrentShift(userId);
+                setCurrentShift(updatedShift);
+            }
 
             setShowReceipt(true);
             clearCart();
@@ -604,6 +629,15 @@ export default function POSPage() {
                             <span className="text-xs text-primary font-mono">
                                 Cash Sales: {formatPrice(currentShift.total_cash_sales)}
                             </span>
+                            {pendingSales > 0 && (
+                                <span
+                                    className="flex items-center gap-1 text-xs text-yellow-400"
+                                    title="Saved on this computer; they sync when the server is reachable"
+                                >
+                                    <CloudOff className="w-3 h-3" />
+                                    {pendingSales} sale{pendingSales === 1 ? "" : "s"} waiting to sync
+                                </span>
+                            )}
                         </div>
                         <Button
                             variant="ghost"
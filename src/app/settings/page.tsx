@@ -24,6 +24,7 @@ import {
     Edit,
     Loader2,
     Truck,
+    HardDrive,
 } from "lucide-react";
 import { LogoUpload } from "@/components/settings/LogoUpload";
 import { Button } from "@/components/ui/button";
@@ -48,6 +49,7 @@ import { Switch } from "@/components/ui/switch";
 import { getCategories, createCategory, deleteCategory, type Category } from "@/lib/categories";
 import { getAttributes, createAttribute, deleteAttribute, type ProductAttribute, type AttributeType } from "@/lib/attributes";
 import { EwayBillSettings } from "@/components/settings/EwayBillSettings";
+import { BackupSettings } from "@/components/settings/BackupSettings";
 
 interface SettingSection {
     id: string;
@@ -62,6 +64,7 @@ const sections: SettingSection[] = [
     { id: "categories", title: "Categories", icon: <FolderTree size={20} /> },
     { id: "attributes", title: "Product Attributes", icon: <Tags size={20} /> },
     { id: "integrations", title: "Integrations", icon: <Database size={20} /> },
+    { id: "backups", title: "Backups", icon: <HardDrive size={20} /> },
     { id: "whatsapp", title: "WhatsApp", icon: <MessageCircle size={20} /> },
     { id: "notifications", title: "Notifications", icon: <Bell size={20} /> },
     { id: "security", title: "Security", icon: <Shield size={20} /> },
@@ -867,6 +870,12 @@ export default function SettingsPage() {
                                 </div>
                             )}
 
+                            {activeSection === "backups" && (
+                                <div className="animate-fade-in">
+                                    <BackupSettings />
+                                </div>
+                            )}
+
                             {activeSection === "whatsapp" && (
                                 <div className="space-y-8 animate-fade-in">
                                     <div className="border-b border-border pb-4">
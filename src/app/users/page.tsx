@@ -77,6 +77,7 @@ function UsersContent() {
     const [newUser, setNewUser] = useState({
         name: "",
         email: "",
+        phone: "",
         password: "",
         role: "Viewer" // Default role
     });
@@ -157,6 +158,7 @@ function UsersContent() {
                 password: newUser.password,
                 passwordConfirm: newUser.password,
                 name: newUser.name,
+                phone: newUser.phone.trim(),
                 emailVisibility: true,
                 verified: true,
             });
@@ -170,7 +172,7 @@ function UsersContent() {
             }
 
             setShowAddUser(false);
-            setNewUser({ name: "", email: "", password: "", role: "Viewer" });
+            setNewUser({ name: "", email: "", phone: "", password: "", role: "Viewer" });
             loadData();
         } catch (error: any) {
             console.error("Error creating user:", error);
@@ -422,6 +424,18 @@ function UsersContent() {
                                 onChange={e => setNewUser({ ...newUser, email: e.target.value })}
                             />
                         </div>
+                        <div className="space-y-2">
+                            <Label>WhatsApp Number (optional)</Label>
+                            <Input
+                                type="tel"
+                                placeholder="+91 98765 43210"
+                                value={newUser.phone}
+                                onChange={e => setNewUser({ ...newUser, phone: e.target.value })}
+                            />
+                            <p className="text-xs text-muted-foreground">
+                                Lets this user send ! commands to the shop&apos;s WhatsApp number
+                            </p>
+                        </div>
                         <div className="space-y-2">
                             <Label>Password (min 8 chars)</Label>
                             <Input
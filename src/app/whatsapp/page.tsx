@@ -7,8 +7,11 @@ import {
     checkWPPConnectStatus,
     parseOrderFromMessage,
     generateAutoReply,
+    syncCatalog,
+    getCatalogSyncStatus,
+    onCatalogSync,
 } from "@/lib/whatsapp";
-import type { WPPMessage, WPPChat } from "@/lib/whatsapp";
+import type { WPPMessage, WPPChat, CatalogSyncStatus } from "@/lib/whatsapp";
 import { pb } from "@/lib/pocketbase";
 import { findMatchingProducts, generateImageHash } from "@/lib/image-matcher";
 import {
@@ -455,6 +458,8 @@ export default function WhatsAppPage() {
     const [customer, setCustomer] = useState<Customer | null>(null);
     const [customerOrders, setCustomerOrders] = useState<CustomerOrder[]>([]);
     const [loadingCustomer, setLoadingCustomer] = useState(false);
+    const [catalogStatus, setCatalogStatus] = useState<CatalogSyncStatus | null>(null);
+    const [catalogSyncing, setCatalogSyncing] = useState(false);
 
     // Add Product Modal State
     const [showAddProductModal, setShowAddProductModal] = useState(false);
@@ -552,6 +557,14 @@ export default function WhatsAppPage() {
         return () => clearInterval(interval);
     }, [loadExistingChats]);
 
+    // Catalog Sync Status
+    useEffect(() => {
+        const refresh = () => getCatalogSyncStatus().then(setCatalogStatus).catch(console.error);
+        refresh();
+        const unlisten = onCatalogSync(refresh);
+        return () => { unlisten.then((fn) => fn()); };
+    }, []);
+
     // Chat Selection
     useEffect(() => {
         const loadChatMessages = async () => {
@@ -1880,45 +1893,80 @@ export default function WhatsAppPage() {
                                                 </div>
                                                 <h2 className="font-bold text-lg">Catalog Sync</h2>
                                                 <p className="text-xs text-muted-foreground max-w-[250px]">
-                                                    Sync your Supabase inventory with WhatsApp Business Catalog automatically.
+                                                    Sync your inventory with WhatsApp Business Catalog automatically.
                                                 </p>
                                             </div>
 
                                             <div className="bg-card border border-border rounded-lg p-4 space-y-4">
                                                 <div className="flex justify-between items-center">
                                                     <span className="text-sm font-bold">Sync Status</span>
-                                                    <span className="text-xs px-2 py-1 rounded-full bg-green-500/20 text-green-500 font-bold">Active</span>
+                                                    {catalogStatus?.enabled ? (
+                                                        <span className="text-xs px-2 py-1 rounded-full bg-green-500/20 text-green-500 font-bold">
+                                                            {catalogStatus.running || catalogSyncing ? 'Syncing' : 'Active'}
+                                                        </span>
+                                                    ) : (
+                                                        <span className="text-xs px-2 py-1 rounded-full bg-muted text-muted-foreground font-bold">Off</span>
+                                                    )}
                                                 </div>
 
                                                 <div className="space-y-2">
                                                     <div className="flex justify-between text-xs">
                                                         <span className="text-muted-foreground">Last Sync:</span>
-                                                        <span>Just now</span>
+                                                        <span>
+                                                            {catalogStatus?.lastRun
+                                                                ? `${new Date(catalogStatus.lastRun.startedAt).toLocaleString()} (${catalogStatus.lastRun.status})`
+                                                                : 'Never'}
+                                                        </span>
                                                     </div>
+                                                    {catalogStatus?.lastRun && (
+                                                        <div className="flex justify-between text-xs">
+                                                            <span className="text-muted-foreground">Last Changes:</span>
+                                                            <span>
+                                                                +{catalogStatus.lastRun.added} ~{catalogStatus.lastRun.updated} -{catalogStatus.lastRun.deleted}
+                                                            </span>
+                                                        </div>
+                                                    )}
                                                     <div className="flex justify-between text-xs">
                                                         <span className="text-muted-foreground">Products Synced:</span>
-                                                        <span>45</span>
+                                                        <span>{catalogStatus?.listed ?? 0}</span>
                                                     </div>
                                                     <div className="flex justify-between text-xs">
                                                         <span className="text-muted-foreground">Errors:</span>
-                                                        <span className="text-red-500">0</span>
+                                                        <span className="text-red-500">{catalogStatus?.issues.length ?? 0}</span>
                                                     </div>
+                                                    {catalogStatus?.lastRun?.error && (
+                                                        <p className="text-[10px] text-red-500">{catalogStatus.lastRun.error}</p>
+                                                    )}
+                                                    {catalogStatus?.issues.slice(0, 5).map((issue) => (
+                                                        <p key={issue.productId} className="text-[10px] text-muted-foreground truncate" title={issue.error}>
+                                                            {issue.sku}: {issue.error}
+                                                        </p>
+                                                    ))}
                                                 </div>
 
                                                 <button
+                                                    disabled={!catalogStatus || catalogStatus.running || catalogSyncing}
                                                     onClick={async () => {
-                                                        const res = await fetch('/api/catalog-sync', { method: 'POST' });
-                                                        const data = await res.json();
-                                                        alert(JSON.stringify(data, null, 2));
+                                                        setCatalogSyncing(true);
+                                                        try {
+                                                            await syncCatalog();
+                                                        } catch (e) {
+                                                            alert(`Catalog sync failed: ${e}`);
+                                                        } finally {
+                                                            setCatalogSyncing(false);
+                                                            getCatalogSyncStatus().then(setCatalogStatus).catch(console.error);
+                                                        }
                                                     }}
-                                                    className="w-full bg-primary hover:bg-primary/90 text-primary-foreground font-bold text-xs py-3 rounded-lg flex items-center justify-center gap-2 transition-all shadow-sm active:scale-95"
+                                                    className="w-full bg-primary hover:bg-primary/90 text-primary-foreground font-bold text-xs py-3 rounded-lg flex items-center justify-center gap-2 transition-all shadow-sm active:scale-95 disabled:opacity-50"
                                                 >
-                                                    <RefreshCw size={14} /> Trigger Manual Sync
+                                                    <RefreshCw size={14} className={catalogSyncing ? 'animate-spin' : ''} /> Trigger Manual Sync
                                                 </button>
                                             </div>
 
                                             <div className="text-[10px] text-muted-foreground text-center">
-                                                Automatic sync runs every hour.
+                                                {catalogStatus
+                                                    ? `Automatic sync runs every ${catalogStatus.intervalMinutes} minutes and after product changes.`
+                                                    : 'Catalog sync needs the desktop app.'}
                                             </div>
                                         </div>
                                     )}
@@ -0,0 +1,215 @@
+"use client";
+
+import { useState } from "react";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
+import { Switch } from "@/components/ui/switch";
+import {
+    Select,
+    SelectContent,
+    SelectItem,
+    SelectTrigger,
+    SelectValue,
+} from "@/components/ui/select";
+import { toast } from "sonner";
+import {
+    testBackupDestination,
+    type BackupDestination,
+    type BackupDestinationKind,
+    type BackupDestinationStatus,
+} from "@/lib/backup";
+import { Loader2, Plus, Trash2, CheckCircle, AlertTriangle, FolderOpen, Usb, Cloud } from "lucide-react";
+
+interface BackupDestinationsProps {
+    destinations: BackupDestination[];
+    /** Saved destinations' copy results, by id */
+    statuses: BackupDestinationStatus[];
+    onChange: (destinations: BackupDestination[]) => void;
+}
+
+const NEW_KIND: Record<BackupDestinationKind["type"], BackupDestinationKind> = {
+    folder: { type: "folder", path: "" },
+    drive: { type: "drive", label: "", folder: "Luminila Backups" },
+    s3: {
+        type: "s3",
+        endpoint: "",
+        region: "us-east-1",
+        bucket: "",
+        prefix: "",
+        accessKeyId: "",
+        secretAccessKey: "",
+        pathStyle: false,
+    },
+};
+
+const KIND_ICON = { folder: FolderOpen, drive: Usb, s3: Cloud };
+
+function formatTime(millis: number | null): string {
+    return millis ? new Date(millis).toLocaleString() : "Never";
+}
+
+export function BackupDestinations({ destinations, statuses, onChange }: BackupDestinationsProps) {
+    const [testing, setTesting] = useState<number | null>(null);
+
+    const update = (index: number, changes: Partial<BackupDestination>) =>
+        onChange(destinations.map((d, i) => (i === index ? ({ ...d, ...changes } as BackupDestination) : d)));
+
+    const setKind = (index: number, type: BackupDestinationKind["type"]) => {
+        const { id, name, enabled } = destinations[index];
+        onChange(destinations.map((d, i) => (i === index ? { id, name, enabled, ...NEW_KIND[type] } : d)));
+    };
+
+    const add = () =>
+        onChange([...destinations, { id: "", name: "", enabled: true, ...NEW_KIND.folder }]);
+
+    const remove = (index: number) => onChange(destinations.filter((_, i) => i !== index));
+
+    const test = async (index: number) => {
+        setTesting(index);
+        try {
+            await testBackupDestination(destinations[index]);
+            toast.success(`${destinations[index].name || "Destination"} is reachable and writable`);
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setTesting(null);
+        }
+    };
+
+    return (
+        <div className="space-y-3">
+            <div>
+                <Label>Copy backups to</Label>
+                <p className="text-xs text-muted-foreground mt-1">
+                    Each backup is copied and checked in the background, and removed there when it is removed here.
+                </p>
+            </div>
+
+            {destinations.map((destination, index) => {
+                const status = statuses.find((s) => s.id === destination.id);
+                const Icon = KIND_ICON[destination.type];
+                return (
+                    <div key={destination.id || `new-${index}`} className="p-4 space-y-3 bg-surface-navy rounded-lg border border-surface-hover">
+                        <div className="flex items-center gap-3">
+                            <Icon className="w-4 h-4 text-moonstone" />
+                            <Input
+                                value={destination.name}
+                                onChange={(e) => update(index, { name: e.target.value })}
+                                placeholder="Name, e.g. Office NAS"
+                            />
+                            <Select
+                                value={destination.type}
+                                onValueChange={(val) => setKind(index, val as BackupDestinationKind["type"])}
+                            >
+                                <SelectTrigger className="w-44">
+                                    <SelectValue />
+                                </SelectTrigger>
+                                <SelectContent>
+                                    <SelectItem value="folder">Folder</SelectItem>
+                                    <SelectItem value="drive">Removable drive</SelectItem>
+                                    <SelectItem value="s3">S3-compatible</SelectItem>
+                                </SelectContent>
+                            </Select>
+                            <Switch
+                                checked={destination.enabled}
+                                onCheckedChange={(checked) => update(index, { enabled: checked })}
+                            />
+                            <Button variant="ghost" size="icon" onClick={() => remove(index)}>
+                                <Trash2 className="w-4 h-4" />
+                            </Button>
+                        </div>
+
+                        {destination.type === "folder" && (
+                            <Input
+                                value={destination.path}
+                                onChange={(e) => update(index, { path: e.target.value })}
+                                placeholder="Full path, e.g. a network share or synced folder"
+                            />
+                        )}
+
+                        {destination.type === "drive" && (
+                            <div className="grid gap-3 md:grid-cols-2">
+                                <Input
+                                    value={destination.label}
+                                    onChange={(e) => update(index, { label: e.target.value })}
+                                    placeholder="Drive name, e.g. BACKUP"
+                                />
+                                <Input
+                                    value={destination.folder}
+                                    onChange={(e) => update(index, { folder: e.target.value })}
+                                    placeholder="Folder on the drive"
+                                />
+                            </div>
+                        )}
+
+                        {destination.type === "s3" && (
+                            <div className="grid gap-3 md:grid-cols-2">
+                                <Input
+                                    value={destination.endpoint}
+                                    onChange={(e) => update(index, { endpoint: e.target.value })}
+                                    placeholder="Endpoint, e.g. https://s3.eu-central-1.amazonaws.com"
+                                />
+                                <Input
+                                    value={destination.region}
+                                    onChange={(e) => update(index, { region: e.target.value })}
+                                    placeholder="Region"
+                                />
+                                <Input
+                                    value={destination.bucket}
+                                    onChange={(e) => update(index, { bucket: e.target.value })}
+                                    placeholder="Bucket"
+                                />
+                                <Input
+                                    value={destination.prefix}
+                                    onChange={(e) => update(index, { prefix: e.target.value })}
+                                    placeholder="Folder in the bucket (optional)"
+                                />
+                                <Input
+                                    value={destination.accessKeyId}
+                                    onChange={(e) => update(index, { accessKeyId: e.target.value })}
+                                    placeholder="Access key ID"
+                                />
+                                <Input
+                                    type="password"
+                                    value={destination.secretAccessKey}
+                                    onChange={(e) => update(index, { secretAccessKey: e.target.value })}
+                                    placeholder="Secret access key"
+                                />
+                                <label className="flex items-center gap-2 text-xs text-moonstone md:col-span-2">
+                                    <Switch
+                                        checked={destination.pathStyle}
+                                        onCheckedChange={(checked) => update(index, { pathStyle: checked })}
+                                    />
+                                    Path-style addressing (MinIO and most self-hosted servers)
+                                </label>
+                            </div>
+                        )}
+
+                        <div className="flex items-center justify-between text-xs">
+                            <span className="flex items-center gap-2 text-moonstone">
+                                {status?.lastError ? (
+                                    <AlertTriangle className="w-4 h-4 text-yellow-500" />
+                                ) : (
+                                    status?.lastSuccessAt && <CheckCircle className="w-4 h-4 text-green-500" />
+                                )}
+                                {status
+                                    ? `Last copy: ${formatTime(status.lastSuccessAt)}${status.pending ? ` · ${status.pending} waiting` : ""}`
+                                    : "Not saved yet"}
+                                {status?.lastError && ` · ${status.lastError}`}
+                            </span>
+                            <Button variant="outline" size="sm" onClick={() => test(index)} disabled={testing !== null}>
+                                {testing === index ? <Loader2 className="w-4 h-4 animate-spin" /> : "Test"}
+                            </Button>
+                        </div>
+                    </div>
+                );
+            })}
+
+            <Button variant="ghost" size="sm" onClick={add}>
+                <Plus className="w-4 h-4 mr-2" />
+                Add Destination
+            </Button>
+        </div>
+    );
+}
@@ -0,0 +1,552 @@
+"use client";
+
+import { useState, useEffect } from "react";
+import { Card, CardContent, CardDescription, CardHeader, CardTitle } from "@/components/ui/card";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
+import { Switch } from "@/components/ui/switch";
+import {
+    Select,
+    SelectContent,
+    SelectItem,
+    SelectTrigger,
+    SelectValue,
+} from "@/components/ui/select";
+import { toast } from "sonner";
+import { RestoreBackupDialog } from "@/components/settings/RestoreBackupDialog";
+import { BackupDestinations } from "@/components/settings/BackupDestinations";
+import { PointInTimeRestoreDialog } from "@/components/settings/PointInTimeRestoreDialog";
+import {
+    backupNow,
+    getBackupStatus,
+    isDesktopBackupAvailable,
+    listBackups,
+    onBackup,
+    onBackupCopy,
+    onBackupWal,
+    saveBackupSettings,
+    setBackupPassphrase,
+    verifyBackup,
+    type BackupOverview,
+    type BackupRecord,
+    type BackupSettings as BackupConfig,
+} from "@/lib/backup";
+import { Loader2, HardDrive, CheckCircle, XCircle, AlertTriangle, KeyRound, History } from "lucide-react";
+
+function formatSize(bytes: number | null): string {
+    if (bytes === null) return "-";
+    if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+    return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
+
+function formatTime(millis: number | null | undefined): string {
+    return millis ? new Date(millis).toLocaleString() : "Never";
+}
+
+export function BackupSettings() {
+    const [overview, setOverview] = useState<BackupOverview | null>(null);
+    const [settings, setSettings] = useState<BackupConfig | null>(null);
+    const [backups, setBackups] = useState<BackupRecord[]>([]);
+    const [isLoading, setIsLoading] = useState(true);
+    const [isSaving, setIsSaving] = useState(false);
+    const [isBackingUp, setIsBackingUp] = useState(false);
+    const [passphrase, setPassphrase] = useState("");
+    const [confirmPassphrase, setConfirmPassphrase] = useState("");
+    const [isSettingPassphrase, setIsSettingPassphrase] = useState(false);
+    const [checkPassphrase, setCheckPassphrase] = useState("");
+    const [isVerifying, setIsVerifying] = useState(false);
+    const [restorePath, setRestorePath] = useState<string | null>(null);
+    const [isPointInTimeOpen, setIsPointInTimeOpen] = useState(false);
+
+    const loadStatus = async () => {
+        try {
+            const [status, recent] = await Promise.all([getBackupStatus(), listBackups(10)]);
+            setOverview(status);
+            if (status) setSettings(prev => prev ?? status.settings);
+            setBackups(recent);
+        } catch (error) {
+            console.error("Error loading backup status:", error);
+        } finally {
+            setIsLoading(false);
+        }
+    };
+
+    useEffect(() => {
+        loadStatus();
+        const unlisten = onBackup(() => loadStatus());
+        const unlistenCopy = onBackupCopy(() => loadStatus());
+        const unlistenWal = onBackupWal((wal) => setOverview(prev => prev && { ...prev, wal }));
+        return () => {
+            unlisten.then(fn => fn());
+            unlistenCopy.then(fn => fn());
+            unlistenWal.then(fn => fn());
+        };
+    }, []);
+
+    const save = async () => {
+        if (!settings) return;
+        setIsSaving(true);
+        try {
+            const status = await saveBackupSettings(settings);
+            setOverview(status);
+            setSettings(status.settings);
+            toast.success("Backup settings saved");
+        } catch (error) {
+            console.error("Error saving backup settings:", error);
+            toast.error(String(error));
+        } finally {
+            setIsSaving(false);
+        }
+    };
+
+    const runBackup = async () => {
+        setIsBackingUp(true);
+        try {
+            const record = await backupNow();
+            if (record.state === "success") {
+                toast.success(`Backup saved as ${record.fileName}`);
+            } else {
+                toast.error(record.error || "Backup failed");
+            }
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsBackingUp(false);
+            loadStatus();
+        }
+    };
+
+    const updatePassphrase = async () => {
+        if (passphrase !== confirmPassphrase) {
+            toast.error("Passphrases do not match");
+            return;
+        }
+        setIsSettingPassphrase(true);
+        try {
+            setOverview(await setBackupPassphrase(passphrase));
+            setPassphrase("");
+            setConfirmPassphrase("");
+            toast.success("Backup passphrase saved. Keep a copy somewhere safe, backups cannot be restored without it.");
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsSettingPassphrase(false);
+        }
+    };
+
+    const verifyLatest = async () => {
+        const latest = overview?.lastSuccess;
+        if (!latest) return;
+        setIsVerifying(true);
+        try {
+            await verifyBackup(`${latest.directory}/${latest.fileName}`, checkPassphrase);
+            setCheckPassphrase("");
+            toast.success(`Passphrase opens ${latest.fileName} and the backup is intact`);
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsVerifying(false);
+        }
+    };
+
+    if (!isDesktopBackupAvailable()) {
+        return (
+            <Card>
+                <CardContent className="py-8 text-center text-sm text-muted-foreground">
+                    Full data backups are taken by the desktop app.
+                </CardContent>
+            </Card>
+        );
+    }
+
+    if (isLoading || !settings || !overview) {
+        return (
+            <Card>
+                <CardContent className="flex items-center justify-center py-12">
+                    <Loader2 className="w-6 h-6 animate-spin text-primary" />
+                </CardContent>
+            </Card>
+        );
+    }
+
+    const setRetention = (key: keyof BackupConfig["retention"], value: string) =>
+        setSettings(prev => prev && {
+            ...prev,
+            retention: { ...prev.retention, [key]: Math.max(0, parseInt(value) || 0) },
+        });
+
+    const setWal = (wal: Partial<BackupConfig["wal"]>) =>
+        setSettings(prev => prev && { ...prev, wal: { ...prev.wal, ...wal } });
+    const savedDestinations = settings.destinations.filter((destination) => destination.id);
+
+    return (
+        <Card>
+            <CardHeader>
+                <CardTitle className="flex items-center gap-2">
+                    <HardDrive className="w-5 h-5" />
+                    Data Backups
+                </CardTitle>
+                <CardDescription>
+                    Snapshots of the whole database and uploaded files, taken while the app keeps running.
+                    Older backups are removed automatically.
+                </CardDescription>
+            </CardHeader>
+            <CardContent className="space-y-6">
+                {/* Latest results */}
+                <div className="grid gap-3 md:grid-cols-2">
+                    <div className="flex items-start gap-2 p-3 bg-green-500/10 border border-green-500/30 rounded-lg">
+                        <CheckCircle className="w-5 h-5 text-green-500 mt-0.5" />
+                        <div>
+                            <p className="text-sm text-green-500 font-medium">Last successful backup</p>
+                            <p className="text-xs text-moonstone">
+                                {formatTime(overview.lastSuccess?.finishedAt)}
+                                {overview.lastSuccess && ` · ${formatSize(overview.lastSuccess.size)}`}
+                            </p>
+                        </div>
+                    </div>
+                    <div className="flex items-start gap-2 p-3 bg-surface-navy border border-surface-hover rounded-lg">
+                        <HardDrive className="w-5 h-5 text-moonstone mt-0.5" />
+                        <div>
+                            <p className="text-sm font-medium">Next scheduled backup</p>
+                            <p className="text-xs text-moonstone">
+                                {overview.nextDueAt ? formatTime(overview.nextDueAt) : "Scheduled backups are off"}
+                            </p>
+                        </div>
+                    </div>
+                </div>
+
+                {overview.lastFailure &&
+                    overview.lastFailure.startedAt > (overview.lastSuccess?.startedAt ?? 0) && (
+                    <div className="flex items-start gap-2 p-3 bg-red-500/10 border border-red-500/30 rounded-lg">
+                        <XCircle className="w-5 h-5 text-red-500 mt-0.5" />
+                        <div>
+                            <p className="text-sm text-red-500 font-medium">
+                                Backup failed at {formatTime(overview.lastFailure.startedAt)}
+                            </p>
+                            <p className="text-xs text-moonstone">{overview.lastFailure.error}</p>
+                        </div>
+                    </div>
+                )}
+
+                {/* Encryption */}
+                {!overview.passphraseSet && (
+                    <div className="flex items-start gap-2 p-3 bg-yellow-500/10 border border-yellow-500/30 rounded-lg">
+                        <AlertTriangle className="w-5 h-5 text-yellow-500 mt-0.5" />
+                        <div>
+                            <p className="text-sm text-yellow-500 font-medium">Set a passphrase to start backing up</p>
+                            <p className="text-xs text-moonstone">
+                                Backups are encrypted with this passphrase. It is needed to restore them, so keep a copy somewhere safe.
+                            </p>
+                        </div>
+                    </div>
+                )}
+
+                <div className="space-y-2">
+                    <Label className="flex items-center gap-2">
+                        <KeyRound className="w-4 h-4" />
+                        {overview.passphraseSet ? "Change passphrase" : "Backup passphrase"}
+                    </Label>
+                    <div className="grid gap-4 md:grid-cols-[1fr_1fr_auto]">
+                        <Input
+                            type="password"
+                            value={passphrase}
+                            onChange={(e) => setPassphrase(e.target.value)}
+                            placeholder="At least 8 characters"
+                        />
+                        <Input
+                            type="password"
+                            value={confirmPassphrase}
+                            onChange={(e) => setConfirmPassphrase(e.target.value)}
+                            placeholder="Repeat passphrase"
+                        />
+                        <Button
+                            variant="outline"
+                            onClick={updatePassphrase}
+                            disabled={isSettingPassphrase || !passphrase}
+                        >
+                            {isSettingPassphrase ? <Loader2 className="w-4 h-4 animate-spin" /> : "Save Passphrase"}
+                        </Button>
+                    </div>
+                    {overview.passphraseSet && (
+                        <p className="text-xs text-muted-foreground">
+                            Existing backups still need the passphrase they were made with.
+                        </p>
+                    )}
+                </div>
+
+                {overview.lastSuccess && (
+                    <div className="space-y-2">
+                        <Label>Check passphrase against the latest backup</Label>
+                        <div className="flex gap-4">
+                            <Input
+                                type="password"
+                                value={checkPassphrase}
+                                onChange={(e) => setCheckPassphrase(e.target.value)}
+                                placeholder="Passphrase"
+                            />
+                            <Button
+                                variant="outline"
+                                onClick={verifyLatest}
+                                disabled={isVerifying || !checkPassphrase}
+                            >
+                                {isVerifying ? <Loader2 className="w-4 h-4 animate-spin" /> : "Verify"}
+                            </Button>
+                        </div>
+                    </div>
+                )}
+
+                {/* Schedule */}
+                <div className="flex items-center justify-between p-4 bg-surface-navy rounded-lg border border-surface-hover">
+                    <div>
+                        <Label className="text-white font-medium">Scheduled Backups</Label>
+                        <p className="text-sm text-moonstone mt-1">
+                            {settings.enabled ? "Backups run automatically" : "Only manual backups are taken"}
+                        </p>
+                    </div>
+                    <Switch
+                        checked={settings.enabled}
+                        onCheckedChange={(checked) => setSettings(prev => prev && { ...prev, enabled: checked })}
+                    />
+                </div>
+
+                <div className="grid gap-4 md:grid-cols-2">
+                    <div className="space-y-2">
+                        <Label>Frequency</Label>
+                        <Select
+                            value={settings.frequency}
+                            onValueChange={(val) =>
+                                setSettings(prev => prev && { ...prev, frequency: val as BackupConfig["frequency"] })
+                            }
+                        >
+                            <SelectTrigger>
+                                <SelectValue />
+                            </SelectTrigger>
+                            <SelectContent>
+                                <SelectItem value="hourly">Every hour</SelectItem>
+                                <SelectItem value="daily">Once a day</SelectItem>
+                            </SelectContent>
+                        </Select>
+                    </div>
+                    {settings.frequency === "daily" && (
+                        <div className="space-y-2">
+                            <Label htmlFor="backup_hour">Time of day</Label>
+                            <Select
+                                value={String(settings.hour)}
+                                onValueChange={(val) => setSettings(prev => prev && { ...prev, hour: parseInt(val) })}
+                            >
+                                <SelectTrigger id="backup_hour">
+                                    <SelectValue />
+                                </SelectTrigger>
+                                <SelectContent>
+                                    {Array.from({ length: 24 }, (_, hour) => (
+                                        <SelectItem key={hour} value={String(hour)}>
+                                            {`${String(hour).padStart(2, "0")}:00`}
+                                        </SelectItem>
+                                    ))}
+                                </SelectContent>
+                            </Select>
+                        </div>
+                    )}
+                    <div className="space-y-2 md:col-span-2">
+                        <Label htmlFor="backup_directory">Backup folder</Label>
+                        <Input
+                            id="backup_directory"
+                            value={settings.directory ?? ""}
+                            onChange={(e) =>
+                                setSettings(prev => prev && { ...prev, directory: e.target.value.trim() || null })
+                            }
+                            placeholder={overview.directory}
+                        />
+                        <p className="text-xs text-muted-foreground">Leave empty to keep backups in the app data folder</p>
+                    </div>
+                </div>
+
+                {/* Retention */}
+                <div className="space-y-2">
+                    <Label>Keep</Label>
+                    <div className="grid gap-4 grid-cols-3">
+                        {(["daily", "weekly", "monthly"] as const).map((key) => (
+                            <div key={key} className="space-y-1">
+                                <Input
+                                    type="number"
+                                    min={0}
+                                    value={settings.retention[key]}
+                                    onChange={(e) => setRetention(key, e.target.value)}
+                                />
+                                <p className="text-xs text-muted-foreground capitalize">{key} backups</p>
+                            </div>
+                        ))}
+                    </div>
+                </div>
+
+                {/* Destinations */}
+                <BackupDestinations
+                    destinations={settings.destinations}
+                    statuses={overview.destinations}
+                    onChange={(destinations) => setSettings(prev => prev && { ...prev, destinations })}
+                />
+
+                {/* Point-in-time recovery */}
+                <div className="space-y-4">
+                    <div className="flex items-center justify-between p-4 bg-surface-navy rounded-lg border border-surface-hover">
+                        <div>
+                            <Label className="text-white font-medium flex items-center gap-2">
+                                <History className="w-4 h-4" />
+                                Point-in-time Recovery
+                            </Label>
+                            <p className="text-sm text-moonstone mt-1">
+                                {settings.wal.enabled
+                                    ? "Every change is copied within seconds, so data can be restored as it was at any moment"
+                                    : "Data can only be restored as it was when a backup was taken"}
+                            </p>
+                        </div>
+                        <Switch
+                            checked={settings.wal.enabled}
+                            onCheckedChange={(checked) => setWal({ enabled: checked })}
+                        />
+                    </div>
+
+                    {settings.wal.enabled && (
+                        <div className="grid gap-4 md:grid-cols-2">
+                            <div className="space-y-2">
+                                <Label htmlFor="wal_destination">Copy changes to</Label>
+                                <Select
+                                    value={settings.wal.destinationId ?? ""}
+                                    onValueChange={(val) => setWal({ destinationId: val || null })}
+                                >
+                                    <SelectTrigger id="wal_destination">
+                                        <SelectValue placeholder="Choose a destination" />
+                                    </SelectTrigger>
+                                    <SelectContent>
+                                        {savedDestinations.map((destination) => (
+                                            <SelectItem key={destination.id} value={destination.id}>
+                                                {destination.name}
+                                            </SelectItem>
+                                        ))}
+                                    </SelectContent>
+                                </Select>
+                                {savedDestinations.length === 0 && (
+                                    <p className="text-xs text-muted-foreground">Add and save a destination above first</p>
+                                )}
+                            </div>
+                            <div className="space-y-2">
+                                <Label htmlFor="wal_retention">Reach back (days)</Label>
+                                <Input
+                                    id="wal_retention"
+                                    type="number"
+                                    min={1}
+                                    value={settings.wal.retentionDays}
+                                    onChange={(e) => setWal({ retentionDays: Math.max(1, parseInt(e.target.value) || 1) })}
+                                />
+                            </div>
+                        </div>
+                    )}
+
+                    {overview.wal.enabled && (
+                        <div className="flex items-center justify-between text-xs">
+                            <span className="flex items-center gap-2">
+                                {overview.wal.lastError ? (
+                                    <XCircle className="w-4 h-4 text-red-500" />
+                                ) : overview.wal.shipping ? (
+                                    <CheckCircle className="w-4 h-4 text-green-500" />
+                                ) : (
+                                    <AlertTriangle className="w-4 h-4 text-moonstone" />
+                                )}
+                                <span className="text-moonstone">
+                                    {overview.wal.lastError
+                                        ?? (overview.wal.shipping
+                                            ? `Copied up to ${formatTime(overview.wal.shippedThrough)}`
+                                            : "Waiting for the server and the backup passphrase")}
+                                    {overview.wal.pending > 0 && ` · ${overview.wal.pending} waiting`}
+                                </span>
+                            </span>
+                            {overview.wal.restorableFrom && (
+                                <button
+                                    className="text-primary hover:underline"
+                                    onClick={() => setIsPointInTimeOpen(true)}
+                                >
+                                    Restore to a Point in Time...
+                                </button>
+                            )}
+                        </div>
+                    )}
+                </div>
+
+                {/* Actions */}
+                <div className="flex items-center gap-3 pt-4">
+                    <Button
+                        variant="outline"
+                        onClick={runBackup}
+                        disabled={isBackingUp || overview.running || !overview.passphraseSet}
+                    >
+                        {isBackingUp || overview.running ? (
+                            <>
+                                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                                Backing up...
+                            </>
+                        ) : (
+                            "Back Up Now"
+                        )}
+                    </Button>
+                    <Button onClick={save} disabled={isSaving}>
+                        {isSaving ? (
+                            <>
+                                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                                Saving...
+                            </>
+                        ) : (
+                            "Save Settings"
+                        )}
+                    </Button>
+                    <Button variant="ghost" onClick={() => setRestorePath("")}>
+                        Restore from File...
+                    </Button>
+                </div>
+
+                {/* Recent backups */}
+                {backups.length > 0 && (
+                    <div className="pt-4 border-t border-surface-hover space-y-2">
+                        <p className="text-sm font-medium">Recent backups</p>
+                        {backups.map((backup) => (
+                            <div key={backup.id} className="flex items-center justify-between text-xs">
+                                <span className="flex items-center gap-2">
+                                    {backup.state === "success" && <CheckCircle className="w-4 h-4 text-green-500" />}
+                                    {backup.state === "failed" && <XCircle className="w-4 h-4 text-red-500" />}
+                                    {backup.state === "running" && <Loader2 className="w-4 h-4 animate-spin text-primary" />}
+                                    {backup.state === "pruned" && <AlertTriangle className="w-4 h-4 text-moonstone" />}
+                                    <span className={backup.state === "pruned" ? "text-moonstone line-through" : ""}>
+                                        {backup.fileName}
+                                    </span>
+                                </span>
+                                <span className="flex items-center gap-3 text-moonstone">
+                                    {formatTime(backup.startedAt)} · {formatSize(backup.size)}
+                                    {backup.state === "success" && (
+                                        <button
+                                            className="text-primary hover:underline"
+                                            onClick={() => setRestorePath(`${backup.directory}/${backup.fileName}`)}
+                                        >
+                                            Restore
+                                        </button>
+                                    )}
+                                </span>
+                            </div>
+                        ))}
+                    </div>
+                )}
+
+                <RestoreBackupDialog
+                    open={restorePath !== null}
+                    onOpenChange={(open) => !open && setRestorePath(null)}
+                    path={restorePath ?? undefined}
+                />
+                <PointInTimeRestoreDialog
+                    open={isPointInTimeOpen}
+                    onOpenChange={setIsPointInTimeOpen}
+                    restorableFrom={overview.wal.restorableFrom}
+                    shippedThrough={overview.wal.shippedThrough}
+                />
+            </CardContent>
+        </Card>
+    );
+}
@@ -0,0 +1,201 @@
+"use client";
+
+import { useState, useEffect } from "react";
+import {
+    Dialog,
+    DialogContent,
+    DialogDescription,
+    DialogFooter,
+    DialogHeader,
+    DialogTitle,
+} from "@/components/ui/dialog";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
+import { toast } from "sonner";
+import { restoreBackupToPointInTime, type PointInTimePlan } from "@/lib/backup";
+import { Loader2, AlertTriangle } from "lucide-react";
+
+interface PointInTimeRestoreDialogProps {
+    open: boolean;
+    onOpenChange: (open: boolean) => void;
+    /** Earliest time that can be restored to */
+    restorableFrom: number | null;
+    /** Latest time shipped to the destination */
+    shippedThrough: number | null;
+}
+
+/** Value for a datetime-local input, in local time */
+function toLocalInput(millis: number): string {
+    const date = new Date(millis - new Date(millis).getTimezoneOffset() * 60 * 1000);
+    return date.toISOString().slice(0, 16);
+}
+
+export function PointInTimeRestoreDialog({
+    open,
+    onOpenChange,
+    restorableFrom,
+    shippedThrough,
+}: PointInTimeRestoreDialogProps) {
+    const [at, setAt] = useState("");
+    const [passphrase, setPassphrase] = useState("");
+    const [plan, setPlan] = useState<PointInTimePlan | null>(null);
+    const [isPreviewing, setIsPreviewing] = useState(false);
+    const [isRestoring, setIsRestoring] = useState(false);
+
+    useEffect(() => {
+        if (open) {
+            setAt(toLocalInput(shippedThrough ?? Date.now()));
+            setPassphrase("");
+            setPlan(null);
+        }
+    }, [open, shippedThrough]);
+
+    const target = at ? new Date(at).getTime() : NaN;
+
+    const preview = async () => {
+        setIsPreviewing(true);
+        setPlan(null);
+        try {
+            setPlan(await restoreBackupToPointInTime(target, passphrase, true));
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsPreviewing(false);
+        }
+    };
+
+    const restore = async () => {
+        setIsRestoring(true);
+        try {
+            await restoreBackupToPointInTime(target, passphrase, false);
+            toast.success("Data restored. Reloading...");
+            onOpenChange(false);
+            // Records and sign-ins may have changed under the page
+            setTimeout(() => window.location.reload(), 1500);
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsRestoring(false);
+        }
+    };
+
+    const busy = isPreviewing || isRestoring;
+
+    return (
+        <Dialog open={open} onOpenChange={(next) => !busy && onOpenChange(next)}>
+            <DialogContent className="max-w-lg">
+                <DialogHeader>
+                    <DialogTitle>Restore to a Point in Time</DialogTitle>
+                    <DialogDescription>
+                        Puts the database back as it was at the chosen time. Uploaded files are left as they are.
+                    </DialogDescription>
+                </DialogHeader>
+
+                <div className="space-y-4">
+                    <div className="space-y-2">
+                        <Label htmlFor="pitr_at">Restore to</Label>
+                        <Input
+                            id="pitr_at"
+                            type="datetime-local"
+                            value={at}
+                            min={restorableFrom ? toLocalInput(restorableFrom) : undefined}
+                            max={toLocalInput(Date.now())}
+                            onChange={(e) => {
+                                setAt(e.target.value);
+                                setPlan(null);
+                            }}
+                        />
+                        {restorableFrom && (
+                            <p className="text-xs text-muted-foreground">
+                                Any time from {new Date(restorableFrom).toLocaleString()}
+                                {shippedThrough && ` to ${new Date(shippedThrough).toLocaleString()}`}
+                            </p>
+                        )}
+                    </div>
+                    <div className="space-y-2">
+                        <Label htmlFor="pitr_passphrase">Passphrase</Label>
+                        <Input
+                            id="pitr_passphrase"
+                            type="password"
+                            value={passphrase}
+                            onChange={(e) => {
+                                setPassphrase(e.target.value);
+                                setPlan(null);
+                            }}
+                            placeholder="Backup passphrase at that time"
+                        />
+                    </div>
+
+                    {plan && (
+                        <div className="space-y-3">
+                            <p className="text-sm">
+                                Data as of <strong>{new Date(plan.restoredTo).toLocaleString()}</strong>
+                            </p>
+                            {plan.collections.length === 0 ? (
+                                <p className="text-sm text-muted-foreground">Nothing changed since then.</p>
+                            ) : (
+                                <div className="max-h-60 overflow-y-auto rounded-lg border border-surface-hover">
+                                    <table className="w-full text-xs">
+                                        <thead className="bg-muted/30 text-left">
+                                            <tr>
+                                                <th className="p-2">Collection</th>
+                                                <th className="p-2 text-right">Added</th>
+                                                <th className="p-2 text-right">Changed</th>
+                                                <th className="p-2 text-right">Removed</th>
+                                            </tr>
+                                        </thead>
+                                        <tbody>
+                                            {plan.collections.map((diff) => (
+                                                <tr key={diff.name} className="border-t border-surface-hover">
+                                                    <td className="p-2">{diff.name}</td>
+                                                    <td className="p-2 text-right text-green-500">{diff.added || ""}</td>
+                                                    <td className="p-2 text-right text-yellow-500">{diff.changed || ""}</td>
+                                                    <td className="p-2 text-right text-red-500">{diff.removed || ""}</td>
+                                                </tr>
+                                            ))}
+                                        </tbody>
+                                    </table>
+                                </div>
+                            )}
+                            {plan.pendingMigrations.length > 0 && (
+                                <p className="text-xs text-moonstone">
+                                    {plan.pendingMigrations.length} database update(s) newer than that time will be applied after restoring.
+                                </p>
+                            )}
+                            <div className="flex items-start gap-2 p-3 bg-yellow-500/10 border border-yellow-500/30 rounded-lg">
+                                <AlertTriangle className="w-5 h-5 text-yellow-500 mt-0.5" />
+                                <p className="text-xs text-moonstone">
+                                    The server restarts during the restore. The current database is kept aside and put back if the restored one fails to start.
+                                </p>
+                            </div>
+                        </div>
+                    )}
+                </div>
+
+                <DialogFooter>
+                    <Button variant="outline" onClick={preview} disabled={busy || isNaN(target) || !passphrase}>
+                        {isPreviewing ? (
+                            <>
+                                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                                Checking...
+                            </>
+                        ) : (
+                            "Preview Changes"
+                        )}
+                    </Button>
+                    <Button variant="destructive" onClick={restore} disabled={busy || !plan}>
+                        {isRestoring ? (
+                            <>
+                                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                                Restoring...
+                            </>
+                        ) : (
+                            "Restore"
+                        )}
+                    </Button>
+                </DialogFooter>
+            </DialogContent>
+        </Dialog>
+    );
+}
@@ -0,0 +1,184 @@
+"use client";
+
+import { useState, useEffect } from "react";
+import {
+    Dialog,
+    DialogContent,
+    DialogDescription,
+    DialogFooter,
+    DialogHeader,
+    DialogTitle,
+} from "@/components/ui/dialog";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
+import { toast } from "sonner";
+import { restoreBackup, type RestorePlan } from "@/lib/backup";
+import { Loader2, AlertTriangle } from "lucide-react";
+
+interface RestoreBackupDialogProps {
+    open: boolean;
+    onOpenChange: (open: boolean) => void;
+    /** Backup to restore; asked for when empty */
+    path?: string;
+}
+
+export function RestoreBackupDialog({ open, onOpenChange, path: initialPath }: RestoreBackupDialogProps) {
+    const [path, setPath] = useState(initialPath ?? "");
+    const [passphrase, setPassphrase] = useState("");
+    const [plan, setPlan] = useState<RestorePlan | null>(null);
+    const [isPreviewing, setIsPreviewing] = useState(false);
+    const [isRestoring, setIsRestoring] = useState(false);
+
+    useEffect(() => {
+        if (open) {
+            setPath(initialPath ?? "");
+            setPassphrase("");
+            setPlan(null);
+        }
+    }, [open, initialPath]);
+
+    const preview = async () => {
+        setIsPreviewing(true);
+        setPlan(null);
+        try {
+            setPlan(await restoreBackup(path.trim(), passphrase, true));
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsPreviewing(false);
+        }
+    };
+
+    const restore = async () => {
+        setIsRestoring(true);
+        try {
+            await restoreBackup(path.trim(), passphrase, false);
+            toast.success("Backup restored. Reloading...");
+            onOpenChange(false);
+            // Records and sign-ins may have changed under the page
+            setTimeout(() => window.location.reload(), 1500);
+        } catch (error) {
+            toast.error(String(error));
+        } finally {
+            setIsRestoring(false);
+        }
+    };
+
+    const busy = isPreviewing || isRestoring;
+
+    return (
+        <Dialog open={open} onOpenChange={(next) => !busy && onOpenChange(next)}>
+            <DialogContent className="max-w-lg">
+                <DialogHeader>
+                    <DialogTitle>Restore Backup</DialogTitle>
+                    <DialogDescription>
+                        Replaces all current data with the backup. Preview the changes first.
+                    </DialogDescription>
+                </DialogHeader>
+
+                <div className="space-y-4">
+                    <div className="space-y-2">
+                        <Label htmlFor="restore_path">Backup file</Label>
+                        <Input
+                            id="restore_path"
+                            value={path}
+                            onChange={(e) => {
+                                setPath(e.target.value);
+                                setPlan(null);
+                            }}
+                            placeholder="Full path to a .lmbk file"
+                        />
+                    </div>
+                    <div className="space-y-2">
+                        <Label htmlFor="restore_passphrase">Passphrase</Label>
+                        <Input
+                            id="restore_passphrase"
+                            type="password"
+                            value={passphrase}
+                            onChange={(e) => {
+                                setPassphrase(e.target.value);
+                                setPlan(null);
+                            }}
+                            placeholder="Passphrase the backup was made with"
+                        />
+                    </div>
+
+                    {plan && (
+                        <div className="space-y-3">
+                            <p className="text-sm">
+                                Backup from <strong>{new Date(plan.info.createdAt).toLocaleString()}</strong>,
+                                app version {plan.info.appVersion}
+                            </p>
+                            {plan.collections.length === 0 ? (
+                                <p className="text-sm text-muted-foreground">The backup matches the current data.</p>
+                            ) : (
+                                <div className="max-h-60 overflow-y-auto rounded-lg border border-surface-hover">
+                                    <table className="w-full text-xs">
+                                        <thead className="bg-muted/30 text-left">
+                                            <tr>
+                                                <th className="p-2">Collection</th>
+                                                <th className="p-2 text-right">Added</th>
+                                                <th className="p-2 text-right">Changed</th>
+                                                <th className="p-2 text-right">Removed</th>
+                                            </tr>
+                                        </thead>
+                                        <tbody>
+                                            {plan.collections.map((diff) => (
+                                                <tr key={diff.name} className="border-t border-surface-hover">
+                                                    <td className="p-2">{diff.name}</td>
+                                                    <td className="p-2 text-right text-green-500">{diff.added || ""}</td>
+                                                    <td className="p-2 text-right text-yellow-500">{diff.changed || ""}</td>
+                                                    <td className="p-2 text-right text-red-500">{diff.removed || ""}</td>
+                                                </tr>
+                                            ))}
+                                        </tbody>
+                                    </table>
+                                </div>
+                            )}
+                            {plan.pendingMigrations.length > 0 && (
+                                <p className="text-xs text-moonstone">
+                                    {plan.pendingMigrations.length} database update(s) newer than the backup will be applied after restoring.
+                                </p>
+                            )}
+                            {plan.missingFiles.length > 0 && (
+                                <p className="text-xs text-moonstone">
+                                    {plan.missingFiles.length} uploaded file(s) were deleted while the backup was taken and are not in it.
+                                </p>
+                            )}
+                            <div className="flex items-start gap-2 p-3 bg-yellow-500/10 border border-yellow-500/30 rounded-lg">
+                                <AlertTriangle className="w-5 h-5 text-yellow-500 mt-0.5" />
+                                <p className="text-xs text-moonstone">
+                                    The server restarts during the restore. Current data is kept aside and put back if the restored data fails to start.
+                                </p>
+                            </div>
+                        </div>
+                    )}
+                </div>
+
+                <DialogFooter>
+                    <Button variant="outline" onClick={preview} disabled={busy || !path.trim() || !passphrase}>
+                        {isPreviewing ? (
+                            <>
+                                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                                Checking...
+                            </>
+                        ) : (
+                            "Preview Changes"
+                        )}
+                    </Button>
+                    <Button variant="destructive" onClick={restore} disabled={busy || !plan}>
+                        {isRestoring ? (
+                            <>
+                                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                                Restoring...
+                            </>
+                        ) : (
+                            "Restore"
+                        )}
+                    </Button>
+                </DialogFooter>
+            </DialogContent>
+        </Dialog>
+    );
+}
@@ -1,6 +1,7 @@
 "use client";
 
 import { createContext, useContext, useEffect, useState, ReactNode } from "react";
+import { invoke } from "@tauri-apps/api/core";
 import { pb } from "@/lib/pocketbase";
 import type { AuthModel } from "pocketbase";
 
@@ -24,6 +25,17 @@ const AuthContext = createContext<AuthContextType>({
     logout: () => { },
 });
 
+/**
+ * Hand the auth token to the desktop app's Rust side, which reads PocketBase
+ * as the signed-in user
+ */
+function shareAuthWithDesktop(token: string | null) {
+    if (typeof window === "undefined" || !("__TAURI_INTERNALS__" in window)) return;
+    invoke("pocketbase_set_auth", { token }).catch((err) => {
+        console.error("Failed to share auth with desktop app:", err);
+    });
+}
+
 export function AuthProvider({ children }: { children: ReactNode }) {
     const [user, setUser] = useState<AuthModel | null>(null);
     const [isValid, setIsValid] = useState(false);
@@ -34,11 +46,13 @@ export function AuthProvider({ children }: { children: ReactNode }) {
         setUser(pb.authStore.model);
         setIsValid(pb.authStore.isValid);
         setIsLoading(false);
+        shareAuthWithDesktop(pb.authStore.isValid ? pb.authStore.token : null);
 
         // Subscribe to auth changes
         const unsubscribe = pb.authStore.onChange((token, model) => {
             setUser(model);
             setIsValid(pb.authStore.isValid);
+            shareAuthWithDesktop(token || null);
         });
 
         return () => {
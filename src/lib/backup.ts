@@ -3,6 +3,8 @@
  * Handles data export and import for disaster recovery using PocketBase
  */
 
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { pb } from "./pocketbase";
 import { downloadAsFile, readFileAsText } from "./csv";
 
@@ -309,3 +311,266 @@ export async function restoreFromAutoBackup(): Promise<boolean> {
         return false;
     }
 }
+
+// ============================================
+// DESKTOP: PocketBase data backups
+// ============================================
+
+export type BackupFrequency = "hourly" | "daily";
+export type BackupState = "running" | "success" | "failed" | "pruned";
+
+export interface BackupRetention {
+    daily: number;
+    weekly: number;
+    monthly: number;
+}
+
+export interface BackupSettings {
+    enabled: boolean;
+    frequency: BackupFrequency;
+    /** Local hour (0-23) daily backups run at */
+    hour: number;
+    /** Where archives are written; the app data folder when null */
+    directory: string | null;
+    retention: BackupRetention;
+    /** Where finished backups are copied to */
+    destinations: BackupDestination[];
+    wal: WalSettings;
+}
+
+/** Continuous shipping of PocketBase's write-ahead log, for point-in-time recovery */
+export interface WalSettings {
+    enabled: boolean;
+    /** Backup destination the log is copied to */
+    destinationId: string | null;
+    /** Days back a restore can reach */
+    retentionDays: number;
+}
+
+/** S3-compatible bucket (AWS, MinIO, Backblaze B2, Wasabi, R2...) */
+export interface S3Target {
+    endpoint: string;
+    region: string;
+    bucket: string;
+    /** Key prefix archives are stored under */
+    prefix: string;
+    accessKeyId: string;
+    /** A saved key comes back as "********"; saving that keeps the saved key */
+    secretAccessKey: string;
+    /** Address the bucket as endpoint/bucket; needed by MinIO */
+    pathStyle: boolean;
+}
+
+export type BackupDestinationKind =
+    | { type: "folder"; path: string }
+    /** Removable drive, used whenever one with this name is plugged in */
+    | { type: "drive"; label: string; folder: string }
+    | ({ type: "s3" } & S3Target);
+
+export type BackupDestination = {
+    /** Assigned when first saved */
+    id: string;
+    name: string;
+    enabled: boolean;
+} & BackupDestinationKind;
+
+/** How copies to one destination are going */
+export interface BackupDestinationStatus {
+    id: string;
+    name: string;
+    enabled: boolean;
+    /** Last verified copy */
+    lastSuccessAt: number | null;
+    /** Why waiting copies have not been made yet */
+    lastError: string | null;
+    pending: number;
+}
+
+/** Copy of a backup at one destination */
+export interface BackupCopy {
+    backupId: string;
+    destinationId: string;
+    state: "pending" | "done" | "deleted";
+    location: string | null;
+    attempts: number;
+    error: string | null;
+    nextAttemptAt: number;
+    finishedAt: number | null;
+}
+
+/** Backup of pb_data taken by the desktop app */
+export interface BackupRecord {
+    id: string;
+    fileName: string;
+    directory: string;
+    trigger: "schedule" | "manual";
+    state: BackupState;
+    size: number | null;
+    sha256: string | null;
+    files: number | null;
+    missingFiles: number | null;
+    error: string | null;
+    startedAt: number;
+    finishedAt: number | null;
+}
+
+export interface BackupOverview {
+    settings: BackupSettings;
+    directory: string;
+    running: boolean;
+    /** Nothing is backed up until a passphrase is set */
+    passphraseSet: boolean;
+    lastSuccess: BackupRecord | null;
+    lastFailure: BackupRecord | null;
+    nextDueAt: number | null;
+    destinations: BackupDestinationStatus[];
+    wal: WalStatus;
+}
+
+/** How log shipping is going */
+export interface WalStatus {
+    enabled: boolean;
+    /** Whether PocketBase's log is being shipped right now */
+    shipping: boolean;
+    generation: string | null;
+    /** Earliest time that can be restored to */
+    restorableFrom: number | null;
+    /** Latest shipped segment; anything committed after it is only on this computer */
+    shippedThrough: number | null;
+    /** Segments waiting to be copied */
+    pending: number;
+    lastError: string | null;
+}
+
+/** Cleartext header of an encrypted backup archive */
+export interface BackupInfo {
+    format: number;
+    createdAt: number;
+    appVersion: string;
+    migrations: string[];
+    files: number;
+    missingFiles: number;
+    size: number;
+    sha256: string;
+}
+
+/** Records a restore would add, change and remove in one collection */
+export interface CollectionDiff {
+    name: string;
+    added: number;
+    changed: number;
+    removed: number;
+}
+
+/** What restoring a backup changes */
+export interface RestorePlan {
+    info: BackupInfo;
+    /** Migrations newer than the backup, applied when PocketBase restarts */
+    pendingMigrations: string[];
+    collections: CollectionDiff[];
+    missingFiles: string[];
+    /** False for a dry run */
+    applied: boolean;
+}
+
+/** What restoring to a point in time changes */
+export interface PointInTimePlan {
+    /** Time asked for */
+    target: number;
+    /** Time the restored data is from: when the last replayed segment was cut */
+    restoredTo: number;
+    generation: string;
+    segments: number;
+    /** Migrations newer than the restored database, applied when PocketBase restarts */
+    pendingMigrations: string[];
+    collections: CollectionDiff[];
+    /** False for a dry run */
+    applied: boolean;
+}
+
+/** Whether full data backups are available (desktop app only) */
+export function isDesktopBackupAvailable(): boolean {
+    return typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
+}
+
+/** Back up PocketBase's data now; resolves once the archive is written */
+export async function backupNow(): Promise<BackupRecord> {
+    return invoke<BackupRecord>("backup_now");
+}
+
+export async function listBackups(limit = 50): Promise<BackupRecord[]> {
+    if (!isDesktopBackupAvailable()) return [];
+    return invoke<BackupRecord[]>("backup_list", { limit });
+}
+
+export async function getBackupStatus(): Promise<BackupOverview | null> {
+    if (!isDesktopBackupAvailable()) return null;
+    return invoke<BackupOverview>("backup_status");
+}
+
+export async function saveBackupSettings(settings: BackupSettings): Promise<BackupOverview> {
+    return invoke<BackupOverview>("backup_save_settings", { settings });
+}
+
+/** Where a backup has been copied to */
+export async function listBackupCopies(backupId: string): Promise<BackupCopy[]> {
+    return invoke<BackupCopy[]>("backup_copies", { backupId });
+}
+
+/** Write and delete a test file at a destination; rejects with the reason it is unusable */
+export async function testBackupDestination(destination: BackupDestination): Promise<void> {
+    return invoke<void>("backup_test_destination", { destination });
+}
+
+/** Set the passphrase new backups are encrypted with, or clear it with null */
+export async function setBackupPassphrase(passphrase: string | null): Promise<BackupOverview> {
+    return invoke<BackupOverview>("backup_set_passphrase", { passphrase });
+}
+
+/** Read what a backup contains without the passphrase */
+export async function inspectBackup(path: string): Promise<BackupInfo> {
+    return invoke<BackupInfo>("backup_inspect", { path });
+}
+
+/** Check a passphrase opens a backup and the backup is intact, without restoring it */
+export async function verifyBackup(path: string, passphrase: string): Promise<BackupInfo> {
+    return invoke<BackupInfo>("backup_verify", { path, passphrase });
+}
+
+/**
+ * Restore PocketBase's data from a backup. A dry run (the default) changes
+ * nothing and reports what restoring would add, change and remove.
+ */
+export async function restoreBackup(path: string, passphrase: string, dryRun = true): Promise<RestorePlan> {
+    return invoke<RestorePlan>("backup_restore", { path, passphrase, dryRun });
+}
+
+/**
+ * Restore PocketBase's database as it was at `at` (Unix ms) from the shipped
+ * write-ahead log. A dry run (the default) changes nothing.
+ */
+export async function restoreBackupToPointInTime(
+    at: number,
+    passphrase: string,
+    dryRun = true
+): Promise<PointInTimePlan> {
+    return invoke<PointInTimePlan>("backup_restore_point_in_time", { at, passphrase, dryRun });
+}
+
+/** Backups starting, finishing or being pruned */
+export async function onBackup(handler: (record: BackupRecord) => void): Promise<UnlistenFn> {
+    if (!isDesktopBackupAvailable()) return () => {};
+    return listen<BackupRecord>("backup", (event) => handler(event.payload));
+}
+
+/** Backups being copied to a destination, or removed from it */
+export async function onBackupCopy(handler: (copy: BackupCopy) => void): Promise<UnlistenFn> {
+    if (!isDesktopBackupAvailable()) return () => {};
+    return listen<BackupCopy>("backup-copy", (event) => handler(event.payload));
+}
+
+/** Log shipping status changing: segments copied, or shipping failing */
+export async function onBackupWal(handler: (status: WalStatus) => void): Promise<UnlistenFn> {
+    if (!isDesktopBackupAvailable()) return () => {};
+    return listen<WalStatus>("backup-wal", (event) => handler(event.payload));
+}
@@ -8,8 +8,14 @@
  * 3. Create stock movements (decrement inventory)
  * 4. Update shift totals
  * 5. Generate invoice from sale
+ *
+ * In the desktop app steps 1-4 go through the Rust POS journal instead, so a
+ * sale can be completed while PocketBase is down: it is stored locally and
+ * replayed once PocketBase is back, and its invoice is issued after that.
  */
 
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { pb } from './pocketbase';
 import { createInvoiceFromSale, type Invoice } from './invoice';
 
@@ -54,6 +60,39 @@ export interface POSSaleResult {
     invoiceId: string;
     invoiceNumber: string;
     transactionId: string;
+    /** Saved locally but not in PocketBase yet, so there is no invoice yet */
+    pending?: boolean;
+}
+
+export type POSJournalStatus = 'pending' | 'synced' | 'conflict' | 'discarded';
+
+/** Sale in the desktop app's local journal */
+export interface POSJournalEntry {
+    /** PocketBase id of the sale */
+    id: string;
+    transactionId: string;
+    shiftId: string;
+    paymentMethod: string;
+    total: number;
+    itemCount: number;
+    status: POSJournalStatus;
+    attempts: number;
+    lastError: string | null;
+    /** Write PocketBase rejected, for conflicts */
+    conflict: string | null;
+    /** Issues that did not stop the sale, e.g. overselling */
+    warnings: string[];
+    opsDone: number;
+    opsTotal: number;
+    invoiceId: string | null;
+    invoiceNumber: string | null;
+    createdAt: number;
+    nextAttemptAt: number;
+    syncedAt: number | null;
+}
+
+function isTauri(): boolean {
+    return typeof window !== 'undefined' && '__TAURI_INTERNALS__' in window;
 }
 
 // ============================================
@@ -63,6 +102,10 @@ export interface POSSaleResult {
 export async function createPOSSale(data: POSSaleData): Promise<POSSaleResult> {
     const transactionId = `TXN-${Date.now().toString(36).toUpperCase()}`;
 
+    if (isTauri()) {
+        return recordPOSSale(transactionId, data);
+    }
+
     try {
         // 1. Create sale record
         const sale = await pb.collection('sales').create({
@@ -145,6 +188,122 @@ export async function createPOSSale(data: POSSaleData): Promise<POSSaleResult> {
     }
 }
 
+// ============================================
+// DESKTOP: Local sales journal
+// ============================================
+
+async function recordPOSSale(transactionId: string, data: POSSaleData): Promise<POSSaleResult> {
+    // Once this returns the sale is saved, even if PocketBase is down
+    const entry = await invoke<POSJournalEntry>('pos_record_sale', {
+        sale: {
+            transactionId,
+            items: data.items.map(item => ({
+                productId: item.productId || null,
+                variantId: item.variantId || null,
+                quantity: item.quantity,
+                unitPrice: item.price,
+                remarks: item.remarks || null,
+            })),
+            subtotal: data.subtotal,
+            discount: data.discountAmount + data.loyaltyDiscount,
+            total: data.total,
+            paymentMethod: data.paymentMethod,
+            cashTendered: data.cashTendered || null,
+            changeGiven: data.changeGiven || null,
+            customerId: data.customerId || null,
+            customerName: data.customerName || null,
+            customerPhone: data.customerPhone || null,
+            shiftId: data.shiftId,
+            notes: data.notes || null,
+        },
+    });
+
+    const result = { saleId: entry.id, invoiceId: '', invoiceNumber: '', transactionId, pending: true };
+    if (entry.status !== 'synced') {
+        return result;
+    }
+    try {
+        const invoice = await issueJournalInvoice(entry);
+        return { ...result, invoiceId: invoice.id!, invoiceNumber: invoice.invoice_number!, pending: false };
+    } catch (error) {
+        // The sale is in PocketBase; issuePendingInvoices() tries again later
+        console.error('Error creating invoice for sale:', entry.id, error);
+        return { ...result, pending: false };
+    }
+}
+
+// Invoices are issued one at a time, so a sale invoiced from the till and
+// from a replay notification at once still gets a single invoice
+let invoiceQueue: Promise<unknown> = Promise.resolve();
+
+function issueJournalInvoice(entry: POSJournalEntry): Promise<Invoice> {
+    const run = invoiceQueue.then(() => issueInvoiceOnce(entry));
+    invoiceQueue = run.catch(() => {});
+    return run;
+}
+
+async function issueInvoiceOnce(entry: POSJournalEntry): Promise<Invoice> {
+    // An earlier attempt may have created the invoice without recording it
+    const existing = await pb.collection('invoices').getList(1, 1, {
+        filter: `sale="${entry.id}"`,
+    });
+    const invoice = existing.items.length > 0
+        ? existing.items[0] as unknown as Invoice
+        : await createInvoiceFromSale(entry.id);
+    await invoke('pos_journal_set_invoice', {
+        id: entry.id,
+        invoiceId: invoice.id,
+        invoiceNumber: invoice.invoice_number,
+    });
+    return invoice;
+}
+
+/**
+ * Issue invoices for journalled sales that have reached PocketBase since
+ * they were rung up. Returns how many were issued.
+ */
+export async function issuePendingInvoices(): Promise<number> {
+    if (!isTauri()) return 0;
+    const entries = await invoke<POSJournalEntry[]>('pos_journal_uninvoiced');
+    let issued = 0;
+    for (const entry of entries) {
+        try {
+            await issueJournalInvoice(entry);
+            issued++;
+        } catch (error) {
+            console.error('Error creating invoice for sale:', entry.id, error);
+        }
+    }
+    return issued;
+}
+
+/** Number of sales saved locally that have not reached PocketBase yet */
+export async function getPendingSaleCount(): Promise<number> {
+    if (!isTauri()) return 0;
+    return invoke<number>('pos_journal_pending_count');
+}
+
+export async function listJournalledSales(status?: POSJournalStatus, limit = 100): Promise<POSJournalEntry[]> {
+    if (!isTauri()) return [];
+    return invoke<POSJournalEntry[]>('pos_journal_list', { status: status ?? null, limit });
+}
+
+/** Replay a sale PocketBase rejected, e.g. after restoring a deleted variant */
+export async function retryJournalledSale(id: string): Promise<POSJournalEntry> {
+    return invoke<POSJournalEntry>('pos_journal_retry', { id });
+}
+
+/** Give up on a sale PocketBase rejected; what already reached it stays */
+export async function discardJournalledSale(id: string): Promise<POSJournalEntry> {
+    return invoke<POSJournalEntry>('pos_journal_discard', { id });
+}
+
+/** Journal changes pushed by the desktop app as sales are replayed */
+export async function onPOSJournal(handler: (entry: POSJournalEntry) => void): Promise<UnlistenFn> {
+    if (!isTauri()) return () => {};
+    return listen<POSJournalEntry>('pos-journal', (event) => handler(event.payload));
+}
+
 // ============================================
 // HELPER: Update Shift Totals
 // ============================================
@@ -47,6 +47,8 @@ export interface UserProfile {
     name: string;
     full_name?: string;
     avatar?: string;
+    /** WhatsApp number allowed to send admin commands */
+    phone?: string;
     is_active?: boolean;
     last_login?: string;
     created: string;
@@ -168,6 +170,7 @@ export async function getUsers(): Promise<UserProfile[]> {
                 name: u.name,
                 full_name: u.name || u.email?.split('@')[0],
                 avatar: u.avatar ? pb.files.getUrl(u, u.avatar) : undefined,
+                phone: u.phone || undefined,
                 is_active: u.is_active !== false, // Default true if missing/undefined
                 last_login: u.last_login ? u.last_login : undefined,
                 created: u.created,
@@ -6,9 +6,348 @@
  * It provides REST API for WhatsApp Web automation.
  */
 
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+// Fallback when running outside Tauri (plain `next dev` against a manually started sidecar).
 // Use 127.0.0.1 for more reliable localhost connections
-const WPPCONNECT_URL = "http://127.0.0.1:21465";
+const DEFAULT_WPPCONNECT_URL = "http://127.0.0.1:21465";
+
+let wppconnectUrl: Promise<string> | null = null;
+
+/**
+ * Base URL of the WPPConnect sidecar. The desktop app picks the port at launch,
+ * so ask the Rust side instead of hardcoding it.
+ */
+export function getWPPConnectUrl(): Promise<string> {
+    if (!wppconnectUrl) {
+        wppconnectUrl = invoke<string>("get_sidecar_url", { name: "wppconnect" }).catch(
+            () => DEFAULT_WPPCONNECT_URL
+        );
+    }
+    return wppconnectUrl;
+}
+
+interface ProxyResponse {
+    status: number;
+    body: unknown;
+}
+
+function isTauri(): boolean {
+    return typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
+}
+
+/**
+ * Call a sidecar `/api/...` route. Inside the desktop app this goes through the
+ * `whatsapp_request` command, which attaches the per-launch token that the
+ * webview never sees.
+ */
+export async function sidecarFetch(path: string, init: RequestInit = {}): Promise<Response> {
+    if (!isTauri()) {
+        return fetch(`${await getWPPConnectUrl()}${path}`, init);
+    }
+
+    const res = await invoke<ProxyResponse>("whatsapp_request", {
+        method: init.method ?? "GET",
+        path,
+        body: typeof init.body === "string" ? JSON.parse(init.body) : null,
+    });
+    const nullBody = res.status === 204 || res.status === 205 || res.status === 304;
+    return new Response(nullBody ? null : JSON.stringify(res.body), {
+        status: res.status,
+        headers: { "Content-Type": "application/json" },
+    });
+}
+
+export interface WhatsAppInboundMessage {
+    session: string;
+    chatId: string;
+    id: string;
+    from: string;
+    to: string;
+    body: string;
+    kind: string;
+    timestamp: number | null;
+    fromMe: boolean;
+    isGroupMsg: boolean;
+    senderName: string | null;
+}
+
+export interface WhatsAppAck {
+    session: string;
+    messageId: string;
+    ack: number;
+    status: "error" | "pending" | "sent" | "delivered" | "read" | "played";
+}
+
+export interface WhatsAppStateChange {
+    session: string;
+    state: "qr_ready" | "connected" | "disconnected";
+    qrCode: string | null;
+}
+
+/**
+ * Subscribe to messages pushed by the desktop app's event stream.
+ * Returns an unsubscribe function (a no-op outside Tauri).
+ */
+export async function onWhatsAppMessage(
+    handler: (message: WhatsAppInboundMessage) => void
+): Promise<UnlistenFn> {
+    if (!isTauri()) return () => {};
+    return listen<WhatsAppInboundMessage>("whatsapp-message", (event) => handler(event.payload));
+}
+
+export async function onWhatsAppAck(handler: (ack: WhatsAppAck) => void): Promise<UnlistenFn> {
+    if (!isTauri()) return () => {};
+    return listen<WhatsAppAck>("whatsapp-ack", (event) => handler(event.payload));
+}
+
+export async function onWhatsAppState(
+    handler: (change: WhatsAppStateChange) => void
+): Promise<UnlistenFn> {
+    if (!isTauri()) return () => {};
+    return listen<WhatsAppStateChange>("whatsapp-state", (event) => handler(event.payload));
+}
+
+export interface WhatsAppSessionInfo {
+    name: string;
+    phone: string | null;
+    purpose: string;
+    state: "stopped" | "starting" | "qr_ready" | "pairing" | "connected" | "disconnected" | "error";
+    lastError: string | null;
+    updatedAt: number;
+}
+
+/**
+ * WhatsApp numbers configured in the desktop app (retail, wholesale, ...).
+ * Empty outside Tauri.
+ */
+export async function listWhatsAppSessions(): Promise<WhatsAppSessionInfo[]> {
+    if (!isTauri()) return [];
+    return invoke<WhatsAppSessionInfo[]>("whatsapp_list_sessions");
+}
+
+export interface WhatsAppCommandAudit {
+    id: string;
+    session: string;
+    messageId: string;
+    sender: string;
+    userId: string | null;
+    userName: string | null;
+    command: string;
+    body: string;
+    outcome: "executed" | "failed" | "denied" | "rejected" | "pending" | "cancelled" | "expired";
+    detail: string | null;
+    createdAt: number;
+    updatedAt: number;
+}
+
+/**
+ * Admin commands received over WhatsApp, newest first. Commands are handled
+ * by the desktop app; empty in the browser.
+ */
+export async function getWhatsAppCommandAudit(limit = 100): Promise<WhatsAppCommandAudit[]> {
+    if (!isTauri()) return [];
+    return invoke<WhatsAppCommandAudit[]>("whatsapp_command_audit", { limit });
+}
+
+export interface CampaignAudience {
+    /** Customer tiers to include; all tiers when empty */
+    tiers?: string[];
+    minTotalSpent?: number | null;
+    maxTotalSpent?: number | null;
+    /** Hand-picked customers; when set, only these are considered */
+    customerIds?: string[];
+}
+
+export interface CampaignCounts {
+    total: number;
+    pending: number;
+    queued: number;
+    sent: number;
+    delivered: number;
+    read: number;
+    failed: number;
+    skipped: number;
+    cancelled: number;
+}
+
+export interface WhatsAppCampaign {
+    id: string;
+    name: string;
+    session: string;
+    template: string;
+    audience: CampaignAudience;
+    intervalSecs: number;
+    status: "scheduled" | "sending" | "completed" | "cancelled";
+    scheduledAt: number;
+    startedAt: number | null;
+    finishedAt: number | null;
+    lastError: string | null;
+    createdAt: number;
+    counts: CampaignCounts;
+}
+
+export interface NewWhatsAppCampaign {
+    name: string;
+    session: string;
+    /** Supports {{name}}, {{first_name}}, {{tier}}, {{loyalty_points}}, {{total_spent}}, {{phone}} and {{var|fallback}} */
+    template: string;
+    audience: CampaignAudience;
+    /** Unix ms; sends right away when omitted */
+    scheduledAt?: number;
+    intervalSecs?: number;
+}
+
+export interface CampaignPreview {
+    recipients: number;
+    optedOut: number;
+    invalidPhone: number;
+    samples: { customerName: string; phone: string; message: string }[];
+}
+
+export interface CampaignRecipientResult {
+    customerId: string;
+    customerName: string;
+    phone: string;
+    status: "pending" | "queued" | "sent" | "failed" | "skipped" | "cancelled";
+    delivery: "error" | "pending" | "sent" | "delivered" | "read" | "played" | null;
+    error: string | null;
+    messageId: string | null;
+    sentAt: number | null;
+    updatedAt: number;
+}
+
+export interface CampaignReport {
+    campaign: WhatsAppCampaign;
+    recipients: CampaignRecipientResult[];
+}
+
+export interface WhatsAppOptOut {
+    phone: string;
+    session: string;
+    keyword: string;
+    createdAt: number;
+}
+
+/*
+ * Broadcast campaigns are scheduled and paced by the desktop app, which keeps
+ * sending while the webview is closed and honours STOP replies. The helpers
+ * below throw outside Tauri.
+ */
+
+function requireTauri(feature = "Campaigns"): void {
+    if (!isTauri()) throw new Error(`${feature} need the desktop app`);
+}
+
+export async function previewCampaign(audience: CampaignAudience, template: string): Promise<CampaignPreview> {
+    requireTauri();
+    return invoke<CampaignPreview>("whatsapp_campaign_preview", { audience, template });
+}
+
+export async function createCampaign(campaign: NewWhatsAppCampaign): Promise<WhatsAppCampaign> {
+    requireTauri();
+    return invoke<WhatsAppCampaign>("whatsapp_campaign_create", { campaign });
+}
+
+export async function listCampaigns(limit = 50): Promise<WhatsAppCampaign[]> {
+    if (!isTauri()) return [];
+    return invoke<WhatsAppCampaign[]>("whatsapp_campaign_list", { limit });
+}
+
+export async function getCampaignReport(id: string): Promise<CampaignReport> {
+    requireTauri();
+    return invoke<CampaignReport>("whatsapp_campaign_report", { id });
+}
+
+export async function cancelCampaign(id: string): Promise<WhatsAppCampaign> {
+    requireTauri();
+    return invoke<WhatsAppCampaign>("whatsapp_campaign_cancel", { id });
+}
+
+export async function listOptOuts(): Promise<WhatsAppOptOut[]> {
+    if (!isTauri()) return [];
+    return invoke<WhatsAppOptOut[]>("whatsapp_opt_outs");
+}
+
+export async function removeOptOut(phone: string): Promise<boolean> {
+    requireTauri();
+    return invoke<boolean>("whatsapp_remove_opt_out", { phone });
+}
+
+/** Campaign progress and status changes pushed by the desktop app */
+export async function onWhatsAppCampaign(
+    handler: (campaign: WhatsAppCampaign) => void
+): Promise<UnlistenFn> {
+    if (!isTauri()) return () => {};
+    return listen<WhatsAppCampaign>("whatsapp-campaign", (event) => handler(event.payload));
+}
 
+export interface CatalogSyncRun {
+    id: string;
+    session: string;
+    trigger: "schedule" | "change" | "manual";
+    status: "running" | "success" | "partial" | "failed";
+    added: number;
+    updated: number;
+    deleted: number;
+    unchanged: number;
+    /** Products that can't be listed, e.g. without an image */
+    skipped: number;
+    failed: number;
+    /** Failed products waiting for their retry time */
+    deferred: number;
+    error: string | null;
+    startedAt: number;
+    finishedAt: number | null;
+}
+
+export interface CatalogSyncIssue {
+    productId: string;
+    sku: string;
+    error: string;
+    attempts: number;
+    nextAttemptAt: number;
+}
+
+export interface CatalogSyncStatus {
+    enabled: boolean;
+    session: string | null;
+    intervalMinutes: number;
+    running: boolean;
+    lastRun: CatalogSyncRun | null;
+    /** Products currently in the WhatsApp catalog */
+    listed: number;
+    issues: CatalogSyncIssue[];
+}
+
+/*
+ * The WhatsApp Business catalog is synced by the desktop app on a schedule
+ * and after product changes, pushing only what changed since the last run.
+ */
+
+export async function syncCatalog(): Promise<CatalogSyncRun> {
+    requireTauri("Catalog sync");
+    return invoke<CatalogSyncRun>("whatsapp_catalog_sync");
+}
+
+export async function getCatalogSyncStatus(): Promise<CatalogSyncStatus | null> {
+    if (!isTauri()) return null;
+    return invoke<CatalogSyncStatus>("whatsapp_catalog_status");
+}
+
+export async function listCatalogSyncRuns(limit = 20): Promise<CatalogSyncRun[]> {
+    if (!isTauri()) return [];
+    return invoke<CatalogSyncRun[]>("whatsapp_catalog_runs", { limit });
+}
+
+/** Finished catalog sync runs pushed by the desktop app */
+export async function onCatalogSync(
+    handler: (run: CatalogSyncRun) => void
+): Promise<UnlistenFn> {
+    if (!isTauri()) return () => {};
+    return listen<CatalogSyncRun>("whatsapp-catalog-sync", (event) => handler(event.payload));
+}
 
 interface WPPSession {
     id: string;
@@ -60,7 +399,7 @@ interface WPPContact {
  */
 export async function checkWPPConnectStatus(): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/status`);
+        const response = await sidecarFetch(`/api/status`);
         return response.ok;
     } catch {
         return false;
@@ -72,7 +411,7 @@ export async function checkWPPConnectStatus(): Promise<boolean> {
  */
 export async function startSession(sessionId: string): Promise<WPPSession | null> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/start`, {
+        const response = await sidecarFetch(`/api/${sessionId}/start`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
         });
@@ -96,7 +435,7 @@ export async function startSession(sessionId: string): Promise<WPPSession | null
  */
 export async function getSessionQR(sessionId: string): Promise<string | null> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/qrcode`);
+        const response = await sidecarFetch(`/api/${sessionId}/qrcode`);
         if (!response.ok) return null;
 
         const data = await response.json();
@@ -111,7 +450,7 @@ export async function getSessionQR(sessionId: string): Promise<string | null> {
  */
 export async function getSessionStatus(sessionId: string): Promise<WPPSession["status"]> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/status`);
+        const response = await sidecarFetch(`/api/${sessionId}/status`);
         if (!response.ok) return "DISCONNECTED";
 
         const data = await response.json();
@@ -131,13 +470,13 @@ export async function getSessionStatus(sessionId: string): Promise<WPPSession["s
 export async function closeSession(sessionId: string): Promise<boolean> {
     try {
         // First try the logout endpoint for clean disconnect
-        const logoutResponse = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/logout`, {
+        const logoutResponse = await sidecarFetch(`/api/${sessionId}/logout`, {
             method: "POST",
         });
         if (logoutResponse.ok) return true;
 
         // Fallback to close endpoint
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/close`, {
+        const response = await sidecarFetch(`/api/${sessionId}/close`, {
             method: "POST",
         });
         return response.ok;
@@ -157,7 +496,7 @@ export async function sendImage(
     filename?: string
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/send-image`, {
+        const response = await sidecarFetch(`/api/${sessionId}/send-image`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -185,7 +524,7 @@ export async function sendFile(
     caption?: string
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/send-file`, {
+        const response = await sidecarFetch(`/api/${sessionId}/send-file`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -207,7 +546,7 @@ export async function sendFile(
  */
 export async function markAsRead(sessionId: string, chatId: string): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/mark-as-read/${encodeURIComponent(chatId)}`, {
+        const response = await sidecarFetch(`/api/${sessionId}/mark-as-read/${encodeURIComponent(chatId)}`, {
             method: "POST"
         });
         const data = await response.json();
@@ -222,7 +561,7 @@ export async function markAsRead(sessionId: string, chatId: string): Promise<boo
  */
 export async function setTyping(sessionId: string, chatId: string, isTyping: boolean): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/set-presence`, {
+        const response = await sidecarFetch(`/api/${sessionId}/set-presence`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -247,7 +586,7 @@ export async function replyToMessage(
     replyToMessageId: string
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/reply-message`, {
+        const response = await sidecarFetch(`/api/${sessionId}/reply-message`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -272,7 +611,7 @@ export async function forwardMessage(
     toChatId: string
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/forward-message`, {
+        const response = await sidecarFetch(`/api/${sessionId}/forward-message`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -296,7 +635,7 @@ export async function deleteMessage(
     forEveryone: boolean = false
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/delete-message`, {
+        const response = await sidecarFetch(`/api/${sessionId}/delete-message`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -320,7 +659,7 @@ export async function starMessage(
     star: boolean = true
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/star-message`, {
+        const response = await sidecarFetch(`/api/${sessionId}/star-message`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -344,7 +683,7 @@ export async function archiveChat(
     archive: boolean = true
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/archive-chat`, {
+        const response = await sidecarFetch(`/api/${sessionId}/archive-chat`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -364,7 +703,7 @@ export async function archiveChat(
  */
 export async function getAllContacts(sessionId: string): Promise<WPPContact[]> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/contacts`);
+        const response = await sidecarFetch(`/api/${sessionId}/contacts`);
         if (!response.ok) return [];
 
         const data = await response.json();
@@ -390,7 +729,7 @@ export async function checkNumberStatus(
 ): Promise<{ exists: boolean; jid?: string }> {
     try {
         const formattedPhone = phone.replace(/\D/g, '');
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/check-number/${formattedPhone}`);
+        const response = await sidecarFetch(`/api/${sessionId}/check-number/${formattedPhone}`);
         if (!response.ok) return { exists: false };
 
         const data = await response.json();
@@ -419,7 +758,7 @@ export interface WPPLabel {
  */
 export async function getAllLabels(sessionId: string): Promise<WPPLabel[]> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/labels`);
+        const response = await sidecarFetch(`/api/${sessionId}/labels`);
         if (!response.ok) return [];
 
         const data = await response.json();
@@ -438,7 +777,7 @@ export async function addLabel(
     color?: number
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/labels`, {
+        const response = await sidecarFetch(`/api/${sessionId}/labels`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({ name, color })
@@ -460,7 +799,7 @@ export async function addOrRemoveLabel(
     action: 'add' | 'remove' = 'add'
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/labels/${labelId}`, {
+        const response = await sidecarFetch(`/api/${sessionId}/labels/${labelId}`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({ chatIds, action })
@@ -477,7 +816,7 @@ export async function addOrRemoveLabel(
  */
 export async function deleteLabel(sessionId: string, labelId: string): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/labels/${labelId}`, {
+        const response = await sidecarFetch(`/api/${sessionId}/labels/${labelId}`, {
             method: "DELETE"
         });
         const data = await response.json();
@@ -508,9 +847,9 @@ export async function getBusinessProfile(
 ): Promise<BusinessProfile | null> {
     try {
         const url = chatId
-            ? `${WPPCONNECT_URL}/api/${sessionId}/business-profile?chatId=${encodeURIComponent(chatId)}`
-            : `${WPPCONNECT_URL}/api/${sessionId}/business-profile`;
-        const response = await fetch(url);
+            ? `/api/${sessionId}/business-profile?chatId=${encodeURIComponent(chatId)}`
+            : `/api/${sessionId}/business-profile`;
+        const response = await sidecarFetch(url);
         if (!response.ok) return null;
 
         const data = await response.json();
@@ -528,7 +867,7 @@ export async function editBusinessProfile(
     profile: BusinessProfile
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/business-profile`, {
+        const response = await sidecarFetch(`/api/${sessionId}/business-profile`, {
             method: "PUT",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify(profile)
@@ -564,9 +903,9 @@ export async function getCatalogProducts(
 ): Promise<CatalogProduct[]> {
     try {
         const url = chatId
-            ? `${WPPCONNECT_URL}/api/${sessionId}/catalog/products?chatId=${encodeURIComponent(chatId)}`
-            : `${WPPCONNECT_URL}/api/${sessionId}/catalog/products`;
-        const response = await fetch(url);
+            ? `/api/${sessionId}/catalog/products?chatId=${encodeURIComponent(chatId)}`
+            : `/api/${sessionId}/catalog/products`;
+        const response = await sidecarFetch(url);
         if (!response.ok) return [];
 
         const data = await response.json();
@@ -591,7 +930,7 @@ export async function createCatalogProduct(
     }
 ): Promise<{ success: boolean; productId?: string }> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/catalog/products`, {
+        const response = await sidecarFetch(`/api/${sessionId}/catalog/products`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify(product)
@@ -611,7 +950,7 @@ export async function deleteCatalogProduct(
     productId: string
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/catalog/products/${productId}`, {
+        const response = await sidecarFetch(`/api/${sessionId}/catalog/products/${productId}`, {
             method: "DELETE"
         });
         const data = await response.json();
@@ -631,7 +970,7 @@ export async function sendCatalogOrder(
     options?: { orderText?: string }
 ): Promise<boolean> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/send-order`, {
+        const response = await sidecarFetch(`/api/${sessionId}/send-order`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({ phone, items, options })
@@ -662,7 +1001,7 @@ export interface WPPChat {
 
 export async function getAllChats(sessionId: string): Promise<WPPChat[]> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/chats`);
+        const response = await sidecarFetch(`/api/${sessionId}/chats`);
         if (!response.ok) return [];
 
         const data = await response.json();
@@ -701,7 +1040,7 @@ export async function getAllChats(sessionId: string): Promise<WPPChat[]> {
  */
 export async function getProfilePicture(sessionId: string, contactId: string): Promise<string | null> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/profile-pic/${encodeURIComponent(contactId)}`);
+        const response = await sidecarFetch(`/api/${sessionId}/profile-pic/${encodeURIComponent(contactId)}`);
         if (!response.ok) return null;
 
         const data = await response.json();
@@ -724,7 +1063,7 @@ export async function getProfilePicture(sessionId: string, contactId: string): P
 export async function downloadMedia(sessionId: string, messageId: string): Promise<string | null> {
     try {
         console.log('[WPP] Downloading media for message:', messageId);
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/download-media`, {
+        const response = await sidecarFetch(`/api/${sessionId}/download-media`, {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ messageId })
@@ -754,7 +1093,7 @@ export async function downloadMedia(sessionId: string, messageId: string): Promi
  */
 export async function getChatMessages(sessionId: string, chatId: string, count = 20): Promise<WPPMessage[]> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/messages/${encodeURIComponent(chatId)}?count=${count}`);
+        const response = await sidecarFetch(`/api/${sessionId}/messages/${encodeURIComponent(chatId)}?count=${count}`);
         if (!response.ok) return [];
 
         const data = await response.json();
@@ -870,7 +1209,7 @@ export async function sendMessage(
             ? phone
             : phone.replace(/\D/g, "");
 
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/send-message`, {
+        const response = await sidecarFetch(`/api/${sessionId}/send-message`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({
@@ -887,6 +1226,28 @@ export async function sendMessage(
     }
 }
 
+/**
+ * Queue a text message in the durable outbox. The desktop app delivers it
+ * with rate limiting once the session is up; in the browser it is sent
+ * immediately.
+ */
+export async function queueMessage(
+    sessionId: string,
+    phone: string,
+    message: string
+): Promise<boolean> {
+    if (!isTauri()) {
+        return sendMessage(sessionId, phone, message);
+    }
+    try {
+        await invoke("whatsapp_enqueue_message", { session: sessionId, phone, message });
+        return true;
+    } catch (err) {
+        console.error("Failed to queue message:", err);
+        return false;
+    }
+}
+
 /**
  * Send order confirmation message
  */
@@ -916,7 +1277,7 @@ Thank you for your order! We'll update you when it ships.
 
 _Zennila - Fashion Jewelry_`;
 
-    return sendMessage(sessionId, phone, message);
+    return queueMessage(sessionId, phone, message);
 }
 
 /**
@@ -940,7 +1301,7 @@ ${orderDetails.estimatedDelivery ? `Expected Delivery: ${orderDetails.estimatedD
 
 _Zennila - Fashion Jewelry_`;
 
-    return sendMessage(sessionId, phone, message);
+    return queueMessage(sessionId, phone, message);
 }
 
 /**
@@ -948,7 +1309,7 @@ _Zennila - Fashion Jewelry_`;
  */
 export async function getUnreadMessages(sessionId: string): Promise<WPPMessage[]> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/unread-messages`);
+        const response = await sidecarFetch(`/api/${sessionId}/unread-messages`);
         if (!response.ok) return [];
 
         const data = await response.json();
@@ -960,7 +1321,8 @@ export async function getUnreadMessages(sessionId: string): Promise<WPPMessage[]
 
 /**
  * Parse order from message text
- * Looks for patterns like "order: item1, item2" or product SKUs
+ * Looks for patterns like "order: item1, item2" or product SKUs.
+ * Quick keyword check only; use `parseOrderDraft` to match against the catalog.
  */
 export function parseOrderFromMessage(message: string): {
     isOrder: boolean;
@@ -990,6 +1352,47 @@ export function parseOrderFromMessage(message: string): {
     };
 }
 
+export interface OrderDraftMatch {
+    productId: string;
+    variantId: string | null;
+    sku: string;
+    name: string;
+    variantName: string | null;
+    size: string | null;
+    color: string | null;
+    unitPrice: number;
+    stock: number | null;
+    score: number;
+}
+
+export interface OrderDraftLine {
+    text: string;
+    quantity: number;
+    quantityGiven: boolean;
+    sku: string | null;
+    size: string | null;
+    color: string | null;
+    matched: OrderDraftMatch | null;
+    alternatives: OrderDraftMatch[];
+    confidence: number;
+}
+
+export interface OrderDraft {
+    isOrder: boolean;
+    intent: "purchase" | "inquiry";
+    lines: OrderDraftLine[];
+    confidence: number;
+}
+
+/**
+ * Read a draft order out of a customer message, with each line matched to a
+ * catalog product and scored. Desktop app only; returns null in the browser.
+ */
+export async function parseOrderDraft(message: string, refresh = false): Promise<OrderDraft | null> {
+    if (!isTauri()) return null;
+    return invoke<OrderDraft>("parse_order_message", { message, refresh });
+}
+
 /**
  * Generate auto-reply based on message content
  */
@@ -1035,7 +1438,7 @@ export function generateAutoReply(
  */
 export async function requestPairingCode(sessionId: string, phone: string): Promise<{ code: string; session: string } | null> {
     try {
-        const response = await fetch(`${WPPCONNECT_URL}/api/${sessionId}/pair-phone`, {
+        const response = await sidecarFetch(`/api/${sessionId}/pair-phone`, {
             method: "POST",
             headers: { "Content-Type": "application/json" },
             body: JSON.stringify({ phone })
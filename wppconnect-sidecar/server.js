@@ -3,6 +3,15 @@
  * Lightweight WhatsApp API server for Luminila
  */
 
+// Per-launch bearer token set by the Tauri supervisor. Every route except
+// /health requires it, so the server refuses to start without one. Checked
+// before anything else loads, so a bad launch fails straight away.
+const SECRET_KEY = process.env.WPPCONNECT_SECRET || null;
+if (!SECRET_KEY) {
+    console.error('WPPCONNECT_SECRET is not set; refusing to start without an access token');
+    process.exit(1);
+}
+
 const express = require('express');
 const cors = require('cors');
 const http = require('http');
@@ -10,10 +19,10 @@ const { Server } = require('socket.io');
 const QRCode = require('qrcode');
 const fs = require('fs');
 const { v4: uuidv4 } = require('uuid');
+const crypto = require('crypto');
 
 // Configuration
 const PORT = process.env.WPPCONNECT_PORT || 21465;
-const SECRET_KEY = process.env.WPPCONNECT_SECRET || 'luminila-secret-key';
 
 const app = express();
 const server = http.createServer(app);
@@ -36,6 +45,76 @@ app.use((req, res, next) => {
 
 app.use(express.json({ limit: '50mb' })); // Increase limit for media uploads
 
+function isAuthorized(token) {
+    if (!SECRET_KEY || typeof token !== 'string') return false;
+    const expected = Buffer.from(SECRET_KEY);
+    const actual = Buffer.from(token);
+    return actual.length === expected.length && crypto.timingSafeEqual(actual, expected);
+}
+
+// Require the supervisor's bearer token
+app.use((req, res, next) => {
+    if (req.method === 'OPTIONS' || req.path === '/health') return next();
+
+    const header = req.headers.authorization || '';
+    const token = header.startsWith('Bearer ') ? header.slice(7) : null;
+    if (!isAuthorized(token)) {
+        return res.status(401).json({ success: false, error: 'Unauthorized' });
+    }
+    next();
+});
+
+io.use((socket, next) => {
+    if (isAuthorized(socket.handshake.auth && socket.handshake.auth.token)) return next();
+    next(new Error('Unauthorized'));
+});
+
+// Server-sent event feed consumed by the Tauri app. Events are numbered per
+// process run and the latest ones buffered, so a subscriber that reconnects
+// with Last-Event-ID gets what it missed.
+const BOOT_ID = crypto.randomBytes(4).toString('hex');
+const EVENT_BUFFER_SIZE = 500;
+const eventBuffer = [];
+const eventSubscribers = new Set();
+let eventSeq = 0;
+
+function writeEvent(res, entry) {
+    res.write(`id: ${BOOT_ID}-${entry.seq}\nevent: ${entry.event}\ndata: ${JSON.stringify(entry.data)}\n\n`);
+}
+
+// Send an event to socket.io clients and SSE subscribers
+function publish(event, data) {
+    io.emit(event, data);
+    const entry = { seq: ++eventSeq, event, data };
+    eventBuffer.push(entry);
+    if (eventBuffer.length > EVENT_BUFFER_SIZE) eventBuffer.shift();
+    eventSubscribers.forEach((res) => writeEvent(res, entry));
+}
+
+app.get('/api/events', (req, res) => {
+    res.set({
+        'Content-Type': 'text/event-stream',
+        'Cache-Control': 'no-cache',
+        Connection: 'keep-alive'
+    });
+    res.flushHeaders();
+
+    // Replay missed events. An id from a previous run means everything
+    // buffered since this process started is new to the subscriber.
+    const [bootId, seq] = String(req.headers['last-event-id'] || '').split('-');
+    if (bootId) {
+        const since = bootId === BOOT_ID ? parseInt(seq, 10) || 0 : 0;
+        eventBuffer.filter((entry) => entry.seq > since).forEach((entry) => writeEvent(res, entry));
+    }
+
+    eventSubscribers.add(res);
+    const heartbeat = setInterval(() => res.write(': ping\n\n'), 15000);
+    req.on('close', () => {
+        clearInterval(heartbeat);
+        eventSubscribers.delete(res);
+    });
+});
+
 // Session storage
 const sessions = new Map();
 let wppconnect = null;
@@ -110,18 +189,18 @@ app.post('/api/:session/start', async (req, res) => {
 
                 sessionData.qrCode = base64Qr;
                 sessionData.qrReady = true;
-                io.emit('qrcode', { session, qrCode: base64Qr, attempts });
+                publish('qrcode', { session, qrCode: base64Qr, attempts });
             },
             statusFind: (statusSession, session) => {
                 console.log(`[${session}] Status: ${statusSession}`);
                 if (statusSession === 'inChat' || statusSession === 'isLogged') {
                     sessionData.connected = true;
                     sessionData.qrReady = false;
-                    io.emit('connected', { session });
+                    publish('connected', { session });
                 }
                 if (statusSession === 'notLogged' || statusSession === 'browserClose') {
                     sessionData.connected = false;
-                    io.emit('disconnected', { session });
+                    publish('disconnected', { session });
                 }
             },
             headless: true,
@@ -154,15 +233,17 @@ app.post('/api/:session/start', async (req, res) => {
 
             // Handle incoming messages
             client.onMessage((message) => {
-                io.emit('message', {
+                publish('message', {
                     session,
                     message: {
                         id: message.id,
                         from: message.from,
                         to: message.to,
                         body: message.body,
+                        caption: message.caption,
                         type: message.type,
                         timestamp: message.timestamp,
+                        fromMe: message.fromMe,
                         isGroupMsg: message.isGroupMsg,
                         sender: message.sender
                     }
@@ -171,7 +252,7 @@ app.post('/api/:session/start', async (req, res) => {
 
             // Handle ack (message status)
             client.onAck((ack) => {
-                io.emit('ack', { session, ack });
+                publish('ack', { session, ack });
             });
 
             console.log(`[${session}] Client ready`);
@@ -318,6 +399,7 @@ app.post('/api/:session/pair-phone', async (req, res) => {
                 phoneNumber: phone,
                 catchLinkCode: (code) => {
                     console.log(`[${phoneSession}] Pairing Code Received: ${code}`);
+                    publish('paircode', { session: phoneSession, code });
                     clearTimeout(timeout);
                     resolve(code);
                 },
@@ -325,7 +407,7 @@ app.post('/api/:session/pair-phone', async (req, res) => {
                     console.log(`[${phoneSession}] Status: ${statusSession}`);
                     if (statusSession === 'inChat' || statusSession === 'isLogged') {
                         sessionData.connected = true;
-                        io.emit('connected', { session: phoneSession });
+                        publish('connected', { session: phoneSession });
                     }
                 },
                 headless: true,
@@ -342,7 +424,7 @@ app.post('/api/:session/pair-phone', async (req, res) => {
                 sessionData.client = client;
                 sessionData.connected = true;
                 client.onMessage((message) => {
-                    io.emit('message', { session: phoneSession, message });
+                    publish('message', { session: phoneSession, message });
                 });
             }).catch(err => {
                 console.error(`[${phoneSession}] Create Error:`, err);
@@ -1156,7 +1238,25 @@ app.get('/api/:session/get-product-by-id/:productId', async (req, res) => {
 // Add product to catalog
 app.post('/api/:session/add-product', async (req, res) => {
     const { session } = req.params;
-    const { name, description, price, currency = 'INR', image, isHidden } = req.body;
+    const { name, description, price, currency = 'INR', image, isHidden = false, url, retailerId } = req.body;
+    const sessionData = sessions.get(session);
+
+    if (!sessionData || !sessionData.client) {
+        return res.status(404).json({ success: false, error: 'Session not connected' });
+    }
+
+    try {
+        const result = await sessionData.client.createProduct(name, image, description, price, isHidden, url, retailerId, currency);
+        res.json({ success: true, result });
+    } catch (error) {
+        res.status(500).json({ success: false, error: error.message });
+    }
+});
+
+// Edit product fields (name, description, price, currency, isHidden, retailerId)
+app.post('/api/:session/edit-product', async (req, res) => {
+    const { session } = req.params;
+    const { id, options } = req.body;
     const sessionData = sessions.get(session);
 
     if (!sessionData || !sessionData.client) {
@@ -1164,7 +1264,25 @@ app.post('/api/:session/add-product', async (req, res) => {
     }
 
     try {
-        const result = await sessionData.client.createProduct(name, image, description, price, isHidden, currency);
+        const result = await sessionData.client.editProduct(id, options);
+        res.json({ success: true, result });
+    } catch (error) {
+        res.status(500).json({ success: false, error: error.message });
+    }
+});
+
+// Replace product image
+app.post('/api/:session/change-product-image', async (req, res) => {
+    const { session } = req.params;
+    const { id, base64 } = req.body;
+    const sessionData = sessions.get(session);
+
+    if (!sessionData || !sessionData.client) {
+        return res.status(404).json({ success: false, error: 'Session not connected' });
+    }
+
+    try {
+        const result = await sessionData.client.changeProductImage(id, base64);
         res.json({ success: true, result });
     } catch (error) {
         res.status(500).json({ success: false, error: error.message });
@@ -1863,6 +1981,12 @@ process.on('SIGINT', async () => {
     process.exit(0);
 });
 
+// Graceful shutdown requested by the Tauri supervisor (SIGTERM is not available on Windows)
+app.post('/api/shutdown', (req, res) => {
+    res.json({ success: true, message: 'Shutting down' });
+    setImmediate(() => process.emit('SIGINT'));
+});
+
 // --- Catalog / Product Management ---
 
 // Get all products from catalog
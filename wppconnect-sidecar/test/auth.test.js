@@ -0,0 +1,23 @@
+const { test } = require('node:test');
+const assert = require('node:assert');
+const { spawnSync } = require('node:child_process');
+const path = require('node:path');
+
+const SERVER = path.join(__dirname, '..', 'server.js');
+
+test('refuses to start without an access token', () => {
+    const env = { ...process.env, WPPCONNECT_PORT: '0' };
+    delete env.WPPCONNECT_SECRET;
+    const result = spawnSync(process.execPath, [SERVER], { env, encoding: 'utf8', timeout: 10000 });
+
+    assert.strictEqual(result.status, 1);
+    assert.match(result.stderr, /WPPCONNECT_SECRET is not set/);
+});
+
+test('treats an empty access token as missing', () => {
+    const env = { ...process.env, WPPCONNECT_PORT: '0', WPPCONNECT_SECRET: '' };
+    const result = spawnSync(process.execPath, [SERVER], { env, encoding: 'utf8', timeout: 10000 });
+
+    assert.strictEqual(result.status, 1);
+    assert.match(result.stderr, /WPPCONNECT_SECRET is not set/);
+});